@@ -45,7 +45,7 @@ async fn main() -> Result<()> {
     tidal.refresh_user_info().await?;
     save_session_data(&tidal.get_json());
 
-    println!("User: {}", tidal.user_info.as_ref().unwrap().username);
+    println!("User: {}", tidal.user_info.get().unwrap().username);
 
     // get subscription info
     let subscription = tidal.subscription().await?;
@@ -131,7 +131,7 @@ async fn main() -> Result<()> {
     };
 
     // Create streamer and play the track
-    let streamer = DashStreamer::new();
+    let streamer = DashStreamer::new(tidal.clone());
 
     println!("\n=== Starting Playback ===");
     streamer.stream_track(&playback_info, max_segments).await?;
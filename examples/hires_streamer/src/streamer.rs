@@ -1,53 +1,40 @@
-use bytes::Bytes;
 use color_eyre::eyre::{Result, eyre};
+use futures_util::TryStreamExt;
 use rodio::{Decoder, OutputStream, Sink};
-use std::io::BufReader;
-use tidlers::client::models::track::playback::{
-    DashManifest, ParsedTrackManifest, TrackPlaybackInfoResponse,
+use std::io::{BufReader, Cursor};
+use tidlers::{
+    TidalClient,
+    client::models::track::playback::{
+        DashManifest, ParsedTrackManifest, TrackPlaybackInfoResponse,
+    },
+    download::{flac::remux_fmp4_flac, segments::SegmentFetchOptions},
 };
 
 pub struct DashStreamer {
-    http_client: reqwest::Client,
+    client: TidalClient,
+    fetch_options: SegmentFetchOptions,
 }
 
 impl DashStreamer {
-    pub fn new() -> Self {
+    pub fn new(client: TidalClient) -> Self {
         Self {
-            http_client: reqwest::Client::new(),
+            client,
+            fetch_options: SegmentFetchOptions {
+                concurrency: 8,
+                ..Default::default()
+            },
         }
     }
 
-    async fn download_segment(&self, url: &str) -> Result<Bytes> {
-        let url_display = if url.len() > 100 {
-            format!("{}...", &url[..100])
-        } else {
-            url.to_string()
-        };
-        println!("  Downloading: {}", url_display);
-
-        let response = self
-            .http_client
-            .get(url)
-            .timeout(std::time::Duration::from_secs(30))
-            .send()
-            .await
-            .map_err(|e| eyre!("Failed to download segment: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(eyre!(
-                "Failed to download segment: HTTP {}",
-                response.status()
-            ));
-        }
+    async fn download_file(&self, url: &str) -> Result<Vec<u8>> {
+        println!("  Downloading: {}", url.get(..100).unwrap_or(url));
 
-        let bytes = response
-            .bytes()
+        let bytes: Vec<u8> = self
+            .client
+            .fetch_segments([url.to_string()], &self.fetch_options)
+            .try_concat()
             .await
-            .map_err(|e| eyre!("Failed to read segment bytes: {}", e))?;
-
-        if bytes.is_empty() {
-            return Err(eyre!("Downloaded segment is empty"));
-        }
+            .map_err(|e| eyre!("Failed to download file: {}", e))?;
 
         println!("  Downloaded {} bytes", bytes.len());
 
@@ -59,64 +46,49 @@ impl DashStreamer {
         dash: &DashManifest,
         max_segments: Option<u32>,
     ) -> Result<Vec<u8>> {
-        let mut combined_data = Vec::new();
-
-        // Download initialization segment first
-        if let Some(init_url) = dash.get_init_url() {
-            println!("Downloading initialization segment...");
-            let init_data = self
-                .download_segment(init_url)
-                .await
-                .map_err(|e| eyre!("Failed to download init segment: {}", e))?;
-            println!("  Init segment size: {} bytes", init_data.len());
-            combined_data.extend_from_slice(&init_data);
-        } else {
+        let segment_list = dash
+            .segments()
+            .map_err(|e| eyre!("Failed to list DASH segments: {}", e))?;
+
+        let Some(init_url) = segment_list.initialization.clone() else {
             return Err(eyre!("No initialization segment found"));
-        }
+        };
 
-        // Determine how many segments to download
-        let segment_count = max_segments.unwrap_or(999);
-
-        println!("Downloading up to {} media segments...", segment_count);
-        let mut segments_downloaded = 0;
-        let mut consecutive_failures = 0;
-
-        for segment_num in 1..=segment_count {
-            if let Some(segment_url) = dash.get_segment_url(segment_num) {
-                match self.download_segment(&segment_url).await {
-                    Ok(segment_data) => {
-                        combined_data.extend_from_slice(&segment_data);
-                        segments_downloaded += 1;
-                        consecutive_failures = 0;
-                    }
-                    Err(e) => {
-                        consecutive_failures += 1;
-                        if consecutive_failures >= 3 {
-                            println!(
-                                "Stopped after {} consecutive failures",
-                                consecutive_failures
-                            );
-                            break;
-                        }
-                        println!(
-                            "  Warning: Segment {} failed ({}), trying next...",
-                            segment_num, e
-                        );
-                    }
-                }
-            } else {
-                println!("No more segments available");
-                break;
-            }
-        }
+        // The manifest lists every segment, so only the user's preview limit applies
+        let segment_count = max_segments
+            .map(|max| (max as usize).min(segment_list.segments.len()))
+            .unwrap_or(segment_list.segments.len());
 
-        if segments_downloaded == 0 {
-            return Err(eyre!("No media segments were downloaded"));
+        if segment_count == 0 {
+            return Err(eyre!("No media segments to download"));
         }
 
+        println!(
+            "Downloading {} of {} media segments ({:.1}s total, {} at a time)...",
+            segment_count,
+            segment_list.segments.len(),
+            segment_list.total_duration().as_secs_f64(),
+            self.fetch_options.concurrency
+        );
+
+        // Segments are fetched concurrently but arrive in order, init segment first
+        let urls = std::iter::once(init_url).chain(
+            segment_list
+                .segments
+                .iter()
+                .take(segment_count)
+                .map(|segment| segment.url.clone()),
+        );
+        let combined_data: Vec<u8> = self
+            .client
+            .fetch_segments(urls, &self.fetch_options)
+            .try_concat()
+            .await
+            .map_err(|e| eyre!("Failed to download segments: {}", e))?;
+
         println!(
             "Total: {} segments, {} bytes",
-            segments_downloaded,
+            segment_count,
             combined_data.len()
         );
 
@@ -157,10 +129,19 @@ impl DashStreamer {
 
                 // Download and combine segments
                 println!("Starting download...");
-                let audio_data = self
+                let mut audio_data = self
                     .download_and_combine_segments(dash, max_segments)
                     .await?;
 
+                // rodio can't open FLAC inside fragmented MP4, so remux it to native FLAC
+                if dash.codecs.eq_ignore_ascii_case("flac") {
+                    println!("Remuxing to native FLAC...");
+                    let mut flac = Cursor::new(Vec::new());
+                    remux_fmp4_flac(Cursor::new(&audio_data), &mut flac)
+                        .map_err(|e| eyre!("Failed to remux FLAC: {}", e))?;
+                    audio_data = flac.into_inner();
+                }
+
                 // Play the audio
                 println!("\nInitializing audio playback...");
                 self.play_audio_data(audio_data)?;
@@ -176,10 +157,10 @@ impl DashStreamer {
                 // For JSON manifest (non-HiRes), just download the single URL
                 if let Some(url) = json_manifest.urls.first() {
                     println!("Downloading audio file...");
-                    let audio_data = self.download_segment(url).await?;
+                    let audio_data = self.download_file(url).await?;
 
                     println!("\nInitializing audio playback...");
-                    self.play_audio_data(audio_data.to_vec())?;
+                    self.play_audio_data(audio_data)?;
                 } else {
                     return Err(eyre!("No URLs in manifest"));
                 }
@@ -30,7 +30,7 @@ async fn main() -> Result<()> {
         cl
     };
 
-    if !tidal.session.auth.is_logged_in() {
+    if !tidal.session.auth.read().is_logged_in() {
         let oauth = tidal.get_oauth_link().await?;
 
         println!(
@@ -75,12 +75,12 @@ async fn main() -> Result<()> {
     println!("checking login..");
     println!(
         "status: {:?}",
-        tidal.session.auth.check_login().await.is_ok()
+        tidal.session.auth.snapshot().check_login().await.is_ok()
     );
     println!("getting user info..");
     tidal.refresh_user_info().await?;
 
-    println!("user info: {:#?}", tidal.user_info);
+    println!("user info: {:#?}", tidal.user_info.get());
 
     Ok(())
 }
@@ -349,6 +349,16 @@ pub enum TrackCommands {
 
     Lyrics,
     Credits,
+
+    /// Serve the track over a local HTTP proxy for external players until Ctrl-C
+    Serve {
+        #[clap(short, long, default_value_t = ArgAudioQuality::High, value_enum)]
+        quality: ArgAudioQuality,
+
+        /// Port to listen on (default picks a free port)
+        #[clap(short, long, default_value = "0")]
+        port: u16,
+    },
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -16,7 +16,7 @@ use tidlers::{TidalClient, client::models::track::config::UserUploadsIncludeOpti
 pub async fn execute_command(mut tidal: TidalClient, command: Commands) -> eyre::Result<()> {
     match command {
         Commands::UserInfo => {
-            println!("{:#?}", tidal.user_info);
+            println!("{:#?}", tidal.user_info.get());
         }
 
         Commands::User { user_id } => {
@@ -1,5 +1,8 @@
 use crate::args::TrackCommands;
-use tidlers::{TidalClient, client::models::track::config::TrackPlaybackInfoConfig};
+use tidlers::{
+    TidalClient, client::models::track::config::TrackPlaybackInfoConfig, proxy::ProxyOptions,
+    stream::StreamOptions,
+};
 
 pub async fn execute(
     tidal: &mut TidalClient,
@@ -52,6 +55,27 @@ pub async fn execute(
 
             println!("Credits:\n{:#?}", credits?);
         }
+
+        TrackCommands::Serve { quality, port } => {
+            let proxy = tidal
+                .start_stream_proxy(ProxyOptions {
+                    addr: ([127, 0, 0, 1], port).into(),
+                    stream: StreamOptions {
+                        playback: TrackPlaybackInfoConfig {
+                            audio_quality: Some(quality.to_api_quality()),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .await?;
+
+            println!("Streaming at {}", proxy.track_url(track_id));
+            println!("Press Ctrl-C to stop");
+            tokio::signal::ctrl_c().await?;
+            proxy.shutdown();
+        }
     };
 
     Ok(())
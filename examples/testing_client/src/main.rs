@@ -73,7 +73,7 @@ async fn complete_login_if_needed(client: &mut TidalClient) -> eyre::Result<()>
         return Ok(());
     }
 
-    if client.session.auth.pkce_login && client.session.auth.access_token.is_none() {
+    if client.session.auth.read().pkce_login && client.session.auth.access_token().is_none() {
         println!("Completing PKCE login...");
         handle_pkce_flow(client).await?;
         println!("PKCE complete");
@@ -59,22 +59,35 @@ impl PkceConfig {
         let bits: u64 = rng.random();
         let client_unique_key = format!("{:02x}", bits);
 
+        Ok(Self {
+            redirect_uri: PKCE_URI_REDIRECT.to_string(),
+            client_id,
+            client_secret,
+            client_unique_key,
+            code_challenge: String::new(),
+            code_verifier: String::new(),
+            state: None,
+        }
+        .rotated())
+    }
+
+    /// Same config with a fresh code verifier, its challenge and a new `state`
+    pub(crate) fn rotated(mut self) -> Self {
+        let mut rng = rand::rng();
+
         let mut random_bytes = [0u8; 32];
         rng.fill_bytes(&mut random_bytes);
-        let code_verifier = general_purpose::URL_SAFE_NO_PAD.encode(random_bytes);
+        self.code_verifier = general_purpose::URL_SAFE_NO_PAD.encode(random_bytes);
 
         let mut hasher = Sha256::new();
-        hasher.update(code_verifier.as_bytes());
+        hasher.update(self.code_verifier.as_bytes());
         let hash_result = hasher.finalize();
-        let code_challenge = general_purpose::URL_SAFE_NO_PAD.encode(hash_result);
+        self.code_challenge = general_purpose::URL_SAFE_NO_PAD.encode(hash_result);
 
-        Ok(Self {
-            redirect_uri: PKCE_URI_REDIRECT.to_string(),
-            client_id,
-            client_secret,
-            client_unique_key,
-            code_challenge,
-            code_verifier,
-        })
+        let mut state = [0u8; 16];
+        rng.fill_bytes(&mut state);
+        self.state = Some(general_purpose::URL_SAFE_NO_PAD.encode(state));
+
+        self
     }
 }
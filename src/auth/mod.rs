@@ -10,6 +10,7 @@ use crate::{
 pub mod credentials;
 pub mod init;
 pub mod pkce;
+pub mod shared;
 pub mod token;
 
 /// Authentication credentials and configuration for the Tidal API client.
@@ -168,7 +169,7 @@ impl TidalAuth {
     /// let client = TidalClient::new(&auth);
     ///     
     /// // Get access token
-    /// let token = client.session.auth.get_access_token().await?;
+    /// let token = client.session.auth.snapshot().get_access_token().await?;
     /// println!("Access token: {}", token.access_token);
     /// # Ok(())
     /// # }
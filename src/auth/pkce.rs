@@ -8,4 +8,7 @@ pub struct PkceConfig {
     pub client_unique_key: String,
     pub code_challenge: String,
     pub code_verifier: String,
+    /// `state` of the pending login, the redirect has to send it back
+    #[serde(default)]
+    pub state: Option<String>,
 }
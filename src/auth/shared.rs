@@ -0,0 +1,133 @@
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{auth::TidalAuth, client::models::user::User};
+
+/// Shared, interior-mutable handle to the session's [`TidalAuth`].
+///
+/// All clones of a `TidalClient` point at the same auth state, so a token refreshed by one clone
+/// is immediately used by every other clone. Serializes exactly like a plain `TidalAuth`.
+///
+/// Guards returned by [`SharedAuth::read`] and [`SharedAuth::write`] must not be held across an
+/// `.await`, use [`SharedAuth::snapshot`] when you need an owned copy.
+#[derive(Debug, Clone, Default)]
+pub struct SharedAuth {
+    state: Arc<RwLock<TidalAuth>>,
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SharedAuth {
+    /// Wraps the given auth state into a shared handle
+    pub fn new(auth: TidalAuth) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(auth)),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Locks the auth state for reading
+    pub fn read(&self) -> RwLockReadGuard<'_, TidalAuth> {
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the auth state for writing
+    pub fn write(&self) -> RwLockWriteGuard<'_, TidalAuth> {
+        self.state.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns an owned copy of the current auth state
+    pub fn snapshot(&self) -> TidalAuth {
+        self.read().clone()
+    }
+
+    /// Replaces the whole auth state
+    pub fn replace(&self, auth: TidalAuth) {
+        *self.write() = auth;
+    }
+
+    /// Returns the current access token, if any
+    pub fn access_token(&self) -> Option<String> {
+        self.read().access_token.clone()
+    }
+
+    /// Serializes token refreshes so concurrent requests don't all hit the token endpoint at once
+    pub(crate) async fn lock_refresh(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.refresh_lock.lock().await
+    }
+}
+
+impl From<TidalAuth> for SharedAuth {
+    fn from(auth: TidalAuth) -> Self {
+        Self::new(auth)
+    }
+}
+
+impl Serialize for SharedAuth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.read().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedAuth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        TidalAuth::deserialize(deserializer).map(Self::new)
+    }
+}
+
+/// Shared, interior-mutable handle to the logged in [`User`].
+///
+/// Like [`SharedAuth`], all clones of a `TidalClient` point at the same user, so the user data
+/// returned by an automatic token refresh is seen by every clone. Serializes exactly like a
+/// plain `Option<User>`.
+#[derive(Debug, Clone, Default)]
+pub struct SharedUser {
+    state: Arc<RwLock<Option<User>>>,
+}
+
+impl SharedUser {
+    /// Wraps the given user into a shared handle
+    pub fn new(user: Option<User>) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(user)),
+        }
+    }
+
+    /// Locks the user for reading, the guard must not be held across an `.await`
+    pub fn read(&self) -> RwLockReadGuard<'_, Option<User>> {
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns an owned copy of the current user, if any
+    pub fn get(&self) -> Option<User> {
+        self.read().clone()
+    }
+
+    /// Replaces the current user
+    pub fn set(&self, user: Option<User>) {
+        *self.state.write().unwrap_or_else(PoisonError::into_inner) = user;
+    }
+
+    /// Returns `true` if a user is logged in
+    pub fn is_some(&self) -> bool {
+        self.read().is_some()
+    }
+}
+
+impl From<Option<User>> for SharedUser {
+    fn from(user: Option<User>) -> Self {
+        Self::new(user)
+    }
+}
+
+impl Serialize for SharedUser {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.read().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedUser {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<User>::deserialize(deserializer).map(Self::new)
+    }
+}
@@ -11,7 +11,6 @@ use crate::{
     auth::TidalAuth,
     client::models::responses::ClientCredentialsTokenResponse,
     requests::{self, TidalRequest},
-    urls::OAUTH2_V1_LOCATION,
 };
 
 impl TidalAuth {
@@ -47,7 +46,7 @@ impl TidalAuth {
             self.client_id.clone(),
             self.client_secret.clone(),
         ));
-        req.base_url = Some(OAUTH2_V1_LOCATION.to_string());
+        req.base_url = Some(self.rq.oauth_base_url().to_string());
 
         let res = self.rq.request(req).await?;
         let json: ClientCredentialsTokenResponse = res.json().await?;
@@ -1,10 +1,13 @@
+use futures_util::Stream;
+
 use crate::{
     client::{
         TidalClient,
         models::album::{
-            AlbumItemsResponse, AlbumItemsWithCreditsResponse, AlbumResponse, AlbumReviewResponse,
-            GeneralCreditsResponse,
+            AlbumItemsEntry, AlbumItemsResponse, AlbumItemsWithCreditsResponse, AlbumResponse,
+            AlbumReviewResponse, GeneralCreditsResponse,
         },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
     ids::AlbumId,
@@ -78,6 +81,43 @@ impl TidalClient {
             .await
     }
 
+    /// Streams every track of an album across all pages
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use futures_util::StreamExt;
+    /// # use tidlers::{TidalClient, auth::TidalAuth, client::pagination::PageOptions};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let auth = TidalAuth::with_oauth();
+    /// # let client = TidalClient::new(&auth);
+    /// let items = client.get_album_items_all("123456789", PageOptions::default());
+    /// let mut items = std::pin::pin!(items);
+    /// while let Some(album_item) = items.next().await {
+    ///     println!("{}", album_item?.item.title);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_album_items_all(
+        &self,
+        album_id: impl Into<AlbumId>,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<AlbumItemsEntry, TidalError>> + '_ {
+        let album_id = album_id.into();
+        Paginator::new(options, move |request: PageRequest| {
+            let album_id = album_id.clone();
+            async move {
+                let res = self
+                    .get_album_items(album_id, Some(request.limit.into()), Some(request.offset()))
+                    .await?;
+                let total = u64::try_from(res.total_number_of_items).ok();
+                Ok(Page::from_offset(res.items, &request, total))
+            }
+        })
+        .into_stream()
+    }
+
     /// Retrieves album credits information
     pub async fn get_album_credits(
         &self,
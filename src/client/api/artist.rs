@@ -1,13 +1,17 @@
+use futures_util::Stream;
+
 use crate::{
     client::{
         TidalClient,
         models::{
+            album::ArtistAlbum,
             artist::{
                 ArtistAlbumsResponse, ArtistBiographyResponse, ArtistLinksResponse, ArtistResponse,
                 ArtistTopTracksResponse, ArtistVideosResponse, SimilarArtistsResponse,
             },
             mixes::TrackMixResponse,
         },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
     ids::ArtistId,
@@ -109,6 +113,30 @@ impl TidalClient {
         .await
     }
 
+    /// Streams every album of an artist across all pages.
+    pub fn get_artist_albums_all(
+        &self,
+        artist_id: impl Into<ArtistId>,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<ArtistAlbum, TidalError>> + '_ {
+        let artist_id = artist_id.into();
+        Paginator::new(options, move |request: PageRequest| {
+            let artist_id = artist_id.clone();
+            async move {
+                let res = self
+                    .get_artist_albums(
+                        artist_id,
+                        Some(request.limit.into()),
+                        Some(request.offset()),
+                    )
+                    .await?;
+                let total = Some(res.total_number_of_items.into());
+                Ok(Page::from_offset(res.items, &request, total))
+            }
+        })
+        .into_stream()
+    }
+
     /// Retrieves videos for an artist.
     pub async fn get_artist_videos(
         &self,
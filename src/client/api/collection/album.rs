@@ -1,5 +1,13 @@
+use futures_util::Stream;
+
 use crate::{
-    TidalClient, TidalError, client::models::collection::album::CollectionFavoriteAlbumsResponse,
+    TidalClient, TidalError,
+    client::{
+        models::collection::album::{
+            CollectionFavoriteAlbumEntry, CollectionFavoriteAlbumsResponse,
+        },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
+    },
     urls::API_V1_LOCATION,
 };
 
@@ -18,11 +26,29 @@ impl TidalClient {
             .with_country_code()
             .with_locale()
             .with_param("limit", limit.unwrap_or(100).to_string())
-            .with_param("ofset", offset.unwrap_or(0).to_string())
+            .with_param("offset", offset.unwrap_or(0).to_string())
             .with_base_url(API_V1_LOCATION)
             .send()
             .await?;
 
         Ok(body)
     }
+
+    /// Streams all of the user's favorite albums across all pages.
+    pub fn get_collection_album_favorites_all(
+        &self,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<CollectionFavoriteAlbumEntry, TidalError>> + '_ {
+        Paginator::new(options, move |request: PageRequest| async move {
+            let res = self
+                .get_collection_album_favorites(
+                    Some(request.limit),
+                    Some(u32::try_from(request.offset())?),
+                )
+                .await?;
+            let total = u64::try_from(res.total_number_of_items).ok();
+            Ok(Page::from_offset(res.items, &request, total))
+        })
+        .into_stream()
+    }
 }
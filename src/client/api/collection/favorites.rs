@@ -21,6 +21,7 @@ impl TidalClient {
         let user_id = self
             .session
             .auth
+            .read()
             .user_id
             .ok_or_else(|| TidalError::NotAuthenticated)?;
 
@@ -46,6 +47,7 @@ impl TidalClient {
         let user_id = self
             .session
             .auth
+            .read()
             .user_id
             .ok_or_else(|| TidalError::NotAuthenticated)?;
 
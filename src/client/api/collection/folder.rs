@@ -1,3 +1,4 @@
+use futures_util::Stream;
 use tracing::debug;
 
 use crate::{
@@ -7,6 +8,7 @@ use crate::{
             OrderDirection,
             collection::folder::{FolderCollectionEntry, FolderListResponse, FolderOrder},
         },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
     urls::API_V2_LOCATION,
@@ -81,4 +83,35 @@ impl TidalClient {
         .send()
         .await
     }
+
+    /// Streams all folders across all pages.
+    pub fn flattened_folders_all(
+        &self,
+        order: Option<FolderOrder>,
+        order_direction: Option<OrderDirection>,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<FolderCollectionEntry, TidalError>> + '_ {
+        Paginator::new(options, move |request: PageRequest| {
+            let order = order.clone();
+            let order_direction = order_direction.clone();
+            async move {
+                let res = self
+                    .flattened_folders(
+                        Some(request.limit),
+                        Some(u32::try_from(request.offset())?),
+                        order,
+                        order_direction,
+                    )
+                    .await?;
+
+                // the endpoint doesn't report a total, a missing cursor marks the last page
+                let mut page = Page::from_offset(res.items, &request, None);
+                if res.cursor.is_none() {
+                    page.next = None;
+                }
+                Ok(page)
+            }
+        })
+        .into_stream()
+    }
 }
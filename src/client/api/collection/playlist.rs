@@ -5,15 +5,17 @@ use crate::{
             OrderDirection,
             collection::{SharingLevel, playlist::CollectionPlaylistEntry},
             playlist::{
-                PlaylistItemsOrder, PlaylistItemsResponse, PlaylistItemsWithEtag, PlaylistResponse,
-                PublicUserPlaylistsResponse, UserPlaylistsResponse,
+                PlaylistItem, PlaylistItemsOrder, PlaylistItemsResponse, PlaylistItemsWithEtag,
+                PlaylistResponse, PublicUserPlaylistsResponse, UserPlaylistsResponse,
             },
         },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
     ids::PlaylistId,
     urls::API_V2_LOCATION,
 };
+use futures_util::Stream;
 use reqwest::header::{HeaderMap, HeaderValue, IF_NONE_MATCH};
 
 impl TidalClient {
@@ -131,6 +133,36 @@ impl TidalClient {
         Ok(response.items)
     }
 
+    /// Streams every item of a playlist across all pages.
+    pub fn get_playlist_items_all(
+        &self,
+        playlist_id: impl Into<PlaylistId>,
+        order: Option<PlaylistItemsOrder>,
+        order_direction: Option<OrderDirection>,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<PlaylistItem, TidalError>> + '_ {
+        let playlist_id = playlist_id.into();
+        Paginator::new(options, move |request: PageRequest| {
+            let playlist_id = playlist_id.clone();
+            let order = order.clone();
+            let order_direction = order_direction.clone();
+            async move {
+                let res = self
+                    .get_playlist_items(
+                        playlist_id,
+                        Some(request.limit.into()),
+                        Some(request.offset()),
+                        order,
+                        order_direction,
+                    )
+                    .await?;
+                let total = Some(res.total_number_of_items);
+                Ok(Page::from_offset(res.items, &request, total))
+            }
+        })
+        .into_stream()
+    }
+
     /// Retrieves playlist items together with the response ETag.
     pub async fn get_playlist_items_with_etag(
         &self,
@@ -1,7 +1,10 @@
+use futures_util::Stream;
+
 use crate::{
     client::{
         TidalClient,
-        models::home::{HomeFeedPhone, HomeFeedWeb},
+        models::home::{HomeFeedPhone, HomeFeedWeb, HomeItem},
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
     urls::WEB_API_V2_LOCATION,
@@ -10,6 +13,16 @@ use crate::{
 impl TidalClient {
     /// Uses the WEB_API_V2_LOCATION/home/feed/static with deviceType=BROWSER
     pub async fn get_home_feed(&self, limit: u32) -> Result<HomeFeedWeb, TidalError> {
+        self.get_home_feed_with_cursor(limit, None).await
+    }
+
+    /// Same as [`TidalClient::get_home_feed`], continuing from the `page.cursor` of a previous
+    /// response
+    pub async fn get_home_feed_with_cursor(
+        &self,
+        limit: u32,
+        cursor: Option<String>,
+    ) -> Result<HomeFeedWeb, TidalError> {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             "x-tidal-client-version",
@@ -23,12 +36,27 @@ impl TidalClient {
             .with_param("deviceType", "BROWSER")
             .with_param("platform", "WEB")
             .with_param("timeOffset", self.session.time_offset.clone())
+            .with_optional_param("cursor", cursor)
             .with_headers(headers)
             .with_base_url(WEB_API_V2_LOCATION)
             .send()
             .await
     }
 
+    /// Streams every home feed module across all pages of the web home feed
+    pub fn get_home_feed_all(
+        &self,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<HomeItem, TidalError>> + '_ {
+        Paginator::new(options, move |request: PageRequest| async move {
+            let res = self
+                .get_home_feed_with_cursor(request.limit, request.cursor())
+                .await?;
+            Ok(Page::from_cursor(res.items, res.page.cursor))
+        })
+        .into_stream()
+    }
+
     /// Uses the WEB_API_V2_LOCATION/home/feed/static with deviceType=PHONE
     pub async fn get_home_feed_phone(&self, limit: u32) -> Result<HomeFeedPhone, TidalError> {
         let mut headers = reqwest::header::HeaderMap::new();
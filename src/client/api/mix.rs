@@ -1,9 +1,16 @@
+use futures_util::Stream;
+
 use crate::{
     client::{
         TidalClient,
-        models::{mix::MixItemsResponse, mixes::ArrivalMixResource, responses::ApiDataResponse},
+        models::{
+            mix::MixItemsResponse, mixes::ArrivalMixResource, responses::ApiDataResponse,
+            track::Track,
+        },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
+    ids::MixId,
     urls::OPEN_API_V2_LOCATION,
 };
 
@@ -23,6 +30,30 @@ impl TidalClient {
             .await
     }
 
+    /// Streams every track of a mix across all pages
+    pub fn get_mix_tracks_all(
+        &self,
+        mix_id: impl Into<MixId>,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<Track, TidalError>> + '_ {
+        let mix_id = mix_id.into();
+        Paginator::new(options, move |request: PageRequest| {
+            let mix_id = mix_id.to_string();
+            async move {
+                let res = self
+                    .get_mix_tracks(
+                        mix_id,
+                        Some(request.limit),
+                        Some(u32::try_from(request.offset())?),
+                    )
+                    .await?;
+                let total = Some(res.total_number_of_items.into());
+                Ok(Page::from_offset(res.items, &request, total))
+            }
+        })
+        .into_stream()
+    }
+
     /// Retrieves the user's arrival mixes.
     pub async fn get_arrival_mixes(
         &self,
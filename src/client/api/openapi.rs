@@ -1,4 +1,18 @@
-use crate::{client::TidalClient, error::TidalError, urls::OPEN_API_V2_LOCATION};
+use crate::{
+    client::{
+        TidalClient,
+        models::{
+            jsonapi::Document,
+            openapi::{
+                AlbumResource, ArtistResource, CreditResource, LyricsResource, PlaylistResource,
+                TrackResource, UserCollectionFolderResource, UserCollectionResource, VideoResource,
+            },
+        },
+    },
+    error::TidalError,
+    urls::OPEN_API_V2_LOCATION,
+};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
 macro_rules! openapi_get_no_id_methods {
@@ -22,12 +36,36 @@ macro_rules! openapi_get_id_methods {
     };
 }
 
+macro_rules! openapi_get_typed_methods {
+    ($(($name:ident, $path:literal, $resource:ty)),+ $(,)?) => {
+        $(pub async fn $name(
+            &self,
+            query_params: &[(&str, &str)],
+        ) -> Result<Document<Vec<$resource>>, TidalError> {
+            self.openapi_get_document($path, query_params).await
+        })+
+    };
+}
+
+macro_rules! openapi_get_typed_id_methods {
+    ($(($name:ident, $path:literal, $resource:ty)),+ $(,)?) => {
+        $(pub async fn $name(
+            &self,
+            id: impl Into<String>,
+            query_params: &[(&str, &str)],
+        ) -> Result<Document<$resource>, TidalError> {
+            let id = id.into();
+            self.openapi_get_document($path.replace("{id}", &id), query_params).await
+        })+
+    };
+}
+
 impl TidalClient {
-    async fn openapi_get_json(
+    async fn openapi_get_json<T: DeserializeOwned>(
         &self,
         path: impl Into<String>,
         query_params: &[(&str, &str)],
-    ) -> Result<Value, TidalError> {
+    ) -> Result<T, TidalError> {
         let mut request = self
             .request(reqwest::Method::GET, path.into())
             .with_base_url(OPEN_API_V2_LOCATION);
@@ -48,6 +86,79 @@ impl TidalClient {
         self.openapi_get_json(path, query_params).await
     }
 
+    /// Same as [`TidalClient::openapi_get_path`], but parses the response as a JSON:API document
+    /// with primary data of type `T`
+    pub async fn openapi_get_document<T: DeserializeOwned>(
+        &self,
+        path: impl Into<String>,
+        query_params: &[(&str, &str)],
+    ) -> Result<Document<T>, TidalError> {
+        self.openapi_get_json(path, query_params).await
+    }
+
+    openapi_get_typed_methods!(
+        (openapi_get_albums_typed, "/albums", AlbumResource),
+        (openapi_get_artists_typed, "/artists", ArtistResource),
+        (openapi_get_credits_typed, "/credits", CreditResource),
+        (openapi_get_lyrics_typed, "/lyrics", LyricsResource),
+        (openapi_get_playlists_typed, "/playlists", PlaylistResource),
+        (openapi_get_tracks_typed, "/tracks", TrackResource),
+        (
+            openapi_get_user_collection_folders_typed,
+            "/userCollectionFolders",
+            UserCollectionFolderResource
+        ),
+        (openapi_get_videos_typed, "/videos", VideoResource),
+    );
+
+    openapi_get_typed_id_methods!(
+        (
+            openapi_get_albums_by_id_typed,
+            "/albums/{id}",
+            AlbumResource
+        ),
+        (
+            openapi_get_artists_by_id_typed,
+            "/artists/{id}",
+            ArtistResource
+        ),
+        (
+            openapi_get_credits_by_id_typed,
+            "/credits/{id}",
+            CreditResource
+        ),
+        (
+            openapi_get_lyrics_by_id_typed,
+            "/lyrics/{id}",
+            LyricsResource
+        ),
+        (
+            openapi_get_playlists_by_id_typed,
+            "/playlists/{id}",
+            PlaylistResource
+        ),
+        (
+            openapi_get_tracks_by_id_typed,
+            "/tracks/{id}",
+            TrackResource
+        ),
+        (
+            openapi_get_user_collection_folders_by_id_typed,
+            "/userCollectionFolders/{id}",
+            UserCollectionFolderResource
+        ),
+        (
+            openapi_get_user_collections_by_id_typed,
+            "/userCollections/{id}",
+            UserCollectionResource
+        ),
+        (
+            openapi_get_videos_by_id_typed,
+            "/videos/{id}",
+            VideoResource
+        ),
+    );
+
     openapi_get_no_id_methods!(
         (openapi_get_albums, "/albums"),
         (openapi_get_artists, "/artists"),
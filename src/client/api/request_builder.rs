@@ -115,15 +115,17 @@ impl<'a> ApiRequestBuilder<'a> {
         self
     }
 
-    /// Executes the request and deserializes the response into type T
-    pub(crate) async fn send<T: DeserializeOwned>(mut self) -> Result<T, TidalError> {
+    /// Builds the underlying request, access token is attached when it's sent
+    fn build_request(&mut self) -> Result<TidalRequest, TidalError> {
         if self.add_country_code {
-            if let Some(user_info) = &self.client.user_info {
-                self.params
-                    .insert("countryCode".to_string(), user_info.country_code.clone());
-            } else {
-                return Err(TidalError::NotAuthenticated);
-            }
+            let country_code = self
+                .client
+                .user_info
+                .read()
+                .as_ref()
+                .map(|user| user.country_code.clone())
+                .ok_or(TidalError::NotAuthenticated)?;
+            self.params.insert("countryCode".to_string(), country_code);
         }
 
         if self.add_locale {
@@ -131,12 +133,19 @@ impl<'a> ApiRequestBuilder<'a> {
                 .insert("locale".to_string(), self.client.session.locale.clone());
         }
 
-        let mut req = TidalRequest::new(self.method, self.url.clone());
-        req.params = Some(self.params);
-        req.form = (!self.form_params.is_empty()).then_some(vec![self.form_params]);
-        req.access_token = self.client.session.auth.access_token.clone();
-        req.base_url = self.base_url;
-        req.headers = Some(self.headers);
+        let mut req = TidalRequest::new(self.method.clone(), self.url.clone());
+        req.params = Some(std::mem::take(&mut self.params));
+        let form_params = std::mem::take(&mut self.form_params);
+        req.form = (!form_params.is_empty()).then_some(vec![form_params]);
+        req.base_url = self.base_url.take();
+        req.headers = Some(std::mem::take(&mut self.headers));
+
+        Ok(req)
+    }
+
+    /// Executes the request and deserializes the response into type T
+    pub(crate) async fn send<T: DeserializeOwned>(mut self) -> Result<T, TidalError> {
+        let req = self.build_request()?;
 
         debug!(
             method = %req.method,
@@ -146,7 +155,7 @@ impl<'a> ApiRequestBuilder<'a> {
             has_headers = req.headers.is_some(),
             "sending API request"
         );
-        let resp = self.client.rq.request(req).await?;
+        let resp = self.client.send_authorized(req).await?;
 
         if resp.status() == reqwest::StatusCode::NOT_FOUND {
             return Err(TidalError::NotFound);
@@ -198,26 +207,7 @@ impl<'a> ApiRequestBuilder<'a> {
     pub(crate) async fn send_with_etag<T: DeserializeOwned>(
         mut self,
     ) -> Result<(T, Option<String>), TidalError> {
-        if self.add_country_code {
-            if let Some(user_info) = &self.client.user_info {
-                self.params
-                    .insert("countryCode".to_string(), user_info.country_code.clone());
-            } else {
-                return Err(TidalError::NotAuthenticated);
-            }
-        }
-
-        if self.add_locale {
-            self.params
-                .insert("locale".to_string(), self.client.session.locale.clone());
-        }
-
-        let mut req = TidalRequest::new(self.method, self.url.clone());
-        req.params = Some(self.params);
-        req.form = (!self.form_params.is_empty()).then_some(vec![self.form_params]);
-        req.access_token = self.client.session.auth.access_token.clone();
-        req.base_url = self.base_url;
-        req.headers = Some(self.headers);
+        let req = self.build_request()?;
 
         debug!(
             method = %req.method,
@@ -227,7 +217,7 @@ impl<'a> ApiRequestBuilder<'a> {
             has_headers = req.headers.is_some(),
             "sending API request"
         );
-        let resp = self.client.rq.request(req).await?;
+        let resp = self.client.send_authorized(req).await?;
 
         if resp.status() == reqwest::StatusCode::NOT_FOUND {
             return Err(TidalError::NotFound);
@@ -284,26 +274,7 @@ impl<'a> ApiRequestBuilder<'a> {
 
     /// Executes the request and returns the raw response as a String
     pub(crate) async fn send_raw(mut self) -> Result<String, TidalError> {
-        if self.add_country_code {
-            if let Some(user_info) = &self.client.user_info {
-                self.params
-                    .insert("countryCode".to_string(), user_info.country_code.clone());
-            } else {
-                return Err(TidalError::NotAuthenticated);
-            }
-        }
-
-        if self.add_locale {
-            self.params
-                .insert("locale".to_string(), self.client.session.locale.clone());
-        }
-
-        let mut req = TidalRequest::new(self.method, self.url);
-        req.params = Some(self.params);
-        req.form = (!self.form_params.is_empty()).then_some(vec![self.form_params]);
-        req.access_token = self.client.session.auth.access_token.clone();
-        req.base_url = self.base_url;
-        req.headers = Some(self.headers);
+        let req = self.build_request()?;
 
         debug!(
             method = %req.method,
@@ -313,7 +284,7 @@ impl<'a> ApiRequestBuilder<'a> {
             has_headers = req.headers.is_some(),
             "sending raw API request"
         );
-        let resp = self.client.rq.request(req).await?;
+        let resp = self.client.send_authorized(req).await?;
         let status = resp.status();
         let response_url = resp.url().to_string();
         let body = resp.text().await?;
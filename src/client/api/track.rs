@@ -1,7 +1,7 @@
 use base64::{Engine, engine::general_purpose};
-use quick_xml::Reader;
-use quick_xml::events::Event;
+use futures_util::Stream;
 use reqwest::StatusCode;
+use tracing::{debug, warn};
 
 use crate::{
     client::{
@@ -9,15 +9,19 @@ use crate::{
         models::{
             album::GeneralCreditsResponse,
             mixes::TrackMixResponse,
-            playback::AssetPresentation,
+            openapi::TrackResource,
+            playback::{AssetPresentation, AudioQuality},
             track::{
                 LyricsResponse, Track, TrackRadioResponse,
                 config::TrackPlaybackInfoConfig,
+                mpd::Mpd,
                 playback::{
                     DashManifest, JsonTrackManifest, ParsedTrackManifest, TrackPlaybackInfoResponse,
                 },
+                quality::{NegotiatedPlayback, QualityLimit, QualityReport},
             },
         },
+        pagination::{Page, PageOptions, PageRequest, Paginator},
     },
     error::TidalError,
     ids::TrackId,
@@ -52,121 +56,61 @@ impl TidalClient {
     }
 
     /// Parses a DASH XML manifest into a structured format
+    ///
+    /// The flat fields describe the highest bandwidth representation, the full MPD is kept in
+    /// `DashManifest::mpd`.
     fn parse_dash_manifest(xml: &str) -> Result<DashManifest, TidalError> {
-        let mut reader = Reader::from_str(xml);
-        reader.config_mut().trim_text(true);
-
-        let mut urls = Vec::new();
-        let mut mime_type = String::new();
-        let mut codecs = String::new();
-        let mut bitrate = None;
-        let mut buf = Vec::new();
-        let mut init_url = None;
-        let mut media_url = None;
-        let mut timescale = None;
-        let mut duration = None;
-        let mut start_number = None;
-
-        loop {
-            match reader.read_event_into(&mut buf) {
-                Ok(Event::Empty(e)) | Ok(Event::Start(e)) => match e.name().as_ref() {
-                    b"AdaptationSet" => {
-                        for attr in e.attributes().flatten() {
-                            if attr.key.as_ref() == b"mimeType" {
-                                mime_type = String::from_utf8_lossy(&attr.value).to_string();
-                            }
-                        }
-                    }
-                    b"Representation" => {
-                        for attr in e.attributes().flatten() {
-                            match attr.key.as_ref() {
-                                b"codecs" => {
-                                    codecs = String::from_utf8_lossy(&attr.value).to_string();
-                                }
-                                b"bandwidth" => {
-                                    bitrate =
-                                        String::from_utf8_lossy(&attr.value).parse::<u32>().ok();
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    b"SegmentTemplate" => {
-                        for attr in e.attributes().flatten() {
-                            match attr.key.as_ref() {
-                                b"initialization" => {
-                                    init_url =
-                                        Some(String::from_utf8_lossy(&attr.value).to_string());
-                                }
-                                b"media" => {
-                                    media_url =
-                                        Some(String::from_utf8_lossy(&attr.value).to_string());
-                                }
-                                b"timescale" => {
-                                    timescale =
-                                        String::from_utf8_lossy(&attr.value).parse::<u32>().ok();
-                                }
-                                b"duration" => {
-                                    duration =
-                                        String::from_utf8_lossy(&attr.value).parse::<u32>().ok();
-                                }
-                                b"startNumber" => {
-                                    start_number =
-                                        String::from_utf8_lossy(&attr.value).parse::<u32>().ok();
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    b"BaseURL" => {
-                        if let Ok(Event::Text(e)) = reader.read_event_into(&mut buf) {
-                            let url = String::from_utf8_lossy(e.as_ref()).to_string();
-                            if !url.is_empty() {
-                                urls.push(url);
-                            }
-                        }
-                    }
-                    _ => {}
-                },
-                Ok(Event::Eof) => break,
-                Err(e) => return Err(TidalError::Other(format!("XML parsing error: {}", e))),
-                _ => {}
-            }
-            buf.clear();
-        }
-
-        let initialization_url = init_url.clone();
-        let media_url_template = media_url.clone();
+        let mpd = Mpd::parse(xml)?;
+        let Some(best) = mpd.best_representation() else {
+            return Err(TidalError::InvalidManifest(
+                "No representation found in DASH manifest".to_string(),
+            ));
+        };
 
-        if let Some(init) = init_url {
-            urls.push(init);
-        }
-        if let Some(media) = media_url {
-            urls.push(media);
-        }
+        let template = best.segment_template();
+        let mut urls: Vec<String> = [
+            &mpd.base_urls,
+            &best.period.base_urls,
+            &best.adaptation_set.base_urls,
+            &best.representation.base_urls,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+        urls.extend(template.initialization.clone());
+        urls.extend(template.media.clone());
 
         if urls.is_empty() {
-            return Err(TidalError::Other(
+            return Err(TidalError::InvalidManifest(
                 "No URLs found in DASH manifest".to_string(),
             ));
         }
 
         Ok(DashManifest {
-            mime_type,
-            codecs,
+            mime_type: best.mime_type().unwrap_or_default().to_string(),
+            codecs: best.codecs().unwrap_or_default().to_string(),
             urls,
-            bitrate,
-            initialization_url,
-            media_url_template,
-            timescale,
-            duration,
-            start_number,
+            bitrate: best
+                .representation
+                .bandwidth
+                .and_then(|bandwidth| u32::try_from(bandwidth).ok()),
+            initialization_url: template.initialization,
+            media_url_template: template.media,
+            timescale: template.timescale.and_then(|t| u32::try_from(t).ok()),
+            duration: template.duration.and_then(|d| u32::try_from(d).ok()),
+            start_number: template.start_number.and_then(|n| u32::try_from(n).ok()),
+            mpd: Some(mpd),
         })
     }
 
     /// Gets track playback information including streaming URLs and manifest
     /// Takes in a track ID and optionally a config that overrides session set config
     ///
+    /// Immersive audio modes are only granted for tracks that offer them (see
+    /// [`Track::immersive_modes`]), check [`TrackPlaybackInfoResponse::audio_mode`] for the mode
+    /// that was actually delivered.
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -193,6 +137,7 @@ impl TidalClient {
         let audio_quality = config
             .audio_quality
             .unwrap_or(self.session.audio_quality.clone());
+        let audio_mode = config.audio_mode.unwrap_or(self.session.audio_mode.clone());
         let playback_mode = config
             .playback_mode
             .unwrap_or(self.session.playback_mode.clone());
@@ -205,6 +150,11 @@ impl TidalClient {
             )
             .with_country_code()
             .with_param("audioquality", audio_quality.to_string())
+            .with_param("audiomode", audio_mode.to_string())
+            .with_optional_param(
+                "immersiveaudio",
+                audio_mode.is_immersive().then_some("true"),
+            )
             .with_param("playbackmode", playback_mode.to_string())
             .with_param("assetpresentation", asset_presentation.to_string())
             .send_raw()
@@ -242,6 +192,117 @@ impl TidalClient {
         Ok(response)
     }
 
+    /// Requests playback info in the best quality of `preferences` that can actually be streamed
+    ///
+    /// `preferences` is ordered from most to least wanted, an empty list means the session's
+    /// audio quality. Qualities above what the track offers (its `audio_quality` and media
+    /// metadata tags), what the subscription's `highest_sound_quality` allows or what the login
+    /// can get (device-code logins stop at `LOSSLESS`) are skipped, if no preference is left the
+    /// best achievable quality is requested. A `403` for one quality falls back to the next.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::client::models::playback::AudioQuality;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let negotiated = client
+    ///     .negotiate_track_playback("123456789", &[AudioQuality::HiRes, AudioQuality::Lossless], None)
+    ///     .await?;
+    /// let report = negotiated.report;
+    /// println!(
+    ///     "asked for {:?}, got {:?} ({:?} Hz, {:?} bit, limited by {:?})",
+    ///     report.requested, report.delivered, report.sample_rate, report.bit_depth, report.limited_by
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn negotiate_track_playback(
+        &self,
+        track_id: impl Into<TrackId>,
+        preferences: &[AudioQuality],
+        config: Option<TrackPlaybackInfoConfig>,
+    ) -> Result<NegotiatedPlayback, TidalError> {
+        let track_id = track_id.into();
+        let preferences = if preferences.is_empty() {
+            vec![self.session.audio_quality.clone()]
+        } else {
+            preferences.to_vec()
+        };
+        let requested = preferences[0].clone();
+
+        let mut limits = Vec::new();
+        let track = self.get_track(track_id.clone()).await?;
+        limits.extend(
+            track
+                .highest_audio_quality()
+                .map(|quality| (quality, QualityLimit::Track)),
+        );
+        match self.subscription().await {
+            Ok(subscription) => limits.extend(
+                subscription
+                    .highest_quality()
+                    .map(|quality| (quality, QualityLimit::Subscription)),
+            ),
+            Err(e) => warn!(error = %e, "couldn't get the subscription, not capping quality by it"),
+        }
+        if self.session.auth.read().oauth_login {
+            limits.push((AudioQuality::Lossless, QualityLimit::Login));
+        }
+        let ceiling = limits.into_iter().min_by(|a, b| a.0.cmp(&b.0));
+
+        let mut candidates: Vec<AudioQuality> = Vec::new();
+        for quality in preferences {
+            let quality = match &ceiling {
+                Some((max, _)) if quality > *max => continue,
+                _ => quality,
+            };
+            if !candidates.contains(&quality) {
+                candidates.push(quality);
+            }
+        }
+        if candidates.is_empty()
+            && let Some((max, _)) = &ceiling
+        {
+            candidates.push(max.clone());
+        }
+        let limited_by = ceiling
+            .filter(|(max, _)| requested > *max)
+            .map(|(_, limit)| limit);
+        debug!(%track_id, ?candidates, ?limited_by, "negotiated playback qualities");
+
+        let mut last_error = None;
+        for quality in candidates {
+            let config = TrackPlaybackInfoConfig {
+                audio_quality: Some(quality.clone()),
+                ..config.clone().unwrap_or_default()
+            };
+            match self
+                .get_track_postpaywall_playback_info(track_id.clone(), Some(config))
+                .await
+            {
+                Ok(playback) => {
+                    let report = QualityReport::new(requested, quality, limited_by, &playback);
+                    return Ok(NegotiatedPlayback { playback, report });
+                }
+                Err(
+                    e @ TidalError::RequestClient(RequestClientError::StatusCode {
+                        status: StatusCode::FORBIDDEN,
+                        ..
+                    }),
+                ) => {
+                    debug!(%track_id, %quality, "quality refused, falling back");
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        // there's always at least one candidate, so this is the last refusal
+        Err(last_error.unwrap_or(TidalError::NotFound))
+    }
+
     /// Retrieves the mix for a track.
     pub async fn get_track_mix(
         &self,
@@ -318,15 +379,9 @@ impl TidalClient {
         include: UserUploadsIncludeOptions,
         next_cursor: Option<String>,
     ) -> Result<UserUploadsResponse, TidalError> {
-        if self.session.auth.user_id.is_none() {
-            return Err(TidalError::NotAuthenticated);
-        }
-
         let includes = include.to_api_params();
 
-        let user_id = if let Some(id) = self.session.auth.user_id {
-            id
-        } else {
+        let Some(user_id) = self.session.auth.read().user_id else {
             return Err(TidalError::NotAuthenticated);
         };
 
@@ -335,16 +390,78 @@ impl TidalClient {
             .with_param("filter[owners.id]", user_id.to_string())
             // .with_param("limit", 1.to_string())
             .with_param("include", includes)
-            .with_optional_param("page_cursor", next_cursor)
+            .with_optional_param("page[cursor]", next_cursor)
             .with_base_url(OPEN_API_V2_LOCATION)
             .send()
             .await
     }
+
+    /// Streams all of the current user's uploaded tracks across all pages.
+    ///
+    /// Only the `data` resources are yielded, `options.page_size` is ignored since the endpoint
+    /// decides its own page size.
+    pub fn get_user_uploads_all(
+        &self,
+        include: UserUploadsIncludeOptions,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<TrackResource, TidalError>> + '_ {
+        Paginator::new(options, move |request: PageRequest| {
+            let include = include.clone();
+            async move {
+                let res = self.get_user_uploads(include, request.cursor()).await?;
+                let next_cursor = res.next_cursor();
+                Ok(Page::from_cursor(res.data, next_cursor))
+            }
+        })
+        .into_stream()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::TidalClient;
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde_json::json;
+
+    use crate::{
+        TidalClient,
+        client::models::{
+            playback::{AudioCodec, AudioMode, AudioQuality},
+            track::{config::TrackPlaybackInfoConfig, quality::QualityLimit},
+        },
+        test_support::{
+            TestRequest, TestResponse, TestServer, api_client, dash_playback_json, playback_json,
+            track_json,
+        },
+    };
+
+    /// Serves a track tagged with `tags`, a subscription up to `subscription` and playback info
+    /// from `playback` for the requested quality
+    fn quality_server(
+        tags: &'static [&'static str],
+        subscription: &'static str,
+        playback: fn(&TestRequest, &str) -> TestResponse,
+    ) -> TestServer {
+        TestServer::spawn(move |request| match request.path.as_str() {
+            "/tracks/1/" => {
+                let mut track = track_json(1, "Track", "Artist");
+                track["mediaMetadata"] = json!({ "tags": tags });
+                TestResponse::ok(track.to_string())
+            }
+            "/users/42/subscription" => TestResponse::ok(
+                json!({
+                    "startDate": "", "validUntil": "", "status": "ACTIVE",
+                    "subscription": { "type": "PREMIUM", "offlineGracePeriod": 30 },
+                    "highestSoundQuality": subscription, "premiumAccess": true,
+                    "canGetTrial": false, "paymentType": "", "paymentOverdue": false
+                })
+                .to_string(),
+            ),
+            "/tracks/1/playbackinfopostpaywall" => {
+                playback(request, request.param("audioquality").unwrap())
+            }
+            _ => TestResponse::new(404),
+        })
+    }
 
     #[test]
     fn parse_dash_manifest_extracts_expected_fields() {
@@ -403,4 +520,159 @@ mod tests {
         let result = TidalClient::parse_dash_manifest(xml);
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn immersive_playback_requests_the_audio_mode() {
+        let mpd = r#"<MPD><Period><AdaptationSet mimeType="audio/mp4">
+            <Representation id="atmos" codecs="ec-3" bandwidth="768000">
+              <AudioChannelConfiguration
+                schemeIdUri="tag:dolby.com,2014:dash:audio_channel_configuration:2011"
+                value="F801"/>
+              <SegmentTemplate timescale="1000" duration="4000" startNumber="1"
+                initialization="https://cdn.example.com/init.mp4"
+                media="https://cdn.example.com/$Number$.mp4"/>
+            </Representation></AdaptationSet></Period></MPD>"#;
+        let server = TestServer::spawn(move |_| {
+            let mut playback = playback_json(1, "");
+            playback["audioMode"] = json!("DOLBY_ATMOS");
+            playback["manifestMimeType"] = json!("application/dash+xml");
+            playback["manifest"] = json!(BASE64.encode(mpd));
+            TestResponse::ok(playback.to_string())
+        });
+        let client = api_client(&server);
+
+        let config = TrackPlaybackInfoConfig {
+            audio_mode: Some(AudioMode::DolbyAtmos),
+            ..Default::default()
+        };
+        let playback = client
+            .get_track_postpaywall_playback_info("1", Some(config))
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.param("audiomode"), Some("DOLBY_ATMOS"));
+        assert_eq!(request.param("immersiveaudio"), Some("true"));
+        assert_eq!(playback.audio_mode(), Some(AudioMode::DolbyAtmos));
+        assert_eq!(playback.audio_codec(), Some(AudioCodec::Eac3));
+    }
+
+    #[tokio::test]
+    async fn stereo_playback_uses_the_session_audio_mode() {
+        let server = TestServer::spawn(|_| TestResponse::ok(playback_json(1, "u").to_string()));
+        let client = api_client(&server);
+
+        client
+            .get_track_postpaywall_playback_info("1", None)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.param("audiomode"), Some("STEREO"));
+        assert_eq!(request.param("immersiveaudio"), None);
+    }
+
+    #[tokio::test]
+    async fn negotiation_skips_qualities_the_track_lacks() {
+        let server = quality_server(&["LOSSLESS"], "HI_RES_LOSSLESS", |_, quality| {
+            let mut playback = playback_json(1, "https://cdn.example.com/a.flac");
+            playback["audioQuality"] = json!(quality);
+            TestResponse::ok(playback.to_string())
+        });
+        let client = api_client(&server);
+
+        let negotiated = client
+            .negotiate_track_playback("1", &[AudioQuality::HiRes, AudioQuality::High], None)
+            .await
+            .unwrap();
+
+        let report = negotiated.report;
+        assert_eq!(report.requested, AudioQuality::HiRes);
+        // HIGH is the only preference the track can satisfy
+        assert_eq!(report.negotiated, AudioQuality::High);
+        assert_eq!(report.limited_by, Some(QualityLimit::Track));
+        assert!(report.is_downgraded());
+
+        let negotiated = client
+            .negotiate_track_playback("1", &[AudioQuality::HiRes], None)
+            .await
+            .unwrap();
+        // no preference is achievable, the best the track offers is requested
+        assert_eq!(negotiated.report.negotiated, AudioQuality::Lossless);
+        assert_eq!(negotiated.report.delivered, Some(AudioQuality::Lossless));
+        assert_eq!(negotiated.report.sample_rate, Some(44_100));
+        assert_eq!(negotiated.report.bit_depth, Some(16));
+        assert_eq!(negotiated.report.codec, Some(AudioCodec::Flac));
+    }
+
+    #[tokio::test]
+    async fn negotiation_reports_hires_details_and_subscription_limits() {
+        let server = quality_server(
+            &["LOSSLESS", "HIRES_LOSSLESS"],
+            "HI_RES_LOSSLESS",
+            |_, _| {
+                let mut playback = dash_playback_json(1, "https://cdn.example.com/", 2);
+                playback["sampleRate"] = json!(96_000);
+                playback["bitDepth"] = json!(24);
+                TestResponse::ok(playback.to_string())
+            },
+        );
+        let client = api_client(&server);
+
+        let report = client
+            .negotiate_track_playback("1", &[AudioQuality::HiRes], None)
+            .await
+            .unwrap()
+            .report;
+
+        assert_eq!(report.delivered, Some(AudioQuality::HiRes));
+        assert_eq!(report.limited_by, None);
+        assert_eq!(
+            (report.sample_rate, report.bit_depth),
+            (Some(96_000), Some(24))
+        );
+        assert!(!report.is_downgraded());
+
+        let server = quality_server(&["HIRES_LOSSLESS"], "LOSSLESS", |_, quality| {
+            let mut playback = playback_json(1, "https://cdn.example.com/a.flac");
+            playback["audioQuality"] = json!(quality);
+            TestResponse::ok(playback.to_string())
+        });
+        let report = api_client(&server)
+            .negotiate_track_playback("1", &[AudioQuality::HiRes], None)
+            .await
+            .unwrap()
+            .report;
+        assert_eq!(report.negotiated, AudioQuality::Lossless);
+        assert_eq!(report.limited_by, Some(QualityLimit::Subscription));
+    }
+
+    #[tokio::test]
+    async fn refused_quality_falls_back_to_the_next_preference() {
+        let server = quality_server(&["HIRES_LOSSLESS"], "HI_RES_LOSSLESS", |_, quality| {
+            if quality == "HI_RES" {
+                return TestResponse::new(403);
+            }
+            let mut playback = playback_json(1, "https://cdn.example.com/a.flac");
+            playback["audioQuality"] = json!(quality);
+            TestResponse::ok(playback.to_string())
+        });
+        let client = api_client(&server);
+
+        let report = client
+            .negotiate_track_playback("1", &[AudioQuality::HiRes, AudioQuality::Lossless], None)
+            .await
+            .unwrap()
+            .report;
+
+        assert_eq!(report.negotiated, AudioQuality::Lossless);
+        assert_eq!(report.limited_by, None);
+        assert!(report.is_downgraded());
+        let qualities: Vec<String> = server
+            .requests()
+            .iter()
+            .filter_map(|request| request.param("audioquality").map(str::to_string))
+            .collect();
+        assert_eq!(qualities, ["HI_RES", "LOSSLESS"]);
+    }
 }
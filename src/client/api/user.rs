@@ -43,15 +43,15 @@ impl TidalClient {
     pub async fn refresh_user_info(&mut self) -> Result<(), TidalError> {
         let ui = self.get_user_info().await?;
 
-        self.user_info = Some(User {
+        self.user_info.set(Some(User {
             user_id: ui.id.parse()?,
             username: ui.attributes.username,
             email: ui.attributes.email,
             country_code: ui.attributes.country,
             email_verified: ui.attributes.email_verified,
 
-            ..self.user_info.clone().unwrap()
-        });
+            ..self.user_info.get().unwrap()
+        }));
 
         Ok(())
     }
@@ -60,10 +60,8 @@ impl TidalClient {
     pub async fn logout(&self) -> Result<(), TidalError> {
         let url = "/logout".to_string();
 
-        let mut req = TidalRequest::new(reqwest::Method::POST, url.clone());
-        req.access_token = self.session.auth.access_token.clone();
-
-        let resp = self.rq.request(req).await?;
+        let req = TidalRequest::new(reqwest::Method::POST, url.clone());
+        let resp = self.send_authorized(req).await?;
         let status = resp.status();
 
         if status != reqwest::StatusCode::NO_CONTENT {
@@ -1,8 +1,10 @@
 use crate::client::models::video::config::VideoPlaybackInfoConfig;
+use crate::client::models::video::hls::{Playlist, VariantPlaylist, VariantSelection};
 use crate::client::models::video::playback::EmuVideoManifest;
 use crate::client::models::video::playback::VideoPlaybackInfoResponse;
 
 use base64::{Engine, engine::general_purpose};
+use tracing::debug;
 
 use crate::{
     TidalClient, TidalError,
@@ -65,4 +67,84 @@ impl TidalClient {
 
         Ok(response)
     }
+
+    /// Fetches and parses the HLS playlist at `url`
+    pub async fn get_hls_playlist(&self, url: &str) -> Result<Playlist, TidalError> {
+        let response = self
+            .rq
+            .http_client()
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?;
+        // relative URIs are relative to where the playlist was found after redirects
+        let url = response.url().to_string();
+        let text = response.text().await?;
+
+        Playlist::parse(&text, &url)
+    }
+
+    /// Resolves the HLS manifest of a video to the media playlist of the variant picked by
+    /// `selection`, see [`MasterPlaylist::select`](crate::client::models::video::hls::MasterPlaylist::select)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::client::models::{playback::VideoQuality, video::hls::VariantSelection};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let playback = client.get_video_postpaywall_playback_info("123456789", None).await?;
+    /// let selection = VariantSelection {
+    ///     quality: Some(VideoQuality::Medium),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let stream = client.get_video_media_playlist(&playback, &selection).await?;
+    /// println!(
+    ///     "{:?}: {} segments",
+    ///     stream.variant.and_then(|variant| variant.resolution),
+    ///     stream.playlist.segments.len()
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_video_media_playlist(
+        &self,
+        playback: &VideoPlaybackInfoResponse,
+        selection: &VariantSelection,
+    ) -> Result<VariantPlaylist, TidalError> {
+        let url = playback.get_primary_url().ok_or_else(|| {
+            TidalError::InvalidManifest("video manifest has no playlist URL".to_string())
+        })?;
+
+        let master = match self.get_hls_playlist(&url).await? {
+            Playlist::Media(playlist) => {
+                return Ok(VariantPlaylist {
+                    variant: None,
+                    playlist,
+                });
+            }
+            Playlist::Master(master) => master,
+        };
+
+        let variant = master.select(selection).cloned().ok_or_else(|| {
+            TidalError::InvalidManifest("master playlist has no variants".to_string())
+        })?;
+        debug!(
+            bandwidth = variant.bandwidth,
+            resolution = ?variant.resolution,
+            "selected video variant"
+        );
+
+        match self.get_hls_playlist(&variant.url).await? {
+            Playlist::Media(playlist) => Ok(VariantPlaylist {
+                variant: Some(variant),
+                playlist,
+            }),
+            Playlist::Master(_) => Err(TidalError::InvalidManifest(
+                "variant points to another master playlist".to_string(),
+            )),
+        }
+    }
 }
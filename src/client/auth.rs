@@ -1,13 +1,12 @@
 use std::collections::HashMap;
 
 use reqwest::Method;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::{
     client::{TidalClient, models::responses::RefreshTokenGrantResponse},
     error::TidalError,
-    requests::{self, TidalRequest},
-    urls::OAUTH2_V1_LOCATION,
+    requests::{self, RequestClientError, TidalRequest},
 };
 
 impl TidalClient {
@@ -35,53 +34,17 @@ impl TidalClient {
     /// ```
     pub async fn refresh_access_token(&mut self, force: bool) -> Result<bool, TidalError> {
         debug!(force, "refresh_access_token called");
-        let Some(refresh_token) = self.session.auth.refresh_token.clone() else {
+        if self.session.auth.read().refresh_token.is_none() {
             return Err(TidalError::Other(
                 "No refresh token available, cannot refresh access token.".to_string(),
             ));
-        };
-
-        let (client_id, client_secret) = if self.session.auth.pkce_login {
-            (
-                self.session.auth.pkce_config.client_id.clone(),
-                self.session.auth.pkce_config.client_secret.clone(),
-            )
-        } else {
-            (
-                self.session.auth.client_id.clone(),
-                self.session.auth.client_secret.clone(),
-            )
-        };
+        }
 
-        let is_expired = self.session.auth.is_token_expired()?;
+        let is_expired = self.session.auth.read().is_token_expired()?;
         if force || is_expired {
             debug!(force, is_expired, "refreshing access token");
-            let mut form = HashMap::new();
-            form.insert("grant_type".to_string(), "refresh_token".to_string());
-            form.insert("refresh_token".to_string(), refresh_token);
-            let mut req = TidalRequest::new(Method::POST, "/token".to_string());
-            req.form = Some(vec![form]);
-            req.basic_auth = Some(requests::BasicAuth::new(client_id, client_secret));
-            req.base_url = Some(OAUTH2_V1_LOCATION.to_string());
-
-            let res = self.rq.request(req).await?;
-            let body = res.text().await?;
-            let json: RefreshTokenGrantResponse = serde_json::from_str(&body)?;
-            debug!(
-                expires_in = json.expires_in,
-                token_type = %json.token_type,
-                user_id = %json.user_id,
-                "received refresh token response"
-            );
-
-            self.session.auth.apply_access_token_state(
-                json.access_token.clone(),
-                json.expires_in.try_into()?,
-                json.user_id.try_into()?,
-                Some(json.client_name.clone()),
-            )?;
-            self.user_info = Some(json.user);
-            info!("access token refreshed successfully");
+            let _guard = self.session.auth.lock_refresh().await;
+            self.exchange_refresh_token().await?;
 
             return Ok(true);
         }
@@ -89,4 +52,279 @@ impl TidalClient {
         debug!("access token refresh skipped because token is still valid");
         Ok(false)
     }
+
+    /// Enables or disables transparent access token refreshing for API requests
+    ///
+    /// When enabled (the default), requests refresh an expired access token before being sent and
+    /// are retried once after a `401 Unauthorized` response, as long as a refresh token is
+    /// available.
+    pub fn set_auto_refresh(&mut self, auto_refresh: bool) {
+        debug!(enabled = auto_refresh, "setting automatic token refresh");
+        self.auto_refresh = auto_refresh;
+    }
+
+    /// Sends an authenticated request, refreshing the access token when needed
+    ///
+    /// The current access token is attached to the request. If it is expired it gets refreshed
+    /// first, and if the API still answers with `401 Unauthorized`, the token is refreshed and the
+    /// request is retried once.
+    pub(crate) async fn send_authorized(
+        &self,
+        mut req: TidalRequest,
+    ) -> Result<reqwest::Response, TidalError> {
+        if self.can_auto_refresh() {
+            self.refresh_if_expired().await?;
+        }
+
+        req.access_token = self.session.auth.access_token();
+        let used_token = req.access_token.clone();
+
+        match self.rq.request(req.clone()).await {
+            Err(RequestClientError::Unauthorized) if self.can_auto_refresh() => {
+                warn!(
+                    path = %req.path,
+                    "request was unauthorized, refreshing access token and retrying"
+                );
+                self.refresh_after_unauthorized(used_token).await?;
+
+                req.access_token = self.session.auth.access_token();
+                Ok(self.rq.request(req).await?)
+            }
+            res => Ok(res?),
+        }
+    }
+
+    fn can_auto_refresh(&self) -> bool {
+        self.auto_refresh && self.session.auth.read().refresh_token.is_some()
+    }
+
+    async fn refresh_if_expired(&self) -> Result<(), TidalError> {
+        if !self.session.auth.read().is_token_expired()? {
+            return Ok(());
+        }
+
+        let _guard = self.session.auth.lock_refresh().await;
+
+        // another request may have refreshed the token while we were waiting for the lock
+        if self.session.auth.read().is_token_expired()? {
+            debug!("access token expired, refreshing before request");
+            self.exchange_refresh_token().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn refresh_after_unauthorized(
+        &self,
+        used_token: Option<String>,
+    ) -> Result<(), TidalError> {
+        let _guard = self.session.auth.lock_refresh().await;
+
+        // only refresh if nobody replaced the rejected token in the meantime
+        if self.session.auth.access_token() == used_token {
+            self.exchange_refresh_token().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Exchanges the refresh token for a new access token and applies it, along with the returned
+    /// user, to the shared auth state
+    ///
+    /// Callers are expected to hold the refresh lock.
+    async fn exchange_refresh_token(&self) -> Result<RefreshTokenGrantResponse, TidalError> {
+        let auth = self.session.auth.snapshot();
+        let Some(refresh_token) = auth.refresh_token else {
+            return Err(TidalError::Other(
+                "No refresh token available, cannot refresh access token.".to_string(),
+            ));
+        };
+
+        let (client_id, client_secret) = if auth.pkce_login {
+            (auth.pkce_config.client_id, auth.pkce_config.client_secret)
+        } else {
+            (auth.client_id, auth.client_secret)
+        };
+
+        let mut form = HashMap::new();
+        form.insert("grant_type".to_string(), "refresh_token".to_string());
+        form.insert("refresh_token".to_string(), refresh_token);
+        let mut req = TidalRequest::new(Method::POST, "/token".to_string());
+        req.form = Some(vec![form]);
+        req.basic_auth = Some(requests::BasicAuth::new(client_id, client_secret));
+        req.base_url = Some(self.rq.oauth_base_url().to_string());
+
+        let res = self.rq.request(req).await?;
+        let body = res.text().await?;
+        let json: RefreshTokenGrantResponse = serde_json::from_str(&body)?;
+        debug!(
+            expires_in = json.expires_in,
+            token_type = %json.token_type,
+            user_id = %json.user_id,
+            "received refresh token response"
+        );
+
+        self.session.auth.write().apply_access_token_state(
+            json.access_token.clone(),
+            json.expires_in.try_into()?,
+            json.user_id.try_into()?,
+            Some(json.client_name.clone()),
+        )?;
+        self.user_info.set(Some(json.user.clone()));
+        self.auth_changed().await;
+        info!("access token refreshed successfully");
+
+        Ok(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        error::TidalError,
+        requests::RequestClient,
+        requests::RequestClientError,
+        requests::TidalRequest,
+        test_support::{TestResponse, TestServer},
+    };
+
+    #[test]
+    fn cloned_clients_share_auth_state() {
+        let client = TidalClient::new(&TidalAuth::with_access_token("old".to_string()));
+        let clone = client.clone();
+
+        client.session.auth.write().access_token = Some("new".to_string());
+
+        assert_eq!(clone.session.auth.access_token().as_deref(), Some("new"));
+    }
+
+    #[tokio::test]
+    async fn send_authorized_attaches_current_access_token() {
+        let server = TestServer::sequence(vec![TestResponse::new(204)]);
+        let mut client = TidalClient::new(&TidalAuth::with_access_token("old".to_string()));
+        client.rq = RequestClient::new(server.url().to_string());
+        client.session.auth.write().access_token = Some("fresh_token".to_string());
+
+        let result = client
+            .send_authorized(TidalRequest::new(Method::GET, "/ok".to_string()))
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            server.requests()[0].header("authorization"),
+            Some("Bearer fresh_token")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_authorized_does_not_retry_without_refresh_token() {
+        let server = TestServer::sequence(vec![TestResponse::new(401)]);
+        let mut client = TidalClient::new(&TidalAuth::with_access_token("token".to_string()));
+        client.rq = RequestClient::new(server.url().to_string());
+
+        let result = client
+            .send_authorized(TidalRequest::new(Method::GET, "/secret".to_string()))
+            .await;
+        assert_eq!(server.requests().len(), 1);
+
+        assert!(matches!(
+            result,
+            Err(TidalError::RequestClient(RequestClientError::Unauthorized))
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_after_unauthorized_skips_when_token_was_already_replaced() {
+        let client = TidalClient::new(&TidalAuth::with_access_token("new".to_string()));
+        client.session.auth.write().refresh_token = Some("refresh".to_string());
+
+        // the rejected token differs from the current one, so no token request is made
+        client
+            .refresh_after_unauthorized(Some("old".to_string()))
+            .await
+            .expect("refresh should be skipped");
+
+        assert_eq!(client.session.auth.access_token().as_deref(), Some("new"));
+    }
+
+    const REFRESH_RESPONSE: &str = r#"{
+        "scope": "r_usr w_usr",
+        "clientName": "tidlers",
+        "token_type": "Bearer",
+        "access_token": "fresh",
+        "expires_in": 3600,
+        "user_id": 42,
+        "user": {
+            "userId": 42, "email": "a@b.c", "countryCode": "CZ", "fullName": null,
+            "firstName": null, "lastName": null, "nickname": null, "username": "refreshed",
+            "address": null, "city": null, "postalcode": null, "usState": null,
+            "phoneNumber": null, "birthday": 0, "channelId": 0, "parentId": 0,
+            "acceptedEULA": true, "created": 0, "updated": 0, "facebookUid": null,
+            "appleUid": null, "googleUid": null, "accountLinkCreated": false,
+            "emailVerified": true, "newUser": false
+        }
+    }"#;
+
+    /// Serves the token endpoint and a `/secret` endpoint that only accepts the refreshed token
+    fn spawn_refreshing_server() -> TestServer {
+        TestServer::spawn(|request| match request.path.as_str() {
+            "/token" => TestResponse::ok(REFRESH_RESPONSE),
+            _ if request.header("authorization") == Some("Bearer fresh") => TestResponse::new(204),
+            _ => TestResponse::new(401),
+        })
+    }
+
+    fn refreshable_client(server: &TestServer) -> TidalClient {
+        let mut client = TidalClient::new(&TidalAuth::with_access_token("stale".to_string()));
+        client.rq = RequestClient::new(server.url().to_string());
+        client.rq.set_oauth_base_url(server.url());
+        client.session.auth.write().refresh_token = Some("refresh".to_string());
+        client
+    }
+
+    #[tokio::test]
+    async fn send_authorized_refreshes_and_retries_after_unauthorized() {
+        let server = spawn_refreshing_server();
+        let client = refreshable_client(&server);
+        {
+            // the token looks valid locally, only the API rejects it
+            let mut auth = client.session.auth.write();
+            auth.refresh_expiry = Some(3600);
+            auth.last_refresh_time = Some(chrono::Utc::now().timestamp() as u64);
+        }
+        let clone = client.clone();
+
+        let result = client
+            .send_authorized(TidalRequest::new(Method::GET, "/secret".to_string()))
+            .await;
+
+        assert_eq!(result.expect("retry should succeed").status(), 204);
+        assert_eq!(
+            server.request_lines(),
+            ["GET /secret", "POST /token", "GET /secret"]
+        );
+        assert_eq!(clone.session.auth.access_token().as_deref(), Some("fresh"));
+        // user data from the refresh reaches every clone
+        let user = clone.user_info.get().expect("user should be set");
+        assert_eq!(user.username, "refreshed");
+        assert_eq!(user.country_code, "CZ");
+    }
+
+    #[tokio::test]
+    async fn send_authorized_refreshes_expired_token_before_request() {
+        let server = spawn_refreshing_server();
+        // no expiry is known, so the token counts as expired
+        let client = refreshable_client(&server);
+
+        let result = client
+            .send_authorized(TidalRequest::new(Method::GET, "/secret".to_string()))
+            .await;
+
+        assert_eq!(result.expect("request should succeed").status(), 204);
+        assert_eq!(server.request_lines(), ["POST /token", "GET /secret"]);
+        assert_eq!(client.user_info.get().unwrap().user_id, 42);
+    }
 }
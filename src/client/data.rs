@@ -1,5 +1,13 @@
-use crate::client::TidalClient;
-use tracing::debug;
+use std::sync::Arc;
+
+use crate::{
+    auth::TidalAuth,
+    client::TidalClient,
+    error::TidalError,
+    session_crypto::{decrypt_session, encrypt_session},
+    session_store::SessionStore,
+};
+use tracing::{debug, warn};
 
 impl TidalClient {
     /// Deserializes a TidalClient from a JSON string
@@ -52,6 +60,97 @@ impl TidalClient {
             panic!("failed to serialize TidalClient to JSON, something is seriously wrong here.")
         })
     }
+
+    /// Serializes the TidalClient like [`TidalClient::get_json`], encrypted with a key derived
+    /// from `passphrase`
+    ///
+    /// See [`session_crypto`](crate::session_crypto) for the format.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// std::fs::write("session.bin", client.get_encrypted("passphrase"))?;
+    ///
+    /// let data = std::fs::read("session.bin")?;
+    /// let client = TidalClient::from_encrypted(&data, "passphrase")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_encrypted(&self, passphrase: &str) -> Vec<u8> {
+        encrypt_session(&self.get_json(), passphrase)
+    }
+
+    /// Deserializes a TidalClient from the output of [`TidalClient::get_encrypted`]
+    ///
+    /// Fails with [`TidalError::SessionDecryption`] for a wrong passphrase or a modified file.
+    pub fn from_encrypted(data: &[u8], passphrase: &str) -> Result<TidalClient, TidalError> {
+        debug!(payload_bytes = data.len(), "decrypting client session");
+        Ok(Self::from_json(&decrypt_session(data, passphrase)?)?)
+    }
+
+    /// Restores the session saved in `store`, or starts a new one with `credentials` if nothing
+    /// was saved yet, and keeps saving to `store` whenever the auth state changes
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tidlers::{TidalClient, auth::TidalAuth, session_store::FileSessionStore};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = FileSessionStore::new("session.json");
+    /// let client = TidalClient::with_session_store(&TidalAuth::with_oauth(), store)?;
+    /// if client.user_info.is_some() {
+    ///     println!("restored the saved session");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_session_store(
+        credentials: &TidalAuth,
+        store: impl SessionStore + 'static,
+    ) -> Result<TidalClient, TidalError> {
+        let mut client = match store.load()? {
+            Some(json) => Self::from_json(&json)?,
+            None => Self::new(credentials),
+        };
+        client.set_session_store(Some(Arc::new(store)));
+        Ok(client)
+    }
+
+    /// Sets the store the session is saved to after every login and token refresh, `None`
+    /// disables saving
+    ///
+    /// The store is shared with every clone of this client, clones made before this call keep
+    /// their previous store.
+    pub fn set_session_store(&mut self, store: Option<Arc<dyn SessionStore>>) {
+        debug!(enabled = store.is_some(), "setting session store");
+        self.session_store = store;
+    }
+
+    /// Saves the session to the store set with [`TidalClient::set_session_store`], does nothing
+    /// without one
+    ///
+    /// The store is called on a blocking thread, so its file I/O doesn't stall the runtime.
+    pub async fn save_session(&self) -> Result<(), TidalError> {
+        let Some(store) = self.session_store.clone() else {
+            return Ok(());
+        };
+        let json = self.get_json();
+
+        tokio::task::spawn_blocking(move || store.save(&json))
+            .await
+            .map_err(|e| TidalError::Other(format!("session save task failed: {e}")))?
+    }
+
+    /// Saves the session after a login or token refresh, a failing store doesn't fail either
+    pub(crate) async fn auth_changed(&self) {
+        if let Err(e) = self.save_session().await {
+            warn!(error = %e, "failed to save session");
+        }
+    }
 }
 
 #[cfg(test)]
@@ -59,7 +158,7 @@ mod tests {
     use crate::{
         TidalClient,
         auth::TidalAuth,
-        client::models::playback::{AudioQuality, PlaybackMode},
+        client::models::playback::{AudioMode, AudioQuality, PlaybackMode},
     };
 
     #[test]
@@ -69,13 +168,14 @@ mod tests {
         client.session.locale = "cs_CZ".to_string();
         client.set_time_offset("+02:00".to_string());
         client.set_audio_quality(AudioQuality::HiRes);
+        client.set_audio_mode(AudioMode::DolbyAtmos);
         client.set_playback_mode(PlaybackMode::Offline);
 
         let json = client.get_json();
         let restored = TidalClient::from_json(&json).expect("client json should deserialize");
 
         assert_eq!(
-            restored.session.auth.access_token.as_deref(),
+            restored.session.auth.read().access_token.as_deref(),
             Some("token_123")
         );
         assert_eq!(restored.session.locale, "cs_CZ");
@@ -84,6 +184,7 @@ mod tests {
             restored.session.audio_quality,
             AudioQuality::HiRes
         ));
+        assert_eq!(restored.session.audio_mode, AudioMode::DolbyAtmos);
         assert!(matches!(
             restored.session.playback_mode,
             PlaybackMode::Offline
@@ -0,0 +1,414 @@
+//! PKCE login completed by a one-shot HTTP listener on the loopback interface
+//!
+//! Instead of asking the user to paste the redirect URL back, the authorize URL redirects to
+//! `http://127.0.0.1:{port}{path}`, where the listener picks up the `code`, checks the `state`
+//! and exchanges the code for tokens. This needs a client ID that accepts loopback redirects,
+//! the default one only redirects to [`PKCE_URI_REDIRECT`](crate::urls::PKCE_URI_REDIRECT).
+//!
+//! Available with the `pkce-loopback` feature.
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    pin::pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_util::future::{Either, select};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{Notify, mpsc},
+};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::{
+    TidalClient, auth::pkce::PkceConfig, client::pkce::pkce_redirect_code, error::TidalError,
+};
+
+/// Longest request line the listener reads
+const MAX_LINE_LEN: usize = 8 * 1024;
+/// How long a connection may take to send its request line, browsers open spare connections
+/// they never send anything on
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Options for [`TidalClient::start_loopback_pkce_login`]
+#[derive(Debug, Clone)]
+pub struct LoopbackLoginOptions {
+    /// Port on `127.0.0.1` to listen on, `0` picks a free one
+    ///
+    /// Clients registered for a fixed redirect URI need that exact port.
+    pub port: u16,
+    /// Path of the redirect URI
+    pub path: String,
+    /// How long to wait for the redirect before failing
+    pub timeout: Duration,
+}
+
+impl Default for LoopbackLoginOptions {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            path: "/callback".to_string(),
+            timeout: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// A started loopback login, finish it with [`TidalClient::finish_loopback_pkce_login`]
+pub struct LoopbackLogin {
+    listener: TcpListener,
+    pkce_config: PkceConfig,
+    authorize_url: String,
+    path: String,
+    timeout: Duration,
+    cancel: Arc<Notify>,
+}
+
+impl fmt::Debug for LoopbackLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoopbackLogin")
+            .field("redirect_uri", &self.pkce_config.redirect_uri)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LoopbackLogin {
+    /// URL to open in the browser
+    pub fn authorize_url(&self) -> &str {
+        &self.authorize_url
+    }
+
+    /// Redirect URI the listener answers on
+    pub fn redirect_uri(&self) -> &str {
+        &self.pkce_config.redirect_uri
+    }
+
+    /// Handle that aborts the login from another task
+    pub fn cancel_handle(&self) -> LoopbackCancel {
+        LoopbackCancel(self.cancel.clone())
+    }
+}
+
+/// Aborts a pending [`LoopbackLogin`], see [`LoopbackLogin::cancel_handle`]
+#[derive(Debug, Clone)]
+pub struct LoopbackCancel(Arc<Notify>);
+
+impl LoopbackCancel {
+    pub fn cancel(&self) {
+        self.0.notify_one();
+    }
+}
+
+impl TidalClient {
+    /// Starts a PKCE login that's completed by a listener on `127.0.0.1`
+    ///
+    /// The listener is bound right away, so the returned [`LoopbackLogin::authorize_url`] can
+    /// be opened before [`TidalClient::finish_loopback_pkce_login`] is awaited.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tidlers::{TidalClient, auth::TidalAuth, client::loopback::LoopbackLoginOptions};
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut client = TidalClient::new(&TidalAuth::with_pkce());
+    /// let login = client
+    ///     .start_loopback_pkce_login(LoopbackLoginOptions::default())
+    ///     .await?;
+    /// println!("Visit: {}", login.authorize_url());
+    ///
+    /// client.finish_loopback_pkce_login(login).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn start_loopback_pkce_login(
+        &self,
+        options: LoopbackLoginOptions,
+    ) -> Result<LoopbackLogin, TidalError> {
+        self.check_pkce_login()?;
+
+        let listener =
+            TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, options.port))).await?;
+        let port = listener.local_addr()?.port();
+        let path = format!("/{}", options.path.trim_start_matches('/'));
+
+        let mut pkce_config = self.rotate_pkce_login();
+        pkce_config.redirect_uri = format!("http://127.0.0.1:{port}{path}");
+        let authorize_url = self.pkce_url(&pkce_config)?;
+        debug!(redirect_uri = %pkce_config.redirect_uri, "loopback login listening");
+
+        Ok(LoopbackLogin {
+            listener,
+            pkce_config,
+            authorize_url,
+            path,
+            timeout: options.timeout,
+            cancel: Arc::default(),
+        })
+    }
+
+    /// Waits for the redirect of a [`LoopbackLogin`] and logs the client in with its code
+    ///
+    /// Redirects are checked like in [`TidalClient::finish_pkce_login`], except that one with
+    /// another `state` is answered with an error page and the listener keeps waiting for the
+    /// real one. A timeout or cancellation fails with [`TidalError::Auth`].
+    pub async fn finish_loopback_pkce_login(
+        &mut self,
+        login: LoopbackLogin,
+    ) -> Result<(), TidalError> {
+        let wait = pin!(tokio::time::timeout(
+            login.timeout,
+            wait_for_redirect(
+                &login.listener,
+                &login.path,
+                login.pkce_config.state.as_deref()
+            ),
+        ));
+        let cancelled = pin!(login.cancel.notified());
+
+        let code = match select(wait, cancelled).await {
+            Either::Left((Ok(code), _)) => code?,
+            Either::Left((Err(_), _)) => {
+                return Err(TidalError::Auth(
+                    "timed out waiting for the login redirect".to_string(),
+                ));
+            }
+            Either::Right(_) => return Err(TidalError::Auth("login cancelled".to_string())),
+        };
+
+        info!("received login redirect, exchanging the authorization code");
+        self.complete_pkce_login(code, &login.pkce_config).await
+    }
+}
+
+/// Accepts connections until one brings the redirect of the pending login, answering it with a
+/// page telling the user whether the login went through, and returns its authorization code
+///
+/// Every connection is read in its own task, so an idle one doesn't hold up the others.
+async fn wait_for_redirect(
+    listener: &TcpListener,
+    path: &str,
+    expected_state: Option<&str>,
+) -> Result<String, TidalError> {
+    let (sender, mut receiver) = mpsc::channel(1);
+    loop {
+        let accepted = match select(pin!(listener.accept()), pin!(receiver.recv())).await {
+            Either::Left((accepted, _)) => accepted?,
+            // a sender is always left in this function, so the channel never closes
+            Either::Right((code, _)) => return code.expect("redirect channel closed"),
+        };
+
+        let (socket, peer) = accepted;
+        let sender = sender.clone();
+        let path = path.to_string();
+        let expected_state = expected_state.map(str::to_string);
+        tokio::spawn(async move {
+            let read = read_redirect(socket, &path, expected_state.as_deref());
+            match tokio::time::timeout(READ_TIMEOUT, read).await {
+                Ok(Ok(Some(code))) => {
+                    let _ = sender.send(code).await;
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => warn!(%peer, error = %e, "failed to read login redirect"),
+                Err(_) => debug!(%peer, "closing idle connection"),
+            }
+        });
+    }
+}
+
+async fn read_redirect(
+    socket: TcpStream,
+    path: &str,
+    expected_state: Option<&str>,
+) -> std::io::Result<Option<Result<String, TidalError>>> {
+    let mut socket = BufReader::new(socket);
+    let mut line = String::new();
+    (&mut socket)
+        .take(MAX_LINE_LEN as u64)
+        .read_line(&mut line)
+        .await?;
+
+    let target = line.split_whitespace().nth(1).unwrap_or_default();
+    let url = Url::parse(&format!("http://127.0.0.1{target}")).ok();
+    let Some(url) = url.filter(|url| url.path() == path) else {
+        socket
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(None);
+    };
+
+    let code = pkce_redirect_code(&url, expected_state);
+    let body = match &code {
+        Ok(_) => "Login complete, you can close this window.",
+        Err(TidalError::PkceStateMismatch) => {
+            "This redirect doesn't belong to the pending login, start it from the application."
+        }
+        Err(_) => "Login failed, check the application for details.",
+    };
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.flush().await?;
+
+    if matches!(code, Err(TidalError::PkceStateMismatch)) {
+        // a stale tab or a forged link, keep waiting for the real redirect
+        warn!("ignoring login redirect with a mismatched state");
+        return Ok(None);
+    }
+    Ok(Some(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tokio::net::TcpStream;
+
+    use super::LoopbackLoginOptions;
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        error::TidalError,
+        requests::RequestClient,
+        test_support::{TestResponse, TestServer, user_json},
+    };
+
+    /// Fake login server redirecting `/authorize` straight back to the redirect URI
+    fn login_server() -> TestServer {
+        TestServer::spawn(|request| match request.path.as_str() {
+            "/authorize" => {
+                let query: Vec<(String, String)> = request
+                    .query
+                    .iter()
+                    .flat_map(|(key, value)| {
+                        url::form_urlencoded::parse(format!("{key}={value}").as_bytes())
+                            .into_owned()
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+                let param = |name: &str| {
+                    query
+                        .iter()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, value)| value.clone())
+                        .unwrap()
+                };
+                TestResponse::new(302).header(
+                    "Location",
+                    format!(
+                        "{}?code=the-code&state={}",
+                        param("redirect_uri"),
+                        param("state")
+                    ),
+                )
+            }
+            "/token" => TestResponse::ok(
+                json!({
+                    "scope": "r_usr w_usr", "user": user_json(), "clientName": "test",
+                    "token_type": "Bearer", "access_token": "access", "refresh_token": "refresh",
+                    "expires_in": 3600, "user_id": 42
+                })
+                .to_string(),
+            ),
+            _ => TestResponse::new(404),
+        })
+    }
+
+    fn pkce_client(server: &TestServer) -> TidalClient {
+        let mut client = TidalClient::new(&TidalAuth::with_pkce());
+        client.rq = RequestClient::new(server.url().to_string());
+        client.rq.set_oauth_base_url(server.url());
+        client.rq.set_pkce_auth_url(server.at("authorize"));
+        client
+    }
+
+    #[tokio::test]
+    async fn login_completes_through_the_loopback_redirect() {
+        let server = login_server();
+        let mut client = pkce_client(&server);
+
+        let login = client
+            .start_loopback_pkce_login(LoopbackLoginOptions::default())
+            .await
+            .unwrap();
+        assert!(login.redirect_uri().starts_with("http://127.0.0.1:"));
+        // the browser, following the redirect to the listener
+        let browser = tokio::spawn(reqwest::get(login.authorize_url().to_string()));
+
+        client.finish_loopback_pkce_login(login).await.unwrap();
+
+        let page = browser.await.unwrap().unwrap().text().await.unwrap();
+        assert!(page.contains("Login complete"));
+        assert_eq!(
+            client.session.auth.read().access_token.as_deref(),
+            Some("access")
+        );
+        assert!(server.request_lines().contains(&"POST /token".to_string()));
+    }
+
+    #[tokio::test]
+    async fn idle_connections_and_wrong_state_dont_end_the_login() {
+        let server = login_server();
+        let mut client = pkce_client(&server);
+
+        let login = client
+            .start_loopback_pkce_login(LoopbackLoginOptions::default())
+            .await
+            .unwrap();
+        // a preconnected socket that never sends a request
+        let idle = TcpStream::connect(login.redirect_uri().trim_start_matches("http://"))
+            .await
+            .ok();
+        let forged = format!("{}?code=forged&state=forged", login.redirect_uri());
+        let authorize_url = login.authorize_url().to_string();
+        let browser = tokio::spawn(async move {
+            let page = reqwest::get(forged).await?.text().await?;
+            reqwest::get(authorize_url).await?;
+            Ok::<_, reqwest::Error>(page)
+        });
+
+        client.finish_loopback_pkce_login(login).await.unwrap();
+
+        let page = browser.await.unwrap().unwrap();
+        assert!(page.contains("doesn't belong to the pending login"));
+        let exchanges: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.path == "/token")
+            .collect();
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].form("code").as_deref(), Some("the-code"));
+        drop(idle);
+    }
+
+    #[tokio::test]
+    async fn login_times_out_or_is_cancelled() {
+        let server = login_server();
+        let mut client = pkce_client(&server);
+
+        let login = client
+            .start_loopback_pkce_login(LoopbackLoginOptions {
+                timeout: Duration::from_millis(50),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let result = client.finish_loopback_pkce_login(login).await;
+        assert!(matches!(result, Err(TidalError::Auth(message)) if message.contains("timed out")));
+
+        let login = client
+            .start_loopback_pkce_login(LoopbackLoginOptions::default())
+            .await
+            .unwrap();
+        login.cancel_handle().cancel();
+        let result = client.finish_loopback_pkce_login(login).await;
+        assert!(matches!(result, Err(TidalError::Auth(message)) if message.contains("cancelled")));
+    }
+}
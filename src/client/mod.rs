@@ -1,26 +1,32 @@
 pub mod api;
 pub mod auth;
 pub mod data;
+#[cfg(feature = "pkce-loopback")]
+pub mod loopback;
 pub mod models;
 pub mod oauth;
+pub mod pagination;
 pub mod pkce;
 
+use std::sync::Arc;
+
 use crate::{
-    auth::TidalAuth,
-    client::models::{
-        playback::{AudioQuality, PlaybackMode, VideoQuality},
-        user::User,
-    },
+    auth::{TidalAuth, shared::SharedUser},
+    client::models::playback::{AudioMode, AudioQuality, PlaybackMode, VideoQuality},
     error::TidalError,
-    requests::{self, RequestClient},
+    rate_limit::RateLimiter,
+    requests::{self, RequestClient, RetryPolicy},
     session::TidalSession,
+    session_store::SessionStore,
     urls::API_V1_LOCATION,
 };
 
 /// Main client for interacting with the Tidal API
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TidalClient {
-    pub user_info: Option<User>,
+    /// Logged in user, shared between clones of the client
+    #[serde(default)]
+    pub user_info: SharedUser,
 
     pub session: TidalSession,
 
@@ -29,6 +35,12 @@ pub struct TidalClient {
 
     #[serde(skip_serializing, skip_deserializing)]
     pub(crate) debug_mode: bool,
+
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) auto_refresh: bool,
+
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) session_store: Option<Arc<dyn SessionStore>>,
 }
 
 impl TidalClient {
@@ -45,20 +57,24 @@ impl TidalClient {
         let session = TidalSession::new(credentials);
         let rq = RequestClient::new(API_V1_LOCATION.to_string());
         TidalClient {
-            user_info: None,
+            user_info: SharedUser::default(),
             session,
             rq,
             debug_mode: false,
+            auto_refresh: true,
+            session_store: None,
         }
     }
 
     /// Checks if the client is waiting for OAuth login completion
     pub fn waiting_for_oauth_login(&self) -> bool {
-        self.session.auth.oauth_login && self.session.auth.access_token.is_none()
+        let auth = self.session.auth.read();
+        auth.oauth_login && auth.access_token.is_none()
     }
 
     pub(crate) fn user_id(&self) -> Result<u64, TidalError> {
         self.user_info
+            .read()
             .as_ref()
             .map(|u| u.user_id)
             .ok_or(TidalError::NotAuthenticated)
@@ -79,6 +95,11 @@ impl TidalClient {
         self.session.audio_quality = quality;
     }
 
+    /// Sets the audio mode requested for playback, e.g. [`AudioMode::DolbyAtmos`]
+    pub fn set_audio_mode(&mut self, mode: AudioMode) {
+        self.session.audio_mode = mode;
+    }
+
     /// Sets the video quality preference for playback
     pub fn set_video_quality(&mut self, quality: VideoQuality) {
         self.session.video_quality = quality;
@@ -94,6 +115,33 @@ impl TidalClient {
         self.session.playback_mode = playback_mode;
     }
 
+    /// Sets the retry policy for API requests, `None` disables retries
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use tidlers::{TidalClient, auth::TidalAuth, requests::RetryPolicy};
+    /// let auth = TidalAuth::with_oauth();
+    /// let mut client = TidalClient::new(&auth);
+    /// client.set_retry_policy(Some(RetryPolicy::default()));
+    /// ```
+    pub fn set_retry_policy(&mut self, retry_policy: Option<RetryPolicy>) {
+        tracing::debug!(?retry_policy, "setting client retry policy");
+        self.rq.set_retry_policy(retry_policy);
+    }
+
+    /// Sets the client-side rate limiter for API requests, `None` disables rate limiting
+    ///
+    /// The limiter is shared with every clone of this client, so concurrent tasks using cloned
+    /// clients are paced together. Clones made before this call keep their previous limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) {
+        tracing::debug!(
+            enabled = rate_limiter.is_some(),
+            "setting client rate limiter"
+        );
+        self.rq.set_rate_limiter(rate_limiter);
+    }
+
     /// Enables or disables debug mode for verbose logging
     pub fn set_debug_mode(&mut self, debug_mode: bool) {
         tracing::debug!(enabled = debug_mode, "setting client debug mode");
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone)]
 pub enum FolderOrder {
     Date,
 }
@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+
+/// A relationship path for the `include` query parameter
+///
+/// Build it from one of the typed relationship enums ([`TrackInclude`], [`AlbumInclude`], ...) or
+/// from a raw path like `"albums.coverArt"` for anything not covered by them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Include(Cow<'static, str>);
+
+impl Include {
+    /// Creates an include from a raw relationship path
+    pub fn path(path: impl Into<Cow<'static, str>>) -> Self {
+        Self(path.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Extends the path with a relationship of the included resources, e.g. `albums.coverArt`
+    pub fn then(self, child: impl Into<Include>) -> Self {
+        Self(Cow::Owned(format!("{}.{}", self.0, child.into().0)))
+    }
+}
+
+impl From<&'static str> for Include {
+    fn from(path: &'static str) -> Self {
+        Self(Cow::Borrowed(path))
+    }
+}
+
+impl From<String> for Include {
+    fn from(path: String) -> Self {
+        Self(Cow::Owned(path))
+    }
+}
+
+macro_rules! include_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $path:literal),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $path),+
+                }
+            }
+
+            /// Includes a relationship of the related resources, see [`Include::then`]
+            pub fn then(self, child: impl Into<Include>) -> Include {
+                Include::from(self).then(child)
+            }
+        }
+
+        impl From<$name> for Include {
+            fn from(include: $name) -> Self {
+                Self(Cow::Borrowed(include.as_str()))
+            }
+        }
+    };
+}
+
+include_enum!(
+    /// Relationships of `tracks` resources
+    TrackInclude {
+        Albums => "albums",
+        Artists => "artists",
+        Credits => "credits",
+        Genres => "genres",
+        Lyrics => "lyrics",
+        Owners => "owners",
+        Providers => "providers",
+        Radio => "radio",
+        SimilarTracks => "similarTracks",
+        SourceFile => "sourceFile",
+        TrackStatistics => "trackStatistics",
+    }
+);
+
+include_enum!(
+    /// Relationships of `albums` resources
+    AlbumInclude {
+        Artists => "artists",
+        CoverArt => "coverArt",
+        Genres => "genres",
+        Items => "items",
+        Owners => "owners",
+        Providers => "providers",
+        SimilarAlbums => "similarAlbums",
+    }
+);
+
+include_enum!(
+    /// Relationships of `artists` resources
+    ArtistInclude {
+        Albums => "albums",
+        Biography => "biography",
+        Owners => "owners",
+        ProfileArt => "profileArt",
+        Radio => "radio",
+        Roles => "roles",
+        SimilarArtists => "similarArtists",
+        Tracks => "tracks",
+        Videos => "videos",
+    }
+);
+
+include_enum!(
+    /// Relationships of `playlists` resources
+    PlaylistInclude {
+        CoverArt => "coverArt",
+        Items => "items",
+        OwnerProfiles => "ownerProfiles",
+        Owners => "owners",
+    }
+);
+
+include_enum!(
+    /// Relationships of `videos` resources
+    VideoInclude {
+        Albums => "albums",
+        Artists => "artists",
+        Credits => "credits",
+        Providers => "providers",
+        SimilarVideos => "similarVideos",
+        ThumbnailArt => "thumbnailArt",
+    }
+);
+
+/// Typed builder for OpenAPI v2 query parameters
+///
+/// # Example
+///
+/// ```no_run
+/// # use tidlers::{TidalClient, auth::TidalAuth};
+/// # use tidlers::client::models::jsonapi::{AlbumInclude, OpenApiQuery, TrackInclude};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = TidalClient::new(&TidalAuth::with_oauth());
+/// let query = OpenApiQuery::new()
+///     .country_code("US")
+///     .include(TrackInclude::Artists)
+///     .include(TrackInclude::Albums)
+///     // cover art of the track's albums, sent as `albums.coverArt`
+///     .include(TrackInclude::Albums.then(AlbumInclude::CoverArt));
+///
+/// let doc = client
+///     .openapi_get_tracks_by_id_typed("123", &query.as_params())
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OpenApiQuery {
+    includes: Vec<Include>,
+    /// `includes` joined with commas, kept up to date so `as_params` can borrow it
+    include_param: String,
+    params: Vec<(String, String)>,
+}
+
+impl OpenApiQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a relationship to the `include` parameter, duplicates are ignored
+    pub fn include(mut self, include: impl Into<Include>) -> Self {
+        let include = include.into();
+        if !self.includes.contains(&include) {
+            if !self.include_param.is_empty() {
+                self.include_param.push(',');
+            }
+            self.include_param.push_str(include.as_str());
+            self.includes.push(include);
+        }
+        self
+    }
+
+    /// Adds several relationships to the `include` parameter
+    pub fn includes<I>(self, includes: impl IntoIterator<Item = I>) -> Self
+    where
+        I: Into<Include>,
+    {
+        includes.into_iter().fold(self, Self::include)
+    }
+
+    pub fn country_code(self, country_code: impl Into<String>) -> Self {
+        self.param("countryCode", country_code)
+    }
+
+    /// Adds a `filter[<field>]` parameter
+    pub fn filter(self, field: &str, value: impl Into<String>) -> Self {
+        self.param(format!("filter[{field}]"), value)
+    }
+
+    /// Adds a `page[cursor]` parameter
+    pub fn page_cursor(self, cursor: impl Into<String>) -> Self {
+        self.param("page[cursor]", cursor)
+    }
+
+    /// Adds a raw query parameter
+    pub fn param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns the value of the `include` parameter, `None` if nothing is included
+    pub fn include_param(&self) -> Option<&str> {
+        (!self.include_param.is_empty()).then_some(self.include_param.as_str())
+    }
+
+    /// Returns the query parameters in the form taken by the `openapi_get_*` methods
+    pub fn as_params(&self) -> Vec<(&str, &str)> {
+        let mut params: Vec<(&str, &str)> = self
+            .params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+
+        if let Some(include) = self.include_param() {
+            params.push(("include", include));
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlbumInclude, OpenApiQuery, TrackInclude};
+
+    #[test]
+    fn nested_includes_are_joined_with_dots() {
+        let query = OpenApiQuery::new()
+            .include(TrackInclude::Albums)
+            .include(TrackInclude::Albums.then(AlbumInclude::CoverArt));
+
+        assert_eq!(query.include_param(), Some("albums,albums.coverArt"));
+    }
+}
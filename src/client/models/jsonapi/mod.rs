@@ -0,0 +1,296 @@
+//! Generic [JSON:API](https://jsonapi.org) document layer used by the OpenAPI v2 endpoints
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+mod include;
+mod resolve;
+
+pub use include::{
+    AlbumInclude, ArtistInclude, Include, OpenApiQuery, PlaylistInclude, TrackInclude, VideoInclude,
+};
+pub use resolve::{
+    DanglingReference, IncludedIndex, Resolve, Resolved, ResolvedAlbum, ResolvedArtist,
+    ResolvedPlaylist, ResolvedTrack, ResolvedVideo,
+};
+
+/// Top level JSON:API document
+///
+/// `T` is the primary data, usually a [`Resource`] or a `Vec<Resource<..>>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document<T> {
+    pub data: T,
+    /// Related resources requested with the `include` query parameter
+    #[serde(default)]
+    pub included: Vec<Resource>,
+    #[serde(default)]
+    pub links: Option<Links>,
+    #[serde(default)]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl<T: DeserializeOwned> Document<T> {
+    /// Parses a document from an untyped OpenAPI response
+    pub fn from_value(value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+}
+
+impl<T> Document<T> {
+    /// Looks up an included resource by its identifier
+    pub fn find_included(&self, identifier: &ResourceIdentifier) -> Option<&Resource> {
+        self.included
+            .iter()
+            .find(|resource| resource.id == identifier.id && resource.kind == identifier.kind)
+    }
+
+    /// Looks up an included resource by its identifier and decodes its attributes
+    ///
+    /// Returns `None` if the resource isn't part of `included`.
+    pub fn included_as<A: DeserializeOwned>(
+        &self,
+        identifier: &ResourceIdentifier,
+    ) -> Option<Result<Resource<A>, serde_json::Error>> {
+        self.find_included(identifier)
+            .map(|resource| resource.clone().decode())
+    }
+
+    /// Returns all included resources of the given type
+    pub fn included_of_type<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Resource> {
+        self.included
+            .iter()
+            .filter(move |resource| resource.kind == kind)
+    }
+
+    /// Returns the cursor of the next page, if there is one
+    pub fn next_cursor(&self) -> Option<String> {
+        self.links.as_ref()?.next_cursor()
+    }
+}
+
+/// A single JSON:API resource object
+///
+/// Attributes default to untyped JSON and relationships to a map keyed by relationship name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resource<A = serde_json::Value, R = Relationships> {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    // a plain `default` would require `A: Default`
+    #[serde(default = "Option::default")]
+    pub attributes: Option<A>,
+    #[serde(default)]
+    pub relationships: R,
+    #[serde(default)]
+    pub links: Option<Links>,
+}
+
+impl<A, R> Resource<A, R> {
+    /// Returns the identifier (`id` + `type`) of this resource
+    pub fn identifier(&self) -> ResourceIdentifier {
+        ResourceIdentifier {
+            id: self.id.clone(),
+            kind: self.kind.clone(),
+            meta: None,
+        }
+    }
+}
+
+impl<R> Resource<serde_json::Value, R> {
+    /// Decodes the untyped attributes into `A`
+    pub fn decode<A: DeserializeOwned>(self) -> Result<Resource<A, R>, serde_json::Error> {
+        Ok(Resource {
+            id: self.id,
+            kind: self.kind,
+            attributes: self.attributes.map(serde_json::from_value).transpose()?,
+            relationships: self.relationships,
+            links: self.links,
+        })
+    }
+}
+
+impl<A> Resource<A, Relationships> {
+    /// Returns a relationship by name
+    pub fn relationship(&self, name: &str) -> Option<&Relationship> {
+        self.relationships.get(name)
+    }
+}
+
+/// Relationships of a resource keyed by name (`artists`, `albums`, `coverArt`, ...)
+pub type Relationships = HashMap<String, Relationship>;
+
+/// Reference to a resource by `id` and `type`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceIdentifier {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// A named link from one resource to others
+///
+/// `data` is only present if the relationship was requested with `include`, otherwise only
+/// `links` are returned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Relationship {
+    #[serde(default)]
+    pub data: Option<RelationshipData>,
+    #[serde(default)]
+    pub links: Option<Links>,
+    #[serde(default)]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl Relationship {
+    /// Returns the referenced identifiers, regardless of the relationship's cardinality
+    pub fn identifiers(&self) -> &[ResourceIdentifier] {
+        match &self.data {
+            Some(RelationshipData::One(identifier)) => std::slice::from_ref(identifier),
+            Some(RelationshipData::Many(identifiers)) => identifiers,
+            None => &[],
+        }
+    }
+}
+
+/// Linkage of a relationship, either a single resource or a list of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RelationshipData {
+    One(ResourceIdentifier),
+    Many(Vec<ResourceIdentifier>),
+}
+
+/// Links of a document, resource or relationship
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Links {
+    #[serde(rename = "self", default)]
+    pub self_link: Option<String>,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub meta: Option<LinksMeta>,
+}
+
+impl Links {
+    /// Returns the cursor of the next page, if there is one
+    ///
+    /// Reads `meta.nextCursor`, falling back to the `page[cursor]` parameter of `next`.
+    pub fn next_cursor(&self) -> Option<String> {
+        if let Some(cursor) = self.meta.as_ref().and_then(|meta| meta.next_cursor.clone()) {
+            return Some(cursor);
+        }
+
+        let next = url::Url::parse("http://localhost")
+            .ok()?
+            .join(self.next.as_deref()?)
+            .ok()?;
+        next.query_pairs()
+            .find(|(key, _)| key == "page[cursor]")
+            .map(|(_, value)| value.into_owned())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinksMeta {
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, Links, LinksMeta, Resource, ResourceIdentifier};
+    use crate::client::models::openapi::{ArtistAttributes, TrackResource};
+
+    fn track_document() -> serde_json::Value {
+        serde_json::json!({
+            "data": {
+                "id": "1",
+                "type": "tracks",
+                "attributes": {
+                    "title": "Song",
+                    "isrc": "ABC123",
+                    "duration": "PT3M2S",
+                    "explicit": false,
+                    "mediaTags": ["LOSSLESS"]
+                },
+                "relationships": {
+                    "artists": {
+                        "data": [{ "id": "7", "type": "artists" }],
+                        "links": { "self": "/tracks/1/relationships/artists" }
+                    },
+                    "lyrics": {
+                        "links": { "self": "/tracks/1/relationships/lyrics" }
+                    }
+                }
+            },
+            "included": [
+                { "id": "7", "type": "artists", "attributes": { "name": "Someone" } }
+            ],
+            "links": { "self": "/tracks/1" }
+        })
+    }
+
+    #[test]
+    fn parses_typed_document_with_relationships() {
+        let doc =
+            Document::<TrackResource>::from_value(track_document()).expect("document should parse");
+
+        let attributes = doc.data.attributes.as_ref().expect("attributes are set");
+        assert_eq!(attributes.title, "Song");
+        assert_eq!(attributes.media_tags, vec!["LOSSLESS".to_string()]);
+
+        let artists = doc
+            .data
+            .relationship("artists")
+            .expect("artists relationship");
+        assert_eq!(artists.identifiers().len(), 1);
+        assert!(
+            doc.data
+                .relationship("lyrics")
+                .unwrap()
+                .identifiers()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn resolves_included_resource_by_identifier() {
+        let doc =
+            Document::<TrackResource>::from_value(track_document()).expect("document should parse");
+        let identifier = &doc.data.relationship("artists").unwrap().identifiers()[0];
+
+        let artist: Resource<ArtistAttributes> = doc
+            .included_as(identifier)
+            .expect("artist is included")
+            .expect("artist attributes decode");
+        assert_eq!(artist.attributes.unwrap().name, "Someone");
+
+        let missing = ResourceIdentifier {
+            id: "8".to_string(),
+            kind: "artists".to_string(),
+            meta: None,
+        };
+        assert!(doc.find_included(&missing).is_none());
+    }
+
+    #[test]
+    fn next_cursor_reads_meta_then_next_link() {
+        let links = Links {
+            next: Some("/tracks?page%5Bcursor%5D=from_link".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(links.next_cursor().as_deref(), Some("from_link"));
+
+        let links = Links {
+            meta: Some(LinksMeta {
+                next_cursor: Some("from_meta".to_string()),
+            }),
+            ..links
+        };
+        assert_eq!(links.next_cursor().as_deref(), Some("from_meta"));
+    }
+}
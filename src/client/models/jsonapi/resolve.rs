@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::client::models::{
+    jsonapi::{Document, Relationships, Resource, ResourceIdentifier},
+    openapi::{
+        AlbumAttributes, AlbumResource, ArtistAttributes, ArtistResource, ArtworkResource,
+        PlaylistResource, TrackResource, VideoResource,
+    },
+};
+
+/// A relationship pointing at a resource that isn't part of `included`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DanglingReference {
+    /// Resource owning the relationship
+    pub from: ResourceIdentifier,
+    pub relationship: String,
+    pub target: ResourceIdentifier,
+}
+
+/// Result of resolving a document, along with every reference that couldn't be resolved
+#[derive(Debug, Clone)]
+pub struct Resolved<T> {
+    pub value: T,
+    pub dangling: Vec<DanglingReference>,
+}
+
+/// Lookup table over the `included` resources of a document
+#[derive(Debug)]
+pub struct IncludedIndex<'a> {
+    resources: HashMap<(&'a str, &'a str), &'a Resource>,
+}
+
+impl<'a> IncludedIndex<'a> {
+    pub fn new(included: &'a [Resource]) -> Self {
+        let resources = included
+            .iter()
+            .map(|resource| ((resource.kind.as_str(), resource.id.as_str()), resource))
+            .collect();
+
+        Self { resources }
+    }
+
+    pub fn get(&self, identifier: &ResourceIdentifier) -> Option<&'a Resource> {
+        self.resources
+            .get(&(identifier.kind.as_str(), identifier.id.as_str()))
+            .copied()
+    }
+
+    /// Decodes the resources referenced by a relationship
+    ///
+    /// References missing from `included` are skipped and recorded in `dangling`.
+    pub fn related<A: DeserializeOwned>(
+        &self,
+        from: &ResourceIdentifier,
+        relationships: &Relationships,
+        name: &str,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Vec<Resource<A>>, serde_json::Error> {
+        let Some(relationship) = relationships.get(name) else {
+            return Ok(Vec::new());
+        };
+
+        let mut resources = Vec::new();
+        for target in relationship.identifiers() {
+            match self.get(target) {
+                Some(resource) => resources.push(resource.clone().decode()?),
+                None => dangling.push(DanglingReference {
+                    from: from.clone(),
+                    relationship: name.to_string(),
+                    target: target.clone(),
+                }),
+            }
+        }
+
+        Ok(resources)
+    }
+}
+
+/// Resources that can be turned into a navigable object graph using a document's `included`
+pub trait Resolve: Sized {
+    type Output;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error>;
+}
+
+impl<T: Resolve> Resolve for Vec<T> {
+    type Output = Vec<T::Output>;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error> {
+        self.into_iter()
+            .map(|resource| resource.resolve(index, dangling))
+            .collect()
+    }
+}
+
+impl<T: Resolve> Document<T> {
+    /// Joins the primary data with the `included` resources it references
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::client::models::jsonapi::{AlbumInclude, OpenApiQuery, TrackInclude};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let query = OpenApiQuery::new()
+    ///     .country_code("US")
+    ///     .includes([TrackInclude::Artists, TrackInclude::Albums])
+    ///     .include(TrackInclude::Albums.then(AlbumInclude::CoverArt));
+    ///
+    /// let track = client
+    ///     .openapi_get_tracks_by_id_typed("123", &query.as_params())
+    ///     .await?
+    ///     .resolve()?
+    ///     .value;
+    ///
+    /// for album in &track.albums {
+    ///     for artwork in &album.cover_art {
+    ///         println!("{:?}", artwork.attributes);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resolve(self) -> Result<Resolved<T::Output>, serde_json::Error> {
+        let index = IncludedIndex::new(&self.included);
+        let mut dangling = Vec::new();
+        let value = self.data.resolve(&index, &mut dangling)?;
+
+        Ok(Resolved { value, dangling })
+    }
+}
+
+/// A track with its artists and albums
+#[derive(Debug, Clone)]
+pub struct ResolvedTrack {
+    pub track: TrackResource,
+    pub artists: Vec<ResolvedArtist>,
+    pub albums: Vec<ResolvedAlbum>,
+}
+
+/// An album with its artists and cover art
+#[derive(Debug, Clone)]
+pub struct ResolvedAlbum {
+    pub album: AlbumResource,
+    pub artists: Vec<ResolvedArtist>,
+    pub cover_art: Vec<ArtworkResource>,
+}
+
+/// An artist with its profile art
+#[derive(Debug, Clone)]
+pub struct ResolvedArtist {
+    pub artist: ArtistResource,
+    pub profile_art: Vec<ArtworkResource>,
+}
+
+/// A video with its artists, albums and thumbnail
+#[derive(Debug, Clone)]
+pub struct ResolvedVideo {
+    pub video: VideoResource,
+    pub artists: Vec<ResolvedArtist>,
+    pub albums: Vec<ResolvedAlbum>,
+    pub thumbnail_art: Vec<ArtworkResource>,
+}
+
+/// A playlist with its cover art
+#[derive(Debug, Clone)]
+pub struct ResolvedPlaylist {
+    pub playlist: PlaylistResource,
+    pub cover_art: Vec<ArtworkResource>,
+}
+
+fn resolve_related<A>(
+    index: &IncludedIndex<'_>,
+    from: &ResourceIdentifier,
+    relationships: &Relationships,
+    name: &str,
+    dangling: &mut Vec<DanglingReference>,
+) -> Result<Vec<<Resource<A> as Resolve>::Output>, serde_json::Error>
+where
+    A: DeserializeOwned,
+    Resource<A>: Resolve,
+{
+    index
+        .related::<A>(from, relationships, name, dangling)?
+        .into_iter()
+        .map(|resource| resource.resolve(index, dangling))
+        .collect()
+}
+
+impl Resolve for TrackResource {
+    type Output = ResolvedTrack;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error> {
+        let from = self.identifier();
+        let artists = resolve_related::<ArtistAttributes>(
+            index,
+            &from,
+            &self.relationships,
+            "artists",
+            dangling,
+        )?;
+        let albums = resolve_related::<AlbumAttributes>(
+            index,
+            &from,
+            &self.relationships,
+            "albums",
+            dangling,
+        )?;
+
+        Ok(ResolvedTrack {
+            track: self,
+            artists,
+            albums,
+        })
+    }
+}
+
+impl Resolve for AlbumResource {
+    type Output = ResolvedAlbum;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error> {
+        let from = self.identifier();
+        let artists = resolve_related::<ArtistAttributes>(
+            index,
+            &from,
+            &self.relationships,
+            "artists",
+            dangling,
+        )?;
+        let cover_art = index.related(&from, &self.relationships, "coverArt", dangling)?;
+
+        Ok(ResolvedAlbum {
+            album: self,
+            artists,
+            cover_art,
+        })
+    }
+}
+
+impl Resolve for ArtistResource {
+    type Output = ResolvedArtist;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error> {
+        let from = self.identifier();
+        let profile_art = index.related(&from, &self.relationships, "profileArt", dangling)?;
+
+        Ok(ResolvedArtist {
+            artist: self,
+            profile_art,
+        })
+    }
+}
+
+impl Resolve for VideoResource {
+    type Output = ResolvedVideo;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error> {
+        let from = self.identifier();
+        let artists = resolve_related::<ArtistAttributes>(
+            index,
+            &from,
+            &self.relationships,
+            "artists",
+            dangling,
+        )?;
+        let albums = resolve_related::<AlbumAttributes>(
+            index,
+            &from,
+            &self.relationships,
+            "albums",
+            dangling,
+        )?;
+        let thumbnail_art = index.related(&from, &self.relationships, "thumbnailArt", dangling)?;
+
+        Ok(ResolvedVideo {
+            video: self,
+            artists,
+            albums,
+            thumbnail_art,
+        })
+    }
+}
+
+impl Resolve for PlaylistResource {
+    type Output = ResolvedPlaylist;
+
+    fn resolve(
+        self,
+        index: &IncludedIndex<'_>,
+        dangling: &mut Vec<DanglingReference>,
+    ) -> Result<Self::Output, serde_json::Error> {
+        let from = self.identifier();
+        let cover_art = index.related(&from, &self.relationships, "coverArt", dangling)?;
+
+        Ok(ResolvedPlaylist {
+            playlist: self,
+            cover_art,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::models::{
+        jsonapi::{Document, ResourceIdentifier},
+        openapi::TrackResource,
+    };
+
+    fn document() -> Document<TrackResource> {
+        Document::from_value(serde_json::json!({
+            "data": {
+                "id": "1",
+                "type": "tracks",
+                "attributes": { "title": "Song" },
+                "relationships": {
+                    "artists": { "data": [
+                        { "id": "7", "type": "artists" },
+                        { "id": "8", "type": "artists" }
+                    ] },
+                    "albums": { "data": [{ "id": "3", "type": "albums" }] }
+                }
+            },
+            "included": [
+                { "id": "7", "type": "artists", "attributes": { "name": "Someone" } },
+                {
+                    "id": "3",
+                    "type": "albums",
+                    "attributes": { "title": "Record" },
+                    "relationships": {
+                        "coverArt": { "data": [{ "id": "a1", "type": "artworks" }] }
+                    }
+                },
+                {
+                    "id": "a1",
+                    "type": "artworks",
+                    "attributes": {
+                        "mediaType": "IMAGE",
+                        "files": [{
+                            "href": "https://resources.example.com/640.jpg",
+                            "meta": { "width": 640, "height": 640 }
+                        }]
+                    }
+                }
+            ]
+        }))
+        .expect("document should parse")
+    }
+
+    #[test]
+    fn resolves_nested_relationships() {
+        let track = document().resolve().expect("resolve should succeed").value;
+
+        assert_eq!(track.artists.len(), 1);
+        assert_eq!(
+            track.artists[0].artist.attributes.as_ref().unwrap().name,
+            "Someone"
+        );
+
+        let album = &track.albums[0];
+        assert_eq!(album.album.attributes.as_ref().unwrap().title, "Record");
+        let files = &album.cover_art[0].attributes.as_ref().unwrap().files;
+        assert_eq!(files[0].meta.as_ref().unwrap().width, 640);
+    }
+
+    #[test]
+    fn reports_dangling_references() {
+        let resolved = document().resolve().expect("resolve should succeed");
+
+        assert_eq!(resolved.dangling.len(), 1);
+        let dangling = &resolved.dangling[0];
+        assert_eq!(dangling.relationship, "artists");
+        assert_eq!(dangling.from.id, "1");
+        assert_eq!(
+            dangling.target,
+            ResourceIdentifier {
+                id: "8".to_string(),
+                kind: "artists".to_string(),
+                meta: None,
+            }
+        );
+    }
+}
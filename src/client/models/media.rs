@@ -1,4 +1,193 @@
+use std::fmt;
+
+use crate::client::models::playback::{AudioMode, AudioQuality};
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MediaMetadata {
-    pub tags: Vec<String>,
+    pub tags: Vec<QualityTag>,
+}
+
+/// Quality or audio mode as found in media metadata tags, audio qualities and audio modes
+///
+/// Stereo qualities are ordered by fidelity and come before the audio modes, unknown values sort
+/// last. Parsing accepts every spelling TIDAL uses (`HI_RES_LOSSLESS` and `HIRES_LOSSLESS`),
+/// serializing writes the one of the media metadata tags.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(from = "String", into = "String")]
+pub enum QualityTag {
+    Low,
+    High,
+    Lossless,
+    /// MQA, delivered as `HI_RES` before HiRes FLAC existed
+    Mqa,
+    HiResLossless,
+    Stereo,
+    DolbyAtmos,
+    Sony360,
+    Unknown(String),
+}
+
+impl QualityTag {
+    /// Stream quality this tag stands for, `None` for audio modes and unknown tags
+    pub fn audio_quality(&self) -> Option<AudioQuality> {
+        match self {
+            Self::Low => Some(AudioQuality::Low),
+            Self::High => Some(AudioQuality::High),
+            Self::Lossless => Some(AudioQuality::Lossless),
+            Self::Mqa | Self::HiResLossless => Some(AudioQuality::HiRes),
+            _ => None,
+        }
+    }
+
+    /// Audio mode this tag stands for, `None` for stream qualities and unknown tags
+    pub fn audio_mode(&self) -> Option<AudioMode> {
+        match self {
+            Self::Stereo => Some(AudioMode::Stereo),
+            Self::DolbyAtmos => Some(AudioMode::DolbyAtmos),
+            Self::Sony360 => Some(AudioMode::Sony360),
+            _ => None,
+        }
+    }
+
+    pub fn is_immersive(&self) -> bool {
+        matches!(self, Self::DolbyAtmos | Self::Sony360)
+    }
+}
+
+impl From<&str> for QualityTag {
+    fn from(tag: &str) -> Self {
+        match tag.to_ascii_uppercase().as_str() {
+            "LOW" => Self::Low,
+            "HIGH" => Self::High,
+            "LOSSLESS" => Self::Lossless,
+            "MQA" | "HI_RES" => Self::Mqa,
+            "HIRES_LOSSLESS" | "HI_RES_LOSSLESS" => Self::HiResLossless,
+            "STEREO" => Self::Stereo,
+            "DOLBY_ATMOS" => Self::DolbyAtmos,
+            "SONY_360RA" => Self::Sony360,
+            _ => Self::Unknown(tag.to_string()),
+        }
+    }
+}
+
+impl From<String> for QualityTag {
+    fn from(tag: String) -> Self {
+        tag.as_str().into()
+    }
+}
+
+impl From<QualityTag> for String {
+    fn from(tag: QualityTag) -> Self {
+        tag.to_string()
+    }
+}
+
+impl From<AudioQuality> for QualityTag {
+    fn from(quality: AudioQuality) -> Self {
+        match quality {
+            AudioQuality::Low => Self::Low,
+            AudioQuality::High => Self::High,
+            AudioQuality::Lossless => Self::Lossless,
+            AudioQuality::HiRes => Self::HiResLossless,
+        }
+    }
+}
+
+impl From<AudioMode> for QualityTag {
+    fn from(mode: AudioMode) -> Self {
+        match mode {
+            AudioMode::Stereo => Self::Stereo,
+            AudioMode::DolbyAtmos => Self::DolbyAtmos,
+            AudioMode::Sony360 => Self::Sony360,
+        }
+    }
+}
+
+impl TryFrom<QualityTag> for AudioQuality {
+    type Error = QualityTag;
+
+    fn try_from(tag: QualityTag) -> Result<Self, Self::Error> {
+        tag.audio_quality().ok_or(tag)
+    }
+}
+
+impl fmt::Display for QualityTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Low => write!(f, "LOW"),
+            Self::High => write!(f, "HIGH"),
+            Self::Lossless => write!(f, "LOSSLESS"),
+            Self::Mqa => write!(f, "MQA"),
+            Self::HiResLossless => write!(f, "HIRES_LOSSLESS"),
+            Self::Stereo => write!(f, "STEREO"),
+            Self::DolbyAtmos => write!(f, "DOLBY_ATMOS"),
+            Self::Sony360 => write!(f, "SONY_360RA"),
+            Self::Unknown(tag) => write!(f, "{tag}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MediaMetadata, QualityTag};
+    use crate::client::models::playback::{AudioMode, AudioQuality};
+
+    #[test]
+    fn tags_parse_with_unknown_fallback_and_roundtrip() {
+        let metadata: MediaMetadata =
+            serde_json::from_str(r#"{"tags":["HIRES_LOSSLESS","MQA","DOLBY_ATMOS","SPATIAL"]}"#)
+                .unwrap();
+
+        assert_eq!(
+            metadata.tags,
+            [
+                QualityTag::HiResLossless,
+                QualityTag::Mqa,
+                QualityTag::DolbyAtmos,
+                QualityTag::Unknown("SPATIAL".to_string())
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&metadata).unwrap(),
+            r#"{"tags":["HIRES_LOSSLESS","MQA","DOLBY_ATMOS","SPATIAL"]}"#
+        );
+        // the playback info spelling of the same quality
+        assert_eq!(
+            QualityTag::from("HI_RES_LOSSLESS"),
+            QualityTag::HiResLossless
+        );
+    }
+
+    #[test]
+    fn tags_order_and_convert_to_audio_qualities_and_modes() {
+        assert!(QualityTag::Lossless < QualityTag::Mqa);
+        assert!(QualityTag::Mqa < QualityTag::HiResLossless);
+        assert_eq!(
+            [
+                QualityTag::High,
+                QualityTag::HiResLossless,
+                QualityTag::Lossless
+            ]
+            .into_iter()
+            .max(),
+            Some(QualityTag::HiResLossless)
+        );
+
+        assert_eq!(
+            AudioQuality::try_from(QualityTag::HiResLossless),
+            Ok(AudioQuality::HiRes)
+        );
+        assert_eq!(
+            AudioQuality::try_from(QualityTag::DolbyAtmos),
+            Err(QualityTag::DolbyAtmos)
+        );
+        assert_eq!(
+            QualityTag::from(AudioQuality::Lossless),
+            QualityTag::Lossless
+        );
+        assert_eq!(QualityTag::Sony360.audio_mode(), Some(AudioMode::Sony360));
+        assert!(QualityTag::DolbyAtmos.is_immersive());
+    }
 }
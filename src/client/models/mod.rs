@@ -8,8 +8,10 @@ pub mod artist;
 pub mod collection;
 pub mod feed;
 pub mod home;
+pub mod jsonapi;
 pub mod media;
 pub mod mix;
+pub mod openapi;
 pub mod page;
 pub mod playback;
 pub mod playlist;
@@ -0,0 +1,271 @@
+//! Typed attributes of the OpenAPI v2 resources
+//!
+//! Durations are ISO 8601 durations (`PT3M2S`) and dates are ISO 8601 strings, both kept as
+//! returned by the API.
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::client::models::jsonapi::Resource;
+
+pub type AlbumResource = Resource<AlbumAttributes>;
+pub type ArtistResource = Resource<ArtistAttributes>;
+pub type TrackResource = Resource<TrackAttributes>;
+pub type VideoResource = Resource<VideoAttributes>;
+pub type PlaylistResource = Resource<PlaylistAttributes>;
+pub type LyricsResource = Resource<LyricsAttributes>;
+pub type CreditResource = Resource<CreditAttributes>;
+pub type ArtworkResource = Resource<ArtworkAttributes>;
+pub type UserCollectionFolderResource = Resource<UserCollectionFolderAttributes>;
+
+/// `userCollections` resources only carry relationships (`albums`, `tracks`, ...)
+pub type UserCollectionResource = Resource<UserCollectionAttributes>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumAttributes {
+    pub title: String,
+    #[serde(default)]
+    pub barcode_id: Option<String>,
+    #[serde(default)]
+    pub number_of_volumes: Option<u32>,
+    #[serde(default)]
+    pub number_of_items: Option<u32>,
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub explicit: bool,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub copyright: Option<Copyright>,
+    #[serde(default)]
+    pub popularity: Option<f64>,
+    #[serde(default)]
+    pub access_type: Option<String>,
+    #[serde(default)]
+    pub availability: Vec<String>,
+    #[serde(default)]
+    pub media_tags: Vec<String>,
+    #[serde(default)]
+    pub external_links: Vec<ExternalLink>,
+    /// `ALBUM`, `EP` or `SINGLE`
+    #[serde(rename = "type", default)]
+    pub album_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistAttributes {
+    pub name: String,
+    #[serde(default)]
+    pub popularity: Option<f64>,
+    #[serde(default)]
+    pub handle: Option<String>,
+    #[serde(default)]
+    pub spotlighted: bool,
+    #[serde(default)]
+    pub contributions_enabled: bool,
+    #[serde(default)]
+    pub external_links: Vec<ExternalLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackAttributes {
+    pub title: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub isrc: Option<String>,
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub copyright: Option<Copyright>,
+    #[serde(default)]
+    pub explicit: bool,
+    #[serde(default)]
+    pub popularity: Option<f64>,
+    #[serde(default)]
+    pub access_type: Option<String>,
+    #[serde(default)]
+    pub availability: Vec<String>,
+    #[serde(default)]
+    pub media_tags: Vec<String>,
+    #[serde(default)]
+    pub tone_tags: Vec<String>,
+    #[serde(default)]
+    pub bpm: Option<f64>,
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub key_scale: Option<String>,
+    #[serde(default)]
+    pub spotlighted: bool,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub external_links: Vec<ExternalLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoAttributes {
+    pub title: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub isrc: Option<String>,
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub copyright: Option<Copyright>,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub explicit: bool,
+    #[serde(default)]
+    pub popularity: Option<f64>,
+    #[serde(default)]
+    pub availability: Vec<String>,
+    #[serde(default)]
+    pub external_links: Vec<ExternalLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistAttributes {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub bounded: Option<bool>,
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub number_of_items: Option<u32>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub last_modified_at: Option<String>,
+    /// `PUBLIC` or `PRIVATE`
+    #[serde(default)]
+    pub privacy: Option<String>,
+    #[serde(default)]
+    pub access_type: Option<String>,
+    /// `EDITORIAL`, `USER`, `MIX` or `ARTIST`
+    #[serde(default)]
+    pub playlist_type: Option<String>,
+    #[serde(default)]
+    pub external_links: Vec<ExternalLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsAttributes {
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Time synced lyrics in LRC format
+    #[serde(default)]
+    pub lrc_text: Option<String>,
+    #[serde(default)]
+    pub direction: Option<String>,
+    #[serde(default)]
+    pub technical_status: Option<String>,
+    #[serde(default)]
+    pub provider: Option<LyricsProvider>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsProvider {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreditAttributes {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Attributes of `artworks` resources (album covers, artist pictures, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkAttributes {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub files: Vec<ArtworkFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkFile {
+    pub href: String,
+    #[serde(default)]
+    pub meta: Option<ArtworkFileMeta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkFileMeta {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserCollectionAttributes {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserCollectionFolderAttributes {
+    pub name: String,
+    #[serde(default)]
+    pub collection_type: Option<String>,
+    #[serde(default)]
+    pub number_of_items: Option<u32>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub last_modified_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalLink {
+    pub href: String,
+    #[serde(default)]
+    pub meta: Option<ExternalLinkMeta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalLinkMeta {
+    #[serde(rename = "type")]
+    pub link_type: String,
+}
+
+/// Copyright notice, returned either as a plain string or as `{ "text": ... }`
+#[derive(Debug, Clone, Serialize)]
+pub struct Copyright {
+    pub text: String,
+}
+
+impl<'de> Deserialize<'de> for Copyright {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Object { text: String },
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Text(text) | Raw::Object { text } => Copyright { text },
+        })
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::client::models::media::QualityTag;
+
 /// Audio quality levels available for streaming
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub enum AudioQuality {
@@ -9,6 +11,71 @@ pub enum AudioQuality {
     HiRes,
 }
 
+impl AudioQuality {
+    /// Parses an API value or media metadata tag like `HI_RES_LOSSLESS` or `HIRES_LOSSLESS`
+    pub fn parse(quality: &str) -> Option<Self> {
+        QualityTag::from(quality).audio_quality()
+    }
+}
+
+/// Audio modes a track can be streamed in
+///
+/// The immersive modes are only handed out to accounts and devices that support them, other
+/// requests fall back to stereo.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum AudioMode {
+    Stereo,
+    DolbyAtmos,
+    Sony360,
+}
+
+impl AudioMode {
+    /// Parses an API value like `DOLBY_ATMOS`, `None` for modes tidlers doesn't know
+    pub fn parse(mode: &str) -> Option<Self> {
+        QualityTag::from(mode).audio_mode()
+    }
+
+    /// Dolby Atmos or Sony 360 Reality Audio
+    pub fn is_immersive(&self) -> bool {
+        !matches!(self, Self::Stereo)
+    }
+}
+
+/// Codec of an audio stream, as given by a manifest's `codecs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioCodec {
+    Flac,
+    Aac,
+    /// E-AC-3 (Dolby Digital Plus), carries Dolby Atmos
+    Eac3,
+    /// AC-4, carries Dolby Atmos
+    Ac4,
+    /// MPEG-H 3D Audio, carries Sony 360 Reality Audio
+    MpegH,
+    Other(String),
+}
+
+impl AudioCodec {
+    /// Parses a codecs string like `flac`, `mp4a.40.2`, `ec-3` or `ac-4.02.01.00`
+    pub fn parse(codecs: &str) -> Self {
+        let codecs = codecs.trim().to_ascii_lowercase();
+        let family = codecs.split('.').next().unwrap_or_default();
+        match family {
+            "flac" | "flc" => Self::Flac,
+            "mp4a" | "aac" => Self::Aac,
+            "ec-3" | "eac3" => Self::Eac3,
+            "ac-4" | "ac4" => Self::Ac4,
+            "mha1" | "mhm1" => Self::MpegH,
+            _ => Self::Other(codecs),
+        }
+    }
+
+    /// Codecs only delivered for immersive audio modes
+    pub fn is_immersive(&self) -> bool {
+        matches!(self, Self::Eac3 | Self::Ac4 | Self::MpegH)
+    }
+}
+
 /// Video quality levels available for streaming
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub enum VideoQuality {
@@ -42,6 +109,16 @@ impl fmt::Display for AudioQuality {
     }
 }
 
+impl fmt::Display for AudioMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stereo => write!(f, "STEREO"),
+            Self::DolbyAtmos => write!(f, "DOLBY_ATMOS"),
+            Self::Sony360 => write!(f, "SONY_360RA"),
+        }
+    }
+}
+
 impl fmt::Display for VideoQuality {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1,3 +1,5 @@
+use crate::client::models::{media::QualityTag, playback::AudioQuality};
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserSubscriptionResponse {
@@ -5,13 +7,20 @@ pub struct UserSubscriptionResponse {
     pub valid_until: String,
     pub status: String,
     pub subscription: SubscriptionPlan,
-    pub highest_sound_quality: String,
+    pub highest_sound_quality: QualityTag,
     pub premium_access: bool,
     pub can_get_trial: bool,
     pub payment_type: String,
     pub payment_overdue: bool,
 }
 
+impl UserSubscriptionResponse {
+    /// Typed `highest_sound_quality`, `None` for values tidlers doesn't know
+    pub fn highest_quality(&self) -> Option<AudioQuality> {
+        self.highest_sound_quality.audio_quality()
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionPlan {
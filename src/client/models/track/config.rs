@@ -1,12 +1,14 @@
-use crate::client::models::playback::{AssetPresentation, AudioQuality, PlaybackMode};
+use crate::client::models::playback::{AssetPresentation, AudioMode, AudioQuality, PlaybackMode};
 
 #[derive(Clone, Default)]
 pub struct TrackPlaybackInfoConfig {
     pub audio_quality: Option<AudioQuality>,
+    pub audio_mode: Option<AudioMode>,
     pub playback_mode: Option<PlaybackMode>,
     pub asset_presentation: Option<AssetPresentation>,
 }
 
+#[derive(Clone)]
 pub struct UserUploadsIncludeOptions {
     pub albums: bool,
     pub albums_cover_art: bool,
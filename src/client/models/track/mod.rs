@@ -1,9 +1,16 @@
 use std::collections::HashMap;
 
-use crate::client::models::{album::Album, artist::Artist, media::MediaMetadata};
+use crate::client::models::{
+    album::Album,
+    artist::Artist,
+    media::{MediaMetadata, QualityTag},
+    playback::{AudioMode, AudioQuality},
+};
 
 pub mod config;
+pub mod mpd;
 pub mod playback;
+pub mod quality;
 pub mod user_uploads;
 
 /// Represents a track
@@ -35,8 +42,8 @@ pub struct Track {
     pub isrc: Option<String>,
     pub editable: bool,
     pub explicit: bool,
-    pub audio_quality: String,
-    pub audio_modes: Vec<String>,
+    pub audio_quality: QualityTag,
+    pub audio_modes: Vec<QualityTag>,
     pub media_metadata: Option<MediaMetadata>,
     pub upload: bool,
     pub access_type: Option<String>,
@@ -50,6 +57,48 @@ pub struct Track {
     pub item_uuid: Option<String>,
 }
 
+impl Track {
+    /// Highest quality the track exists in, from `audio_quality` and the media metadata tags
+    pub fn highest_audio_quality(&self) -> Option<AudioQuality> {
+        let tags = self.media_metadata.iter().flat_map(|m| &m.tags);
+        std::iter::once(&self.audio_quality)
+            .chain(tags)
+            .filter_map(QualityTag::audio_quality)
+            .max()
+    }
+
+    /// Audio modes the track can be streamed in, from `audio_modes` and the media metadata tags
+    pub fn available_audio_modes(&self) -> Vec<AudioMode> {
+        let tags = self.media_metadata.iter().flat_map(|m| &m.tags);
+        let mut modes: Vec<AudioMode> = self
+            .audio_modes
+            .iter()
+            .chain(tags)
+            .filter_map(QualityTag::audio_mode)
+            .collect();
+        modes.sort();
+        modes.dedup();
+        modes
+    }
+
+    /// Immersive formats the track offers, empty for stereo only tracks
+    pub fn immersive_modes(&self) -> Vec<AudioMode> {
+        self.available_audio_modes()
+            .into_iter()
+            .filter(AudioMode::is_immersive)
+            .collect()
+    }
+
+    pub fn has_dolby_atmos(&self) -> bool {
+        self.available_audio_modes()
+            .contains(&AudioMode::DolbyAtmos)
+    }
+
+    pub fn has_sony_360(&self) -> bool {
+        self.available_audio_modes().contains(&AudioMode::Sony360)
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TrackRadioResponse {
@@ -73,24 +122,57 @@ pub struct LyricsResponse {
 
 #[cfg(test)]
 mod tests {
-    use crate::client::models::track::playback::{
-        DashManifest, JsonTrackManifest, ParsedTrackManifest, TrackPlaybackInfoResponse,
+    use serde_json::json;
+
+    use super::Track;
+    use crate::{
+        client::models::{
+            media::QualityTag,
+            playback::AudioMode,
+            track::playback::{
+                DashManifest, JsonTrackManifest, ParsedTrackManifest, TrackPlaybackInfoResponse,
+            },
+        },
+        test_support::track_json,
     };
 
+    #[test]
+    fn immersive_modes_come_from_audio_modes_and_tags() {
+        let mut json = track_json(1, "Track", "Artist");
+        json["audioModes"] = json!(["STEREO", "DOLBY_ATMOS"]);
+        json["mediaMetadata"] = json!({ "tags": ["LOSSLESS", "SONY_360RA", "DOLBY_ATMOS"] });
+        let track: Track = serde_json::from_value(json).unwrap();
+
+        assert_eq!(
+            track.available_audio_modes(),
+            [AudioMode::Stereo, AudioMode::DolbyAtmos, AudioMode::Sony360]
+        );
+        assert_eq!(
+            track.immersive_modes(),
+            [AudioMode::DolbyAtmos, AudioMode::Sony360]
+        );
+        assert!(track.has_dolby_atmos() && track.has_sony_360());
+
+        let stereo: Track = serde_json::from_value(track_json(2, "Track", "Artist")).unwrap();
+        assert!(stereo.immersive_modes().is_empty());
+        assert!(!stereo.has_dolby_atmos());
+    }
+
     #[test]
     fn playback_info_helpers_work_for_json_manifest() {
         let manifest = JsonTrackManifest {
             mime_type: "audio/flac".to_string(),
             codecs: "flac".to_string(),
             encryption_type: "NONE".to_string(),
+            key_id: None,
             urls: vec!["https://example.com/a.flac".to_string()],
         };
 
         let playback = TrackPlaybackInfoResponse {
             track_id: 1,
             asset_presentation: "FULL".to_string(),
-            audio_mode: "STEREO".to_string(),
-            audio_quality: "LOSSLESS".to_string(),
+            audio_mode: QualityTag::Stereo,
+            audio_quality: QualityTag::Lossless,
             manifest_mime_type: "application/json".to_string(),
             manifest_hash: "hash".to_string(),
             manifest: Some(manifest.clone()),
@@ -99,6 +181,8 @@ mod tests {
             album_peak_amplitude: 0.0,
             track_replay_gain: 0.0,
             track_peak_amplitude: 0.0,
+            bit_depth: None,
+            sample_rate: None,
         };
 
         assert_eq!(
@@ -121,6 +205,7 @@ mod tests {
             timescale: Some(1),
             duration: Some(1),
             start_number: Some(1),
+            mpd: None,
         };
 
         assert_eq!(dash.get_segment_url(42).as_deref(), Some("seg-42.m4s"));
@@ -0,0 +1,923 @@
+//! MPEG-DASH manifest (MPD) model, as returned for HiRes playback
+//!
+//! Only the parts used by TIDAL are modelled: `Period`, `AdaptationSet`, `Representation`,
+//! `BaseURL` and `SegmentTemplate` with or without a `SegmentTimeline`.
+
+use std::time::Duration;
+
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::models::{playback::AudioCodec, track::playback::StreamProperties},
+    error::TidalError,
+};
+
+/// Parsed MPD document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Mpd {
+    pub media_presentation_duration: Option<Duration>,
+    pub base_urls: Vec<String>,
+    pub periods: Vec<Period>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Period {
+    pub id: Option<String>,
+    pub duration: Option<Duration>,
+    pub base_urls: Vec<String>,
+    pub segment_template: Option<SegmentTemplate>,
+    pub adaptation_sets: Vec<AdaptationSet>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdaptationSet {
+    pub id: Option<String>,
+    pub content_type: Option<String>,
+    pub mime_type: Option<String>,
+    pub codecs: Option<String>,
+    pub audio_channel_configuration: Option<AudioChannelConfiguration>,
+    pub base_urls: Vec<String>,
+    pub segment_template: Option<SegmentTemplate>,
+    pub representations: Vec<Representation>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Representation {
+    pub id: Option<String>,
+    pub mime_type: Option<String>,
+    pub codecs: Option<String>,
+    pub bandwidth: Option<u64>,
+    pub audio_sampling_rate: Option<u32>,
+    pub audio_channel_configuration: Option<AudioChannelConfiguration>,
+    pub base_urls: Vec<String>,
+    pub segment_template: Option<SegmentTemplate>,
+}
+
+/// `AudioChannelConfiguration` element
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioChannelConfiguration {
+    pub scheme_id_uri: Option<String>,
+    /// Channel count, or a channel mask like `F801` for Dolby schemes
+    pub value: String,
+}
+
+impl AudioChannelConfiguration {
+    /// Number of channels, from a plain count or a Dolby 2011 channel mask
+    ///
+    /// `None` for schemes tidlers can't decode, like the AC-4 mask.
+    pub fn channels(&self) -> Option<u8> {
+        let scheme = self.scheme_id_uri.as_deref().unwrap_or_default();
+        if scheme.contains("dolby") {
+            if !scheme.ends_with(":2011") {
+                return None;
+            }
+            // bits from the most significant one: L, C, R, Ls, Rs, Lc/Rc, Lrs/Rrs, Cs, Ts,
+            // Lsd/Rsd, Lw/Rw, Vhl/Vhr, Vhc, Lts/Rts, LFE2, LFE
+            const PAIRS: u16 = 0b0000_0110_0111_0100;
+            let mask = u16::from_str_radix(&self.value, 16).ok()?;
+            let channels = mask.count_ones() + (mask & PAIRS).count_ones();
+            return u8::try_from(channels).ok();
+        }
+
+        self.value.parse().ok()
+    }
+}
+
+/// `SegmentTemplate` element, attributes missing on a level are inherited from the parent level
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SegmentTemplate {
+    pub initialization: Option<String>,
+    pub media: Option<String>,
+    pub timescale: Option<u64>,
+    /// Duration of every segment in `timescale` units, used when there is no timeline
+    pub duration: Option<u64>,
+    pub start_number: Option<u64>,
+    pub presentation_time_offset: Option<u64>,
+    pub timeline: Vec<TimelineEntry>,
+}
+
+/// `S` element of a `SegmentTimeline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    /// Start time in `timescale` units, continues from the previous entry if absent
+    pub t: Option<u64>,
+    /// Duration in `timescale` units
+    pub d: u64,
+    /// Number of additional repeats, `-1` repeats until the next entry or the end of the period
+    pub r: i64,
+}
+
+impl SegmentTemplate {
+    /// Returns `self` with missing attributes taken from `parent`
+    fn inherit(&self, parent: &SegmentTemplate) -> SegmentTemplate {
+        SegmentTemplate {
+            initialization: self
+                .initialization
+                .clone()
+                .or_else(|| parent.initialization.clone()),
+            media: self.media.clone().or_else(|| parent.media.clone()),
+            timescale: self.timescale.or(parent.timescale),
+            duration: self.duration.or(parent.duration),
+            start_number: self.start_number.or(parent.start_number),
+            presentation_time_offset: self
+                .presentation_time_offset
+                .or(parent.presentation_time_offset),
+            timeline: if self.timeline.is_empty() {
+                parent.timeline.clone()
+            } else {
+                self.timeline.clone()
+            },
+        }
+    }
+}
+
+/// A single media segment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    pub number: u64,
+    /// Start time in `timescale` units
+    pub time: u64,
+    pub duration: Duration,
+    pub url: String,
+}
+
+/// Every URL needed to fetch a representation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentList {
+    pub initialization: Option<String>,
+    pub segments: Vec<Segment>,
+}
+
+impl SegmentList {
+    /// Sum of all segment durations
+    pub fn total_duration(&self) -> Duration {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+}
+
+/// A representation together with the period and adaptation set it belongs to
+#[derive(Debug, Clone, Copy)]
+pub struct RepresentationRef<'a> {
+    pub mpd: &'a Mpd,
+    pub period: &'a Period,
+    pub adaptation_set: &'a AdaptationSet,
+    pub representation: &'a Representation,
+}
+
+impl<'a> RepresentationRef<'a> {
+    pub fn codecs(&self) -> Option<&'a str> {
+        self.representation
+            .codecs
+            .as_deref()
+            .or(self.adaptation_set.codecs.as_deref())
+    }
+
+    pub fn mime_type(&self) -> Option<&'a str> {
+        self.representation
+            .mime_type
+            .as_deref()
+            .or(self.adaptation_set.mime_type.as_deref())
+    }
+
+    /// Codec family of [`Self::codecs`], e.g. [`AudioCodec::Eac3`] for Dolby Atmos streams
+    pub fn audio_codec(&self) -> Option<AudioCodec> {
+        self.codecs().map(AudioCodec::parse)
+    }
+
+    pub fn audio_channel_configuration(&self) -> Option<&'a AudioChannelConfiguration> {
+        self.representation
+            .audio_channel_configuration
+            .as_ref()
+            .or(self.adaptation_set.audio_channel_configuration.as_ref())
+    }
+
+    /// Codec, sample rate, bit depth and channel count from the representation's attributes
+    ///
+    /// TIDAL names representations `CODEC,SAMPLE_RATE,BIT_DEPTH` (e.g. `FLAC,96000,24`), which
+    /// fills in what the standard attributes leave out.
+    pub fn stream_properties(&self) -> StreamProperties {
+        let mut from_id = self
+            .representation
+            .id
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .skip(1);
+        let id_sample_rate = from_id.next().and_then(|rate| rate.parse().ok());
+        let id_bit_depth = from_id.next().and_then(|depth| depth.parse().ok());
+
+        StreamProperties {
+            codec: self.audio_codec(),
+            sample_rate: self.representation.audio_sampling_rate.or(id_sample_rate),
+            bit_depth: id_bit_depth,
+            channels: self
+                .audio_channel_configuration()
+                .and_then(AudioChannelConfiguration::channels),
+        }
+    }
+
+    /// Base URL after resolving every `BaseURL` level from the MPD down to the representation
+    pub fn base_url(&self) -> Option<String> {
+        [
+            &self.mpd.base_urls,
+            &self.period.base_urls,
+            &self.adaptation_set.base_urls,
+            &self.representation.base_urls,
+        ]
+        .into_iter()
+        .filter_map(|urls| urls.first())
+        .fold(None, |base, url| Some(resolve_url(base.as_deref(), url)))
+    }
+
+    /// Segment template after inheriting attributes from the adaptation set and period
+    pub fn segment_template(&self) -> SegmentTemplate {
+        let default = SegmentTemplate::default();
+        let period = self.period.segment_template.as_ref().unwrap_or(&default);
+        let set = self
+            .adaptation_set
+            .segment_template
+            .as_ref()
+            .map_or_else(|| period.clone(), |set| set.inherit(period));
+
+        match &self.representation.segment_template {
+            Some(template) => template.inherit(&set),
+            None => set,
+        }
+    }
+
+    /// Enumerates the initialization URL and every media segment of this representation
+    pub fn segments(&self) -> Result<SegmentList, TidalError> {
+        let template = self.segment_template();
+        let base_url = self.base_url();
+        let media = template.media.as_deref().ok_or_else(|| {
+            TidalError::InvalidManifest("segment template has no media attribute".to_string())
+        })?;
+        let timescale = template.timescale.unwrap_or(1).max(1);
+        let start_number = template.start_number.unwrap_or(1);
+
+        let period_duration = self
+            .period
+            .duration
+            .or(self.mpd.media_presentation_duration)
+            .map(|duration| (duration.as_secs_f64() * timescale as f64).round() as u64);
+
+        let mut timing = Vec::new();
+        if template.timeline.is_empty() {
+            let (Some(duration), Some(total)) = (template.duration, period_duration) else {
+                return Err(TidalError::InvalidManifest(
+                    "segment count can't be determined without a timeline or durations".to_string(),
+                ));
+            };
+            if duration == 0 {
+                return Err(TidalError::InvalidManifest(
+                    "segment duration is zero".to_string(),
+                ));
+            }
+
+            let mut time = 0;
+            while time < total {
+                timing.push((time, duration.min(total - time)));
+                time += duration;
+            }
+        } else {
+            let mut time = template.timeline[0]
+                .t
+                .unwrap_or(template.presentation_time_offset.unwrap_or(0));
+            for (index, entry) in template.timeline.iter().enumerate() {
+                if let Some(t) = entry.t {
+                    time = t;
+                }
+                if entry.d == 0 {
+                    return Err(TidalError::InvalidManifest(
+                        "timeline entry has zero duration".to_string(),
+                    ));
+                }
+
+                let repeats = if entry.r >= 0 {
+                    entry.r as u64
+                } else {
+                    let end = template
+                        .timeline
+                        .get(index + 1)
+                        .and_then(|next| next.t)
+                        .or(period_duration)
+                        .ok_or_else(|| {
+                            TidalError::InvalidManifest(
+                                "open ended timeline repeat without a period duration".to_string(),
+                            )
+                        })?;
+                    end.saturating_sub(time).div_ceil(entry.d).saturating_sub(1)
+                };
+
+                for _ in 0..=repeats {
+                    timing.push((time, entry.d));
+                    time += entry.d;
+                }
+            }
+        }
+
+        let representation_id = self.representation.id.as_deref().unwrap_or_default();
+        let bandwidth = self.representation.bandwidth.unwrap_or_default();
+        let segments = timing
+            .into_iter()
+            .enumerate()
+            .map(|(index, (time, duration))| {
+                let number = start_number + index as u64;
+                let path = fill_template(media, representation_id, bandwidth, number, time);
+                Segment {
+                    number,
+                    time,
+                    duration: Duration::from_secs_f64(duration as f64 / timescale as f64),
+                    url: resolve_url(base_url.as_deref(), &path),
+                }
+            })
+            .collect();
+
+        let initialization = template.initialization.as_deref().map(|init| {
+            let path = fill_template(init, representation_id, bandwidth, start_number, 0);
+            resolve_url(base_url.as_deref(), &path)
+        });
+
+        Ok(SegmentList {
+            initialization,
+            segments,
+        })
+    }
+}
+
+impl Mpd {
+    /// Parses an MPD XML document
+    pub fn parse(xml: &str) -> Result<Self, TidalError> {
+        MpdParser::default().parse(xml)
+    }
+
+    /// Iterates over every representation of every period
+    pub fn representations(&self) -> impl Iterator<Item = RepresentationRef<'_>> {
+        self.periods.iter().flat_map(move |period| {
+            period
+                .adaptation_sets
+                .iter()
+                .flat_map(move |adaptation_set| {
+                    adaptation_set
+                        .representations
+                        .iter()
+                        .map(move |representation| RepresentationRef {
+                            mpd: self,
+                            period,
+                            adaptation_set,
+                            representation,
+                        })
+                })
+        })
+    }
+
+    /// Returns the representation with the highest bandwidth
+    pub fn best_representation(&self) -> Option<RepresentationRef<'_>> {
+        self.representations()
+            .max_by_key(|r| r.representation.bandwidth.unwrap_or_default())
+    }
+}
+
+/// Which element the text of the currently open `BaseURL` belongs to
+#[derive(Clone, Copy)]
+enum Level {
+    Mpd,
+    Period,
+    AdaptationSet,
+    Representation,
+}
+
+#[derive(Default)]
+struct MpdParser {
+    mpd: Mpd,
+    open: Vec<Level>,
+    template_level: Option<Level>,
+    base_url: Option<String>,
+}
+
+impl MpdParser {
+    fn parse(mut self, xml: &str) -> Result<Mpd, TidalError> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) => self.start(&e, false)?,
+                Ok(Event::Empty(e)) => self.start(&e, true)?,
+                Ok(Event::End(e)) => match e.local_name().as_ref() {
+                    b"MPD" | b"Period" | b"AdaptationSet" | b"Representation" => {
+                        self.open.pop();
+                    }
+                    b"SegmentTemplate" => self.template_level = None,
+                    b"BaseURL" => {
+                        if let Some(url) = self.base_url.take() {
+                            self.push_base_url(url.trim().to_string());
+                        }
+                    }
+                    _ => {}
+                },
+                Ok(Event::Text(e)) => {
+                    if let Some(url) = &mut self.base_url {
+                        url.push_str(&e.decode().map_err(xml_error)?);
+                    }
+                }
+                Ok(Event::GeneralRef(e)) => {
+                    if let Some(url) = &mut self.base_url {
+                        let entity = format!("&{};", e.decode().map_err(xml_error)?);
+                        url.push_str(&quick_xml::escape::unescape(&entity).map_err(xml_error)?);
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(e) => return Err(xml_error(e)),
+                _ => {}
+            }
+        }
+
+        if self.mpd.periods.is_empty() {
+            return Err(TidalError::InvalidManifest(
+                "MPD doesn't contain any period".to_string(),
+            ));
+        }
+
+        Ok(self.mpd)
+    }
+
+    fn start(&mut self, e: &BytesStart<'_>, empty: bool) -> Result<(), TidalError> {
+        let attrs = Attributes::read(e)?;
+        let level = match e.local_name().as_ref() {
+            b"MPD" => {
+                self.mpd.media_presentation_duration = attrs
+                    .get("mediaPresentationDuration")
+                    .and_then(parse_iso_duration);
+                Some(Level::Mpd)
+            }
+            b"Period" => {
+                self.mpd.periods.push(Period {
+                    id: attrs.get("id").map(str::to_string),
+                    duration: attrs.get("duration").and_then(parse_iso_duration),
+                    ..Default::default()
+                });
+                Some(Level::Period)
+            }
+            b"AdaptationSet" => {
+                let set = AdaptationSet {
+                    id: attrs.get("id").map(str::to_string),
+                    content_type: attrs.get("contentType").map(str::to_string),
+                    mime_type: attrs.get("mimeType").map(str::to_string),
+                    codecs: attrs.get("codecs").map(str::to_string),
+                    ..Default::default()
+                };
+                self.period()?.adaptation_sets.push(set);
+                Some(Level::AdaptationSet)
+            }
+            b"Representation" => {
+                let representation = Representation {
+                    id: attrs.get("id").map(str::to_string),
+                    mime_type: attrs.get("mimeType").map(str::to_string),
+                    codecs: attrs.get("codecs").map(str::to_string),
+                    bandwidth: attrs.parse("bandwidth"),
+                    audio_sampling_rate: attrs.parse("audioSamplingRate"),
+                    ..Default::default()
+                };
+                self.adaptation_set()?.representations.push(representation);
+                Some(Level::Representation)
+            }
+            b"SegmentTemplate" => {
+                let template = SegmentTemplate {
+                    initialization: attrs.get("initialization").map(str::to_string),
+                    media: attrs.get("media").map(str::to_string),
+                    timescale: attrs.parse("timescale"),
+                    duration: attrs.parse("duration"),
+                    start_number: attrs.parse("startNumber"),
+                    presentation_time_offset: attrs.parse("presentationTimeOffset"),
+                    timeline: Vec::new(),
+                };
+                let level = self.open.last().copied().unwrap_or(Level::Mpd);
+                *self.template_slot(level)? = Some(template);
+                if !empty {
+                    self.template_level = Some(level);
+                }
+                None
+            }
+            b"S" => {
+                let Some(level) = self.template_level else {
+                    return Ok(());
+                };
+                let entry = TimelineEntry {
+                    t: attrs.parse("t"),
+                    d: attrs.parse("d").ok_or_else(|| {
+                        TidalError::InvalidManifest("timeline entry without duration".to_string())
+                    })?,
+                    r: attrs.parse("r").unwrap_or(0),
+                };
+                if let Some(template) = self.template_slot(level)? {
+                    template.timeline.push(entry);
+                }
+                None
+            }
+            b"AudioChannelConfiguration" => {
+                let value = attrs.get("value").map(|value| AudioChannelConfiguration {
+                    scheme_id_uri: attrs.get("schemeIdUri").map(str::to_string),
+                    value: value.to_string(),
+                });
+                match self.open.last() {
+                    Some(Level::Representation) => {
+                        self.representation()?.audio_channel_configuration = value;
+                    }
+                    Some(Level::AdaptationSet) => {
+                        self.adaptation_set()?.audio_channel_configuration = value;
+                    }
+                    _ => {}
+                }
+                None
+            }
+            b"BaseURL" if !empty => {
+                self.base_url = Some(String::new());
+                None
+            }
+            _ => None,
+        };
+
+        if let Some(level) = level
+            && !empty
+        {
+            self.open.push(level);
+        }
+
+        Ok(())
+    }
+
+    fn period(&mut self) -> Result<&mut Period, TidalError> {
+        self.mpd
+            .periods
+            .last_mut()
+            .ok_or_else(|| TidalError::InvalidManifest("element outside of a period".to_string()))
+    }
+
+    fn adaptation_set(&mut self) -> Result<&mut AdaptationSet, TidalError> {
+        self.period()?.adaptation_sets.last_mut().ok_or_else(|| {
+            TidalError::InvalidManifest("representation outside of an adaptation set".to_string())
+        })
+    }
+
+    fn representation(&mut self) -> Result<&mut Representation, TidalError> {
+        self.adaptation_set()?
+            .representations
+            .last_mut()
+            .ok_or_else(|| TidalError::InvalidManifest("missing representation".to_string()))
+    }
+
+    fn template_slot(&mut self, level: Level) -> Result<&mut Option<SegmentTemplate>, TidalError> {
+        match level {
+            Level::Mpd => Err(TidalError::InvalidManifest(
+                "segment template outside of a period".to_string(),
+            )),
+            Level::Period => Ok(&mut self.period()?.segment_template),
+            Level::AdaptationSet => Ok(&mut self.adaptation_set()?.segment_template),
+            Level::Representation => Ok(&mut self.representation()?.segment_template),
+        }
+    }
+
+    fn push_base_url(&mut self, url: String) {
+        if url.is_empty() {
+            return;
+        }
+
+        let urls = match self.open.last().copied().unwrap_or(Level::Mpd) {
+            Level::Mpd => Some(&mut self.mpd.base_urls),
+            Level::Period => self.period().ok().map(|p| &mut p.base_urls),
+            Level::AdaptationSet => self.adaptation_set().ok().map(|s| &mut s.base_urls),
+            Level::Representation => self.representation().ok().map(|r| &mut r.base_urls),
+        };
+        if let Some(urls) = urls {
+            urls.push(url);
+        }
+    }
+}
+
+/// Unescaped attributes of an element
+struct Attributes(Vec<(String, String)>);
+
+impl Attributes {
+    fn read(e: &BytesStart<'_>) -> Result<Self, TidalError> {
+        let mut attrs = Vec::new();
+        for attr in e.attributes() {
+            let attr = attr.map_err(xml_error)?;
+            let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).to_string();
+            let value = attr
+                .normalized_value(quick_xml::XmlVersion::Implicit1_0)
+                .map_err(xml_error)?
+                .to_string();
+            attrs.push((key, value));
+        }
+        Ok(Self(attrs))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn parse<T: std::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| value.trim().parse().ok())
+    }
+}
+
+fn xml_error(e: impl std::fmt::Display) -> TidalError {
+    TidalError::InvalidManifest(format!("XML parsing error: {}", e))
+}
+
+/// Resolves `url` against `base`, relative paths are appended when `base` isn't an absolute URL
+pub(crate) fn resolve_url(base: Option<&str>, url: &str) -> String {
+    let Some(base) = base else {
+        return url.to_string();
+    };
+
+    if let Ok(base) = url::Url::parse(base) {
+        return base
+            .join(url)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| url.to_string());
+    }
+
+    if url::Url::parse(url).is_ok() {
+        return url.to_string();
+    }
+
+    match base.rfind('/') {
+        Some(index) => format!("{}{}", &base[..=index], url),
+        None => url.to_string(),
+    }
+}
+
+/// Substitutes `$RepresentationID$`, `$Number$`, `$Time$`, `$Bandwidth$` (optionally with a
+/// `%0Nd` width) and `$$` in a segment template
+fn fill_template(
+    template: &str,
+    representation_id: &str,
+    bandwidth: u64,
+    number: u64,
+    time: u64,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+
+        let identifier = &after[..end];
+        let (name, width) = match identifier.split_once('%') {
+            Some((name, format)) => (
+                name,
+                format
+                    .strip_prefix('0')
+                    .and_then(|f| f.strip_suffix('d'))
+                    .and_then(|w| w.parse::<usize>().ok()),
+            ),
+            None => (identifier, None),
+        };
+        let value = match name {
+            "" => Some("$".to_string()),
+            "RepresentationID" => Some(representation_id.to_string()),
+            "Number" => Some(number.to_string()),
+            "Time" => Some(time.to_string()),
+            "Bandwidth" => Some(bandwidth.to_string()),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => {
+                let width = width.unwrap_or(0);
+                out.push_str(&format!("{value:0>width$}"));
+            }
+            // unknown identifiers are left untouched
+            None => {
+                out.push('$');
+                out.push_str(identifier);
+                out.push('$');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Parses an ISO 8601 duration such as `PT3M2.293S` or `P1DT2H`
+pub fn parse_iso_duration(value: &str) -> Option<Duration> {
+    let rest = value.trim().strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) => (date, time),
+        None => (rest, ""),
+    };
+
+    let mut seconds = 0.0;
+    for (part, units) in [
+        (date, &[('W', 604_800.0), ('D', 86_400.0)][..]),
+        (time, &[('H', 3_600.0), ('M', 60.0), ('S', 1.0)][..]),
+    ] {
+        let mut number = String::new();
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                number.push(c);
+                continue;
+            }
+
+            let (_, factor) = units.iter().find(|(unit, _)| *unit == c)?;
+            seconds += number.parse::<f64>().ok()? * factor;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Mpd, fill_template, parse_iso_duration};
+    use crate::client::models::playback::AudioCodec;
+
+    const TIMELINE_MPD: &str = r#"<?xml version='1.0' encoding='UTF-8'?>
+        <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT9.5S">
+          <Period id="0">
+            <AdaptationSet id="0" contentType="audio" mimeType="audio/mp4">
+              <Representation id="FLAC,44100,16" codecs="flac" bandwidth="1000000" audioSamplingRate="44100">
+                <SegmentTemplate timescale="44100" initialization="https://cdn.example.com/t/0.mp4?token=a&amp;b=1" media="https://cdn.example.com/t/$Number$.mp4?token=a&amp;b=1" startNumber="1">
+                  <SegmentTimeline>
+                    <S d="176400" r="1"/>
+                    <S d="66150"/>
+                  </SegmentTimeline>
+                </SegmentTemplate>
+              </Representation>
+              <Representation id="FLAC,96000,24" codecs="flac" bandwidth="4000000" audioSamplingRate="96000">
+                <BaseURL>https://cdn.example.com/hires/</BaseURL>
+                <SegmentTemplate timescale="96000" initialization="init.mp4" media="seg-$Number%03d$.mp4" startNumber="0">
+                  <SegmentTimeline>
+                    <S t="0" d="384000" r="-1"/>
+                  </SegmentTimeline>
+                </SegmentTemplate>
+              </Representation>
+            </AdaptationSet>
+          </Period>
+        </MPD>"#;
+
+    #[test]
+    fn expands_timeline_repeats_and_unescapes_urls() {
+        let mpd = Mpd::parse(TIMELINE_MPD).expect("mpd should parse");
+        let representation = mpd.representations().next().expect("representation");
+
+        let list = representation.segments().expect("segments");
+        assert_eq!(
+            list.initialization.as_deref(),
+            Some("https://cdn.example.com/t/0.mp4?token=a&b=1")
+        );
+        let numbers: Vec<u64> = list.segments.iter().map(|s| s.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        assert_eq!(
+            list.segments[2].url,
+            "https://cdn.example.com/t/3.mp4?token=a&b=1"
+        );
+        assert_eq!(list.segments[1].time, 176_400);
+        assert_eq!(list.total_duration(), Duration::from_millis(9_500));
+    }
+
+    #[test]
+    fn open_ended_repeat_runs_until_presentation_end() {
+        let mpd = Mpd::parse(TIMELINE_MPD).expect("mpd should parse");
+        let best = mpd.best_representation().expect("representation");
+        assert_eq!(best.representation.bandwidth, Some(4_000_000));
+
+        let list = best.segments().expect("segments");
+        // 9.5s of 4s segments
+        assert_eq!(list.segments.len(), 3);
+        assert_eq!(
+            list.initialization.as_deref(),
+            Some("https://cdn.example.com/hires/init.mp4")
+        );
+        assert_eq!(
+            list.segments[0].url,
+            "https://cdn.example.com/hires/seg-000.mp4"
+        );
+        assert_eq!(best.codecs(), Some("flac"));
+        assert_eq!(best.mime_type(), Some("audio/mp4"));
+
+        let properties = best.stream_properties();
+        assert_eq!(properties.codec, Some(AudioCodec::Flac));
+        assert_eq!(properties.sample_rate, Some(96_000));
+        assert_eq!(properties.bit_depth, Some(24));
+    }
+
+    #[test]
+    fn fixed_duration_template_inherits_from_adaptation_set() {
+        let xml = r#"
+            <MPD mediaPresentationDuration="PT5S">
+              <BaseURL>https://cdn.example.com/a/</BaseURL>
+              <Period>
+                <AdaptationSet mimeType="audio/mp4">
+                  <SegmentTemplate timescale="1000" duration="2000" media="$RepresentationID$/$Number$.m4s" initialization="$RepresentationID$/init.mp4" />
+                  <Representation id="r1" codecs="mp4a.40.2" bandwidth="320000" />
+                </AdaptationSet>
+              </Period>
+            </MPD>
+        "#;
+
+        let mpd = Mpd::parse(xml).expect("mpd should parse");
+        let list = mpd
+            .best_representation()
+            .expect("representation")
+            .segments()
+            .expect("segments");
+
+        assert_eq!(list.segments.len(), 3);
+        assert_eq!(list.segments[2].url, "https://cdn.example.com/a/r1/3.m4s");
+        assert_eq!(list.segments[2].duration, Duration::from_secs(1));
+        assert_eq!(
+            list.initialization.as_deref(),
+            Some("https://cdn.example.com/a/r1/init.mp4")
+        );
+    }
+
+    #[test]
+    fn immersive_representations_keep_codec_and_channel_configuration() {
+        let xml = r#"
+            <MPD mediaPresentationDuration="PT4S">
+              <Period>
+                <AdaptationSet mimeType="audio/mp4" codecs="ac-4.02.01.00">
+                  <AudioChannelConfiguration
+                    schemeIdUri="tag:dolby.com,2015:dash:audio_channel_configuration:2015"
+                    value="0000C7" />
+                  <Representation id="ac4" bandwidth="448000" />
+                  <Representation id="ec3" codecs="ec-3" bandwidth="768000">
+                    <AudioChannelConfiguration
+                      schemeIdUri="tag:dolby.com,2014:dash:audio_channel_configuration:2011"
+                      value="F801" />
+                  </Representation>
+                </AdaptationSet>
+              </Period>
+            </MPD>
+        "#;
+
+        let mpd = Mpd::parse(xml).expect("mpd should parse");
+        let reps: Vec<_> = mpd.representations().collect();
+
+        assert_eq!(reps[0].audio_codec(), Some(AudioCodec::Ac4));
+        let ac4 = reps[0]
+            .audio_channel_configuration()
+            .expect("configuration");
+        assert_eq!(ac4.value, "0000C7");
+        assert_eq!(ac4.channels(), None);
+        let best = mpd.best_representation().expect("representation");
+        assert_eq!(best.audio_codec(), Some(AudioCodec::Eac3));
+        assert_eq!(best.stream_properties().channels, Some(6));
+    }
+
+    #[test]
+    fn parses_iso_durations() {
+        assert_eq!(
+            parse_iso_duration("PT3M2.293S"),
+            Some(Duration::from_millis(182_293))
+        );
+        assert_eq!(
+            parse_iso_duration("P1DT1H"),
+            Some(Duration::from_secs(90_000))
+        );
+        assert_eq!(parse_iso_duration("PT"), Some(Duration::ZERO));
+        assert_eq!(parse_iso_duration("3M"), None);
+        assert_eq!(parse_iso_duration("PT5X"), None);
+    }
+
+    #[test]
+    fn fills_template_identifiers() {
+        assert_eq!(
+            fill_template(
+                "$RepresentationID$/$Number%05d$-$Time$-$$.mp4",
+                "a",
+                1,
+                42,
+                7
+            ),
+            "a/00042-7-$.mp4"
+        );
+        assert_eq!(
+            fill_template("$Unknown$-$Number$", "a", 1, 3, 0),
+            "$Unknown$-3"
+        );
+    }
+}
@@ -1,11 +1,20 @@
+use crate::{
+    client::models::{
+        media::QualityTag,
+        playback::{AudioCodec, AudioMode},
+        track::mpd::{Mpd, SegmentList},
+    },
+    error::TidalError,
+};
+
 /// Response containing track playback information including manifest data
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TrackPlaybackInfoResponse {
     pub track_id: u64,
     pub asset_presentation: String,
-    pub audio_mode: String,
-    pub audio_quality: String,
+    pub audio_mode: QualityTag,
+    pub audio_quality: QualityTag,
     pub manifest_mime_type: String,
     pub manifest_hash: String,
     #[serde(skip_deserializing, default)]
@@ -16,6 +25,11 @@ pub struct TrackPlaybackInfoResponse {
     pub album_peak_amplitude: f64,
     pub track_replay_gain: f64,
     pub track_peak_amplitude: f64,
+    /// Sent alongside `HI_RES_LOSSLESS` and `LOSSLESS` streams
+    #[serde(default)]
+    pub bit_depth: Option<u8>,
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
 }
 
 impl TrackPlaybackInfoResponse {
@@ -65,6 +79,55 @@ impl TrackPlaybackInfoResponse {
             ParsedTrackManifest::Dash(dash_manifest) => dash_manifest.codecs.clone(),
         })
     }
+
+    /// Codec of the stream, e.g. [`AudioCodec::Eac3`] for Dolby Atmos
+    pub fn audio_codec(&self) -> Option<AudioCodec> {
+        self.get_codecs().map(|codecs| AudioCodec::parse(&codecs))
+    }
+
+    /// Codec, sample rate, bit depth and channel count of the stream
+    ///
+    /// The playback info's `sampleRate` and `bitDepth` take precedence over the DASH
+    /// representation, progressive `LOSSLESS` FLAC is CD quality and stereo streams have two
+    /// channels.
+    pub fn stream_properties(&self) -> StreamProperties {
+        let mut properties = match &self.manifest_parsed {
+            Some(ParsedTrackManifest::Dash(dash)) => dash
+                .mpd
+                .as_ref()
+                .and_then(Mpd::best_representation)
+                .map(|best| best.stream_properties())
+                .unwrap_or_default(),
+            _ => StreamProperties::default(),
+        };
+        properties.codec = properties.codec.or_else(|| self.audio_codec());
+        properties.sample_rate = self.sample_rate.or(properties.sample_rate);
+        properties.bit_depth = self.bit_depth.or(properties.bit_depth);
+
+        if self.audio_quality == QualityTag::Lossless && properties.codec == Some(AudioCodec::Flac)
+        {
+            properties.sample_rate.get_or_insert(44_100);
+            properties.bit_depth.get_or_insert(16);
+        }
+        if self.audio_mode == QualityTag::Stereo {
+            properties.channels.get_or_insert(2);
+        }
+        properties
+    }
+
+    /// Audio mode of the delivered stream, `None` for modes tidlers doesn't know
+    pub fn audio_mode(&self) -> Option<AudioMode> {
+        self.audio_mode.audio_mode()
+    }
+}
+
+/// Codec, sample rate, bit depth and channel count of a stream, as far as they're known
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamProperties {
+    pub codec: Option<AudioCodec>,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
 }
 
 /// JSON manifest containing track streaming information
@@ -74,6 +137,9 @@ pub struct JsonTrackManifest {
     pub mime_type: String,
     pub codecs: String,
     pub encryption_type: String,
+    /// Security token holding the stream key of `OLD_AES` streams, see [`crate::decrypt`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
     pub urls: Vec<String>,
 }
 
@@ -105,6 +171,9 @@ pub struct DashManifest {
         rename = "startNumber"
     )]
     pub start_number: Option<u32>,
+    /// Full MPD the fields above were taken from
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub mpd: Option<Mpd>,
 }
 
 impl DashManifest {
@@ -138,6 +207,7 @@ impl DashManifest {
     /// #     timescale: None,
     /// #     duration: None,
     /// #     start_number: None,
+    /// #     mpd: None,
     /// # };
     /// if let Some(url) = manifest.get_segment_url(1) {
     ///     println!("Segment 1: {}", url); // "segment_1.m4s"
@@ -147,4 +217,16 @@ impl DashManifest {
         self.get_media_template()
             .map(|template| template.replace("$Number$", &segment_number.to_string()))
     }
+
+    /// Lists the initialization URL and every media segment of the highest bandwidth
+    /// representation, as described by the MPD's segment template and timeline
+    pub fn segments(&self) -> Result<SegmentList, TidalError> {
+        self.mpd
+            .as_ref()
+            .and_then(Mpd::best_representation)
+            .ok_or_else(|| {
+                TidalError::InvalidManifest("DASH manifest has no representation".to_string())
+            })?
+            .segments()
+    }
 }
@@ -0,0 +1,71 @@
+//! Typed report of a quality negotiation, see [`TidalClient::negotiate_track_playback`]
+//!
+//! [`TidalClient::negotiate_track_playback`]: crate::TidalClient::negotiate_track_playback
+
+use crate::client::models::{
+    playback::{AudioCodec, AudioQuality},
+    track::playback::TrackPlaybackInfoResponse,
+};
+
+/// What kept a negotiation from requesting the preferred quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum QualityLimit {
+    /// The track isn't available in a higher quality
+    Track,
+    /// The subscription's `highest_sound_quality` is lower
+    Subscription,
+    /// Device-code logins can't stream HiRes, log in with PKCE for that
+    Login,
+}
+
+/// Requested vs. delivered quality of a negotiated playback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityReport {
+    /// First entry of the preference list
+    pub requested: AudioQuality,
+    /// Quality that was asked for in the playback info request
+    pub negotiated: AudioQuality,
+    /// Quality TIDAL actually delivered, `None` if it sent a value tidlers doesn't know
+    pub delivered: Option<AudioQuality>,
+    /// Set when `requested` was above what the track, subscription or login allow
+    pub limited_by: Option<QualityLimit>,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    pub codec: Option<AudioCodec>,
+}
+
+impl QualityReport {
+    pub(crate) fn new(
+        requested: AudioQuality,
+        negotiated: AudioQuality,
+        limited_by: Option<QualityLimit>,
+        playback: &TrackPlaybackInfoResponse,
+    ) -> Self {
+        let properties = playback.stream_properties();
+        Self {
+            requested,
+            negotiated,
+            delivered: playback.audio_quality.audio_quality(),
+            limited_by,
+            sample_rate: properties.sample_rate,
+            bit_depth: properties.bit_depth,
+            channels: properties.channels,
+            codec: properties.codec,
+        }
+    }
+
+    /// Whether less than the preferred quality was delivered
+    pub fn is_downgraded(&self) -> bool {
+        self.delivered
+            .as_ref()
+            .is_none_or(|delivered| *delivered < self.requested)
+    }
+}
+
+/// Playback info together with the report of how its quality was chosen
+#[derive(Debug)]
+pub struct NegotiatedPlayback {
+    pub playback: TrackPlaybackInfoResponse,
+    pub report: QualityReport,
+}
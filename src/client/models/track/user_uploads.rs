@@ -1,14 +1,31 @@
-use crate::client::models::responses::ApiLinks;
+use crate::client::models::{jsonapi::Links, openapi::TrackResource, responses::ApiLinks};
 
 /// Response containing user uploaded tracks
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserUploadsResponse {
-    pub data: Vec<serde_json::Value>,
+    pub data: Vec<TrackResource>,
     #[serde(default)]
     pub included: Vec<UserUploadIncludedResource>,
     #[serde(default)]
-    pub links: Option<serde_json::Value>,
+    pub links: Option<Links>,
+}
+
+impl UserUploadsResponse {
+    /// Returns the cursor of the next page, if there is one
+    pub fn next_cursor(&self) -> Option<String> {
+        self.links.as_ref()?.next_cursor()
+    }
+
+    /// Returns the included `sourceFile` resource of an uploaded track
+    ///
+    /// Requires the response to be requested with `source_file` included.
+    pub fn source_file(&self, track: &TrackResource) -> Option<&UserUploadIncludedResource> {
+        let target = track.relationship("sourceFile")?.identifiers().first()?;
+        self.included
+            .iter()
+            .find(|resource| resource.id == target.id && resource.resource_type == target.kind)
+    }
 }
 
 /// Represents a user upload source file resource
@@ -87,3 +104,71 @@ pub struct UserUploadOwnerRelationship {
 
 /// Links for relationships
 pub type UserUploadRelationshipLinks = ApiLinks;
+
+#[cfg(test)]
+mod tests {
+    use super::UserUploadsResponse;
+
+    fn response(links: serde_json::Value) -> UserUploadsResponse {
+        UserUploadsResponse {
+            data: Vec::new(),
+            included: Vec::new(),
+            links: Some(serde_json::from_value(links).expect("links should parse")),
+        }
+    }
+
+    #[test]
+    fn next_cursor_prefers_meta_next_cursor() {
+        let res = response(serde_json::json!({
+            "meta": { "nextCursor": "abc" },
+            "next": "/tracks?page%5Bcursor%5D=other"
+        }));
+
+        assert_eq!(res.next_cursor().as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn next_cursor_falls_back_to_next_link() {
+        let res = response(serde_json::json!({
+            "next": "/tracks?countryCode=US&page%5Bcursor%5D=xyz%3D"
+        }));
+
+        assert_eq!(res.next_cursor().as_deref(), Some("xyz="));
+    }
+
+    #[test]
+    fn next_cursor_is_none_on_last_page() {
+        let res = response(serde_json::json!({ "self": "/tracks" }));
+
+        assert_eq!(res.next_cursor(), None);
+    }
+
+    #[test]
+    fn parses_typed_tracks_and_resolves_source_file() {
+        let res: UserUploadsResponse = serde_json::from_value(serde_json::json!({
+            "data": [{
+                "id": "1",
+                "type": "tracks",
+                "attributes": { "title": "Demo", "duration": "PT1M" },
+                "relationships": {
+                    "sourceFile": { "data": { "id": "sf1", "type": "trackSourceFiles" } }
+                }
+            }],
+            "included": [{
+                "id": "sf1",
+                "type": "trackSourceFiles",
+                "attributes": { "md5Hash": "abc", "size": 42 }
+            }],
+            "links": { "self": "/tracks", "next": "/tracks?page%5Bcursor%5D=xyz%3D" }
+        }))
+        .expect("response should parse");
+
+        assert_eq!(res.data[0].attributes.as_ref().unwrap().title, "Demo");
+        assert_eq!(res.next_cursor().as_deref(), Some("xyz="));
+
+        let source_file = res
+            .source_file(&res.data[0])
+            .expect("source file is included");
+        assert_eq!(source_file.attributes.as_ref().unwrap().size, Some(42));
+    }
+}
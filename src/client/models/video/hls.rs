@@ -0,0 +1,476 @@
+//! HLS playlist (m3u8) model, as returned for video playback
+//!
+//! The manifest of a video points to a master playlist listing one variant per resolution, every
+//! variant is a media playlist of MPEG-TS or fragmented MP4 segments. Only the tags needed to
+//! pick a variant and fetch its segments are modelled.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::models::{playback::VideoQuality, track::mpd::resolve_url},
+    error::TidalError,
+};
+
+/// Parsed HLS playlist
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+/// Playlist listing the variants of a stream
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+}
+
+/// `EXT-X-STREAM-INF` entry of a master playlist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+    /// Absolute URL of the variant's media playlist
+    pub url: String,
+    /// Peak bits per second
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub resolution: Option<Resolution>,
+    pub codecs: Option<String>,
+    pub frame_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Playlist listing the segments of one variant
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaPlaylist {
+    pub target_duration: Option<Duration>,
+    pub media_sequence: u64,
+    /// Absolute URL of the `EXT-X-MAP` initialization section, set for fragmented MP4 segments
+    pub initialization: Option<String>,
+    pub segments: Vec<MediaSegment>,
+    /// `EXT-X-ENDLIST` was present, no segments will be added later
+    pub ended: bool,
+}
+
+/// A single media segment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaSegment {
+    /// Media sequence number of the segment
+    pub sequence: u64,
+    pub duration: Duration,
+    /// Absolute URL of the segment
+    pub url: String,
+}
+
+/// Media playlist of the variant picked from a master playlist
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantPlaylist {
+    /// `None` if the manifest pointed to a media playlist directly
+    pub variant: Option<Variant>,
+    pub playlist: MediaPlaylist,
+}
+
+/// Which variant of a master playlist to pick, see [`MasterPlaylist::select`]
+#[derive(Debug, Clone, Default)]
+pub struct VariantSelection {
+    /// Caps the resolution the way the quality setting of TIDAL's own players does, see
+    /// [`VideoQuality::max_height`]
+    pub quality: Option<VideoQuality>,
+    /// Highest vertical resolution in pixels
+    pub max_height: Option<u32>,
+    /// Highest peak bandwidth in bits per second
+    pub max_bandwidth: Option<u64>,
+}
+
+impl Playlist {
+    /// Parses a master or media playlist, relative URIs are resolved against `url`
+    pub fn parse(text: &str, url: &str) -> Result<Self, TidalError> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some("#EXTM3U") {
+            return Err(TidalError::InvalidManifest(
+                "playlist doesn't start with #EXTM3U".to_string(),
+            ));
+        }
+
+        if text.contains("#EXT-X-STREAM-INF:") {
+            parse_master(lines, url).map(Playlist::Master)
+        } else {
+            parse_media(lines, url).map(Playlist::Media)
+        }
+    }
+}
+
+impl MasterPlaylist {
+    /// Picks the highest bandwidth variant within the limits of `selection`
+    ///
+    /// Variants without a `RESOLUTION` pass any height limit. If no variant is within the
+    /// limits the lowest bandwidth variant is picked, `None` only for an empty playlist.
+    pub fn select(&self, selection: &VariantSelection) -> Option<&Variant> {
+        let max_height = [
+            selection
+                .quality
+                .as_ref()
+                .and_then(VideoQuality::max_height),
+            selection.max_height,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        self.variants
+            .iter()
+            .filter(|variant| {
+                max_height.is_none_or(|max| {
+                    variant
+                        .resolution
+                        .is_none_or(|resolution| resolution.height <= max)
+                })
+            })
+            .filter(|variant| {
+                selection
+                    .max_bandwidth
+                    .is_none_or(|max| variant.bandwidth <= max)
+            })
+            .max_by_key(|variant| variant.bandwidth)
+            .or_else(|| self.variants.iter().min_by_key(|variant| variant.bandwidth))
+    }
+
+    /// Returns the variant with the highest bandwidth
+    pub fn best_variant(&self) -> Option<&Variant> {
+        self.variants.iter().max_by_key(|variant| variant.bandwidth)
+    }
+}
+
+impl MediaPlaylist {
+    /// Every URL of the variant in playback order, the initialization section first
+    pub fn urls(&self) -> impl Iterator<Item = String> + '_ {
+        self.initialization
+            .iter()
+            .cloned()
+            .chain(self.segments.iter().map(|segment| segment.url.clone()))
+    }
+
+    /// Sum of all segment durations
+    pub fn total_duration(&self) -> Duration {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+
+    /// Whether the segments are fragmented MP4 rather than MPEG-TS
+    pub fn is_fragmented_mp4(&self) -> bool {
+        self.initialization.is_some()
+    }
+}
+
+impl VideoQuality {
+    /// Highest vertical resolution TIDAL serves for this quality, `None` for no limit
+    pub fn max_height(&self) -> Option<u32> {
+        match self {
+            Self::Low => Some(480),
+            Self::Medium => Some(720),
+            Self::High => None,
+        }
+    }
+}
+
+fn parse_master<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    url: &str,
+) -> Result<MasterPlaylist, TidalError> {
+    let mut playlist = MasterPlaylist::default();
+    let mut pending: Option<Vec<(String, String)>> = None;
+
+    for line in lines {
+        if let Some(list) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attributes(list));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let Some(attributes) = pending.take() else {
+            continue;
+        };
+        let get = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        let bandwidth = get("BANDWIDTH")
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                TidalError::InvalidManifest("variant has no valid BANDWIDTH".to_string())
+            })?;
+        playlist.variants.push(Variant {
+            url: resolve_url(Some(url), line),
+            bandwidth,
+            average_bandwidth: get("AVERAGE-BANDWIDTH").and_then(|value| value.parse().ok()),
+            resolution: get("RESOLUTION").and_then(parse_resolution),
+            codecs: get("CODECS").map(str::to_string),
+            frame_rate: get("FRAME-RATE").and_then(|value| value.parse().ok()),
+        });
+    }
+
+    Ok(playlist)
+}
+
+fn parse_media<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    url: &str,
+) -> Result<MediaPlaylist, TidalError> {
+    let mut playlist = MediaPlaylist::default();
+    let mut duration = None;
+
+    for line in lines {
+        let Some(tag) = line.strip_prefix('#') else {
+            playlist.segments.push(MediaSegment {
+                sequence: playlist.media_sequence + playlist.segments.len() as u64,
+                duration: duration.take().unwrap_or_default(),
+                url: resolve_url(Some(url), line),
+            });
+            continue;
+        };
+
+        let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+        match name {
+            "EXTINF" => {
+                let seconds = value.split(',').next().unwrap_or_default().trim();
+                duration = Some(parse_seconds(seconds).ok_or_else(|| {
+                    TidalError::InvalidManifest(format!("invalid EXTINF duration: {seconds}"))
+                })?);
+            }
+            "EXT-X-TARGETDURATION" => playlist.target_duration = parse_seconds(value),
+            "EXT-X-MEDIA-SEQUENCE" => {
+                playlist.media_sequence = value.trim().parse().map_err(|_| {
+                    TidalError::InvalidManifest(format!("invalid media sequence: {value}"))
+                })?;
+            }
+            "EXT-X-MAP" => {
+                let attributes = parse_attributes(value);
+                if attributes.iter().any(|(key, _)| key == "BYTERANGE") {
+                    return Err(TidalError::InvalidManifest(
+                        "byte range initialization sections are not supported".to_string(),
+                    ));
+                }
+                let uri = attributes
+                    .into_iter()
+                    .find_map(|(key, value)| (key == "URI").then_some(value))
+                    .ok_or_else(|| {
+                        TidalError::InvalidManifest("EXT-X-MAP has no URI".to_string())
+                    })?;
+                playlist.initialization = Some(resolve_url(Some(url), &uri));
+            }
+            "EXT-X-KEY" => {
+                let method = parse_attributes(value)
+                    .into_iter()
+                    .find_map(|(key, value)| (key == "METHOD").then_some(value))
+                    .unwrap_or_default();
+                if method != "NONE" {
+                    return Err(TidalError::InvalidManifest(format!(
+                        "encrypted segments are not supported ({method})"
+                    )));
+                }
+            }
+            "EXT-X-BYTERANGE" => {
+                return Err(TidalError::InvalidManifest(
+                    "byte range segments are not supported".to_string(),
+                ));
+            }
+            "EXT-X-ENDLIST" => playlist.ended = true,
+            _ => {}
+        }
+    }
+
+    Ok(playlist)
+}
+
+/// Splits an attribute list like `BANDWIDTH=1280000,CODECS="avc1.4d401f,mp4a.40.2"`
+fn parse_attributes(list: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = list.trim();
+
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let next = quoted[end..].trim_start_matches('"');
+                (&quoted[..end], next)
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+
+        attributes.push((key.trim().to_string(), value.to_string()));
+        rest = next.trim_start_matches(',').trim_start();
+    }
+
+    attributes
+}
+
+fn parse_resolution(value: &str) -> Option<Resolution> {
+    let (width, height) = value.split_once(['x', 'X'])?;
+    Some(Resolution {
+        width: width.trim().parse().ok()?,
+        height: height.trim().parse().ok()?,
+    })
+}
+
+fn parse_seconds(value: &str) -> Option<Duration> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{MasterPlaylist, Playlist, Resolution, VariantSelection, parse_attributes};
+    use crate::{client::models::playback::VideoQuality, error::TidalError};
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,AVERAGE-BANDWIDTH=4500000,CODECS=\"avc1.640028,mp4a.40.2\",RESOLUTION=1920x1080,FRAME-RATE=25.000
+1080p/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,CODECS=\"avc1.64001f,mp4a.40.2\",RESOLUTION=1280x720
+720p/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360
+https://other.example.com/360p.m3u8
+";
+
+    fn master() -> MasterPlaylist {
+        match Playlist::parse(MASTER, "https://cdn.example.com/v/master.m3u8?token=a").unwrap() {
+            Playlist::Master(master) => master,
+            Playlist::Media(_) => panic!("expected a master playlist"),
+        }
+    }
+
+    #[test]
+    fn quoted_attribute_values_keep_their_commas() {
+        let attributes = parse_attributes(r#"BANDWIDTH=1,CODECS="a,b",RESOLUTION=2x3"#);
+        assert_eq!(
+            attributes,
+            [
+                ("BANDWIDTH".to_string(), "1".to_string()),
+                ("CODECS".to_string(), "a,b".to_string()),
+                ("RESOLUTION".to_string(), "2x3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_master_playlist_variants() {
+        let master = master();
+        assert_eq!(master.variants.len(), 3);
+
+        let best = master.best_variant().unwrap();
+        assert_eq!(best.url, "https://cdn.example.com/v/1080p/index.m3u8");
+        assert_eq!(best.average_bandwidth, Some(4_500_000));
+        assert_eq!(best.codecs.as_deref(), Some("avc1.640028,mp4a.40.2"));
+        assert_eq!(
+            best.resolution,
+            Some(Resolution {
+                width: 1920,
+                height: 1080
+            })
+        );
+        assert_eq!(best.frame_rate, Some(25.0));
+        assert_eq!(
+            master.variants[2].url,
+            "https://other.example.com/360p.m3u8"
+        );
+    }
+
+    #[test]
+    fn selects_variants_within_the_limits() {
+        let master = master();
+        let height = |selection: VariantSelection| {
+            master
+                .select(&selection)
+                .and_then(|variant| variant.resolution)
+                .map(|resolution| resolution.height)
+        };
+
+        assert_eq!(height(VariantSelection::default()), Some(1080));
+        assert_eq!(
+            height(VariantSelection {
+                quality: Some(VideoQuality::Medium),
+                ..Default::default()
+            }),
+            Some(720)
+        );
+        assert_eq!(
+            height(VariantSelection {
+                quality: Some(VideoQuality::High),
+                max_bandwidth: Some(1_000_000),
+                ..Default::default()
+            }),
+            Some(360)
+        );
+        // nothing fits, the smallest variant is the closest
+        assert_eq!(
+            height(VariantSelection {
+                max_height: Some(240),
+                ..Default::default()
+            }),
+            Some(360)
+        );
+    }
+
+    #[test]
+    fn parses_media_playlist_segments() {
+        let text = "#EXTM3U
+#EXT-X-VERSION:7
+#EXT-X-TARGETDURATION:4
+#EXT-X-MEDIA-SEQUENCE:5
+#EXT-X-PLAYLIST-TYPE:VOD
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXTINF:4.000,
+seg-5.m4s
+#EXTINF:2.5,
+seg-6.m4s
+#EXT-X-ENDLIST
+";
+        let Playlist::Media(media) =
+            Playlist::parse(text, "https://cdn.example.com/v/720p/index.m3u8").unwrap()
+        else {
+            panic!("expected a media playlist");
+        };
+
+        assert!(media.ended);
+        assert!(media.is_fragmented_mp4());
+        assert_eq!(media.target_duration, Some(Duration::from_secs(4)));
+        assert_eq!(media.segments[1].sequence, 6);
+        assert_eq!(media.total_duration(), Duration::from_millis(6_500));
+        assert_eq!(
+            media.urls().collect::<Vec<_>>(),
+            [
+                "https://cdn.example.com/v/720p/init.mp4",
+                "https://cdn.example.com/v/720p/seg-5.m4s",
+                "https://cdn.example.com/v/720p/seg-6.m4s",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_encrypted_and_malformed_playlists() {
+        let encrypted = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXTINF:4,\na.ts\n";
+        assert!(matches!(
+            Playlist::parse(encrypted, "https://cdn.example.com/"),
+            Err(TidalError::InvalidManifest(message)) if message.contains("AES-128")
+        ));
+
+        assert!(Playlist::parse("<MPD/>", "https://cdn.example.com/").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::client::models::{album::Album, artist::Artist};
 
 pub mod config;
+pub mod hls;
 pub mod playback;
 
 /// Represents a video
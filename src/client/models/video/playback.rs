@@ -12,6 +12,18 @@ pub struct VideoPlaybackInfoResponse {
     pub manifest: Option<EmuVideoManifest>,
 }
 
+impl VideoPlaybackInfoResponse {
+    /// Gets the URL of the HLS master playlist, see [`TidalClient::get_video_media_playlist`]
+    ///
+    /// [`TidalClient::get_video_media_playlist`]: crate::TidalClient::get_video_media_playlist
+    pub fn get_primary_url(&self) -> Option<String> {
+        self.manifest
+            .as_ref()
+            .and_then(|manifest| manifest.urls.first().cloned())
+    }
+}
+
+/// Manifest of a video stream, `urls` point to HLS playlists
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EmuVideoManifest {
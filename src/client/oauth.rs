@@ -1,7 +1,8 @@
-use std::{collections::HashMap, time::SystemTimeError};
+use std::{collections::HashMap, time::Duration, time::SystemTimeError};
 
+use futures_util::{Stream, stream};
 use reqwest::Method;
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, time::Instant};
 use tracing::{debug, info, warn};
 
 use crate::{
@@ -17,17 +18,94 @@ use crate::{
     },
     error::TidalError,
     requests::{self, TidalRequest},
-    urls::OAUTH2_V1_LOCATION,
 };
 
+/// Scopes requested by [`TidalClient::get_oauth_link`]
+pub const DEFAULT_OAUTH_SCOPES: &[&str] = &["r_usr", "w_usr", "w_sub"];
+
+/// How much a `slow_down` response grows the polling interval (RFC 8628 §3.5)
+const SLOW_DOWN_STEP: Duration = Duration::from_secs(5);
+
 /// Status updates during the OAuth flow
 #[derive(Debug, Clone, PartialEq)]
 pub enum OAuthStatus {
+    /// The user hasn't confirmed the code yet (`authorization_pending`)
     Waiting,
+    /// TIDAL asked to poll less often (`slow_down`), polling continues every `interval`
+    SlowDown {
+        interval: Duration,
+    },
+    /// The user declined the login (`access_denied`)
+    Denied,
+    /// The device code expired before the user confirmed it (`expired_token`)
+    Expired,
     Error(String),
     Success,
 }
 
+impl OAuthStatus {
+    /// Whether polling stops after this status
+    pub fn is_final(&self) -> bool {
+        !matches!(self, Self::Waiting | Self::SlowDown { .. })
+    }
+}
+
+/// Options for [`TidalClient::start_device_login`]
+#[derive(Debug, Clone)]
+pub struct DeviceLoginOptions {
+    /// OAuth scopes to request, [`DEFAULT_OAUTH_SCOPES`] by default
+    pub scopes: Vec<String>,
+}
+
+impl Default for DeviceLoginOptions {
+    fn default() -> Self {
+        Self {
+            scopes: DEFAULT_OAUTH_SCOPES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+/// A started device-code login, poll it with [`TidalClient::device_login_stream`]
+#[derive(Debug, Clone)]
+pub struct DeviceLogin {
+    /// Code and links to show to the user
+    pub authorization: OAuthDeviceAuthorizationResponse,
+    scope: String,
+    interval: Duration,
+    slow_down_step: Duration,
+}
+
+impl DeviceLogin {
+    /// Space separated scopes the login was started with
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+}
+
+/// Outcome of a single poll of the token endpoint
+enum DevicePoll {
+    Authorized(Box<OAuthTokenResponse>),
+    Pending,
+    SlowDown,
+    Denied,
+    Expired,
+    /// Any other OAuth error, polling can't continue
+    Rejected(String),
+    /// Neither a token nor an OAuth error
+    Unexpected(String),
+}
+
+/// State of a [`TidalClient::device_login_stream`]
+struct DevicePolling<'a> {
+    client: &'a TidalClient,
+    req: TidalRequest,
+    interval: Duration,
+    slow_down_step: Duration,
+    deadline: Instant,
+    attempt: u64,
+    done: bool,
+}
+
 impl TidalClient {
     /// Initiates the OAuth device authorization flow and returns the verification link
     ///
@@ -45,33 +123,60 @@ impl TidalClient {
     /// # }
     /// ```
     pub async fn get_oauth_link(&self) -> Result<OAuthDeviceAuthorizationResponse, TidalError> {
+        self.device_authorization(&DEFAULT_OAUTH_SCOPES.join(" "))
+            .await
+    }
+
+    /// Starts a device-code login with custom scopes, poll it with
+    /// [`TidalClient::device_login_stream`]
+    pub async fn start_device_login(
+        &self,
+        options: DeviceLoginOptions,
+    ) -> Result<DeviceLogin, TidalError> {
+        let scope = options.scopes.join(" ");
+        let authorization = self.device_authorization(&scope).await?;
+        Ok(DeviceLogin {
+            interval: Duration::from_secs(authorization.interval),
+            slow_down_step: SLOW_DOWN_STEP,
+            authorization,
+            scope,
+        })
+    }
+
+    async fn device_authorization(
+        &self,
+        scope: &str,
+    ) -> Result<OAuthDeviceAuthorizationResponse, TidalError> {
         debug!("requesting OAuth device authorization link");
-        if self.session.auth.is_token_auth() {
+        if self.session.auth.read().is_token_auth() {
             return Err(TidalError::InvalidArgument(
                 "Client secret provided, you should probably use get_access_token instead.\nIf you want to login with OAuth2, use TidalAuth::with_oauth()".to_string()
             ));
         }
 
-        if self.session.auth.client_id.is_empty() {
+        if self.session.auth.read().client_id.is_empty() {
             return Err(TidalError::InvalidArgument(
                 "No client ID provided, cannot get OAuth link.".to_string(),
             ));
         }
 
-        if !self.session.auth.oauth_login {
+        if !self.session.auth.read().oauth_login {
             return Err(TidalError::InvalidArgument(
                 "OAuth login not enabled in TidalAuth, cannot get OAuth link. Use TidalAuth::with_oauth() to enable it.".to_string()
             ));
         }
 
         let mut form = HashMap::new();
-        form.insert("client_id".to_string(), self.session.auth.client_id.clone());
-        form.insert("scope".to_string(), "r_usr w_usr w_sub".to_string());
+        form.insert(
+            "client_id".to_string(),
+            self.session.auth.read().client_id.clone(),
+        );
+        form.insert("scope".to_string(), scope.to_string());
 
         let mut req = TidalRequest::new(Method::POST, "/device_authorization".to_string());
         req.form = Some(vec![form]);
         req.send_params_as_form = true;
-        req.base_url = Some(OAUTH2_V1_LOCATION.to_string());
+        req.base_url = Some(self.rq.oauth_base_url().to_string());
 
         let res = self.rq.request(req).await?;
         let body = res.text().await?;
@@ -87,6 +192,91 @@ impl TidalClient {
         Ok(json)
     }
 
+    /// Polls the token endpoint of a device-code login, yielding every status
+    ///
+    /// Polling follows RFC 8628: the interval grows on `slow_down` and the stream ends after a
+    /// final status ([`OAuthStatus::is_final`]), after [`OAuthStatus::Success`] the client is
+    /// logged in. Dropping the stream cancels the login, `StreamExt::take_until` cancels it
+    /// with a future.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use tidlers::{
+    ///     TidalClient,
+    ///     auth::TidalAuth,
+    ///     client::oauth::{DeviceLoginOptions, OAuthStatus},
+    /// };
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let login = client.start_device_login(DeviceLoginOptions::default()).await?;
+    /// println!("Visit: {}", login.authorization.verification_uri_complete);
+    ///
+    /// let mut statuses = std::pin::pin!(client.device_login_stream(login));
+    /// while let Some(status) = statuses.next().await {
+    ///     if status == OAuthStatus::Success {
+    ///         println!("logged in");
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn device_login_stream(&self, login: DeviceLogin) -> impl Stream<Item = OAuthStatus> + '_ {
+        let polling = DevicePolling {
+            client: self,
+            req: self.device_token_request(&login.authorization.device_code, &login.scope),
+            interval: login.interval,
+            slow_down_step: login.slow_down_step,
+            deadline: Instant::now() + Duration::from_secs(login.authorization.expires_in),
+            attempt: 0,
+            done: false,
+        };
+
+        stream::unfold(polling, |mut polling| async move {
+            if polling.done {
+                return None;
+            }
+            let status = loop {
+                if polling.attempt > 0 {
+                    tokio::time::sleep(polling.interval).await;
+                }
+                if Instant::now() >= polling.deadline {
+                    break OAuthStatus::Expired;
+                }
+                polling.attempt += 1;
+
+                let poll = match polling.client.poll_device_token(&polling.req).await {
+                    Ok(poll) => poll,
+                    Err(e) => break OAuthStatus::Error(e.to_string()),
+                };
+                break match poll {
+                    DevicePoll::Authorized(token) => {
+                        match polling.client.apply_device_token(&token).await {
+                            Ok(()) => OAuthStatus::Success,
+                            Err(e) => OAuthStatus::Error(e.to_string()),
+                        }
+                    }
+                    DevicePoll::Pending => OAuthStatus::Waiting,
+                    DevicePoll::SlowDown => {
+                        polling.interval += polling.slow_down_step;
+                        OAuthStatus::SlowDown {
+                            interval: polling.interval,
+                        }
+                    }
+                    DevicePoll::Denied => OAuthStatus::Denied,
+                    DevicePoll::Expired => OAuthStatus::Expired,
+                    DevicePoll::Rejected(msg) => OAuthStatus::Error(msg),
+                    DevicePoll::Unexpected(_) => continue,
+                };
+            };
+            debug!(attempt = polling.attempt, ?status, "device login status");
+            polling.done = status.is_final();
+            Some((status, polling))
+        })
+    }
+
     /// Polls the OAuth endpoint until the user completes authentication or the request times out
     ///
     /// # Arguments
@@ -128,123 +318,62 @@ impl TidalClient {
             device_code_len = device_code.len(),
             expires_in, interval, "starting OAuth device polling"
         );
-        if self.session.auth.is_token_auth() {
-            return Err(TidalError::InvalidArgument(
-                "Client secret provided, cannot use this function.".to_string(),
-            ));
-        }
-
-        let mut form = HashMap::new();
-        form.insert("client_id".to_string(), self.session.auth.client_id.clone());
-        form.insert(
-            "client_secret".to_string(),
-            self.session.auth.client_secret.clone(),
-        );
-        form.insert("device_code".to_string(), device_code.to_string());
-        form.insert(
-            "grant_type".to_string(),
-            "urn:ietf:params:oauth:grant-type:device_code".to_string(),
-        );
-        form.insert("scope".to_string(), "r_usr w_usr w_sub".to_string());
-
-        let mut req = TidalRequest::new(Method::POST, "/token".to_string());
-        req.form = Some(vec![form]);
-        req.send_params_as_form = true;
-        req.base_url = Some(OAUTH2_V1_LOCATION.to_string());
+        let send = |status: OAuthStatus| {
+            if let Some(tx) = &status_tx {
+                let _ = tx.send(status);
+            }
+        };
 
-        let mut expiry = expires_in;
+        let req = self.device_token_request(device_code, &DEFAULT_OAUTH_SCOPES.join(" "));
+        let deadline = Instant::now() + Duration::from_secs(expires_in);
+        let mut interval = Duration::from_secs(interval);
         let mut attempt = 0_u64;
-        while expiry > 0 {
+        while Instant::now() < deadline {
             attempt += 1;
-            debug!(
-                attempt,
-                remaining_seconds = expiry,
-                "polling OAuth token endpoint"
-            );
-            // OAuth Device Authorization Grant uses HTTP 400 with a body of
-            // `{"error":"authorization_pending", ...}` as the *normal* "keep
-            // polling" signal (RFC 8628 §3.5).  After the request layer was
-            // changed to turn every 4xx into a hard `StatusCode` error, this
-            // loop short-circuits before the body is ever inspected and
-            // surfaces a fatal error on the very first poll.
-            //
-            // Recover the body from the error's `body_snippet` (truncated to
-            // 1024 chars by the request layer, but the OAuth response payload
-            // is well under 200 chars) so the parsing logic below can
-            // distinguish `authorization_pending` from a real failure.
-            let body: Vec<u8> = match self.rq.request(req.clone()).await {
-                Ok(res) => res.bytes().await?.to_vec(),
-                Err(requests::RequestClientError::StatusCode {
-                    status,
-                    body_snippet,
-                    ..
-                }) if status == reqwest::StatusCode::BAD_REQUEST => body_snippet.into_bytes(),
-                Err(e) => return Err(TidalError::RequestClient(e)),
-            };
-            // println!("oauth check response: {}", String::from_utf8_lossy(&body));
-            let json: Result<OAuthTokenResponse, _> = serde_json::from_slice(&body);
-            match json {
-                Ok(json) => {
-                    if let Some(tx) = &status_tx {
-                        let _ = tx.send(OAuthStatus::Success);
-                    }
-                    self.session.auth.apply_oauth_token_state(
-                        json.access_token.clone(),
-                        json.refresh_token.clone(),
-                        json.expires_in,
-                        json.user_id,
-                        Some(json.client_name.clone()),
-                    )?;
-                    self.user_info = Some(json.user.clone());
+            debug!(attempt, "polling OAuth token endpoint");
+            let msg = match self.poll_device_token(&req).await? {
+                DevicePoll::Authorized(json) => {
+                    send(OAuthStatus::Success);
+                    self.apply_device_token(&json).await?;
                     info!(
                         attempt,
                         user_id = json.user_id,
                         "OAuth flow completed successfully"
                     );
-                    return Ok(json);
+                    return Ok(*json);
                 }
-                Err(_) => {
-                    let json_waiting: Result<OAuthPendingAuthorizationResponse, _> =
-                        serde_json::from_slice(&body);
-
-                    match json_waiting {
-                        Ok(pending) if pending.error == "authorization_pending" => {
-                            if let Some(tx) = &status_tx {
-                                let _ = tx.send(OAuthStatus::Waiting);
-                            }
-                            debug!(attempt, "OAuth authorization still pending");
-                        }
-                        Ok(other) => {
-                            // Any other RFC 8628 §3.5 error (slow_down,
-                            // expired_token, access_denied, invalid_request,
-                            // ...) is fatal — keep-polling would just hammer
-                            // TIDAL with the same dead device_code forever.
-                            let msg = format!(
-                                "OAuth error {} (sub_status {}): {}",
-                                other.error, other.sub_status, other.error_description,
-                            );
-                            if let Some(tx) = &status_tx {
-                                let _ = tx.send(OAuthStatus::Error(msg.clone()));
-                            }
-                            warn!(attempt, error = %msg, "OAuth flow rejected by TIDAL");
-                            return Err(TidalError::InvalidResponse(msg));
-                        }
-                        Err(e) => {
-                            if let Some(tx) = &status_tx {
-                                let _ = tx.send(OAuthStatus::Error(e.to_string()));
-                            }
-                            warn!(
-                                attempt,
-                                error = %e,
-                                body = %String::from_utf8_lossy(&body),
-                                "unexpected OAuth polling response payload"
-                            );
-                        }
-                    }
+                DevicePoll::Pending => {
+                    send(OAuthStatus::Waiting);
+                    debug!(attempt, "OAuth authorization still pending");
+                    None
+                }
+                DevicePoll::SlowDown => {
+                    interval += SLOW_DOWN_STEP;
+                    send(OAuthStatus::SlowDown { interval });
+                    None
+                }
+                DevicePoll::Denied => {
+                    send(OAuthStatus::Denied);
+                    Some("OAuth error access_denied".to_string())
+                }
+                DevicePoll::Expired => {
+                    send(OAuthStatus::Expired);
+                    Some("OAuth error expired_token".to_string())
+                }
+                DevicePoll::Rejected(msg) => {
+                    send(OAuthStatus::Error(msg.clone()));
+                    Some(msg)
                 }
+                DevicePoll::Unexpected(msg) => {
+                    send(OAuthStatus::Error(msg));
+                    None
+                }
+            };
+            if let Some(msg) = msg {
+                warn!(attempt, error = %msg, "OAuth flow rejected by TIDAL");
+                return Err(TidalError::InvalidResponse(msg));
             }
-            tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
-            expiry -= interval;
+            tokio::time::sleep(interval).await;
         }
 
         warn!("OAuth polling timed out before authorization completed");
@@ -253,6 +382,89 @@ impl TidalClient {
         ))
     }
 
+    fn device_token_request(&self, device_code: &str, scope: &str) -> TidalRequest {
+        let auth = self.session.auth.read();
+        let mut form = HashMap::new();
+        form.insert("client_id".to_string(), auth.client_id.clone());
+        form.insert("client_secret".to_string(), auth.client_secret.clone());
+        form.insert("device_code".to_string(), device_code.to_string());
+        form.insert(
+            "grant_type".to_string(),
+            "urn:ietf:params:oauth:grant-type:device_code".to_string(),
+        );
+        form.insert("scope".to_string(), scope.to_string());
+
+        let mut req = TidalRequest::new(Method::POST, "/token".to_string());
+        req.form = Some(vec![form]);
+        req.send_params_as_form = true;
+        req.base_url = Some(self.rq.oauth_base_url().to_string());
+        req
+    }
+
+    async fn poll_device_token(&self, req: &TidalRequest) -> Result<DevicePoll, TidalError> {
+        if self.session.auth.read().is_token_auth() {
+            return Err(TidalError::InvalidArgument(
+                "Client secret provided, cannot use this function.".to_string(),
+            ));
+        }
+
+        // OAuth Device Authorization Grant uses HTTP 400 with a body of
+        // `{"error":"authorization_pending", ...}` as the *normal* "keep
+        // polling" signal (RFC 8628 §3.5), while the request layer turns
+        // every 4xx into a hard `StatusCode` error.
+        //
+        // Recover the body from the error's `body_snippet` (truncated to
+        // 1024 chars by the request layer, but the OAuth response payload
+        // is well under 200 chars) so `authorization_pending` can be told
+        // apart from a real failure.
+        let body: Vec<u8> = match self.rq.request(req.clone()).await {
+            Ok(res) => res.bytes().await?.to_vec(),
+            Err(requests::RequestClientError::StatusCode {
+                status,
+                body_snippet,
+                ..
+            }) if status == reqwest::StatusCode::BAD_REQUEST => body_snippet.into_bytes(),
+            Err(e) => return Err(TidalError::RequestClient(e)),
+        };
+
+        if let Ok(json) = serde_json::from_slice::<OAuthTokenResponse>(&body) {
+            return Ok(DevicePoll::Authorized(Box::new(json)));
+        }
+        match serde_json::from_slice::<OAuthPendingAuthorizationResponse>(&body) {
+            Ok(pending) => Ok(match pending.error.as_str() {
+                "authorization_pending" => DevicePoll::Pending,
+                "slow_down" => DevicePoll::SlowDown,
+                "access_denied" => DevicePoll::Denied,
+                "expired_token" => DevicePoll::Expired,
+                _ => DevicePoll::Rejected(format!(
+                    "OAuth error {} (sub_status {}): {}",
+                    pending.error, pending.sub_status, pending.error_description,
+                )),
+            }),
+            Err(e) => {
+                warn!(
+                    error = %e,
+                    body = %String::from_utf8_lossy(&body),
+                    "unexpected OAuth polling response payload"
+                );
+                Ok(DevicePoll::Unexpected(e.to_string()))
+            }
+        }
+    }
+
+    async fn apply_device_token(&self, json: &OAuthTokenResponse) -> Result<(), TidalError> {
+        self.session.auth.write().apply_oauth_token_state(
+            json.access_token.clone(),
+            json.refresh_token.clone(),
+            json.expires_in,
+            json.user_id,
+            Some(json.client_name.clone()),
+        )?;
+        self.user_info.set(Some(json.user.clone()));
+        self.auth_changed().await;
+        Ok(())
+    }
+
     /// Manually log in with OAuth tokens
     /// Use this after obtaining the tokens from the OAuth flow
     pub async fn oauth_manual_login(
@@ -270,15 +482,155 @@ impl TidalClient {
             expires_in,
             "applying manual OAuth login state"
         );
-        self.session.auth.apply_oauth_token_state(
+        self.session.auth.write().apply_oauth_token_state(
             access_token,
             refresh_token,
             expires_in,
             user_id,
             None,
         )?;
-        self.user_info = Some(user);
+        self.user_info.set(Some(user));
+        self.auth_changed().await;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::pin, time::Duration};
+
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    use super::{DeviceLogin, DeviceLoginOptions, OAuthStatus};
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        requests::RequestClient,
+        test_support::{TestResponse, TestServer, user_json},
+    };
+
+    fn device_authorization() -> TestResponse {
+        TestResponse::ok(
+            json!({
+                "deviceCode": "device", "userCode": "ABCDE", "verificationUri": "link.tidal.com",
+                "verificationUriComplete": "link.tidal.com/ABCDE", "expiresIn": 300, "interval": 1
+            })
+            .to_string(),
+        )
+    }
+
+    fn oauth_error(error: &str) -> TestResponse {
+        TestResponse::new(400).body(
+            json!({
+                "status": 400, "error": error, "sub_status": 1002,
+                "error_description": "Device Authorization code is not authorized yet"
+            })
+            .to_string(),
+        )
+    }
+
+    fn token() -> TestResponse {
+        TestResponse::ok(
+            json!({
+                "scope": "r_usr", "user": user_json(), "clientName": "test",
+                "token_type": "Bearer", "access_token": "access", "refresh_token": "refresh",
+                "expires_in": 3600, "user_id": 42
+            })
+            .to_string(),
+        )
+    }
+
+    fn oauth_client(server: &TestServer) -> TidalClient {
+        let mut client = TidalClient::new(&TidalAuth::with_oauth());
+        client.rq = RequestClient::new(server.url().to_string());
+        client.rq.set_oauth_base_url(server.url());
+        client
+    }
+
+    async fn fast_login(client: &TidalClient, scopes: &[&str]) -> DeviceLogin {
+        let mut login = client
+            .start_device_login(DeviceLoginOptions {
+                scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            })
+            .await
+            .unwrap();
+        login.interval = Duration::from_millis(10);
+        login.slow_down_step = Duration::from_millis(5);
+        login
+    }
+
+    #[tokio::test]
+    async fn stream_follows_slow_down_until_logged_in() {
+        let server = TestServer::sequence(vec![
+            device_authorization(),
+            oauth_error("authorization_pending"),
+            oauth_error("slow_down"),
+            oauth_error("authorization_pending"),
+            token(),
+        ]);
+        let client = oauth_client(&server);
+        let login = fast_login(&client, &["r_usr"]).await;
+
+        let statuses: Vec<_> = client.device_login_stream(login).collect().await;
+
+        assert_eq!(
+            statuses,
+            [
+                OAuthStatus::Waiting,
+                OAuthStatus::SlowDown {
+                    interval: Duration::from_millis(15)
+                },
+                OAuthStatus::Waiting,
+                OAuthStatus::Success
+            ]
+        );
+        assert_eq!(
+            client.session.auth.read().access_token.as_deref(),
+            Some("access")
+        );
+        let requests = server.requests();
+        assert_eq!(requests[0].form("scope").as_deref(), Some("r_usr"));
+        assert_eq!(requests[1].form("scope").as_deref(), Some("r_usr"));
+        assert_eq!(requests[1].form("device_code").as_deref(), Some("device"));
+    }
+
+    #[tokio::test]
+    async fn stream_ends_on_denied_and_expired_codes() {
+        for (error, status) in [
+            ("access_denied", OAuthStatus::Denied),
+            ("expired_token", OAuthStatus::Expired),
+        ] {
+            let server = TestServer::sequence(vec![device_authorization(), oauth_error(error)]);
+            let client = oauth_client(&server);
+            let login = fast_login(&client, &["r_usr", "w_usr"]).await;
+
+            let statuses: Vec<_> = client.device_login_stream(login).collect().await;
+
+            assert_eq!(statuses, [status]);
+            assert_eq!(server.requests().len(), 2);
+            assert!(client.session.auth.read().access_token.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_stream_stops_polling() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/device_authorization" => device_authorization(),
+            _ => oauth_error("authorization_pending"),
+        });
+        let client = oauth_client(&server);
+        let login = fast_login(&client, &["r_usr"]).await;
+
+        let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+        let mut statuses = pin!(client.device_login_stream(login).take_until(cancelled));
+        assert_eq!(statuses.next().await, Some(OAuthStatus::Waiting));
+        cancel.send(()).unwrap();
+        assert_eq!(statuses.next().await, None);
+
+        let polls = server.requests().len();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(server.requests().len(), polls);
+    }
+}
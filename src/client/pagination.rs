@@ -0,0 +1,328 @@
+use std::{collections::VecDeque, future::Future};
+
+use futures_util::{Stream, stream};
+
+use crate::error::TidalError;
+
+/// Controls page size and the total number of items yielded by a paginated stream
+///
+/// # Example
+///
+/// ```
+/// # use tidlers::client::pagination::PageOptions;
+/// let options = PageOptions {
+///     page_size: 50,
+///     max_items: Some(200),
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct PageOptions {
+    /// Number of items requested per page, most endpoints accept at most 100
+    pub page_size: u32,
+    /// Stop after yielding this many items, `None` means all items
+    pub max_items: Option<usize>,
+}
+
+impl Default for PageOptions {
+    fn default() -> Self {
+        Self {
+            page_size: 100,
+            max_items: None,
+        }
+    }
+}
+
+/// Position of the next page to fetch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageCursor {
+    /// Offset based endpoints (`limit` + `offset`)
+    Offset(u64),
+    /// Cursor based endpoints (`links.next`, `page.cursor`)
+    Cursor(String),
+}
+
+/// Describes the page the paginator wants to fetch next
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub limit: u32,
+    /// `None` for the first page
+    pub cursor: Option<PageCursor>,
+}
+
+impl PageRequest {
+    /// Offset of the requested page, `0` for the first page or cursor based requests
+    pub fn offset(&self) -> u64 {
+        match self.cursor {
+            Some(PageCursor::Offset(offset)) => offset,
+            _ => 0,
+        }
+    }
+
+    /// Cursor of the requested page, `None` for the first page or offset based requests
+    pub fn cursor(&self) -> Option<String> {
+        match &self.cursor {
+            Some(PageCursor::Cursor(cursor)) => Some(cursor.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A single fetched page and the position of the page after it
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// `None` when this is the last page
+    pub next: Option<PageCursor>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page for offset based endpoints
+    ///
+    /// The next page exists while `offset + items` is below `total`, or, when the endpoint doesn't
+    /// report a total, while full pages keep coming back.
+    pub fn from_offset(items: Vec<T>, request: &PageRequest, total: Option<u64>) -> Self {
+        let fetched = items.len() as u64;
+        let next_offset = request.offset() + fetched;
+        let has_more = match total {
+            Some(total) => fetched > 0 && next_offset < total,
+            None => fetched > 0 && fetched >= u64::from(request.limit),
+        };
+
+        Self {
+            items,
+            next: has_more.then_some(PageCursor::Offset(next_offset)),
+        }
+    }
+
+    /// Builds a page for cursor based endpoints
+    pub fn from_cursor(items: Vec<T>, next_cursor: Option<String>) -> Self {
+        let next = next_cursor
+            .filter(|cursor| !cursor.is_empty() && !items.is_empty())
+            .map(PageCursor::Cursor);
+
+        Self { items, next }
+    }
+}
+
+/// Turns a page fetching closure into a stream of items across all pages
+///
+/// Pages are fetched lazily as the stream is polled, and the stream ends after the last page,
+/// after `max_items` items, or after the first error.
+///
+/// # Example
+///
+/// ```no_run
+/// # use futures_util::StreamExt;
+/// # use tidlers::{TidalClient, auth::TidalAuth};
+/// # use tidlers::client::pagination::{Page, PageOptions, PageRequest, Paginator};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = TidalClient::new(&TidalAuth::with_oauth());
+/// let tracks = Paginator::new(PageOptions::default(), |request: PageRequest| {
+///     let client = &client;
+///     async move {
+///         let res = client
+///             .get_artist_tracks("123", Some(request.limit.into()), Some(request.offset()))
+///             .await?;
+///         Ok(Page::from_offset(res.items, &request, Some(res.total_number_of_items.into())))
+///     }
+/// })
+/// .into_stream();
+///
+/// let mut tracks = std::pin::pin!(tracks);
+/// while let Some(track) = tracks.next().await {
+///     println!("{}", track?.title);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Paginator<F> {
+    options: PageOptions,
+    fetch: F,
+}
+
+struct PaginatorState<F, T> {
+    options: PageOptions,
+    fetch: F,
+    buffer: VecDeque<T>,
+    next: Option<PageCursor>,
+    yielded: usize,
+    done: bool,
+}
+
+impl<F> Paginator<F> {
+    pub fn new(options: PageOptions, fetch: F) -> Self {
+        Self { options, fetch }
+    }
+
+    /// Returns a stream yielding every item across pages
+    pub fn into_stream<T, Fut>(self) -> impl Stream<Item = Result<T, TidalError>>
+    where
+        F: FnMut(PageRequest) -> Fut,
+        Fut: Future<Output = Result<Page<T>, TidalError>>,
+    {
+        let state = PaginatorState {
+            options: self.options,
+            fetch: self.fetch,
+            buffer: VecDeque::new(),
+            next: None,
+            yielded: 0,
+            done: false,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                let remaining = state
+                    .options
+                    .max_items
+                    .map(|max| max.saturating_sub(state.yielded));
+                if remaining == Some(0) {
+                    return None;
+                }
+
+                if let Some(item) = state.buffer.pop_front() {
+                    state.yielded += 1;
+                    return Some((Ok(item), state));
+                }
+
+                if state.done {
+                    return None;
+                }
+
+                let limit = match remaining {
+                    Some(remaining) => state
+                        .options
+                        .page_size
+                        .min(u32::try_from(remaining).unwrap_or(u32::MAX)),
+                    None => state.options.page_size,
+                }
+                .max(1);
+                let request = PageRequest {
+                    limit,
+                    cursor: state.next.take(),
+                };
+
+                match (state.fetch)(request).await {
+                    Ok(page) => {
+                        state.done = page.next.is_none() || page.items.is_empty();
+                        state.next = page.next;
+                        state.buffer.extend(page.items);
+                    }
+                    Err(e) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{StreamExt, TryStreamExt};
+
+    use super::{Page, PageCursor, PageOptions, PageRequest, Paginator};
+    use crate::error::TidalError;
+
+    fn options(page_size: u32, max_items: Option<usize>) -> PageOptions {
+        PageOptions {
+            page_size,
+            max_items,
+        }
+    }
+
+    #[tokio::test]
+    async fn offset_pagination_yields_all_items_up_to_total() {
+        let data: Vec<u32> = (0..7).collect();
+        let mut requests = Vec::new();
+
+        let items: Vec<u32> = Paginator::new(options(3, None), |request: PageRequest| {
+            requests.push(request.offset());
+            let start = request.offset() as usize;
+            let end = (start + request.limit as usize).min(data.len());
+            let items = data[start..end].to_vec();
+            async move { Ok(Page::from_offset(items, &request, Some(7))) }
+        })
+        .into_stream()
+        .try_collect()
+        .await
+        .expect("pagination should succeed");
+
+        assert_eq!(items, data);
+        assert_eq!(requests, vec![0, 3, 6]);
+    }
+
+    #[tokio::test]
+    async fn offset_pagination_without_total_stops_on_short_page() {
+        let items: Vec<u32> = Paginator::new(options(2, None), |request: PageRequest| {
+            let items = match request.offset() {
+                0 => vec![1, 2],
+                2 => vec![3],
+                _ => panic!("should not fetch past a short page"),
+            };
+            async move { Ok(Page::from_offset(items, &request, None)) }
+        })
+        .into_stream()
+        .try_collect()
+        .await
+        .expect("pagination should succeed");
+
+        assert_eq!(items, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn cursor_pagination_follows_next_cursor() {
+        let items: Vec<&str> = Paginator::new(options(10, None), |request: PageRequest| {
+            let page = match request.cursor().as_deref() {
+                None => Page::from_cursor(vec!["a", "b"], Some("second".to_string())),
+                Some("second") => Page::from_cursor(vec!["c"], None),
+                Some(other) => panic!("unexpected cursor {other}"),
+            };
+            async move { Ok(page) }
+        })
+        .into_stream()
+        .try_collect()
+        .await
+        .expect("pagination should succeed");
+
+        assert_eq!(items, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn max_items_limits_yielded_items_and_page_size() {
+        let mut limits = Vec::new();
+
+        let items: Vec<u64> = Paginator::new(options(4, Some(6)), |request: PageRequest| {
+            limits.push(request.limit);
+            let items = (request.offset()..request.offset() + u64::from(request.limit)).collect();
+            async move { Ok(Page::from_offset(items, &request, Some(100))) }
+        })
+        .into_stream()
+        .try_collect()
+        .await
+        .expect("pagination should succeed");
+
+        assert_eq!(items, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(limits, vec![4, 2]);
+    }
+
+    #[tokio::test]
+    async fn stream_ends_after_error() {
+        let results: Vec<Result<u32, TidalError>> =
+            Paginator::new(options(2, None), |request: PageRequest| async move {
+                match request.cursor {
+                    None => Ok(Page {
+                        items: vec![1, 2],
+                        next: Some(PageCursor::Offset(2)),
+                    }),
+                    Some(_) => Err(TidalError::NotFound),
+                }
+            })
+            .into_stream()
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[2], Err(TidalError::NotFound)));
+    }
+}
@@ -5,17 +5,16 @@ use tracing::info;
 use url::Url;
 
 use crate::{
-    TidalClient,
-    auth::pkce::PkceConfig,
-    requests::TidalRequest,
-    responses::OAuthTokenResponse,
-    urls::{OAUTH2_V1_LOCATION, PKCE_AUTH_URL},
+    TidalClient, auth::pkce::PkceConfig, requests::TidalRequest, responses::OAuthTokenResponse,
 };
 
 impl TidalClient {
     /// Initiates the PKCE login flow by generating a code verifier and code challenge
     /// and returning the URL to redirect the user to for authentication.
     ///
+    /// Every call starts a new login with a fresh verifier and `state`, stored in the session, so
+    /// only the redirect of the latest login is accepted by [`TidalClient::finish_pkce_login`].
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -32,26 +31,53 @@ impl TidalClient {
     pub fn initiate_pkce_login(&mut self) -> Result<String, crate::TidalError> {
         self.check_pkce_login()?;
 
-        self.pkce_url()
+        let pkce_config = self.rotate_pkce_login();
+        self.pkce_url(&pkce_config)
     }
 
     /// Completes the PKCE login flow by exchanging the authorization code from a redirect URL.
+    ///
+    /// Fails with [`TidalError::AuthorizationDenied`](crate::TidalError::AuthorizationDenied)
+    /// when the redirect carries an `error` and with
+    /// [`TidalError::PkceStateMismatch`](crate::TidalError::PkceStateMismatch) when it doesn't
+    /// belong to the login started by [`TidalClient::initiate_pkce_login`].
     pub async fn finish_pkce_login(&mut self, redirect_url: &str) -> Result<(), crate::TidalError> {
         self.check_pkce_login()?;
 
-        let code = self.parse_pkce_redirect(redirect_url)?;
-        let auth_response = self
-            .get_auth_from_oauth2(code, &self.session.auth.pkce_config)
-            .await?;
+        let pkce_config = self.session.auth.read().pkce_config.clone();
+        let code = pkce_redirect_code(&Url::parse(redirect_url)?, pkce_config.state.as_deref())?;
+        self.complete_pkce_login(code, &pkce_config).await
+    }
 
-        self.session.auth.apply_oauth_token_state(
-            auth_response.access_token.clone(),
-            auth_response.refresh_token.clone(),
-            auth_response.expires_in,
-            auth_response.user_id,
-            Some(auth_response.client_name.clone()),
-        )?;
-        self.user_info = Some(auth_response.user.clone());
+    /// Replaces the session's verifier and `state` with fresh ones, returning the new config
+    pub(crate) fn rotate_pkce_login(&self) -> PkceConfig {
+        let mut auth = self.session.auth.write();
+        auth.pkce_config = auth.pkce_config.clone().rotated();
+        auth.pkce_config.clone()
+    }
+
+    /// Exchanges an authorization code for tokens and logs the client in with them
+    pub(crate) async fn complete_pkce_login(
+        &mut self,
+        code: String,
+        pkce_config: &PkceConfig,
+    ) -> Result<(), crate::TidalError> {
+        let auth_response = self.get_auth_from_oauth2(code, pkce_config).await?;
+
+        {
+            let mut auth = self.session.auth.write();
+            auth.apply_oauth_token_state(
+                auth_response.access_token.clone(),
+                auth_response.refresh_token.clone(),
+                auth_response.expires_in,
+                auth_response.user_id,
+                Some(auth_response.client_name.clone()),
+            )?;
+            // the redirect can't be replayed
+            auth.pkce_config.state = None;
+        }
+        self.user_info.set(Some(auth_response.user.clone()));
+        self.auth_changed().await;
 
         info!(
             user_id = auth_response.user_id,
@@ -84,17 +110,16 @@ impl TidalClient {
         let mut req = TidalRequest::new(Method::POST, "/token".to_string());
         req.form = Some(vec![form]);
         req.send_params_as_form = true;
-        req.base_url = Some(OAUTH2_V1_LOCATION.to_string());
+        req.base_url = Some(self.rq.oauth_base_url().to_string());
 
         let res = self.rq.request(req).await?;
         Ok(res.json().await?)
     }
 
-    fn pkce_url(&self) -> Result<String, crate::TidalError> {
+    /// Authorize URL for `pkce_config`, its `state` is sent back with the redirect
+    pub(crate) fn pkce_url(&self, pkce_config: &PkceConfig) -> Result<String, crate::TidalError> {
         self.check_pkce_login()?;
 
-        let pkce_config = &self.session.auth.pkce_config;
-
         let mut params = HashMap::new();
         params.insert("response_type".to_string(), "code".to_string());
         params.insert("redirect_uri".to_string(), pkce_config.redirect_uri.clone());
@@ -111,33 +136,175 @@ impl TidalClient {
         );
         params.insert("code_challenge_method".to_string(), "S256".to_string());
         params.insert("restrict_signup".to_string(), "true".to_string());
+        if let Some(state) = &pkce_config.state {
+            params.insert("state".to_string(), state.clone());
+        }
 
-        let mut url = Url::parse(PKCE_AUTH_URL)?;
+        let mut url = Url::parse(self.rq.pkce_auth_url())?;
         url.query_pairs_mut().extend_pairs(params.iter());
 
         Ok(url.to_string())
     }
 
-    fn check_pkce_login(&self) -> Result<(), crate::TidalError> {
-        match self.session.auth.pkce_login {
+    pub(crate) fn check_pkce_login(&self) -> Result<(), crate::TidalError> {
+        match self.session.auth.read().pkce_login {
             true => Ok(()),
             false => Err(crate::TidalError::Other(
                 "PKCE login not configured. Use TidalAuth::with_pkce() first.".to_string(),
             )),
         }
     }
+}
+
+/// Authorization code of a login redirect, checking its `state` against `expected_state`
+pub(crate) fn pkce_redirect_code(
+    url: &Url,
+    expected_state: Option<&str>,
+) -> Result<String, crate::TidalError> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+
+    if let Some(error) = param("error") {
+        return Err(crate::TidalError::AuthorizationDenied {
+            error,
+            description: param("error_description"),
+        });
+    }
+    if expected_state.is_none() || param("state").as_deref() != expected_state {
+        return Err(crate::TidalError::PkceStateMismatch);
+    }
+
+    param("code").ok_or_else(|| {
+        crate::TidalError::Other("Authorization code not found in redirect URL.".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use url::Url;
+
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        error::TidalError,
+        requests::RequestClient,
+        test_support::{TestResponse, TestServer, user_json},
+    };
+
+    fn token_server() -> TestServer {
+        TestServer::spawn(|_| {
+            TestResponse::ok(
+                json!({
+                    "scope": "r_usr w_usr", "user": user_json(), "clientName": "test",
+                    "token_type": "Bearer", "access_token": "access", "refresh_token": "refresh",
+                    "expires_in": 3600, "user_id": 42
+                })
+                .to_string(),
+            )
+        })
+    }
+
+    fn pkce_client(server: &TestServer) -> TidalClient {
+        let mut client = TidalClient::new(&TidalAuth::with_pkce());
+        client.rq = RequestClient::new(server.url().to_string());
+        client.rq.set_oauth_base_url(server.url());
+        client
+    }
 
-    fn parse_pkce_redirect(&self, redirect_url: &str) -> Result<String, crate::TidalError> {
-        let url = Url::parse(redirect_url)?;
-        let code = url
+    fn param(url: &str, name: &str) -> String {
+        Url::parse(url)
+            .unwrap()
             .query_pairs()
-            .find(|(key, _)| key == "code")
+            .find(|(key, _)| key == name)
             .map(|(_, value)| value.to_string())
-            .ok_or_else(|| {
-                crate::TidalError::Other(
-                    "Authorization code not found in redirect URL.".to_string(),
-                )
-            })?;
-        Ok(code)
+            .unwrap()
+    }
+
+    #[test]
+    fn every_login_gets_a_fresh_verifier_and_state() {
+        let server = token_server();
+        let mut client = pkce_client(&server);
+
+        let first = client.initiate_pkce_login().unwrap();
+        let first_verifier = client.session.auth.read().pkce_config.code_verifier.clone();
+        let second = client.initiate_pkce_login().unwrap();
+
+        assert_ne!(param(&first, "state"), param(&second, "state"));
+        assert_ne!(
+            param(&first, "code_challenge"),
+            param(&second, "code_challenge")
+        );
+        assert_ne!(
+            client.session.auth.read().pkce_config.code_verifier,
+            first_verifier
+        );
+        assert_eq!(
+            client.session.auth.read().pkce_config.state.as_deref(),
+            Some(param(&second, "state").as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_of_a_stale_login_is_rejected() {
+        let server = token_server();
+        let mut client = pkce_client(&server);
+
+        let stale = client.initiate_pkce_login().unwrap();
+        client.initiate_pkce_login().unwrap();
+        let redirect = format!(
+            "https://tidal.com/android/login/auth?code=abc&state={}",
+            param(&stale, "state")
+        );
+
+        let result = client.finish_pkce_login(&redirect).await;
+
+        assert!(matches!(result, Err(TidalError::PkceStateMismatch)));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn redirect_errors_surface_as_authorization_denied() {
+        let server = token_server();
+        let mut client = pkce_client(&server);
+        client.initiate_pkce_login().unwrap();
+
+        let result = client
+            .finish_pkce_login(
+                "https://tidal.com/android/login/auth?error=access_denied&error_description=User%20cancelled",
+            )
+            .await;
+
+        match result {
+            Err(TidalError::AuthorizationDenied { error, description }) => {
+                assert_eq!(error, "access_denied");
+                assert_eq!(description.as_deref(), Some("User cancelled"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn matching_redirect_logs_in_once() {
+        let server = token_server();
+        let mut client = pkce_client(&server);
+
+        let url = client.initiate_pkce_login().unwrap();
+        let redirect = format!(
+            "https://tidal.com/android/login/auth?code=abc&state={}",
+            param(&url, "state")
+        );
+        client.finish_pkce_login(&redirect).await.unwrap();
+
+        assert_eq!(
+            client.session.auth.read().access_token.as_deref(),
+            Some("access")
+        );
+        // the same redirect can't be used again
+        let replay = client.finish_pkce_login(&redirect).await;
+        assert!(matches!(replay, Err(TidalError::PkceStateMismatch)));
     }
 }
@@ -0,0 +1,178 @@
+//! Decryption of legacy encrypted track streams
+//!
+//! Manifests with the `OLD_AES` encryption type carry a key ID, a security token made of a
+//! 16 byte IV followed by the stream's key and nonce, AES-256-CBC encrypted with a master key
+//! built into TIDAL's clients. The stream itself is AES-128-CTR encrypted, the counter block
+//! being the nonce followed by a 64-bit big-endian block counter. CTR allows decrypting any byte
+//! range on its own, so the downloader and [`TrackStream`](crate::stream::TrackStream) decrypt
+//! every chunk as it arrives.
+
+use std::{borrow::Cow, fmt};
+
+use aes::{Aes128, Aes256};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use cbc::cipher::{
+    BlockModeDecrypt, KeyIvInit, StreamCipher, StreamCipherSeek, block_padding::NoPadding,
+};
+
+use crate::error::TidalError;
+
+/// Master key the security tokens of `OLD_AES` streams are encrypted with
+pub(crate) const MASTER_KEY: &str = "UIlTTEMmmLfGowo/UC60x2H45W6MdGgTRfo/umg4754=";
+
+type TokenCipher = cbc::Decryptor<Aes256>;
+type StreamCtr = ctr::Ctr64BE<Aes128>;
+
+/// Encryption scheme of a stream, as given by a manifest's `encryptionType`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionScheme {
+    None,
+    /// AES-128-CTR with the key in the manifest's key ID
+    OldAes,
+    /// Any scheme tidlers can't decrypt, e.g. DRM protected streams
+    Unsupported(String),
+}
+
+impl EncryptionScheme {
+    pub fn parse(encryption_type: &str) -> Self {
+        match encryption_type.to_ascii_uppercase().as_str() {
+            "" | "NONE" => Self::None,
+            "OLD_AES" => Self::OldAes,
+            _ => Self::Unsupported(encryption_type.to_string()),
+        }
+    }
+}
+
+/// Key and nonce of an `OLD_AES` stream
+#[derive(Clone, PartialEq, Eq)]
+pub struct StreamKey {
+    key: [u8; 16],
+    nonce: [u8; 8],
+}
+
+impl fmt::Debug for StreamKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamKey").finish_non_exhaustive()
+    }
+}
+
+impl StreamKey {
+    /// Decrypts the key and nonce from a manifest's base64 key ID
+    pub fn from_key_id(key_id: &str) -> Result<Self, TidalError> {
+        let token = BASE64.decode(key_id.trim())?;
+        let invalid = || TidalError::InvalidManifest("invalid key ID".to_string());
+        if token.len() < 48 || token.len() % 16 != 0 {
+            return Err(invalid());
+        }
+
+        let (iv, encrypted) = token.split_at(16);
+        let master_key = BASE64.decode(MASTER_KEY)?;
+        let mut buf = encrypted.to_vec();
+        let decrypted = TokenCipher::new_from_slices(&master_key, iv)
+            .map_err(|_| invalid())?
+            .decrypt_padded::<NoPadding>(&mut buf)
+            .map_err(|_| invalid())?;
+
+        let mut key = StreamKey {
+            key: [0; 16],
+            nonce: [0; 8],
+        };
+        key.key.copy_from_slice(&decrypted[..16]);
+        key.nonce.copy_from_slice(&decrypted[16..24]);
+        Ok(key)
+    }
+
+    /// Decrypts `data` in place, `offset` being the position of its first byte in the stream
+    pub fn apply(&self, offset: u64, data: &mut [u8]) {
+        let mut iv = [0; 16];
+        iv[..8].copy_from_slice(&self.nonce);
+        let mut cipher = StreamCtr::new(&self.key.into(), &iv.into());
+        cipher.seek(offset);
+        cipher.apply_keystream(data);
+    }
+}
+
+/// Key to decrypt a stream with, `None` for unencrypted streams
+///
+/// Fails with [`TidalError::UnsupportedEncryption`] for schemes that can't be decrypted.
+pub(crate) fn stream_key(
+    encryption_type: &str,
+    key_id: Option<&str>,
+) -> Result<Option<StreamKey>, TidalError> {
+    match EncryptionScheme::parse(encryption_type) {
+        EncryptionScheme::None => Ok(None),
+        EncryptionScheme::OldAes => {
+            let key_id = key_id.filter(|id| !id.is_empty()).ok_or_else(|| {
+                TidalError::InvalidManifest("OLD_AES manifest has no key ID".to_string())
+            })?;
+            StreamKey::from_key_id(key_id).map(Some)
+        }
+        EncryptionScheme::Unsupported(scheme) => Err(TidalError::UnsupportedEncryption(scheme)),
+    }
+}
+
+/// `data` decrypted with `key` if there is one, `offset` being its position in the stream
+pub(crate) fn decrypted<'a>(key: Option<&StreamKey>, offset: u64, data: &'a [u8]) -> Cow<'a, [u8]> {
+    match key {
+        Some(key) => {
+            let mut data = data.to_vec();
+            key.apply(offset, &mut data);
+            Cow::Owned(data)
+        }
+        None => Cow::Borrowed(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncryptionScheme, StreamKey, stream_key};
+    use crate::{
+        error::TidalError,
+        test_support::{KEY, NONCE, encrypt_old_aes, old_aes_key_id},
+    };
+
+    #[test]
+    fn key_id_decrypts_to_key_and_nonce() {
+        let key = StreamKey::from_key_id(&old_aes_key_id()).unwrap();
+        assert_eq!(key.key, KEY);
+        assert_eq!(key.nonce, NONCE);
+    }
+
+    #[test]
+    fn decrypts_ranges_at_any_offset() {
+        let plain: Vec<u8> = (0..100).collect();
+        let encrypted = encrypt_old_aes(&plain);
+        let key = StreamKey::from_key_id(&old_aes_key_id()).unwrap();
+
+        let mut whole = encrypted.clone();
+        key.apply(0, &mut whole);
+        assert_eq!(whole, plain);
+
+        // a range starting in the middle of a block
+        let mut range = encrypted[37..71].to_vec();
+        key.apply(37, &mut range);
+        assert_eq!(range, &plain[37..71]);
+    }
+
+    #[test]
+    fn schemes_map_to_keys_or_typed_errors() {
+        assert_eq!(EncryptionScheme::parse("NONE"), EncryptionScheme::None);
+        assert_eq!(EncryptionScheme::parse("old_aes"), EncryptionScheme::OldAes);
+        assert!(stream_key("NONE", None).unwrap().is_none());
+        assert!(
+            stream_key("OLD_AES", Some(&old_aes_key_id()))
+                .unwrap()
+                .is_some()
+        );
+
+        assert!(matches!(
+            stream_key("OLD_AES", None),
+            Err(TidalError::InvalidManifest(_))
+        ));
+        assert!(matches!(
+            stream_key("WIDEVINE", None),
+            Err(TidalError::UnsupportedEncryption(scheme)) if scheme == "WIDEVINE"
+        ));
+        assert!(StreamKey::from_key_id("c2hvcnQ=").is_err());
+    }
+}
@@ -0,0 +1,1081 @@
+//! Downloading whole albums and playlists into a directory layout built from a path template
+
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use futures_util::{StreamExt, TryStreamExt, stream};
+use reqwest::StatusCode;
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::{
+    TidalClient,
+    client::{
+        models::{
+            playlist::PlaylistItem,
+            track::{Track, playback::TrackPlaybackInfoResponse},
+        },
+        pagination::PageOptions,
+    },
+    download::{Container, DownloadOptions, DownloadPlan, DownloadedTrack},
+    error::TidalError,
+    ids::{AlbumId, PlaylistId},
+    resources,
+};
+
+/// Name of the cover art file saved next to the tracks
+pub const COVER_FILE_NAME: &str = "folder.jpg";
+
+const MAX_COMPONENT_BYTES: usize = 200;
+
+/// Template turning track metadata into a relative file path
+///
+/// Placeholders are written as `{name}` or `{name:0N}` to zero-pad numbers to `N` digits, `/`
+/// separates directories and `{{`/`}}` are literal braces. Every value is sanitized before it's
+/// inserted, so a `/` in a title never creates a directory.
+///
+/// | Placeholder | Value |
+/// |---|---|
+/// | `title` | track title, with the version in parentheses if there is one |
+/// | `artist` | main artist of the track |
+/// | `artists` | all artists, comma separated |
+/// | `album_artist` | main artist of the album |
+/// | `album` | album title |
+/// | `year` | release year of the album, brackets directly around it are dropped when it's unknown |
+/// | `track` | track number |
+/// | `disc` | disc (volume) number |
+/// | `isrc` | ISRC of the track |
+/// | `track_id` | TIDAL track id |
+/// | `playlist` | playlist title, empty for albums |
+/// | `playlist_index` | position in the playlist starting at 1, skipped videos count too, 0 for albums |
+/// | `quality` | audio quality of the downloaded stream |
+/// | `ext` | file extension of the downloaded container |
+///
+/// # Example
+///
+/// ```
+/// # use tidlers::download::batch::PathTemplate;
+/// let template =
+///     PathTemplate::new("{album_artist}/{album} ({year})/{disc:02}-{track:02} {title}.{ext}")?;
+/// # Ok::<(), tidlers::error::TidalError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    source: String,
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Placeholder { field: Field, width: usize },
+    Separator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Artist,
+    Artists,
+    AlbumArtist,
+    Album,
+    Year,
+    Track,
+    Disc,
+    Isrc,
+    TrackId,
+    Playlist,
+    PlaylistIndex,
+    Quality,
+    Ext,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "title" => Field::Title,
+            "artist" => Field::Artist,
+            "artists" => Field::Artists,
+            "album_artist" => Field::AlbumArtist,
+            "album" => Field::Album,
+            "year" => Field::Year,
+            "track" => Field::Track,
+            "disc" => Field::Disc,
+            "isrc" => Field::Isrc,
+            "track_id" => Field::TrackId,
+            "playlist" => Field::Playlist,
+            "playlist_index" => Field::PlaylistIndex,
+            "quality" => Field::Quality,
+            "ext" => Field::Ext,
+            _ => return None,
+        })
+    }
+}
+
+/// Values a [`PathTemplate`] is rendered with
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TemplateValues {
+    pub title: String,
+    pub artist: String,
+    pub artists: Vec<String>,
+    pub album_artist: String,
+    pub album: String,
+    pub year: Option<String>,
+    pub track: u32,
+    pub disc: u32,
+    pub isrc: Option<String>,
+    pub track_id: u64,
+    pub playlist: Option<String>,
+    pub playlist_index: Option<usize>,
+    pub quality: String,
+    pub ext: String,
+}
+
+impl TemplateValues {
+    /// Values of a track, album specific fields fall back to the track's album
+    pub fn from_track(track: &Track) -> Self {
+        let title = match &track.version {
+            Some(version) if !version.is_empty() => format!("{} ({})", track.title, version),
+            _ => track.title.clone(),
+        };
+
+        Self {
+            title,
+            artist: track.artist.name.clone(),
+            artists: track.artists.iter().map(|a| a.name.clone()).collect(),
+            album_artist: track.artist.name.clone(),
+            album: track
+                .album
+                .as_ref()
+                .map(|album| album.title.clone())
+                .unwrap_or_default(),
+            year: track
+                .album
+                .as_ref()
+                .and_then(|album| album.release_date.as_deref())
+                .and_then(release_year),
+            track: track.track_number,
+            disc: track.volume_number,
+            isrc: track.isrc.clone(),
+            track_id: track.id,
+            playlist: None,
+            playlist_index: None,
+            quality: track.audio_quality.to_string(),
+            ext: String::new(),
+        }
+    }
+}
+
+fn release_year(date: &str) -> Option<String> {
+    date.get(..4)
+        .filter(|year| year.bytes().all(|b| b.is_ascii_digit()))
+        .map(str::to_string)
+}
+
+impl PathTemplate {
+    /// Parses a template, failing on unknown placeholders and unbalanced braces
+    pub fn new(template: impl Into<String>) -> Result<Self, TidalError> {
+        let source = template.into();
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(template_error(&source, "unclosed placeholder")),
+                        }
+                    }
+
+                    let (name, spec) = match placeholder.split_once(':') {
+                        Some((name, spec)) => (name, Some(spec)),
+                        None => (placeholder.as_str(), None),
+                    };
+                    let field = Field::parse(name).ok_or_else(|| {
+                        template_error(&source, &format!("unknown placeholder {{{name}}}"))
+                    })?;
+                    let width = match spec {
+                        None => 0,
+                        Some(spec) => spec
+                            .strip_prefix('0')
+                            .and_then(|width| width.parse().ok())
+                            .ok_or_else(|| {
+                                template_error(
+                                    &source,
+                                    &format!("invalid format {{{placeholder}}}"),
+                                )
+                            })?,
+                    };
+
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Placeholder { field, width });
+                }
+                '}' => return Err(template_error(&source, "unmatched `}`")),
+                '/' | '\\' => {
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Separator);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        if parts.is_empty() || parts.last() == Some(&TemplatePart::Separator) {
+            return Err(template_error(
+                &source,
+                "template must end with a file name",
+            ));
+        }
+
+        Ok(Self { source, parts })
+    }
+
+    /// Renders the relative path for `values`
+    ///
+    /// Every path component is sanitized, empty components are dropped. A placeholder that
+    /// renders empty takes brackets directly around it with it, so `{album} ({year})` becomes
+    /// just the album without a year.
+    pub fn render(&self, values: &TemplateValues) -> PathBuf {
+        let mut path = PathBuf::new();
+        let mut component = String::new();
+        let mut skip_bracket = false;
+
+        for (index, part) in self.parts.iter().enumerate() {
+            match part {
+                TemplatePart::Literal(text) if std::mem::take(&mut skip_bracket) => {
+                    component.push_str(&text[1..]);
+                }
+                TemplatePart::Literal(text) => component.push_str(text),
+                TemplatePart::Placeholder { field, width } => {
+                    let value = sanitize(&render_field(*field, *width, values));
+                    if value.is_empty() {
+                        skip_bracket = drop_open_bracket(&mut component, self.parts.get(index + 1));
+                    }
+                    component.push_str(&value);
+                }
+                TemplatePart::Separator => {
+                    push_component(&mut path, &component);
+                    component.clear();
+                }
+            }
+        }
+        push_component(&mut path, &component);
+
+        path
+    }
+
+    fn uses(&self, field: Field) -> bool {
+        self.parts.iter().any(
+            |part| matches!(part, TemplatePart::Placeholder { field: used, .. } if *used == field),
+        )
+    }
+}
+
+/// Removes an opening bracket (and the whitespace before it) from the end of `component` if
+/// `next` starts with the matching closing bracket, returns whether it did
+fn drop_open_bracket(component: &mut String, next: Option<&TemplatePart>) -> bool {
+    let closing = match component.chars().last() {
+        Some('(') => ')',
+        Some('[') => ']',
+        _ => return false,
+    };
+    if !matches!(next, Some(TemplatePart::Literal(text)) if text.starts_with(closing)) {
+        return false;
+    }
+    component.pop();
+    component.truncate(component.trim_end().len());
+    true
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        Self::new("{album_artist}/{album} ({year})/{disc:02}-{track:02} {title}.{ext}")
+            .expect("default template is valid")
+    }
+}
+
+fn template_error(template: &str, message: &str) -> TidalError {
+    TidalError::InvalidArgument(format!("invalid path template `{template}`: {message}"))
+}
+
+fn render_field(field: Field, width: usize, values: &TemplateValues) -> String {
+    let number = |n: u64| format!("{n:0width$}");
+    let text = |s: &str| s.to_string();
+
+    match field {
+        Field::Title => text(&values.title),
+        Field::Artist => text(&values.artist),
+        Field::Artists => values.artists.join(", "),
+        Field::AlbumArtist => text(&values.album_artist),
+        Field::Album => text(&values.album),
+        Field::Year => values.year.clone().unwrap_or_default(),
+        Field::Track => number(values.track.into()),
+        Field::Disc => number(values.disc.into()),
+        Field::Isrc => values.isrc.clone().unwrap_or_default(),
+        Field::TrackId => number(values.track_id),
+        Field::Playlist => values.playlist.clone().unwrap_or_default(),
+        Field::PlaylistIndex => number(values.playlist_index.unwrap_or_default() as u64),
+        Field::Quality => text(&values.quality),
+        Field::Ext => text(&values.ext),
+    }
+}
+
+fn push_component(path: &mut PathBuf, component: &str) {
+    let component = trim_component(component);
+    if !component.is_empty() {
+        path.push(component);
+    }
+}
+
+/// Replaces characters that are invalid in file names on common filesystems
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Trims whitespace and trailing dots (invalid on Windows), avoids `.`/`..` and caps the length
+fn trim_component(component: &str) -> String {
+    let mut trimmed = component
+        .trim()
+        .trim_end_matches('.')
+        .trim_end()
+        .to_string();
+    if trimmed.len() > MAX_COMPONENT_BYTES {
+        // keep the extension of file names
+        let ext = trimmed
+            .rfind('.')
+            .filter(|dot| trimmed.len() - dot <= 16)
+            .map(|dot| trimmed[dot..].to_string())
+            .unwrap_or_default();
+        let mut cut = MAX_COMPONENT_BYTES - ext.len();
+        while !trimmed.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        trimmed = format!("{}{}", trimmed[..cut].trim_end(), ext);
+    }
+    if trimmed.chars().all(|c| c == '.') {
+        trimmed.clear();
+    }
+    trimmed
+}
+
+/// Options for [`TidalClient::download_album`] and [`TidalClient::download_playlist`]
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub template: PathTemplate,
+    /// Insert a `Disc N` folder before the file name for albums with more than one disc
+    pub disc_folders: bool,
+    /// Number of tracks downloaded at the same time
+    pub concurrency: usize,
+    /// Save the album cover as `folder.jpg` in every directory tracks are written to
+    pub cover_art: bool,
+    pub cover_size: u32,
+    /// Leave tracks whose file already exists alone instead of failing on them
+    ///
+    /// Unless the template uses `{quality}` this is checked before playback info is fetched,
+    /// with every extension a download can have for `{ext}`.
+    pub skip_existing: bool,
+    /// Options used for every track
+    pub download: DownloadOptions,
+    /// Page size and limit for walking the album or playlist
+    pub page: PageOptions,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            template: PathTemplate::default(),
+            disc_folders: false,
+            concurrency: 4,
+            cover_art: true,
+            cover_size: 1280,
+            skip_existing: true,
+            download: DownloadOptions::default(),
+            page: PageOptions::default(),
+        }
+    }
+}
+
+/// A track that couldn't be downloaded
+#[derive(Debug)]
+pub struct BatchFailure {
+    pub track_id: u64,
+    pub title: String,
+    pub error: TidalError,
+}
+
+/// Outcome of a batch download, in album or playlist order
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub downloaded: Vec<DownloadedTrack>,
+    /// Paths that already existed with `skip_existing`
+    pub skipped: Vec<PathBuf>,
+    pub failed: Vec<BatchFailure>,
+}
+
+enum TrackOutcome {
+    Downloaded(DownloadedTrack),
+    Skipped(PathBuf),
+}
+
+/// A track queued for download along with its template values and cover
+#[derive(Debug, Clone)]
+pub(crate) struct BatchItem {
+    pub(crate) track: Track,
+    pub(crate) values: TemplateValues,
+    /// Cover art UUID saved as `folder.jpg`
+    pub(crate) cover: Option<String>,
+    /// Insert a `Disc N` folder, set for multi-disc albums with `disc_folders`
+    pub(crate) disc_folder: bool,
+}
+
+impl BatchItem {
+    /// Destination of the item once the stream to download is known
+    pub(crate) fn dest(
+        &self,
+        root: &Path,
+        options: &BatchOptions,
+        playback: &TrackPlaybackInfoResponse,
+        plan: &DownloadPlan,
+    ) -> PathBuf {
+        template_dest(
+            root,
+            options,
+            &self.values,
+            self.disc_folder,
+            playback,
+            plan,
+        )
+    }
+}
+
+/// Renders the destination of a track once the stream to download is known
+pub(crate) fn template_dest(
+    root: &Path,
+    options: &BatchOptions,
+    values: &TemplateValues,
+    disc_folder: bool,
+    playback: &TrackPlaybackInfoResponse,
+    plan: &DownloadPlan,
+) -> PathBuf {
+    let mut values = values.clone();
+    values.quality = playback.audio_quality.to_string();
+    values.ext = if options.download.remux_flac && plan.is_fragmented_flac() {
+        "flac".to_string()
+    } else {
+        plan.container.extension().to_string()
+    };
+
+    root.join(batch_path(&options.template, &values, disc_folder))
+}
+
+/// Destination of a track that was already downloaded, found without fetching playback info
+///
+/// `{ext}` is tried with the extension of every container a download can end up in, templates
+/// using `{quality}` can't be resolved before the stream is known.
+pub(crate) async fn existing_template_dest(
+    root: &Path,
+    options: &BatchOptions,
+    values: &TemplateValues,
+    disc_folder: bool,
+) -> Result<Option<PathBuf>, TidalError> {
+    if options.template.uses(Field::Quality) {
+        return Ok(None);
+    }
+
+    let containers = if options.template.uses(Field::Ext) {
+        vec![Container::Flac, Container::Mp4]
+    } else {
+        vec![Container::Other(String::new())]
+    };
+    for container in containers {
+        let mut values = values.clone();
+        values.ext = container.extension().to_string();
+        let dest = root.join(batch_path(&options.template, &values, disc_folder));
+        if fs::try_exists(&dest).await? {
+            return Ok(Some(dest));
+        }
+    }
+
+    Ok(None)
+}
+
+impl TidalClient {
+    /// Downloads every track of an album into `root` using `options.template`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::download::batch::{BatchOptions, PathTemplate};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let options = BatchOptions {
+    ///     template: PathTemplate::new("{album_artist}/{album} ({year})/{track:02} {title}.{ext}")?,
+    ///     disc_folders: true,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let report = client.download_album("123456789", "music", &options).await?;
+    /// println!("{} downloaded, {} failed", report.downloaded.len(), report.failed.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_album(
+        &self,
+        album_id: impl Into<AlbumId>,
+        root: impl AsRef<Path>,
+        options: &BatchOptions,
+    ) -> Result<BatchReport, TidalError> {
+        let items = self.album_batch_items(album_id, options).await?;
+        Ok(self.download_batch(items, root.as_ref(), options).await)
+    }
+
+    /// Downloads every track of a playlist into `root` using `options.template`
+    ///
+    /// Album related placeholders use each track's own album, `{playlist}` and
+    /// `{playlist_index}` are available for playlist layouts.
+    pub async fn download_playlist(
+        &self,
+        playlist_id: impl Into<PlaylistId>,
+        root: impl AsRef<Path>,
+        options: &BatchOptions,
+    ) -> Result<BatchReport, TidalError> {
+        let items = self.playlist_batch_items(playlist_id, options).await?;
+        Ok(self.download_batch(items, root.as_ref(), options).await)
+    }
+
+    pub(crate) async fn album_batch_items(
+        &self,
+        album_id: impl Into<AlbumId>,
+        options: &BatchOptions,
+    ) -> Result<Vec<BatchItem>, TidalError> {
+        let album_id = album_id.into();
+        let album = self.get_album(album_id.clone()).await?;
+        let tracks: Vec<Track> = self
+            .get_album_items_all(album_id, options.page.clone())
+            .try_filter_map(|entry| async move {
+                Ok((entry.album_type == "track").then_some(entry.item))
+            })
+            .try_collect()
+            .await?;
+
+        debug!(album = %album.title, tracks = tracks.len(), "listed album tracks");
+
+        let disc_folder = options.disc_folders && album.number_of_volumes > 1;
+        let items = tracks
+            .into_iter()
+            .map(|track| {
+                let mut values = TemplateValues::from_track(&track);
+                values.album_artist = album.artist.name.clone();
+                values.album = album.title.clone();
+                values.year = release_year(&album.release_date).or(values.year);
+                BatchItem {
+                    track,
+                    values,
+                    cover: Some(album.cover.clone()),
+                    disc_folder,
+                }
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    pub(crate) async fn playlist_batch_items(
+        &self,
+        playlist_id: impl Into<PlaylistId>,
+        options: &BatchOptions,
+    ) -> Result<Vec<BatchItem>, TidalError> {
+        let playlist_id = playlist_id.into();
+        let playlist = self.get_playlist(playlist_id.clone()).await?;
+        let entries: Vec<PlaylistItem> = self
+            .get_playlist_items_all(playlist_id, None, None, options.page.clone())
+            .try_collect()
+            .await?;
+
+        debug!(playlist = %playlist.title, items = entries.len(), "listed playlist items");
+
+        // videos are skipped but keep their position, so indexes match the playlist
+        let items = entries
+            .into_iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.item_type == "track")
+            .map(|(index, entry)| {
+                let track = entry.item;
+                let mut values = TemplateValues::from_track(&track);
+                values.playlist = Some(playlist.title.clone());
+                values.playlist_index = Some(index + 1);
+                let cover = track.album.as_ref().and_then(|album| album.cover.clone());
+                BatchItem {
+                    track,
+                    values,
+                    cover,
+                    disc_folder: false,
+                }
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    async fn download_batch(
+        &self,
+        items: Vec<BatchItem>,
+        root: &Path,
+        options: &BatchOptions,
+    ) -> BatchReport {
+        let covers_saved = tokio::sync::Mutex::new(HashSet::new());
+        let covers_saved = &covers_saved;
+
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move {
+                let result = self
+                    .download_batch_item(&item, root, options, covers_saved)
+                    .await;
+                (item, result)
+            })
+            .buffered(options.concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut report = BatchReport::default();
+        for (item, result) in results {
+            match result {
+                Ok(TrackOutcome::Downloaded(track)) => report.downloaded.push(track),
+                Ok(TrackOutcome::Skipped(path)) => report.skipped.push(path),
+                Err(error) => {
+                    warn!(track_id = item.track.id, %error, "track download failed");
+                    report.failed.push(BatchFailure {
+                        track_id: item.track.id,
+                        title: item.track.title,
+                        error,
+                    });
+                }
+            }
+        }
+
+        info!(
+            downloaded = report.downloaded.len(),
+            skipped = report.skipped.len(),
+            failed = report.failed.len(),
+            "batch download finished"
+        );
+
+        report
+    }
+
+    async fn download_batch_item(
+        &self,
+        item: &BatchItem,
+        root: &Path,
+        options: &BatchOptions,
+        covers_saved: &tokio::sync::Mutex<HashSet<PathBuf>>,
+    ) -> Result<TrackOutcome, TidalError> {
+        if options.skip_existing
+            && let Some(dest) =
+                existing_template_dest(root, options, &item.values, item.disc_folder).await?
+        {
+            debug!(path = %dest.display(), "skipping existing track");
+            self.prepare_dir(&dest, item, options, covers_saved).await?;
+            return Ok(TrackOutcome::Skipped(dest));
+        }
+
+        let playback = self
+            .get_track_postpaywall_playback_info(
+                item.track.id.to_string(),
+                Some(options.download.playback.clone()),
+            )
+            .await?;
+        let plan = DownloadPlan::from_playback(&playback)?;
+        let dest = item.dest(root, options, &playback, &plan);
+        self.prepare_dir(&dest, item, options, covers_saved).await?;
+
+        if options.skip_existing && fs::try_exists(&dest).await? {
+            debug!(path = %dest.display(), "skipping existing track");
+            return Ok(TrackOutcome::Skipped(dest));
+        }
+
+        self.download_playback(&playback, &dest, &options.download)
+            .await
+            .map(TrackOutcome::Downloaded)
+    }
+
+    /// Creates the directory of `dest` and saves the item's cover in it once per batch
+    async fn prepare_dir(
+        &self,
+        dest: &Path,
+        item: &BatchItem,
+        options: &BatchOptions,
+        covers_saved: &tokio::sync::Mutex<HashSet<PathBuf>>,
+    ) -> Result<(), TidalError> {
+        let Some(dir) = dest.parent() else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(dir).await?;
+        if covers_saved.lock().await.insert(dir.to_path_buf()) {
+            self.save_folder_cover(item.cover.as_deref(), dir, options)
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Saves the cover of `item` as `folder.jpg` in `dir` unless disabled or already there
+    pub(crate) async fn save_folder_cover(
+        &self,
+        cover: Option<&str>,
+        dir: &Path,
+        options: &BatchOptions,
+    ) {
+        let (true, Some(uuid)) = (options.cover_art, cover) else {
+            return;
+        };
+
+        let path = dir.join(COVER_FILE_NAME);
+        if let Err(error) = self.save_cover(uuid, options.cover_size, &path).await {
+            // a missing cover shouldn't cost the track
+            warn!(path = %path.display(), %error, "failed to save cover art");
+        }
+    }
+
+    async fn save_cover(&self, uuid: &str, size: u32, path: &Path) -> Result<(), TidalError> {
+        if fs::try_exists(path).await? {
+            return Ok(());
+        }
+
+        let url = resources::image_url(self.rq.resources_base_url(), uuid, size);
+        let response = self.rq.http_client().get(&url).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                warn!(%url, "cover art not found");
+                Ok(())
+            }
+            status if !status.is_success() => Err(TidalError::InvalidResponse(format!(
+                "cover art request failed with HTTP {status}"
+            ))),
+            _ => {
+                fs::write(path, response.bytes().await?).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Renders the template, inserting a `Disc N` folder before the file name when requested
+fn batch_path(template: &PathTemplate, values: &TemplateValues, disc_folder: bool) -> PathBuf {
+    let path = template.render(values);
+    if !disc_folder {
+        return path;
+    }
+
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let mut with_disc = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    with_disc.push(format!("Disc {}", values.disc));
+    if let Some(file_name) = file_name {
+        with_disc.push(file_name);
+    }
+    with_disc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use serde_json::Value;
+
+    use super::{BatchOptions, COVER_FILE_NAME, PathTemplate, TemplateValues, batch_path};
+    use crate::{
+        client::pagination::PageOptions,
+        test_support::{
+            TestRequest, TestResponse, TestServer, album_json, api_client, items_page,
+            playback_json, playlist_json, temp_path, track_json,
+        },
+    };
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            title: "What? / Why: <Remix>".to_string(),
+            artist: "AC/DC".to_string(),
+            artists: vec!["AC/DC".to_string(), "Guest".to_string()],
+            album_artist: "AC/DC".to_string(),
+            album: "Back in Black...".to_string(),
+            year: Some("1980".to_string()),
+            track: 3,
+            disc: 2,
+            ext: "flac".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_template_with_padding_and_sanitized_values() {
+        let template =
+            PathTemplate::new("{album_artist}/{album} ({year})/{disc:02}-{track:02} {title}.{ext}")
+                .unwrap();
+
+        assert_eq!(
+            template.render(&values()),
+            PathBuf::from("AC_DC/Back in Black... (1980)/02-03 What_ _ Why_ _Remix_.flac")
+        );
+    }
+
+    #[test]
+    fn trims_trailing_dots_from_components() {
+        let template = PathTemplate::new("{album}/{track} {title}.{ext}").unwrap();
+        let mut values = values();
+        values.album = "Album...".to_string();
+        values.title = "Song".to_string();
+
+        assert_eq!(template.render(&values), PathBuf::from("Album/3 Song.flac"));
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(PathTemplate::new("{nope}.{ext}").is_err());
+        assert!(PathTemplate::new("{title").is_err());
+        assert!(PathTemplate::new("{track:2}").is_err());
+        assert!(PathTemplate::new("{album}/").is_err());
+        assert!(PathTemplate::new("{{literal}} {title}.{ext}").is_ok());
+    }
+
+    #[test]
+    fn disc_folder_is_inserted_before_file_name() {
+        let template = PathTemplate::new("{album}/{track:02} {ext}").unwrap();
+
+        assert_eq!(
+            batch_path(&template, &values(), true),
+            PathBuf::from("Back in Black/Disc 2/03 flac")
+        );
+    }
+
+    #[test]
+    fn missing_year_drops_its_brackets() {
+        let template =
+            PathTemplate::new("{album} ({year})/{track} [{isrc}] {title}.{ext}").unwrap();
+        let mut values = values();
+        values.album = "Album".to_string();
+        values.title = "Song".to_string();
+        values.year = None;
+
+        assert_eq!(template.render(&values), PathBuf::from("Album/3 Song.flac"));
+    }
+
+    #[test]
+    fn brackets_not_directly_around_an_empty_value_are_kept() {
+        let template = PathTemplate::new("{album} ({year} remaster).{ext}").unwrap();
+        let mut values = values();
+        values.album = "Album".to_string();
+        values.year = None;
+
+        assert_eq!(
+            template.render(&values),
+            PathBuf::from("Album ( remaster).flac")
+        );
+    }
+
+    /// Serves an album or playlist whose tracks are `tracks`, every track is a tiny FLAC file
+    fn batch_server(
+        tracks: Vec<(&'static str, Value)>,
+        playback_delay: Duration,
+    ) -> (TestServer, Arc<AtomicUsize>) {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let active = AtomicUsize::new(0);
+        let max_seen = peak.clone();
+        let server = TestServer::spawn(move |request: &TestRequest| {
+            let path = request.path.as_str();
+            let id = path.split('/').nth(2).unwrap_or_default();
+            match path {
+                "/albums/100/" => TestResponse::ok(album_json(100, "Album", "Band").to_string()),
+                "/playlists/p1/" => TestResponse::ok(playlist_json("p1", "Mix").to_string()),
+                "/albums/100/items" | "/playlists/p1/items" => items_page(request, &tracks),
+                "/images/aaaa/bbbb/1280x1280.jpg" => TestResponse::ok("cover"),
+                _ if path.ends_with("/playbackinfopostpaywall") => {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_seen.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(playback_delay);
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    let host = request.header("host").unwrap_or_default();
+                    let url = format!("http://{host}/files/{id}.flac");
+                    TestResponse::ok(playback_json(id.parse().unwrap(), &url).to_string())
+                }
+                _ if path.starts_with("/files/") => TestResponse::file(request, b"fLaC"),
+                _ => TestResponse::new(404),
+            }
+        });
+        (server, peak)
+    }
+
+    fn album_tracks(count: u64) -> Vec<(&'static str, Value)> {
+        (1..=count)
+            .map(|id| ("track", track_json(id, &format!("Song {id}"), "Band")))
+            .collect()
+    }
+
+    fn batch_options(template: &str) -> BatchOptions {
+        BatchOptions {
+            template: PathTemplate::new(template).unwrap(),
+            cover_size: 1280,
+            ..Default::default()
+        }
+    }
+
+    fn playback_requests(server: &TestServer) -> Vec<String> {
+        server
+            .request_lines()
+            .into_iter()
+            .filter(|line| line.ends_with("/playbackinfopostpaywall"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn download_album_walks_every_page_and_saves_the_cover() {
+        let (server, _) = batch_server(album_tracks(3), Duration::ZERO);
+        let client = api_client(&server);
+        let root = temp_path("album");
+        let options = BatchOptions {
+            page: PageOptions {
+                page_size: 2,
+                max_items: None,
+            },
+            ..batch_options("{album_artist}/{album} ({year})/{title}.{ext}")
+        };
+
+        let report = client
+            .download_album("100", &root, &options)
+            .await
+            .expect("album should be listed");
+
+        let dir = root.join("Band/Album (2020)");
+        assert_eq!(report.downloaded.len(), 3);
+        assert!(report.failed.is_empty());
+        for id in 1..=3 {
+            assert_eq!(
+                std::fs::read(dir.join(format!("Song {id}.flac"))).unwrap(),
+                b"fLaC"
+            );
+        }
+        assert_eq!(std::fs::read(dir.join(COVER_FILE_NAME)).unwrap(), b"cover");
+        let pages = server
+            .request_lines()
+            .iter()
+            .filter(|line| *line == "GET /albums/100/items")
+            .count();
+        assert_eq!(pages, 2);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn download_album_keeps_to_the_concurrency_limit() {
+        let (server, peak) = batch_server(album_tracks(6), Duration::from_millis(30));
+        let client = api_client(&server);
+        let root = temp_path("concurrent");
+        let options = BatchOptions {
+            concurrency: 2,
+            cover_art: false,
+            ..batch_options("{title}.{ext}")
+        };
+
+        let report = client.download_album("100", &root, &options).await.unwrap();
+
+        assert_eq!(report.downloaded.len(), 6);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn skip_existing_checks_before_fetching_playback_info() {
+        let (server, _) = batch_server(album_tracks(2), Duration::ZERO);
+        let client = api_client(&server);
+        let root = temp_path("skip");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("Song 1.flac"), b"existing").unwrap();
+        let options = BatchOptions {
+            cover_art: false,
+            ..batch_options("{title}.{ext}")
+        };
+
+        let report = client.download_album("100", &root, &options).await.unwrap();
+
+        assert_eq!(report.skipped, [root.join("Song 1.flac")]);
+        assert_eq!(report.downloaded.len(), 1);
+        assert_eq!(
+            playback_requests(&server),
+            ["GET /tracks/2/playbackinfopostpaywall"]
+        );
+        assert_eq!(
+            std::fs::read(root.join("Song 1.flac")).unwrap(),
+            b"existing"
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn playlist_index_counts_skipped_videos() {
+        let tracks = vec![
+            ("track", track_json(1, "First", "Band")),
+            ("video", track_json(2, "Video", "Band")),
+            ("track", track_json(3, "Third", "Band")),
+        ];
+        let (server, _) = batch_server(tracks, Duration::ZERO);
+        let client = api_client(&server);
+        let root = temp_path("playlist");
+        let options = batch_options("{playlist}/{playlist_index:02} {title}.{ext}");
+
+        let report = client
+            .download_playlist("p1", &root, &options)
+            .await
+            .unwrap();
+
+        let paths: Vec<PathBuf> = report.downloaded.into_iter().map(|t| t.path).collect();
+        assert_eq!(
+            paths,
+            [
+                root.join("Mix/01 First.flac"),
+                root.join("Mix/03 Third.flac")
+            ]
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
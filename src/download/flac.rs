@@ -0,0 +1,611 @@
+//! Remuxing of FLAC in fragmented MP4 (as delivered by HiRes DASH streams) into native FLAC
+//!
+//! The FLAC metadata blocks are taken from the `dfLa` box of the init segment and every sample
+//! referenced by the `trun` boxes of the fragments is a complete FLAC frame, so the output is
+//! just `fLaC`, the metadata blocks and the frames in order. If STREAMINFO doesn't carry the
+//! total number of samples, it's filled in from the sample durations.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use tracing::debug;
+
+use crate::error::TidalError;
+
+const STREAMINFO_LEN: usize = 34;
+/// Offset of STREAMINFO's data in the output, after `fLaC` and the block header
+const STREAMINFO_OFFSET: u64 = 8;
+
+/// Summary of a finished remux
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlacRemuxStats {
+    pub frames: u64,
+    /// Total number of samples per channel
+    pub total_samples: u64,
+    pub sample_rate: u32,
+    pub bytes_written: u64,
+}
+
+/// Remuxes a fragmented MP4 file (init segment followed by media segments) into a FLAC file
+pub async fn remux_fmp4_flac_file(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+) -> Result<FlacRemuxStats, TidalError> {
+    let src = src.as_ref().to_path_buf();
+    let dest = dest.as_ref().to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let reader = io::BufReader::new(std::fs::File::open(&src)?);
+        let mut writer = io::BufWriter::new(std::fs::File::create(&dest)?);
+        let stats = remux_fmp4_flac(reader, &mut writer)?;
+        writer.flush()?;
+        Ok(stats)
+    })
+    .await
+    .map_err(|e| TidalError::Remux(format!("remux task failed: {}", e)))?
+}
+
+/// Remuxes fragmented MP4 read from `reader` into a FLAC stream written to `writer`
+///
+/// Fragments are processed one at a time, so memory use is bounded by the largest fragment.
+pub fn remux_fmp4_flac<R: Read, W: Write + Seek>(
+    mut reader: R,
+    mut writer: W,
+) -> Result<FlacRemuxStats, TidalError> {
+    let mut position = 0_u64;
+    let mut track: Option<TrackInfo> = None;
+    let mut pending: Vec<Sample> = Vec::new();
+    let mut stats = FlacRemuxStats {
+        frames: 0,
+        total_samples: 0,
+        sample_rate: 0,
+        bytes_written: 0,
+    };
+    let mut duration_ticks = 0_u64;
+
+    while let Some(header) = BoxHeader::read(&mut reader)? {
+        let box_start = position;
+        position += header.header_len;
+        let payload_start = position;
+
+        match &header.kind {
+            b"moov" => {
+                let payload = read_payload(&mut reader, &header)?;
+                let info = TrackInfo::parse(&payload)?;
+                write_header(&mut writer, &info.metadata)?;
+                stats.sample_rate = info.sample_rate;
+                stats.bytes_written = 4 + info.metadata.len() as u64;
+                track = Some(info);
+            }
+            b"moof" => {
+                let info = track
+                    .as_ref()
+                    .ok_or_else(|| remux_error("moof before moov"))?;
+                let payload = read_payload(&mut reader, &header)?;
+                pending = parse_moof(&payload, box_start, info)?;
+            }
+            b"mdat" => {
+                if track.is_none() {
+                    return Err(remux_error("mdat before moov"));
+                }
+                let payload = read_payload(&mut reader, &header)?;
+
+                if pending.is_empty() {
+                    // no sample table, the whole payload is frames
+                    writer.write_all(&payload)?;
+                    stats.bytes_written += payload.len() as u64;
+                }
+                for sample in pending.drain(..) {
+                    let start = sample
+                        .offset
+                        .checked_sub(payload_start)
+                        .ok_or_else(|| remux_error("sample starts before mdat"))?;
+                    let end = start + sample.size;
+                    let frame = usize::try_from(start)
+                        .ok()
+                        .zip(usize::try_from(end).ok())
+                        .and_then(|(start, end)| payload.get(start..end))
+                        .ok_or_else(|| remux_error("sample exceeds mdat"))?;
+
+                    writer.write_all(frame)?;
+                    stats.frames += 1;
+                    stats.bytes_written += frame.len() as u64;
+                    duration_ticks += sample.duration;
+                }
+            }
+            _ => {
+                skip_payload(&mut reader, &header)?;
+            }
+        }
+
+        position = payload_start + header.payload_len.unwrap_or(0);
+        if header.payload_len.is_none() {
+            break;
+        }
+    }
+
+    let track = track.ok_or_else(|| remux_error("no moov box found"))?;
+    if stats.bytes_written <= 4 + track.metadata.len() as u64 {
+        return Err(remux_error("no FLAC frames found"));
+    }
+
+    stats.total_samples = match track.total_samples {
+        0 => {
+            let samples = ticks_to_samples(duration_ticks, track.timescale, track.sample_rate);
+            patch_total_samples(&mut writer, &track.streaminfo, samples)?;
+            samples
+        }
+        samples => samples,
+    };
+
+    debug!(
+        frames = stats.frames,
+        total_samples = stats.total_samples,
+        sample_rate = stats.sample_rate,
+        "remuxed fragmented MP4 to FLAC"
+    );
+
+    Ok(stats)
+}
+
+struct BoxHeader {
+    kind: [u8; 4],
+    header_len: u64,
+    /// `None` for a box extending to the end of the stream
+    payload_len: Option<u64>,
+}
+
+impl BoxHeader {
+    fn read<R: Read>(reader: &mut R) -> Result<Option<Self>, TidalError> {
+        let mut header = [0_u8; 8];
+        match read_exact_or_eof(reader, &mut header)? {
+            0 => return Ok(None),
+            8 => {}
+            _ => return Err(remux_error("truncated box header")),
+        }
+
+        let size = u64::from(u32::from_be_bytes(header[..4].try_into().unwrap()));
+        let kind: [u8; 4] = header[4..].try_into().unwrap();
+
+        let (header_len, size) = match size {
+            0 => return Ok(Some(Self::until_eof(kind))),
+            1 => {
+                let mut large = [0_u8; 8];
+                reader.read_exact(&mut large)?;
+                (16, u64::from_be_bytes(large))
+            }
+            size => (8, size),
+        };
+
+        let payload_len = size
+            .checked_sub(header_len)
+            .ok_or_else(|| remux_error("box size smaller than its header"))?;
+
+        Ok(Some(Self {
+            kind,
+            header_len,
+            payload_len: Some(payload_len),
+        }))
+    }
+
+    fn until_eof(kind: [u8; 4]) -> Self {
+        Self {
+            kind,
+            header_len: 8,
+            payload_len: None,
+        }
+    }
+}
+
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn read_payload<R: Read>(reader: &mut R, header: &BoxHeader) -> Result<Vec<u8>, TidalError> {
+    let mut payload = Vec::new();
+    match header.payload_len {
+        Some(len) => {
+            reader.take(len).read_to_end(&mut payload)?;
+            if payload.len() as u64 != len {
+                return Err(remux_error("truncated box"));
+            }
+        }
+        None => {
+            reader.read_to_end(&mut payload)?;
+        }
+    }
+    Ok(payload)
+}
+
+fn skip_payload<R: Read>(reader: &mut R, header: &BoxHeader) -> Result<(), TidalError> {
+    let len = header.payload_len.unwrap_or(u64::MAX);
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+    if header.payload_len.is_some_and(|len| len != skipped) {
+        return Err(remux_error("truncated box"));
+    }
+    Ok(())
+}
+
+/// Iterates over the child boxes of an in-memory payload
+fn children(mut data: &[u8]) -> impl Iterator<Item = Result<([u8; 4], &[u8]), TidalError>> {
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+
+        let result = (|| {
+            if data.len() < 8 {
+                return Err(remux_error("truncated child box"));
+            }
+            let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = data[4..8].try_into().unwrap();
+            let (header_len, size) = match size {
+                0 => (8, data.len()),
+                1 => {
+                    let large = data
+                        .get(8..16)
+                        .ok_or_else(|| remux_error("truncated child box"))?;
+                    let size = u64::from_be_bytes(large.try_into().unwrap());
+                    (
+                        16,
+                        usize::try_from(size).map_err(|_| remux_error("box too large"))?,
+                    )
+                }
+                size => (8, size),
+            };
+            if size < header_len || size > data.len() {
+                return Err(remux_error("invalid child box size"));
+            }
+
+            let payload = &data[header_len..size];
+            data = &data[size..];
+            Ok((kind, payload))
+        })();
+
+        if result.is_err() {
+            data = &[];
+        }
+        Some(result)
+    })
+}
+
+fn find_child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Result<Option<&'a [u8]>, TidalError> {
+    for child in children(data) {
+        let (child_kind, payload) = child?;
+        if &child_kind == kind {
+            return Ok(Some(payload));
+        }
+    }
+    Ok(None)
+}
+
+fn find_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Result<Option<&'a [u8]>, TidalError> {
+    let mut current = data;
+    for kind in path {
+        match find_child(current, kind)? {
+            Some(payload) => current = payload,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Everything needed from the init segment
+struct TrackInfo {
+    /// FLAC metadata blocks with the last-block flag fixed up
+    metadata: Vec<u8>,
+    streaminfo: [u8; STREAMINFO_LEN],
+    sample_rate: u32,
+    total_samples: u64,
+    timescale: u32,
+    default_sample_duration: u32,
+    default_sample_size: u32,
+}
+
+impl TrackInfo {
+    fn parse(moov: &[u8]) -> Result<Self, TidalError> {
+        let trak = find_child(moov, b"trak")?.ok_or_else(|| remux_error("moov without trak"))?;
+        let mdia = find_child(trak, b"mdia")?.ok_or_else(|| remux_error("trak without mdia"))?;
+        let timescale = find_child(mdia, b"mdhd")?
+            .map(parse_mdhd_timescale)
+            .transpose()?
+            .unwrap_or(0);
+
+        let stsd = find_path(mdia, &[b"minf", b"stbl", b"stsd"])?
+            .ok_or_else(|| remux_error("no sample description"))?;
+        // full box header + entry count
+        let entries = stsd.get(8..).ok_or_else(|| remux_error("truncated stsd"))?;
+        let flac_entry = find_child(entries, b"fLaC")?
+            .ok_or_else(|| remux_error("sample description is not fLaC"))?;
+        // audio sample entry fields precede the child boxes
+        let entry_children = flac_entry
+            .get(28..)
+            .ok_or_else(|| remux_error("truncated fLaC sample entry"))?;
+        let dfla = find_child(entry_children, b"dfLa")?
+            .ok_or_else(|| remux_error("fLaC sample entry without dfLa"))?;
+        let blocks = dfla.get(4..).ok_or_else(|| remux_error("truncated dfLa"))?;
+        let (metadata, streaminfo) = parse_metadata_blocks(blocks)?;
+
+        let sample_rate = (u32::from(streaminfo[10]) << 12)
+            | (u32::from(streaminfo[11]) << 4)
+            | (u32::from(streaminfo[12]) >> 4);
+        let total_samples = (u64::from(streaminfo[13] & 0x0f) << 32)
+            | u64::from(u32::from_be_bytes(streaminfo[14..18].try_into().unwrap()));
+
+        let (default_sample_duration, default_sample_size) =
+            match find_path(moov, &[b"mvex", b"trex"])? {
+                // version/flags, track id, sample description index, duration, size, flags
+                Some(trex) if trex.len() >= 20 => (read_u32(trex, 12)?, read_u32(trex, 16)?),
+                _ => (0, 0),
+            };
+
+        Ok(Self {
+            metadata,
+            streaminfo,
+            sample_rate,
+            total_samples,
+            timescale,
+            default_sample_duration,
+            default_sample_size,
+        })
+    }
+}
+
+fn parse_mdhd_timescale(mdhd: &[u8]) -> Result<u32, TidalError> {
+    match mdhd.first() {
+        Some(1) => read_u32(mdhd, 20),
+        Some(_) => read_u32(mdhd, 12),
+        None => Err(remux_error("truncated mdhd")),
+    }
+}
+
+/// Copies the metadata blocks of a `dfLa` box, making sure only the last one is flagged as last
+fn parse_metadata_blocks(data: &[u8]) -> Result<(Vec<u8>, [u8; STREAMINFO_LEN]), TidalError> {
+    let mut blocks = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 4 {
+        let last = rest[0] & 0x80 != 0;
+        let block_type = rest[0] & 0x7f;
+        let len = (usize::from(rest[1]) << 16) | (usize::from(rest[2]) << 8) | usize::from(rest[3]);
+        let body = rest
+            .get(4..4 + len)
+            .ok_or_else(|| remux_error("truncated FLAC metadata block"))?;
+        blocks.push((block_type, body));
+        rest = &rest[4 + len..];
+        if last {
+            break;
+        }
+    }
+
+    let streaminfo: [u8; STREAMINFO_LEN] = match blocks.first() {
+        Some((0, body)) if body.len() == STREAMINFO_LEN => (*body).try_into().unwrap(),
+        _ => return Err(remux_error("dfLa doesn't start with STREAMINFO")),
+    };
+
+    let mut metadata = Vec::new();
+    let count = blocks.len();
+    for (index, (block_type, body)) in blocks.into_iter().enumerate() {
+        let last = if index + 1 == count { 0x80 } else { 0 };
+        metadata.push(block_type | last);
+        metadata.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        metadata.extend_from_slice(body);
+    }
+
+    Ok((metadata, streaminfo))
+}
+
+fn write_header<W: Write>(writer: &mut W, metadata: &[u8]) -> Result<(), TidalError> {
+    writer.write_all(b"fLaC")?;
+    writer.write_all(metadata)?;
+    Ok(())
+}
+
+fn patch_total_samples<W: Write + Seek>(
+    writer: &mut W,
+    streaminfo: &[u8; STREAMINFO_LEN],
+    total_samples: u64,
+) -> Result<(), TidalError> {
+    let mut patched = *streaminfo;
+    patched[13] = (patched[13] & 0xf0) | ((total_samples >> 32) as u8 & 0x0f);
+    patched[14..18].copy_from_slice(&(total_samples as u32).to_be_bytes());
+
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+    writer.write_all(&patched)?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+fn ticks_to_samples(ticks: u64, timescale: u32, sample_rate: u32) -> u64 {
+    if timescale == 0 || timescale == sample_rate {
+        return ticks;
+    }
+    (u128::from(ticks) * u128::from(sample_rate) / u128::from(timescale)) as u64
+}
+
+/// A FLAC frame inside the next `mdat`, `offset` is absolute within the stream
+struct Sample {
+    offset: u64,
+    size: u64,
+    duration: u64,
+}
+
+fn parse_moof(moof: &[u8], moof_start: u64, track: &TrackInfo) -> Result<Vec<Sample>, TidalError> {
+    let mut samples = Vec::new();
+
+    for child in children(moof) {
+        let (kind, traf) = child?;
+        if &kind != b"traf" {
+            continue;
+        }
+
+        let tfhd = find_child(traf, b"tfhd")?.ok_or_else(|| remux_error("traf without tfhd"))?;
+        let tfhd_flags = read_u32(tfhd, 0)? & 0x00ff_ffff;
+        let mut cursor = 8;
+        let mut base = moof_start;
+        if tfhd_flags & 0x01 != 0 {
+            base = read_u64(tfhd, cursor)?;
+            cursor += 8;
+        }
+        if tfhd_flags & 0x02 != 0 {
+            cursor += 4;
+        }
+        let mut default_duration = track.default_sample_duration;
+        if tfhd_flags & 0x08 != 0 {
+            default_duration = read_u32(tfhd, cursor)?;
+            cursor += 4;
+        }
+        let mut default_size = track.default_sample_size;
+        if tfhd_flags & 0x10 != 0 {
+            default_size = read_u32(tfhd, cursor)?;
+        }
+
+        let mut next_offset = base;
+        for child in children(traf) {
+            let (kind, trun) = child?;
+            if &kind != b"trun" {
+                continue;
+            }
+
+            let flags = read_u32(trun, 0)? & 0x00ff_ffff;
+            let count = read_u32(trun, 4)?;
+            let mut cursor = 8;
+            if flags & 0x01 != 0 {
+                let data_offset = read_u32(trun, cursor)? as i32;
+                next_offset = base
+                    .checked_add_signed(i64::from(data_offset))
+                    .ok_or_else(|| remux_error("invalid trun data offset"))?;
+                cursor += 4;
+            }
+            if flags & 0x04 != 0 {
+                cursor += 4;
+            }
+
+            for _ in 0..count {
+                let mut duration = default_duration;
+                let mut size = default_size;
+                if flags & 0x100 != 0 {
+                    duration = read_u32(trun, cursor)?;
+                    cursor += 4;
+                }
+                if flags & 0x200 != 0 {
+                    size = read_u32(trun, cursor)?;
+                    cursor += 4;
+                }
+                if flags & 0x400 != 0 {
+                    cursor += 4;
+                }
+                if flags & 0x800 != 0 {
+                    cursor += 4;
+                }
+
+                if size == 0 {
+                    return Err(remux_error("sample without size"));
+                }
+                samples.push(Sample {
+                    offset: next_offset,
+                    size: u64::from(size),
+                    duration: u64::from(duration),
+                });
+                next_offset += u64::from(size);
+            }
+        }
+    }
+
+    Ok(samples)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, TidalError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| remux_error("truncated box field"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, TidalError> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| remux_error("truncated box field"))
+}
+
+fn remux_error(message: &str) -> TidalError {
+    TidalError::Remux(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::remux_fmp4_flac;
+    use crate::{
+        error::TidalError,
+        test_support::{fmp4_flac_fragment, fmp4_flac_init, mp4_box},
+    };
+
+    #[test]
+    fn remuxes_fragments_into_native_flac() {
+        let input = [
+            fmp4_flac_init(0),
+            fmp4_flac_fragment(&[b"frame1", b"frame2"], 4096),
+            fmp4_flac_fragment(&[b"frame3"], 1000),
+        ]
+        .concat();
+
+        let mut output = Cursor::new(Vec::new());
+        let stats = remux_fmp4_flac(Cursor::new(input), &mut output).expect("remux should work");
+        let output = output.into_inner();
+
+        assert_eq!(stats.frames, 3);
+        assert_eq!(stats.sample_rate, 96_000);
+        assert_eq!(stats.total_samples, 9192);
+        assert_eq!(&output[..4], b"fLaC");
+        // STREAMINFO is no longer flagged as last, the padding block is
+        assert_eq!(output[4], 0x00);
+        assert_eq!(output[4 + 4 + 34], 0x81);
+        assert!(output.ends_with(b"frame1frame2frame3"));
+
+        // total samples patched into STREAMINFO
+        let info = &output[8..42];
+        let total = (u64::from(info[13] & 0x0f) << 32)
+            | u64::from(u32::from_be_bytes(info[14..18].try_into().unwrap()));
+        assert_eq!(total, 9192);
+        // bits per sample are left alone
+        assert_eq!(info[13] >> 4, 23 & 0x0f);
+    }
+
+    #[test]
+    fn keeps_total_samples_from_streaminfo() {
+        let input = [
+            fmp4_flac_init(123_456),
+            fmp4_flac_fragment(&[b"frame"], 4096),
+        ]
+        .concat();
+
+        let mut output = Cursor::new(Vec::new());
+        let stats = remux_fmp4_flac(Cursor::new(input), &mut output).expect("remux should work");
+
+        assert_eq!(stats.total_samples, 123_456);
+    }
+
+    #[test]
+    fn rejects_non_flac_sample_entries() {
+        let mut mp4a = vec![0_u8; 28];
+        mp4a.extend(mp4_box(b"esds", &[0; 4]));
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_box(b"mp4a", &mp4a));
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let mdia = mp4_box(b"mdia", &mp4_box(b"minf", &stbl));
+        let moov = mp4_box(b"moov", &mp4_box(b"trak", &mdia));
+
+        let result = remux_fmp4_flac(Cursor::new(moov), Cursor::new(Vec::new()));
+        assert!(matches!(result, Err(TidalError::Remux(_))));
+    }
+}
@@ -0,0 +1,1195 @@
+//! Persistent, resumable download queue
+//!
+//! The queue lives in a JSON state file that is rewritten on every change, so a restarted
+//! process picks up where the previous one stopped. Tracks are written to `<dest>.part` with a
+//! checkpoint after every completed part: progressive streams resume with an HTTP `Range`
+//! request, DASH streams continue at the first segment that wasn't complete. Playback info is
+//! re-fetched when the signed stream URLs have expired.
+
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use reqwest::{StatusCode, header};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::broadcast,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    TidalClient,
+    client::models::{
+        playback::AudioQuality,
+        track::{config::TrackPlaybackInfoConfig, playback::TrackPlaybackInfoResponse},
+    },
+    decrypt::{self, StreamKey},
+    download::{
+        DownloadPlan, DownloadProgress,
+        batch::{BatchItem, BatchOptions, TemplateValues, existing_template_dest, template_dest},
+        part_path,
+    },
+    error::TidalError,
+};
+
+pub type JobId = u64;
+
+const EVENT_CAPACITY: usize = 256;
+
+/// What a job downloads
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobTarget {
+    Track {
+        track_id: String,
+        dest: JobDest,
+    },
+    /// Expanded into one track job per album track when it runs
+    Album {
+        album_id: String,
+        root: PathBuf,
+    },
+    /// Expanded into one track job per playlist track when it runs
+    Playlist {
+        playlist_id: String,
+        root: PathBuf,
+    },
+}
+
+/// Where a track job writes to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobDest {
+    Path(PathBuf),
+    /// Rendered with the manager's path template once the stream is known
+    Template {
+        root: PathBuf,
+        values: Box<TemplateValues>,
+        cover: Option<String>,
+        disc_folder: bool,
+    },
+}
+
+/// Lifecycle of a job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Paused,
+    Completed {
+        path: PathBuf,
+    },
+    /// The destination already existed with `skip_existing`
+    Skipped {
+        path: PathBuf,
+    },
+    /// Album or playlist job replaced by the listed track jobs
+    Expanded {
+        jobs: Vec<JobId>,
+    },
+    Failed {
+        error: String,
+    },
+    Cancelled,
+}
+
+impl JobState {
+    /// Whether the job will never run again
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobState::Completed { .. }
+                | JobState::Skipped { .. }
+                | JobState::Expanded { .. }
+                | JobState::Cancelled
+        )
+    }
+}
+
+/// Progress saved after every completed part of a track
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub total_parts: usize,
+    pub parts_completed: usize,
+    /// Size of the completed parts, the `.part` file may hold more of the next part
+    pub bytes_completed: u64,
+}
+
+/// A queued download
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadJob {
+    pub id: JobId,
+    pub target: JobTarget,
+    /// Overrides the quality of the manager's download options
+    pub audio_quality: Option<AudioQuality>,
+    pub state: JobState,
+    /// Destination of a started track job, kept so a resumed job finds its `.part` file
+    pub dest: Option<PathBuf>,
+    pub checkpoint: Option<Checkpoint>,
+}
+
+/// Sent to every [`DownloadManager::subscribe`] receiver
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManagerEvent {
+    Queued {
+        job: JobId,
+    },
+    Started {
+        job: JobId,
+    },
+    Progress {
+        job: JobId,
+        progress: DownloadProgress,
+    },
+    Paused {
+        job: JobId,
+    },
+    Completed {
+        job: JobId,
+        path: PathBuf,
+    },
+    Skipped {
+        job: JobId,
+        path: PathBuf,
+    },
+    Expanded {
+        job: JobId,
+        jobs: Vec<JobId>,
+    },
+    Failed {
+        job: JobId,
+        error: String,
+    },
+    Cancelled {
+        job: JobId,
+    },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ManagerState {
+    next_id: JobId,
+    jobs: Vec<DownloadJob>,
+}
+
+/// How a running track job ended
+enum Outcome {
+    Completed(PathBuf),
+    Skipped(PathBuf),
+    Paused,
+    Cancelled,
+}
+
+/// Persistent queue of track, album and playlist downloads
+///
+/// The manager doesn't own a client, [`run`](Self::run) borrows one, so it can be shared
+/// (e.g. in an `Arc`) to pause, resume or cancel jobs while it runs.
+///
+/// # Example
+///
+/// ```no_run
+/// # use tidlers::{TidalClient, auth::TidalAuth};
+/// # use tidlers::download::{batch::BatchOptions, manager::{DownloadManager, ManagerEvent}};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = TidalClient::new(&TidalAuth::with_oauth());
+/// let manager = DownloadManager::open("downloads.json", BatchOptions::default()).await?;
+/// manager.enqueue_album("123456789", "music", None).await?;
+///
+/// let mut events = manager.subscribe();
+/// tokio::spawn(async move {
+///     while let Ok(event) = events.recv().await {
+///         if let ManagerEvent::Completed { path, .. } = event {
+///             println!("done: {}", path.display());
+///         }
+///     }
+/// });
+///
+/// manager.run(&client).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DownloadManager {
+    state_path: PathBuf,
+    options: BatchOptions,
+    state: Mutex<ManagerState>,
+    /// Serializes writes of the state file
+    save_lock: tokio::sync::Mutex<()>,
+    events: broadcast::Sender<ManagerEvent>,
+}
+
+impl DownloadManager {
+    /// Opens the queue stored at `state_path`, starting empty if the file doesn't exist
+    ///
+    /// Jobs that were running when the previous process stopped are queued again.
+    pub async fn open(
+        state_path: impl AsRef<Path>,
+        options: BatchOptions,
+    ) -> Result<Self, TidalError> {
+        let state_path = state_path.as_ref().to_path_buf();
+        let mut state = match fs::read(&state_path).await {
+            Ok(data) => serde_json::from_slice::<ManagerState>(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ManagerState::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        for job in &mut state.jobs {
+            if job.state == JobState::Running {
+                job.state = JobState::Queued;
+            }
+        }
+        debug!(path = %state_path.display(), jobs = state.jobs.len(), "download queue opened");
+
+        Ok(Self {
+            state_path,
+            options,
+            state: Mutex::new(state),
+            save_lock: tokio::sync::Mutex::new(()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        })
+    }
+
+    /// Receives events of every job from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ManagerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Snapshot of every job in queue order
+    pub fn jobs(&self) -> Vec<DownloadJob> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    pub fn job(&self, id: JobId) -> Option<DownloadJob> {
+        self.state
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .find(|job| job.id == id)
+            .cloned()
+    }
+
+    /// Queues a single track written to `dest`
+    pub async fn enqueue_track(
+        &self,
+        track_id: impl Into<String>,
+        dest: impl AsRef<Path>,
+        audio_quality: Option<AudioQuality>,
+    ) -> Result<JobId, TidalError> {
+        let target = JobTarget::Track {
+            track_id: track_id.into(),
+            dest: JobDest::Path(dest.as_ref().to_path_buf()),
+        };
+        self.enqueue(target, audio_quality).await
+    }
+
+    /// Queues every track of an album, laid out in `root` with the manager's path template
+    pub async fn enqueue_album(
+        &self,
+        album_id: impl Into<String>,
+        root: impl AsRef<Path>,
+        audio_quality: Option<AudioQuality>,
+    ) -> Result<JobId, TidalError> {
+        let target = JobTarget::Album {
+            album_id: album_id.into(),
+            root: root.as_ref().to_path_buf(),
+        };
+        self.enqueue(target, audio_quality).await
+    }
+
+    /// Queues every track of a playlist, laid out in `root` with the manager's path template
+    pub async fn enqueue_playlist(
+        &self,
+        playlist_id: impl Into<String>,
+        root: impl AsRef<Path>,
+        audio_quality: Option<AudioQuality>,
+    ) -> Result<JobId, TidalError> {
+        let target = JobTarget::Playlist {
+            playlist_id: playlist_id.into(),
+            root: root.as_ref().to_path_buf(),
+        };
+        self.enqueue(target, audio_quality).await
+    }
+
+    async fn enqueue(
+        &self,
+        target: JobTarget,
+        audio_quality: Option<AudioQuality>,
+    ) -> Result<JobId, TidalError> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.jobs.push(new_job(id, target, audio_quality));
+            id
+        };
+
+        self.save().await?;
+        self.emit(ManagerEvent::Queued { job: id });
+        Ok(id)
+    }
+
+    /// Pauses a queued or running job, a running job stops after its current chunk
+    ///
+    /// A job whose transfer already completed finishes anyway.
+    pub async fn pause(&self, id: JobId) -> Result<(), TidalError> {
+        let previous = self.transition(id, |state| match state {
+            JobState::Queued | JobState::Running => Some(JobState::Paused),
+            _ => None,
+        })?;
+        self.save().await?;
+
+        // running jobs report their pause once they stopped
+        if previous == JobState::Queued {
+            self.emit(ManagerEvent::Paused { job: id });
+        }
+        Ok(())
+    }
+
+    /// Queues a paused or failed job again, it continues from its last checkpoint
+    pub async fn resume(&self, id: JobId) -> Result<(), TidalError> {
+        self.transition(id, |state| match state {
+            JobState::Paused | JobState::Failed { .. } => Some(JobState::Queued),
+            _ => None,
+        })?;
+        self.save().await?;
+        self.emit(ManagerEvent::Queued { job: id });
+        Ok(())
+    }
+
+    /// Cancels a job and removes its partial file
+    pub async fn cancel(&self, id: JobId) -> Result<(), TidalError> {
+        let previous = self.transition(id, |state| {
+            (!state.is_finished()).then_some(JobState::Cancelled)
+        })?;
+
+        // a running job removes its own file when it notices
+        if previous != JobState::Running
+            && let Some(dest) = self.job(id).and_then(|job| job.dest)
+        {
+            let _ = fs::remove_file(part_path(&dest)).await;
+        }
+
+        self.update(id, |job| job.checkpoint = None);
+        self.save().await?;
+        self.emit(ManagerEvent::Cancelled { job: id });
+        Ok(())
+    }
+
+    /// Runs queued jobs in order until none is left
+    ///
+    /// A failing job is marked as failed and the queue continues, only errors saving the
+    /// state file abort the run.
+    pub async fn run(&self, client: &TidalClient) -> Result<(), TidalError> {
+        while let Some(job) = self.next_queued() {
+            self.emit(ManagerEvent::Started { job: job.id });
+            self.save().await?;
+
+            let result = match &job.target {
+                JobTarget::Track { track_id, dest } => {
+                    self.run_track(client, &job, track_id, dest).await.map(Some)
+                }
+                JobTarget::Album { album_id, root } => {
+                    let items = client.album_batch_items(album_id.as_str(), &self.options);
+                    match items.await {
+                        Ok(items) => self.expand(&job, root, items).await.map(|_| None),
+                        Err(e) => Err(e),
+                    }
+                }
+                JobTarget::Playlist { playlist_id, root } => {
+                    let items = client.playlist_batch_items(playlist_id.as_str(), &self.options);
+                    match items.await {
+                        Ok(items) => self.expand(&job, root, items).await.map(|_| None),
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+
+            match result {
+                Ok(None) => {}
+                Ok(Some(Outcome::Completed(path))) => {
+                    if self.finish(job.id, JobState::Completed { path: path.clone() }) {
+                        self.emit(ManagerEvent::Completed { job: job.id, path });
+                    }
+                }
+                Ok(Some(Outcome::Skipped(path))) => {
+                    if self.finish(job.id, JobState::Skipped { path: path.clone() }) {
+                        self.emit(ManagerEvent::Skipped { job: job.id, path });
+                    }
+                }
+                Ok(Some(Outcome::Paused)) => {
+                    info!(job = job.id, "download paused");
+                }
+                Ok(Some(Outcome::Cancelled)) => {
+                    info!(job = job.id, "download cancelled");
+                }
+                Err(e) => {
+                    warn!(job = job.id, error = %e, "download job failed");
+                    let error = e.to_string();
+                    let failed = JobState::Failed {
+                        error: error.clone(),
+                    };
+                    if self.finish(job.id, failed) {
+                        self.emit(ManagerEvent::Failed { job: job.id, error });
+                    }
+                }
+            }
+            self.save().await?;
+        }
+
+        Ok(())
+    }
+
+    fn next_queued(&self) -> Option<DownloadJob> {
+        let mut state = self.state.lock().unwrap();
+        let job = state
+            .jobs
+            .iter_mut()
+            .find(|job| job.state == JobState::Queued)?;
+        job.state = JobState::Running;
+        Some(job.clone())
+    }
+
+    /// Replaces an album or playlist job by track jobs queued right after it
+    async fn expand(
+        &self,
+        job: &DownloadJob,
+        root: &Path,
+        items: Vec<BatchItem>,
+    ) -> Result<(), TidalError> {
+        let ids = {
+            let mut state = self.state.lock().unwrap();
+            let current = state.jobs.iter().find(|j| j.id == job.id).map(|j| &j.state);
+            match current {
+                Some(JobState::Running) => {}
+                Some(JobState::Paused) => {
+                    // paused while listing the tracks, resuming lists them again
+                    drop(state);
+                    self.emit(ManagerEvent::Paused { job: job.id });
+                    return Ok(());
+                }
+                // cancelled while listing the tracks
+                _ => return Ok(()),
+            }
+
+            let first_id = state.next_id;
+            state.next_id += items.len() as JobId;
+            let children: Vec<DownloadJob> = items
+                .into_iter()
+                .zip(first_id..)
+                .map(|(item, id)| {
+                    let target = JobTarget::Track {
+                        track_id: item.track.id.to_string(),
+                        dest: JobDest::Template {
+                            root: root.to_path_buf(),
+                            values: Box::new(item.values),
+                            cover: item.cover,
+                            disc_folder: item.disc_folder,
+                        },
+                    };
+                    new_job(id, target, job.audio_quality.clone())
+                })
+                .collect();
+            let ids: Vec<JobId> = children.iter().map(|child| child.id).collect();
+
+            let position = state.jobs.iter().position(|j| j.id == job.id).unwrap();
+            state.jobs[position].state = JobState::Expanded { jobs: ids.clone() };
+            state.jobs.splice(position + 1..position + 1, children);
+            ids
+        };
+
+        info!(job = job.id, tracks = ids.len(), "queued tracks");
+        self.emit(ManagerEvent::Expanded {
+            job: job.id,
+            jobs: ids.clone(),
+        });
+        for id in ids {
+            self.emit(ManagerEvent::Queued { job: id });
+        }
+        Ok(())
+    }
+
+    async fn fetch_playback(
+        &self,
+        client: &TidalClient,
+        job: &DownloadJob,
+        track_id: &str,
+    ) -> Result<(TrackPlaybackInfoResponse, DownloadPlan), TidalError> {
+        let mut config: TrackPlaybackInfoConfig = self.options.download.playback.clone();
+        if let Some(quality) = &job.audio_quality {
+            config.audio_quality = Some(quality.clone());
+        }
+
+        let playback = client
+            .get_track_postpaywall_playback_info(track_id, Some(config))
+            .await?;
+        let plan = DownloadPlan::from_playback(&playback)?;
+        Ok((playback, plan))
+    }
+
+    /// Destination of a job that already exists, checked before fetching playback info
+    async fn existing_dest(
+        &self,
+        job: &DownloadJob,
+        target: &JobDest,
+    ) -> Result<Option<PathBuf>, TidalError> {
+        let dest = match (&job.dest, target) {
+            (Some(dest), _) | (None, JobDest::Path(dest)) => dest,
+            (
+                None,
+                JobDest::Template {
+                    root,
+                    values,
+                    disc_folder,
+                    ..
+                },
+            ) => return existing_template_dest(root, &self.options, values, *disc_folder).await,
+        };
+        Ok(fs::try_exists(dest).await?.then(|| dest.clone()))
+    }
+
+    async fn run_track(
+        &self,
+        client: &TidalClient,
+        job: &DownloadJob,
+        track_id: &str,
+        target: &JobDest,
+    ) -> Result<Outcome, TidalError> {
+        if self.options.skip_existing
+            && let Some(dest) = self.existing_dest(job, target).await?
+        {
+            if let (Some(dir), JobDest::Template { cover, .. }) = (dest.parent(), target) {
+                client
+                    .save_folder_cover(cover.as_deref(), dir, &self.options)
+                    .await;
+            }
+            return Ok(Outcome::Skipped(dest));
+        }
+
+        let (mut playback, mut plan) = self.fetch_playback(client, job, track_id).await?;
+
+        let dest = match (&job.dest, target) {
+            (Some(resolved), _) => resolved.clone(),
+            (None, JobDest::Path(path)) => path.clone(),
+            (
+                None,
+                JobDest::Template {
+                    root,
+                    values,
+                    disc_folder,
+                    ..
+                },
+            ) => template_dest(root, &self.options, values, *disc_folder, &playback, &plan),
+        };
+        self.update(job.id, |job| job.dest = Some(dest.clone()));
+        self.save().await?;
+
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir).await?;
+            if let JobDest::Template { cover, .. } = target {
+                client
+                    .save_folder_cover(cover.as_deref(), dir, &self.options)
+                    .await;
+            }
+        }
+
+        if fs::try_exists(&dest).await? {
+            if self.options.skip_existing {
+                return Ok(Outcome::Skipped(dest));
+            }
+            if !self.options.download.overwrite {
+                return Err(TidalError::InvalidArgument(format!(
+                    "{} already exists",
+                    dest.display()
+                )));
+            }
+        }
+
+        let part_path = part_path(&dest);
+        let http = client.rq.http_client();
+        let mut refreshed = false;
+        let transfer = loop {
+            // a refreshed plan with another part count can't continue the old checkpoint
+            let checkpoint = self
+                .job(job.id)
+                .and_then(|job| job.checkpoint)
+                .filter(|checkpoint| checkpoint.total_parts == plan.parts.len());
+
+            match self
+                .transfer(
+                    http,
+                    job.id,
+                    &plan.parts,
+                    plan.key.as_ref(),
+                    &part_path,
+                    checkpoint,
+                )
+                .await
+            {
+                Err(TransferError::Expired(status)) if !refreshed => {
+                    // signed stream URLs are only valid for a while
+                    info!(job = job.id, %status, "stream URLs expired, refreshing playback info");
+                    (playback, plan) = self.fetch_playback(client, job, track_id).await?;
+                    refreshed = true;
+                }
+                Err(TransferError::Expired(status)) => {
+                    return Err(TidalError::IncompleteDownload(format!(
+                        "stream rejected with HTTP {status} after refreshing playback info"
+                    )));
+                }
+                Err(TransferError::Other(e)) => return Err(e),
+                Ok(transfer) => break transfer,
+            }
+        };
+
+        let bytes = match transfer {
+            Transfer::Complete(bytes) => bytes,
+            Transfer::Paused => {
+                self.emit(ManagerEvent::Paused { job: job.id });
+                return Ok(Outcome::Paused);
+            }
+            Transfer::Cancelled => {
+                let _ = fs::remove_file(&part_path).await;
+                return Ok(Outcome::Cancelled);
+            }
+        };
+
+        let downloaded = client
+            .finish_download(
+                &playback,
+                plan,
+                &part_path,
+                &dest,
+                bytes,
+                &self.options.download,
+            )
+            .await?;
+        self.update(job.id, |job| job.checkpoint = None);
+
+        Ok(Outcome::Completed(downloaded.path))
+    }
+
+    /// Writes the parts after `checkpoint` to `part_path`, appending to what's already there
+    ///
+    /// Without a checkpoint the file is started over. Only progressive streams carry a `key`,
+    /// their single part is decrypted as it arrives.
+    ///
+    /// Parts are fetched one after another instead of through
+    /// [`TidalClient::fetch_segments`]: every chunk lands on disk as it arrives, so a pause or
+    /// crash resumes inside the current part with a `Range` request instead of dropping the
+    /// segments held in memory, and expired URLs are told apart from other failures so the
+    /// playback info can be refreshed.
+    async fn transfer(
+        &self,
+        http: &reqwest::Client,
+        id: JobId,
+        parts: &[String],
+        key: Option<&StreamKey>,
+        part_path: &Path,
+        checkpoint: Option<Checkpoint>,
+    ) -> Result<Transfer, TransferError> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(part_path)
+            .await?;
+        let mut written = file.metadata().await?.len();
+        let mut checkpoint = match checkpoint {
+            Some(checkpoint) if written >= checkpoint.bytes_completed => checkpoint,
+            // no checkpoint or the file doesn't match it, start over
+            _ => {
+                written = 0;
+                file.set_len(0).await?;
+                let checkpoint = Checkpoint {
+                    total_parts: parts.len(),
+                    ..Default::default()
+                };
+                // a pause before the first part completes resumes from this one
+                self.update(id, |job| job.checkpoint = Some(checkpoint.clone()));
+                checkpoint
+            }
+        };
+        file.seek(SeekFrom::Start(written)).await?;
+
+        let mut progress = DownloadProgress {
+            bytes_downloaded: written,
+            total_bytes: None,
+            parts_downloaded: checkpoint.parts_completed,
+            total_parts: parts.len(),
+        };
+
+        for (index, url) in parts.iter().enumerate().skip(checkpoint.parts_completed) {
+            let offset = written - checkpoint.bytes_completed;
+            let mut request = http.get(url);
+            if offset > 0 {
+                request = request.header(header::RANGE, format!("bytes={offset}-"));
+            }
+
+            let mut response = request.send().await.map_err(TidalError::from)?;
+            let status = response.status();
+            match status {
+                StatusCode::PARTIAL_CONTENT if offset > 0 => {}
+                StatusCode::OK => {
+                    if offset > 0 {
+                        debug!(job = id, "server ignored the range, restarting the part");
+                        file.set_len(checkpoint.bytes_completed).await?;
+                        file.seek(SeekFrom::Start(checkpoint.bytes_completed))
+                            .await?;
+                        progress.bytes_downloaded -= offset;
+                        written = checkpoint.bytes_completed;
+                    }
+                }
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE => {
+                    file.flush().await?;
+                    return Err(TransferError::Expired(status));
+                }
+                _ => {
+                    return Err(TidalError::IncompleteDownload(format!(
+                        "part {} of {} failed with HTTP {}",
+                        index + 1,
+                        parts.len(),
+                        status
+                    ))
+                    .into());
+                }
+            }
+
+            let expected = response.content_length();
+            if parts.len() == 1 {
+                // a progressive stream is a single part, so its length is the total
+                progress.total_bytes = expected.map(|len| written + len);
+            }
+
+            let mut received = 0;
+            while let Some(chunk) = response.chunk().await.map_err(TidalError::from)? {
+                let offset = written - checkpoint.bytes_completed;
+                file.write_all(&decrypt::decrypted(key, offset, &chunk))
+                    .await?;
+                received += chunk.len() as u64;
+                written += chunk.len() as u64;
+                progress.bytes_downloaded += chunk.len() as u64;
+                self.emit(ManagerEvent::Progress {
+                    job: id,
+                    progress: progress.clone(),
+                });
+
+                match self.job_state(id) {
+                    Some(JobState::Paused) => {
+                        file.flush().await?;
+                        return Ok(Transfer::Paused);
+                    }
+                    Some(JobState::Cancelled) | None => return Ok(Transfer::Cancelled),
+                    _ => {}
+                }
+            }
+
+            let part_len = written - checkpoint.bytes_completed;
+            if part_len == 0 || expected.is_some_and(|expected| expected != received) {
+                file.flush().await?;
+                return Err(TidalError::IncompleteDownload(format!(
+                    "part {} of {} ended after {} of {} bytes",
+                    index + 1,
+                    parts.len(),
+                    received,
+                    expected.map_or_else(|| "unknown".to_string(), |e| e.to_string())
+                ))
+                .into());
+            }
+
+            file.flush().await?;
+            checkpoint.parts_completed += 1;
+            checkpoint.bytes_completed = written;
+            self.update(id, |job| job.checkpoint = Some(checkpoint.clone()));
+            self.save().await?;
+
+            progress.parts_downloaded = checkpoint.parts_completed;
+            if progress.parts_downloaded == progress.total_parts {
+                progress.total_bytes = Some(written);
+            }
+            self.emit(ManagerEvent::Progress {
+                job: id,
+                progress: progress.clone(),
+            });
+        }
+
+        file.sync_all().await?;
+        Ok(Transfer::Complete(written))
+    }
+
+    /// Applies a state change, failing for unknown jobs and changes `change` refuses
+    fn transition(
+        &self,
+        id: JobId,
+        change: impl FnOnce(&JobState) -> Option<JobState>,
+    ) -> Result<JobState, TidalError> {
+        let mut state = self.state.lock().unwrap();
+        let job = state
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .ok_or_else(|| TidalError::InvalidArgument(format!("unknown download job {id}")))?;
+
+        let next = change(&job.state).ok_or_else(|| {
+            TidalError::InvalidArgument(format!("download job {id} is {:?}", job.state))
+        })?;
+        Ok(std::mem::replace(&mut job.state, next))
+    }
+
+    /// Sets the final state of a job unless it was cancelled meanwhile, returns whether it did
+    ///
+    /// A pause only stops a transfer, a job that ended while its pause was pending takes the
+    /// final state.
+    fn finish(&self, id: JobId, next: JobState) -> bool {
+        let mut finished = false;
+        self.update(id, |job| {
+            if matches!(job.state, JobState::Running | JobState::Paused) {
+                job.state = next;
+                finished = true;
+            }
+        });
+        finished
+    }
+
+    fn job_state(&self, id: JobId) -> Option<JobState> {
+        self.state
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .find(|job| job.id == id)
+            .map(|job| job.state.clone())
+    }
+
+    fn update(&self, id: JobId, change: impl FnOnce(&mut DownloadJob)) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.jobs.iter_mut().find(|job| job.id == id) {
+            change(job);
+        }
+    }
+
+    /// Writes the state file atomically
+    async fn save(&self) -> Result<(), TidalError> {
+        let _guard = self.save_lock.lock().await;
+        let data = serde_json::to_vec_pretty(&*self.state.lock().unwrap())?;
+
+        let mut tmp = self.state_path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, &self.state_path).await?;
+        Ok(())
+    }
+
+    fn emit(&self, event: ManagerEvent) {
+        // no receivers is fine
+        let _ = self.events.send(event);
+    }
+}
+
+fn new_job(id: JobId, target: JobTarget, audio_quality: Option<AudioQuality>) -> DownloadJob {
+    DownloadJob {
+        id,
+        target,
+        audio_quality,
+        state: JobState::Queued,
+        dest: None,
+        checkpoint: None,
+    }
+}
+
+enum Transfer {
+    Complete(u64),
+    Paused,
+    Cancelled,
+}
+
+enum TransferError {
+    /// The stream URL was rejected, most likely because its signature expired
+    Expired(StatusCode),
+    Other(TidalError),
+}
+
+impl<E: Into<TidalError>> From<E> for TransferError {
+    fn from(e: E) -> Self {
+        TransferError::Other(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{Checkpoint, DownloadManager, JobState, JobTarget, ManagerEvent, Transfer};
+    use crate::{
+        download::{batch::BatchOptions, part_path},
+        test_support::{TestResponse, TestServer, api_client, playback_json, temp_path},
+    };
+
+    #[tokio::test]
+    async fn queue_survives_reopening() {
+        let state_path = temp_path("state.json");
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let track = manager.enqueue_track("1", "a.flac", None).await.unwrap();
+        let album = manager.enqueue_album("2", "music", None).await.unwrap();
+        manager.pause(album).await.unwrap();
+
+        // a job that was running when the process died is queued again
+        manager.update(track, |job| job.state = JobState::Running);
+        manager.save().await.unwrap();
+
+        let reopened = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let jobs = reopened.jobs();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].state, JobState::Queued);
+        assert_eq!(jobs[1].state, JobState::Paused);
+        assert!(matches!(jobs[1].target, JobTarget::Album { .. }));
+        assert!(reopened.resume(track).await.is_err());
+
+        std::fs::remove_file(state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn transfer_resumes_partial_file_with_range() {
+        let state_path = temp_path("range.json");
+        let dest = temp_path("range.flac");
+        let part = part_path(&dest);
+        std::fs::write(&part, b"fLaC-").unwrap();
+
+        let server = TestServer::spawn(|request| TestResponse::file(request, b"fLaC-data"));
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let id = manager.enqueue_track("1", &dest, None).await.unwrap();
+        let mut events = manager.subscribe();
+
+        let result = manager
+            .transfer(
+                &reqwest::Client::new(),
+                id,
+                &[server.at("track")],
+                None,
+                &part,
+                Some(Checkpoint {
+                    total_parts: 1,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+        assert!(matches!(result, Ok(Transfer::Complete(9))));
+        assert_eq!(std::fs::read(&part).unwrap(), b"fLaC-data");
+        let checkpoint = manager.job(id).unwrap().checkpoint.unwrap();
+        assert_eq!(checkpoint.parts_completed, 1);
+        assert_eq!(checkpoint.bytes_completed, 9);
+
+        // the total is known from the first chunk on
+        match events.try_recv().unwrap() {
+            ManagerEvent::Progress { progress, .. } => {
+                assert_eq!(progress.parts_downloaded, 0);
+                assert_eq!(progress.total_bytes, Some(9));
+            }
+            other => panic!("expected progress, got {other:?}"),
+        }
+
+        std::fs::remove_file(part).unwrap();
+        std::fs::remove_file(state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn transfer_continues_after_completed_segments() {
+        let state_path = temp_path("segments.json");
+        let dest = temp_path("segments.m4a");
+        let part = part_path(&dest);
+        // init and first segment done, plus a torn write of the second one
+        std::fs::write(&part, b"IAB").unwrap();
+
+        let server = TestServer::spawn(|request| TestResponse::file(request, b"BC"));
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let id = manager.enqueue_track("1", &dest, None).await.unwrap();
+        let parts = [server.at("init"), server.at("seg1"), server.at("seg2")];
+
+        let result = manager
+            .transfer(
+                &reqwest::Client::new(),
+                id,
+                &parts,
+                None,
+                &part,
+                Some(Checkpoint {
+                    total_parts: 3,
+                    parts_completed: 2,
+                    bytes_completed: 2,
+                }),
+            )
+            .await;
+
+        // only the last segment is requested, from the byte the torn write ended at
+        assert!(matches!(result, Ok(Transfer::Complete(4))));
+        assert_eq!(std::fs::read(&part).unwrap(), b"IABC");
+        assert_eq!(
+            manager.job(id).unwrap().checkpoint.unwrap().parts_completed,
+            3
+        );
+
+        std::fs::remove_file(part).unwrap();
+        std::fs::remove_file(state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn transfer_restarts_part_when_server_ignores_range() {
+        let state_path = temp_path("no-range.json");
+        let dest = temp_path("no-range.flac");
+        let part = part_path(&dest);
+        std::fs::write(&part, b"fLaC-").unwrap();
+
+        // always answers with the whole file
+        let server = TestServer::spawn(|_| TestResponse::ok("fLaC-data"));
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let id = manager.enqueue_track("1", &dest, None).await.unwrap();
+
+        let result = manager
+            .transfer(
+                &reqwest::Client::new(),
+                id,
+                &[server.at("track")],
+                None,
+                &part,
+                Some(Checkpoint {
+                    total_parts: 1,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+        assert!(matches!(result, Ok(Transfer::Complete(9))));
+        assert_eq!(server.requests()[0].header("range"), Some("bytes=5-"));
+        assert_eq!(std::fs::read(&part).unwrap(), b"fLaC-data");
+
+        std::fs::remove_file(part).unwrap();
+        std::fs::remove_file(state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn checkpoint_of_another_part_count_starts_the_file_over() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/tracks/1/playbackinfopostpaywall" => {
+                let host = request.header("host").unwrap();
+                let url = format!("http://{host}/track.flac");
+                TestResponse::ok(playback_json(1, &url).to_string())
+            }
+            _ => TestResponse::file(request, b"fLaC-data"),
+        });
+        let client = api_client(&server);
+        let state_path = temp_path("replanned.json");
+        let dest = temp_path("replanned.flac");
+        let part = part_path(&dest);
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let id = manager.enqueue_track("1", &dest, None).await.unwrap();
+        // left over from a DASH plan with three parts
+        std::fs::write(&part, b"IAB").unwrap();
+        manager.update(id, |job| {
+            job.dest = Some(dest.clone());
+            job.checkpoint = Some(Checkpoint {
+                total_parts: 3,
+                parts_completed: 2,
+                bytes_completed: 2,
+            });
+        });
+
+        manager.run(&client).await.unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"fLaC-data");
+        assert_eq!(server.requests()[1].header("range"), None);
+
+        std::fs::remove_file(dest).unwrap();
+        std::fs::remove_file(state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn expired_stream_urls_are_refreshed_once() {
+        let playbacks = AtomicUsize::new(0);
+        let server = TestServer::spawn(move |request| match request.path.as_str() {
+            "/tracks/1/playbackinfopostpaywall" => {
+                // the first playback info hands out an expired URL
+                let file = match playbacks.fetch_add(1, Ordering::SeqCst) {
+                    0 => "expired",
+                    _ => "fresh",
+                };
+                let host = request.header("host").unwrap();
+                let url = format!("http://{host}/{file}.flac");
+                TestResponse::ok(playback_json(1, &url).to_string())
+            }
+            "/fresh.flac" => TestResponse::file(request, b"fLaC-data"),
+            _ => TestResponse::new(403),
+        });
+        let client = api_client(&server);
+        let state_path = temp_path("expired.json");
+        let dest = temp_path("expired.flac");
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let id = manager.enqueue_track("1", &dest, None).await.unwrap();
+
+        manager.run(&client).await.unwrap();
+
+        assert_eq!(
+            manager.job(id).unwrap().state,
+            JobState::Completed { path: dest.clone() }
+        );
+        assert_eq!(std::fs::read(&dest).unwrap(), b"fLaC-data");
+        assert_eq!(
+            server.request_lines(),
+            [
+                "GET /tracks/1/playbackinfopostpaywall",
+                "GET /expired.flac",
+                "GET /tracks/1/playbackinfopostpaywall",
+                "GET /fresh.flac",
+            ]
+        );
+
+        std::fs::remove_file(dest).unwrap();
+        std::fs::remove_file(state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn job_paused_after_its_transfer_still_finishes() {
+        let state_path = temp_path("late-pause.json");
+        let manager = DownloadManager::open(&state_path, BatchOptions::default())
+            .await
+            .unwrap();
+        let id = manager.enqueue_track("1", "a.flac", None).await.unwrap();
+        manager.update(id, |job| job.state = JobState::Running);
+
+        // paused while the finished transfer is remuxed or tagged
+        manager.pause(id).await.unwrap();
+        let path = std::path::PathBuf::from("a.flac");
+        assert!(manager.finish(id, JobState::Completed { path: path.clone() }));
+        assert_eq!(manager.job(id).unwrap().state, JobState::Completed { path });
+
+        // a cancel wins, its event was already sent
+        let cancelled = manager.enqueue_track("2", "b.flac", None).await.unwrap();
+        manager.cancel(cancelled).await.unwrap();
+        let failed = JobState::Failed {
+            error: "late".to_string(),
+        };
+        assert!(!manager.finish(cancelled, failed));
+        assert_eq!(manager.job(cancelled).unwrap().state, JobState::Cancelled);
+
+        std::fs::remove_file(state_path).unwrap();
+    }
+}
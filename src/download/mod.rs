@@ -0,0 +1,957 @@
+//! Downloading tracks to playable files
+
+use std::{
+    fmt,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use tokio::{
+    fs,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    TidalClient,
+    client::models::track::{
+        config::TrackPlaybackInfoConfig,
+        playback::{ParsedTrackManifest, TrackPlaybackInfoResponse},
+    },
+    decrypt::{self, StreamKey},
+    download::{segments::SegmentFetchOptions, tags::TagOptions},
+    error::TidalError,
+    ids::TrackId,
+};
+
+pub mod batch;
+pub mod flac;
+pub mod manager;
+pub mod segments;
+pub mod tags;
+pub mod video;
+
+/// Called after every received chunk with the current download state
+pub type ProgressCallback = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// Options for [`TidalClient::download_track`]
+#[derive(Clone, Default)]
+pub struct DownloadOptions {
+    /// Playback config used to request the stream, unset fields fall back to the session config
+    pub playback: TrackPlaybackInfoConfig,
+    /// Replace the destination file if it already exists
+    pub overwrite: bool,
+    /// Remux FLAC delivered in fragmented MP4 (`HI_RES_LOSSLESS`) into a native FLAC file
+    pub remux_flac: bool,
+    /// Tag the file with the track's metadata once downloaded
+    pub tags: Option<TagOptions>,
+    /// Concurrency and bandwidth cap for the segments of DASH streams
+    pub segments: SegmentFetchOptions,
+    pub progress: Option<ProgressCallback>,
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadOptions")
+            .field("overwrite", &self.overwrite)
+            .field("remux_flac", &self.remux_flac)
+            .field("tags", &self.tags)
+            .field("segments", &self.segments)
+            .field("progress", &self.progress.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Progress of a running download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadProgress {
+    pub bytes_downloaded: u64,
+    /// Known as soon as the response headers of a progressive stream arrive, for DASH streams
+    /// once every part reported its size, `None` before that
+    pub total_bytes: Option<u64>,
+    /// Parts are the single file of a progressive stream, or the init and media segments of a
+    /// DASH stream
+    pub parts_downloaded: usize,
+    pub total_parts: usize,
+}
+
+/// Container of a downloaded stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Container {
+    /// Native FLAC file
+    Flac,
+    /// MP4, fragmented for DASH streams (AAC or FLAC inside)
+    Mp4,
+    /// MPEG transport stream, as delivered for HLS video
+    MpegTs,
+    Other(String),
+}
+
+impl Container {
+    fn from_mime_type(mime_type: &str) -> Self {
+        match mime_type {
+            "audio/flac" => Container::Flac,
+            "audio/mp4" | "video/mp4" => Container::Mp4,
+            "video/mp2t" => Container::MpegTs,
+            other => Container::Other(other.to_string()),
+        }
+    }
+
+    /// File extension commonly used for this container
+    pub fn extension(&self) -> &str {
+        match self {
+            Container::Flac => "flac",
+            Container::Mp4 => "m4a",
+            Container::MpegTs => "ts",
+            Container::Other(_) => "bin",
+        }
+    }
+}
+
+/// A finished download
+#[derive(Debug, Clone)]
+pub struct DownloadedTrack {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub container: Container,
+    pub mime_type: String,
+    pub codecs: String,
+    pub audio_quality: String,
+}
+
+/// Ordered list of URLs whose bodies make up the file
+#[derive(Debug, Clone)]
+pub(crate) struct DownloadPlan {
+    pub(crate) parts: Vec<String>,
+    pub(crate) container: Container,
+    pub(crate) mime_type: String,
+    pub(crate) codecs: String,
+    /// Key of `OLD_AES` streams, every byte is decrypted before it's written
+    pub(crate) key: Option<StreamKey>,
+}
+
+impl DownloadPlan {
+    /// FLAC frames inside MP4, as delivered for `HI_RES_LOSSLESS`
+    pub(crate) fn is_fragmented_flac(&self) -> bool {
+        self.container == Container::Mp4 && self.codecs.eq_ignore_ascii_case("flac")
+    }
+
+    pub(crate) fn from_playback(playback: &TrackPlaybackInfoResponse) -> Result<Self, TidalError> {
+        let manifest = playback.manifest_parsed.as_ref().ok_or_else(|| {
+            TidalError::InvalidResponse("playback info has no manifest".to_string())
+        })?;
+
+        match manifest {
+            ParsedTrackManifest::Json(json) => {
+                let key = decrypt::stream_key(&json.encryption_type, json.key_id.as_deref())?;
+
+                let url = json.urls.first().ok_or_else(|| {
+                    TidalError::InvalidManifest("manifest has no stream URL".to_string())
+                })?;
+
+                Ok(Self {
+                    parts: vec![url.clone()],
+                    container: Container::from_mime_type(&json.mime_type),
+                    mime_type: json.mime_type.clone(),
+                    codecs: json.codecs.clone(),
+                    key,
+                })
+            }
+            ParsedTrackManifest::Dash(dash) => {
+                let list = dash.segments()?;
+                let init = list.initialization.ok_or_else(|| {
+                    TidalError::InvalidManifest("DASH manifest has no initialization".to_string())
+                })?;
+
+                let mut parts = vec![init];
+                parts.extend(list.segments.into_iter().map(|segment| segment.url));
+
+                Ok(Self {
+                    parts,
+                    container: Container::from_mime_type(&dash.mime_type),
+                    mime_type: dash.mime_type.clone(),
+                    codecs: dash.codecs.clone(),
+                    key: None,
+                })
+            }
+        }
+    }
+}
+
+impl TidalClient {
+    /// Downloads a track to `dest`
+    ///
+    /// The stream is requested through `get_track_postpaywall_playback_info` and written to a
+    /// `.part` file next to `dest` chunk by chunk, which is renamed to `dest` once every part
+    /// arrived completely. Progressive streams (`LOW` to `LOSSLESS`) are written as is, DASH
+    /// streams (`HI_RES_LOSSLESS`) are written as init segment followed by every media segment.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::download::DownloadOptions;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let options = DownloadOptions {
+    ///     progress: Some(Arc::new(|progress| {
+    ///         println!("{}/{} parts", progress.parts_downloaded, progress.total_parts);
+    ///     })),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let track = client.download_track("123456789", "track.flac", options).await?;
+    /// println!("wrote {} bytes ({:?})", track.bytes, track.container);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_track(
+        &self,
+        track_id: impl Into<TrackId>,
+        dest: impl AsRef<Path>,
+        options: DownloadOptions,
+    ) -> Result<DownloadedTrack, TidalError> {
+        let track_id = track_id.into();
+        debug!(%track_id, "requesting playback info for download");
+        let playback = self
+            .get_track_postpaywall_playback_info(track_id, Some(options.playback.clone()))
+            .await?;
+
+        self.download_playback(&playback, dest, &options).await
+    }
+
+    /// Downloads the stream described by already fetched playback info to `dest`
+    pub async fn download_playback(
+        &self,
+        playback: &TrackPlaybackInfoResponse,
+        dest: impl AsRef<Path>,
+        options: &DownloadOptions,
+    ) -> Result<DownloadedTrack, TidalError> {
+        let dest = dest.as_ref();
+        let plan = DownloadPlan::from_playback(playback)?;
+
+        if !options.overwrite && fs::try_exists(dest).await? {
+            return Err(TidalError::InvalidArgument(format!(
+                "{} already exists",
+                dest.display()
+            )));
+        }
+
+        let part_path = part_path(dest);
+        let result = self.write_parts(&plan, &part_path, options).await;
+        let bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = fs::remove_file(&part_path).await;
+                return Err(e);
+            }
+        };
+
+        self.finish_download(playback, plan, &part_path, dest, bytes, options)
+            .await
+    }
+
+    /// Moves a complete `.part` file to `dest`, remuxing and tagging it depending on `options`
+    ///
+    /// The remux is written to a file next to `dest` and tags are written before the final rename,
+    /// so `dest` only ever holds a finished file. If remuxing or tagging fails the `.part` file is
+    /// kept.
+    pub(crate) async fn finish_download(
+        &self,
+        playback: &TrackPlaybackInfoResponse,
+        plan: DownloadPlan,
+        part_path: &Path,
+        dest: &Path,
+        bytes: u64,
+        options: &DownloadOptions,
+    ) -> Result<DownloadedTrack, TidalError> {
+        let remux = options.remux_flac && plan.is_fragmented_flac();
+        let staged = if remux {
+            remux_path(dest)
+        } else {
+            part_path.to_path_buf()
+        };
+
+        let result = self
+            .stage_download(playback, &plan, part_path, &staged, remux, options)
+            .await;
+        let mut downloaded = match result {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                if remux {
+                    let _ = fs::remove_file(&staged).await;
+                }
+                return Err(e);
+            }
+        };
+
+        fs::rename(&staged, dest).await?;
+        if remux {
+            let _ = fs::remove_file(part_path).await;
+        }
+        downloaded.path = dest.to_path_buf();
+
+        info!(
+            path = %dest.display(),
+            bytes = downloaded.bytes,
+            downloaded = bytes,
+            parts = plan.parts.len(),
+            "track downloaded"
+        );
+
+        Ok(downloaded)
+    }
+
+    /// Remuxes the `.part` file into `staged` if `remux` is set and tags `staged`
+    async fn stage_download(
+        &self,
+        playback: &TrackPlaybackInfoResponse,
+        plan: &DownloadPlan,
+        part_path: &Path,
+        staged: &Path,
+        remux: bool,
+        options: &DownloadOptions,
+    ) -> Result<DownloadedTrack, TidalError> {
+        let mut downloaded = DownloadedTrack {
+            path: staged.to_path_buf(),
+            bytes: 0,
+            container: plan.container.clone(),
+            mime_type: plan.mime_type.clone(),
+            codecs: plan.codecs.clone(),
+            audio_quality: playback.audio_quality.to_string(),
+        };
+
+        if remux {
+            let stats = flac::remux_fmp4_flac_file(part_path, staged).await?;
+            debug!(
+                path = %staged.display(),
+                bytes = stats.bytes_written,
+                frames = stats.frames,
+                "remuxed to FLAC"
+            );
+            downloaded.container = Container::Flac;
+            downloaded.mime_type = "audio/flac".to_string();
+        }
+
+        if let Some(tag_options) = &options.tags {
+            let tags = self
+                .fetch_track_tags(playback.track_id.to_string(), tag_options)
+                .await?
+                .with_playback(playback);
+            tags::write_tags(staged, &downloaded.container, &tags).await?;
+        }
+
+        // remuxing and tagging both change the size
+        downloaded.bytes = fs::metadata(staged).await?.len();
+        Ok(downloaded)
+    }
+
+    /// Writes every part to `path` in order, retrying failed parts
+    ///
+    /// Progressive streams are retried with the client's
+    /// [`RetryPolicy`](crate::requests::RetryPolicy). The segments of DASH streams are fetched
+    /// concurrently and retried with the segment options, see [`TidalClient::fetch_segments`].
+    /// `OLD_AES` streams are decrypted as they arrive.
+    async fn write_parts(
+        &self,
+        plan: &DownloadPlan,
+        path: &Path,
+        options: &DownloadOptions,
+    ) -> Result<u64, TidalError> {
+        match plan.parts.as_slice() {
+            [url] => {
+                self.write_progressive(url, plan.key.as_ref(), path, options)
+                    .await
+            }
+            parts => {
+                self.write_segments(parts, path, &options.segments, options.progress.as_ref())
+                    .await
+            }
+        }
+    }
+
+    /// Streams the single file of a progressive stream to `path`
+    async fn write_progressive(
+        &self,
+        url: &str,
+        key: Option<&StreamKey>,
+        path: &Path,
+        options: &DownloadOptions,
+    ) -> Result<u64, TidalError> {
+        let mut file = fs::File::create(path).await?;
+        let mut progress = DownloadProgress {
+            bytes_downloaded: 0,
+            total_bytes: None,
+            parts_downloaded: 0,
+            total_parts: 1,
+        };
+        let max_attempts = self
+            .rq
+            .retry_policy()
+            .map_or(1, |policy| policy.max_attempts);
+
+        let mut attempt = 1;
+        let expected = loop {
+            let failure = match self
+                .write_part(&mut file, url, key, &mut progress, options)
+                .await
+            {
+                Ok(expected) => break expected,
+                Err(failure) => failure,
+            };
+
+            let error = match failure {
+                PartFailure::Retryable(error) if attempt < max_attempts => error,
+                PartFailure::Retryable(error) | PartFailure::Fatal(error) => {
+                    return Err(TidalError::IncompleteDownload(format!(
+                        "download failed: {error}"
+                    )));
+                }
+            };
+
+            let delay = self
+                .rq
+                .retry_policy()
+                .map_or(Duration::ZERO, |policy| policy.backoff_delay(attempt));
+            warn!(
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                %error,
+                "download failed, retrying"
+            );
+
+            // drop what the failed attempt wrote and fetch the file again
+            file.set_len(0).await?;
+            file.seek(SeekFrom::Start(0)).await?;
+            progress.bytes_downloaded = 0;
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        progress.parts_downloaded = 1;
+        progress.total_bytes = Some(expected.unwrap_or_default().max(progress.bytes_downloaded));
+        if let Some(callback) = &options.progress {
+            callback(&progress);
+        }
+
+        file.flush().await?;
+        file.sync_all().await?;
+
+        Ok(progress.bytes_downloaded)
+    }
+
+    /// Writes the segments of a DASH or HLS stream to `path` as they arrive in order
+    async fn write_segments(
+        &self,
+        parts: &[String],
+        path: &Path,
+        segments: &SegmentFetchOptions,
+        progress_callback: Option<&ProgressCallback>,
+    ) -> Result<u64, TidalError> {
+        let mut file = fs::File::create(path).await?;
+        let mut progress = DownloadProgress {
+            bytes_downloaded: 0,
+            total_bytes: None,
+            parts_downloaded: 0,
+            total_parts: parts.len(),
+        };
+
+        let segments = self.fetch_segments(parts.iter().cloned(), segments);
+        let mut segments = std::pin::pin!(segments);
+        while let Some(segment) = segments.next().await {
+            let segment = segment?;
+            file.write_all(&segment).await?;
+
+            progress.bytes_downloaded += segment.len() as u64;
+            progress.parts_downloaded += 1;
+            if progress.parts_downloaded == progress.total_parts {
+                progress.total_bytes = Some(progress.bytes_downloaded);
+            }
+            if let Some(callback) = progress_callback {
+                callback(&progress);
+            }
+        }
+
+        file.flush().await?;
+        file.sync_all().await?;
+
+        Ok(progress.bytes_downloaded)
+    }
+
+    /// Appends the file of a progressive stream to `file`, returns its advertised length
+    async fn write_part(
+        &self,
+        file: &mut fs::File,
+        url: &str,
+        key: Option<&StreamKey>,
+        progress: &mut DownloadProgress,
+        options: &DownloadOptions,
+    ) -> Result<Option<u64>, PartFailure> {
+        let mut response = self
+            .rq
+            .http_client()
+            .get(url)
+            .send()
+            .await
+            .map_err(PartFailure::from)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error = TidalError::Other(format!("HTTP {status}"));
+            return Err(
+                if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                    PartFailure::Retryable(error)
+                } else {
+                    PartFailure::Fatal(error)
+                },
+            );
+        }
+
+        // a progressive stream is a single part, so its length is the total
+        let expected = response.content_length();
+        progress.total_bytes = expected;
+
+        let mut received = 0;
+        // a body that breaks off is worth another try whatever the cause
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| PartFailure::Retryable(e.into()))?
+        {
+            file.write_all(&decrypt::decrypted(key, received, &chunk))
+                .await
+                .map_err(|e| PartFailure::Fatal(e.into()))?;
+            received += chunk.len() as u64;
+            progress.bytes_downloaded += chunk.len() as u64;
+            if let Some(callback) = &options.progress {
+                callback(progress);
+            }
+        }
+
+        if received == 0 || expected.is_some_and(|expected| expected != received) {
+            return Err(PartFailure::Retryable(TidalError::Other(format!(
+                "ended after {} of {} bytes",
+                received,
+                expected.map_or_else(|| "unknown".to_string(), |e| e.to_string())
+            ))));
+        }
+
+        Ok(expected)
+    }
+}
+
+/// Why a part couldn't be downloaded, decides whether it is fetched again
+#[derive(Debug)]
+enum PartFailure {
+    /// Dropped connections, truncated bodies, `5xx` and `429` responses
+    Retryable(TidalError),
+    Fatal(TidalError),
+}
+
+impl From<reqwest::Error> for PartFailure {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_connect() || error.is_timeout() || error.is_request() {
+            Self::Retryable(error.into())
+        } else {
+            Self::Fatal(error.into())
+        }
+    }
+}
+
+/// `<dest>.part`, where a download is written to until it is complete
+pub(crate) fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// `<dest>.remux`, where the FLAC remux of a complete `.part` file is written to before it's
+/// tagged and renamed to `dest`
+fn remux_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_owned();
+    name.push(".remux");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{Container, DownloadOptions, DownloadProgress, part_path};
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        client::models::{
+            media::QualityTag,
+            track::{
+                mpd::Mpd,
+                playback::{
+                    DashManifest, JsonTrackManifest, ParsedTrackManifest, TrackPlaybackInfoResponse,
+                },
+            },
+        },
+        download::tags::TagOptions,
+        error::TidalError,
+        requests::RetryPolicy,
+        test_support::{
+            TestResponse, TestServer, album_json, api_client, encrypt_old_aes, fmp4_flac_fragment,
+            fmp4_flac_init, old_aes_key_id, temp_path, track_json,
+        },
+    };
+
+    fn playback(manifest: ParsedTrackManifest) -> TrackPlaybackInfoResponse {
+        TrackPlaybackInfoResponse {
+            track_id: 1,
+            asset_presentation: "FULL".to_string(),
+            audio_mode: QualityTag::Stereo,
+            audio_quality: QualityTag::Lossless,
+            manifest_mime_type: String::new(),
+            manifest_hash: String::new(),
+            manifest: None,
+            manifest_parsed: Some(manifest),
+            album_replay_gain: 0.0,
+            album_peak_amplitude: 0.0,
+            track_replay_gain: 0.0,
+            track_peak_amplitude: 0.0,
+            bit_depth: None,
+            sample_rate: None,
+        }
+    }
+
+    fn json_manifest() -> JsonTrackManifest {
+        JsonTrackManifest {
+            mime_type: "audio/flac".to_string(),
+            codecs: "flac".to_string(),
+            encryption_type: "NONE".to_string(),
+            key_id: None,
+            urls: vec![],
+        }
+    }
+
+    fn progressive(server: &TestServer) -> ParsedTrackManifest {
+        ParsedTrackManifest::Json(JsonTrackManifest {
+            urls: vec![server.at("track.flac")],
+            ..json_manifest()
+        })
+    }
+
+    fn dash_manifest(base_url: &str, segments: usize) -> DashManifest {
+        let xml = format!(
+            r#"<MPD><Period><AdaptationSet mimeType="audio/mp4">
+                <Representation id="r" codecs="flac" bandwidth="1">
+                  <SegmentTemplate timescale="1" initialization="{base_url}/init" media="{base_url}/seg$Number$" startNumber="1">
+                    <SegmentTimeline><S d="1" r="{}"/></SegmentTimeline>
+                  </SegmentTemplate>
+                </Representation>
+            </AdaptationSet></Period></MPD>"#,
+            segments - 1
+        );
+
+        DashManifest {
+            mime_type: "audio/mp4".to_string(),
+            codecs: "flac".to_string(),
+            urls: vec![],
+            bitrate: None,
+            initialization_url: None,
+            media_url_template: None,
+            timescale: None,
+            duration: None,
+            start_number: None,
+            mpd: Some(Mpd::parse(&xml).expect("mpd should parse")),
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_progressive_stream() {
+        let server = TestServer::files(vec![("track.flac", b"fLaC-data".to_vec())]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("progressive.flac");
+        let manifest = ParsedTrackManifest::Json(JsonTrackManifest {
+            mime_type: "audio/flac".to_string(),
+            codecs: "flac".to_string(),
+            encryption_type: "NONE".to_string(),
+            key_id: None,
+            urls: vec![server.at("track.flac")],
+        });
+
+        let track = client
+            .download_playback(&playback(manifest), &dest, &DownloadOptions::default())
+            .await
+            .expect("download should succeed");
+
+        assert_eq!(track.container, Container::Flac);
+        assert_eq!(track.bytes, 9);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"fLaC-data");
+        assert!(!part_path(&dest).exists());
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn old_aes_streams_are_written_decrypted() {
+        let plain = b"fLaC-encrypted-track-data".to_vec();
+        let server = TestServer::files(vec![("track.flac", encrypt_old_aes(&plain))]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("encrypted.flac");
+        let manifest = ParsedTrackManifest::Json(JsonTrackManifest {
+            encryption_type: "OLD_AES".to_string(),
+            key_id: Some(old_aes_key_id()),
+            urls: vec![server.at("track.flac")],
+            ..json_manifest()
+        });
+
+        client
+            .download_playback(&playback(manifest), &dest, &DownloadOptions::default())
+            .await
+            .expect("download should succeed");
+
+        assert_eq!(std::fs::read(&dest).unwrap(), plain);
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unsupported_encryption_is_rejected_before_downloading() {
+        let server = TestServer::files(vec![("track.flac", b"drm".to_vec())]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("drm.flac");
+        let manifest = ParsedTrackManifest::Json(JsonTrackManifest {
+            encryption_type: "WIDEVINE".to_string(),
+            urls: vec![server.at("track.flac")],
+            ..json_manifest()
+        });
+
+        let result = client
+            .download_playback(&playback(manifest), &dest, &DownloadOptions::default())
+            .await;
+
+        assert!(matches!(result, Err(TidalError::UnsupportedEncryption(_))));
+        assert!(server.requests().is_empty());
+        assert!(!dest.exists());
+    }
+
+    #[tokio::test]
+    async fn progressive_progress_knows_total_before_completion() {
+        let server = TestServer::files(vec![("track.flac", b"fLaC-data".to_vec())]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("total.flac");
+        let updates: Arc<Mutex<Vec<DownloadProgress>>> = Arc::default();
+        let sink = updates.clone();
+        let options = DownloadOptions {
+            progress: Some(Arc::new(move |progress| {
+                sink.lock().unwrap().push(progress.clone())
+            })),
+            ..Default::default()
+        };
+
+        client
+            .download_playback(&playback(progressive(&server)), &dest, &options)
+            .await
+            .expect("download should succeed");
+
+        let first = updates.lock().unwrap().first().cloned().unwrap();
+        assert_eq!(first.parts_downloaded, 0);
+        assert_eq!(first.total_bytes, Some(9));
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_parts_are_retried_with_the_retry_policy() {
+        let server = TestServer::sequence(vec![
+            TestResponse::new(503),
+            // the connection drops halfway through the body
+            TestResponse::ok("fLaC").truncated(9),
+            TestResponse::ok("fLaC-data"),
+        ]);
+        let mut client = TidalClient::new(&TidalAuth::with_oauth());
+        client.set_retry_policy(Some(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            jitter: 0.0,
+            ..Default::default()
+        }));
+        let dest = temp_path("retried.flac");
+
+        let track = client
+            .download_playback(
+                &playback(progressive(&server)),
+                &dest,
+                &DownloadOptions::default(),
+            )
+            .await
+            .expect("download should succeed after retries");
+
+        assert_eq!(track.bytes, 9);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"fLaC-data");
+        assert_eq!(server.requests().len(), 3);
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn downloads_dash_segments_in_order_and_reports_progress() {
+        let server = TestServer::files(vec![
+            ("init", b"I".to_vec()),
+            ("seg1", b"A".to_vec()),
+            ("seg2", b"B".to_vec()),
+        ]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("dash.m4a");
+        let updates: Arc<Mutex<Vec<DownloadProgress>>> = Arc::default();
+        let sink = updates.clone();
+        let options = DownloadOptions {
+            progress: Some(Arc::new(move |progress| {
+                sink.lock().unwrap().push(progress.clone())
+            })),
+            ..Default::default()
+        };
+
+        let manifest = ParsedTrackManifest::Dash(dash_manifest(server.url(), 2));
+        let track = client
+            .download_playback(&playback(manifest), &dest, &options)
+            .await
+            .expect("download should succeed");
+
+        assert_eq!(track.container, Container::Mp4);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"IAB");
+        let last = updates.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.parts_downloaded, 3);
+        assert_eq!(last.total_parts, 3);
+        assert_eq!(last.total_bytes, Some(3));
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_segment_fails_and_removes_partial_file() {
+        let server = TestServer::files(vec![("init", b"I".to_vec()), ("seg1", b"A".to_vec())]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("incomplete.m4a");
+
+        // the manifest lists a second segment the server doesn't have
+        let manifest = ParsedTrackManifest::Dash(dash_manifest(server.url(), 3));
+        let result = client
+            .download_playback(&playback(manifest), &dest, &DownloadOptions::default())
+            .await;
+
+        assert!(matches!(result, Err(TidalError::IncompleteDownload(_))));
+        assert!(!dest.exists());
+        assert!(!part_path(&dest).exists());
+    }
+
+    #[tokio::test]
+    async fn remuxes_fragmented_flac_into_dest() {
+        let server = TestServer::files(vec![
+            ("init", fmp4_flac_init(5000)),
+            ("seg1", fmp4_flac_fragment(&[b"frame1"], 4096)),
+            ("seg2", fmp4_flac_fragment(&[b"frame2"], 904)),
+        ]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("remuxed.flac");
+        let options = DownloadOptions {
+            remux_flac: true,
+            ..Default::default()
+        };
+
+        let manifest = ParsedTrackManifest::Dash(dash_manifest(server.url(), 2));
+        let track = client
+            .download_playback(&playback(manifest), &dest, &options)
+            .await
+            .expect("download should succeed");
+
+        let data = std::fs::read(&dest).unwrap();
+        assert_eq!(track.container, Container::Flac);
+        assert_eq!(track.bytes, data.len() as u64);
+        assert!(data.starts_with(b"fLaC"));
+        assert!(data.ends_with(b"frame1frame2"));
+        assert!(!part_path(&dest).exists());
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_remux_keeps_part_file_and_existing_dest() {
+        let server = TestServer::files(vec![("init", b"I".to_vec()), ("seg1", b"A".to_vec())]);
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let dest = temp_path("not-flac.flac");
+        std::fs::write(&dest, b"previous").unwrap();
+        let options = DownloadOptions {
+            remux_flac: true,
+            overwrite: true,
+            ..Default::default()
+        };
+
+        let manifest = ParsedTrackManifest::Dash(dash_manifest(server.url(), 1));
+        let result = client
+            .download_playback(&playback(manifest), &dest, &options)
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"previous");
+        assert_eq!(std::fs::read(part_path(&dest)).unwrap(), b"IA");
+        std::fs::remove_file(part_path(&dest)).unwrap();
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    /// FLAC with an empty STREAMINFO followed by `frames`
+    fn flac_file() -> Vec<u8> {
+        [b"fLaC".as_slice(), &[0x80, 0, 0, 34], &[0; 34], b"frames"].concat()
+    }
+
+    fn tag_options() -> DownloadOptions {
+        DownloadOptions {
+            tags: Some(TagOptions {
+                lyrics: false,
+                cover: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn tags_are_written_before_dest_appears() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/track.flac" => TestResponse::file(request, &flac_file()),
+            "/tracks/1/" => TestResponse::ok(track_json(1, "Song", "Guest").to_string()),
+            "/albums/100/" => TestResponse::ok(album_json(100, "Album", "Band").to_string()),
+            _ => TestResponse::new(404),
+        });
+        let client = api_client(&server);
+        let dest = temp_path("tagged.flac");
+
+        let track = client
+            .download_playback(&playback(progressive(&server)), &dest, &tag_options())
+            .await
+            .expect("download should succeed");
+
+        let data = std::fs::read(&dest).unwrap();
+        assert_eq!(track.bytes, data.len() as u64);
+        assert!(data.windows(16).any(|w| w == b"ALBUMARTIST=Band"));
+        assert!(data.ends_with(b"frames"));
+        assert!(!part_path(&dest).exists());
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_tagging_keeps_part_file_and_leaves_dest_alone() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/track.flac" => TestResponse::file(request, &flac_file()),
+            _ => TestResponse::new(500),
+        });
+        let client = api_client(&server);
+        let dest = temp_path("untagged.flac");
+
+        let result = client
+            .download_playback(&playback(progressive(&server)), &dest, &tag_options())
+            .await;
+
+        assert!(result.is_err());
+        assert!(!dest.exists());
+        assert_eq!(std::fs::read(part_path(&dest)).unwrap(), flac_file());
+        std::fs::remove_file(part_path(&dest)).unwrap();
+    }
+}
@@ -0,0 +1,351 @@
+//! Concurrent fetching of DASH segments that still yields them in manifest order
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::{Stream, StreamExt, stream};
+use reqwest::StatusCode;
+use tracing::{debug, warn};
+
+use crate::{
+    TidalClient,
+    download::PartFailure,
+    error::TidalError,
+    requests::{RequestClient, RetryPolicy},
+};
+
+/// Options for [`TidalClient::fetch_segments`]
+#[derive(Debug, Clone)]
+pub struct SegmentFetchOptions {
+    /// Number of segments requested at the same time
+    pub concurrency: usize,
+    /// Cap on the combined download rate of all segments in bytes per second, `None` means
+    /// unlimited
+    pub max_bytes_per_second: Option<u64>,
+    /// Retries of failed segments, independent of the client's policy for API requests
+    pub retry_policy: RetryPolicy,
+}
+
+impl Default for SegmentFetchOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            max_bytes_per_second: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
+impl TidalClient {
+    /// Fetches `urls` concurrently, yielding every segment's body in the order of `urls`
+    ///
+    /// Segments go through the client's HTTP connection pool, failed segments are retried with
+    /// the options' [`RetryPolicy`]. At most `concurrency`
+    /// segments are held in memory ahead of the one the stream is waiting for. The stream ends
+    /// after the first segment that failed for good.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use futures_util::TryStreamExt;
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::client::models::track::playback::DashManifest;
+    /// # use tidlers::download::segments::SegmentFetchOptions;
+    /// # async fn example(client: &TidalClient, dash: &DashManifest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let list = dash.segments()?;
+    /// let urls = list
+    ///     .initialization
+    ///     .into_iter()
+    ///     .chain(list.segments.into_iter().map(|segment| segment.url));
+    ///
+    /// let options = SegmentFetchOptions {
+    ///     concurrency: 8,
+    ///     max_bytes_per_second: Some(2_000_000),
+    ///     ..Default::default()
+    /// };
+    /// let data: Vec<u8> = client
+    ///     .fetch_segments(urls, &options)
+    ///     .try_concat()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fetch_segments(
+        &self,
+        urls: impl IntoIterator<Item = String>,
+        options: &SegmentFetchOptions,
+    ) -> impl Stream<Item = Result<Vec<u8>, TidalError>> + '_ {
+        let urls: Vec<String> = urls.into_iter().collect();
+        let total = urls.len();
+        let throttle = options
+            .max_bytes_per_second
+            .map(Throttle::new)
+            .map(Arc::new);
+        let retry_policy = options.retry_policy.clone();
+
+        stream::iter(urls.into_iter().enumerate())
+            .map(move |(index, url)| {
+                let throttle = throttle.clone();
+                let retry_policy = retry_policy.clone();
+                async move {
+                    fetch_segment(&self.rq, &url, &retry_policy, throttle.as_deref())
+                        .await
+                        .map_err(|error| {
+                            TidalError::IncompleteDownload(format!(
+                                "segment {} of {total} failed: {error}",
+                                index + 1
+                            ))
+                        })
+                }
+            })
+            .buffered(options.concurrency.max(1))
+            .scan(false, |failed, result| {
+                let item = (!*failed).then(|| {
+                    *failed = result.is_err();
+                    result
+                });
+                async move { item }
+            })
+    }
+}
+
+/// Fetches one segment, retrying it with `retry_policy`
+pub(crate) async fn fetch_segment(
+    rq: &RequestClient,
+    url: &str,
+    retry_policy: &RetryPolicy,
+    throttle: Option<&Throttle>,
+) -> Result<Vec<u8>, TidalError> {
+    let mut attempt = 1;
+    loop {
+        let error = match fetch_segment_once(rq, url, throttle).await {
+            Ok(data) => return Ok(data),
+            Err(PartFailure::Retryable(error)) if attempt < retry_policy.max_attempts => error,
+            Err(PartFailure::Retryable(error) | PartFailure::Fatal(error)) => {
+                return Err(error);
+            }
+        };
+
+        let delay = retry_policy.backoff_delay(attempt);
+        warn!(
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            %error,
+            "segment failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+async fn fetch_segment_once(
+    rq: &RequestClient,
+    url: &str,
+    throttle: Option<&Throttle>,
+) -> Result<Vec<u8>, PartFailure> {
+    let mut response = rq
+        .http_client()
+        .get(url)
+        .send()
+        .await
+        .map_err(PartFailure::from)?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error = TidalError::Other(format!("HTTP {status}"));
+        return Err(
+            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                PartFailure::Retryable(error)
+            } else {
+                PartFailure::Fatal(error)
+            },
+        );
+    }
+
+    let expected = response.content_length();
+    let mut data = Vec::with_capacity(expected.unwrap_or_default() as usize);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| PartFailure::Retryable(e.into()))?
+    {
+        data.extend_from_slice(&chunk);
+        if let Some(throttle) = throttle {
+            throttle.consume(chunk.len() as u64).await;
+        }
+    }
+
+    let received = data.len() as u64;
+    if received == 0 || expected.is_some_and(|expected| expected != received) {
+        return Err(PartFailure::Retryable(TidalError::Other(format!(
+            "ended after {} of {} bytes",
+            received,
+            expected.map_or_else(|| "unknown".to_string(), |e| e.to_string())
+        ))));
+    }
+
+    Ok(data)
+}
+
+/// Keeps the average rate of everything consumed through it at or below a limit
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    bytes_per_second: u64,
+    state: Mutex<ThrottleState>,
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    started: Instant,
+    bytes: u64,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            state: Mutex::new(ThrottleState {
+                started: Instant::now(),
+                bytes: 0,
+            }),
+        }
+    }
+
+    /// Accounts for `bytes` received and waits until the rate is back under the limit
+    async fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            state.bytes += bytes;
+            let due = Duration::from_secs_f64(state.bytes as f64 / self.bytes_per_second as f64);
+            due.saturating_sub(state.started.elapsed())
+        };
+
+        if !wait.is_zero() {
+            debug!(
+                wait_ms = wait.as_millis() as u64,
+                "bandwidth limit reached, delaying"
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures_util::{StreamExt, TryStreamExt};
+
+    use super::SegmentFetchOptions;
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        error::TidalError,
+        requests::RetryPolicy,
+        test_support::{TestResponse, TestServer},
+    };
+
+    fn urls(server: &TestServer, count: usize) -> Vec<String> {
+        (1..=count).map(|n| server.at(&format!("seg{n}"))).collect()
+    }
+
+    #[tokio::test]
+    async fn segments_arrive_in_order_while_fetched_concurrently() {
+        // earlier segments take longer, so they finish last
+        let server = TestServer::spawn(|request| {
+            let n: u64 = request.path.trim_start_matches("/seg").parse().unwrap();
+            std::thread::sleep(Duration::from_millis(80 - n * 20));
+            TestResponse::ok(n.to_string())
+        });
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let options = SegmentFetchOptions {
+            concurrency: 3,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let segments: Vec<Vec<u8>> = client
+            .fetch_segments(urls(&server, 3), &options)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(segments, [b"1", b"2", b"3"]);
+        // one at a time would take 60 + 40 + 20 ms
+        assert!(start.elapsed() < Duration::from_millis(110));
+    }
+
+    #[tokio::test]
+    async fn failed_segments_are_retried() {
+        let server = TestServer::sequence(vec![
+            TestResponse::new(503),
+            TestResponse::ok("ab").truncated(4),
+            TestResponse::ok("abcd"),
+        ]);
+        // without a retry policy on the client
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let options = SegmentFetchOptions {
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(1),
+                jitter: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let data: Vec<u8> = client
+            .fetch_segments(urls(&server, 1), &options)
+            .try_concat()
+            .await
+            .unwrap();
+
+        assert_eq!(data, b"abcd");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn stream_ends_after_a_failed_segment() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/seg2" => TestResponse::new(404),
+            _ => TestResponse::ok("x"),
+        });
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+
+        let results: Vec<_> = client
+            .fetch_segments(urls(&server, 3), &SegmentFetchOptions::default())
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            &results[1],
+            Err(TidalError::IncompleteDownload(message)) if message.starts_with("segment 2 of 3")
+        ));
+    }
+
+    #[tokio::test]
+    async fn bandwidth_cap_applies_to_all_segments_together() {
+        let server = TestServer::spawn(|_| TestResponse::ok(vec![0_u8; 1000]));
+        let client = TidalClient::new(&TidalAuth::with_oauth());
+        let options = SegmentFetchOptions {
+            concurrency: 4,
+            max_bytes_per_second: Some(20_000),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let data: Vec<u8> = client
+            .fetch_segments(urls(&server, 4), &options)
+            .try_concat()
+            .await
+            .unwrap();
+
+        assert_eq!(data.len(), 4000);
+        // 4000 bytes at 20 kB/s
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+}
@@ -0,0 +1,901 @@
+//! Tagging of downloaded files with TIDAL metadata
+//!
+//! FLAC files get Vorbis comments and a front cover PICTURE block, MP4 files get iTunes style
+//! `moov/udta/meta/ilst` atoms with freeform `----` atoms for fields iTunes has no atom for.
+//! Existing tags are replaced, the audio data is copied as is.
+
+use std::{
+    io::{self, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use reqwest::StatusCode;
+use tracing::{debug, warn};
+
+use crate::{
+    TidalClient,
+    client::models::{
+        album::AlbumResponse,
+        track::{Track, playback::TrackPlaybackInfoResponse},
+    },
+    download::Container,
+    error::TidalError,
+    ids::TrackId,
+    resources,
+};
+
+const VENDOR: &str = concat!("tidlers ", env!("CARGO_PKG_VERSION"));
+const FLAC_PADDING: usize = 1024;
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// What to fetch besides the track itself when tagging
+#[derive(Debug, Clone)]
+pub struct TagOptions {
+    /// Fetch lyrics with `get_track_lyrics`, tracks without lyrics are tagged without them
+    pub lyrics: bool,
+    /// Embed the album cover
+    pub cover: bool,
+    /// Size of the embedded cover in pixels
+    pub cover_size: u32,
+}
+
+impl Default for TagOptions {
+    fn default() -> Self {
+        Self {
+            lyrics: true,
+            cover: true,
+            cover_size: 1280,
+        }
+    }
+}
+
+/// ReplayGain values, gains in dB and peaks as linear amplitude
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: f64,
+    pub track_peak: f64,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+/// Embedded cover image
+#[derive(Clone, PartialEq, Eq)]
+pub struct CoverArt {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+impl std::fmt::Debug for CoverArt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoverArt")
+            .field("data", &format_args!("{} bytes", self.data.len()))
+            .field("mime_type", &self.mime_type)
+            .finish()
+    }
+}
+
+/// Tags written to a downloaded file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackTags {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub volume_number: Option<u32>,
+    /// Release date as given by TIDAL (`YYYY-MM-DD`)
+    pub date: Option<String>,
+    pub isrc: Option<String>,
+    pub copyright: Option<String>,
+    pub bpm: Option<f32>,
+    /// Musical key, e.g. `C#` with key scale `MINOR` becomes `C#m`
+    pub key: Option<String>,
+    pub lyrics: Option<String>,
+    pub replay_gain: Option<ReplayGain>,
+    pub cover: Option<CoverArt>,
+}
+
+impl TrackTags {
+    /// Tags available from the track itself, without album artist, lyrics and cover
+    ///
+    /// The track only carries a summary of its album, the album artist comes from
+    /// [`with_album`](Self::with_album).
+    pub fn from_track(track: &Track) -> Self {
+        let title = match &track.version {
+            Some(version) if !version.is_empty() => format!("{} ({})", track.title, version),
+            _ => track.title.clone(),
+        };
+
+        let mut artists: Vec<String> = track.artists.iter().map(|a| a.name.clone()).collect();
+        if artists.is_empty() {
+            artists.push(track.artist.name.clone());
+        }
+
+        let key = track
+            .key
+            .as_ref()
+            .map(|key| match track.key_scale.as_deref() {
+                Some("MINOR") => format!("{key}m"),
+                _ => key.clone(),
+            });
+
+        Self {
+            title,
+            artists,
+            album: track.album.as_ref().map(|album| album.title.clone()),
+            album_artist: None,
+            track_number: Some(track.track_number).filter(|n| *n > 0),
+            volume_number: Some(track.volume_number).filter(|n| *n > 0),
+            date: track
+                .album
+                .as_ref()
+                .and_then(|album| album.release_date.clone()),
+            isrc: track.isrc.clone(),
+            copyright: track.copyright.clone(),
+            bpm: track.bpm,
+            key,
+            lyrics: None,
+            replay_gain: Some(ReplayGain {
+                track_gain: track.replay_gain,
+                track_peak: f64::from(track.peak),
+                album_gain: None,
+                album_peak: None,
+            }),
+            cover: None,
+        }
+    }
+
+    /// Takes the album artist from the track's album
+    pub fn with_album(mut self, album: &AlbumResponse) -> Self {
+        self.album_artist = Some(album.artist.name.clone());
+        self
+    }
+
+    /// Takes the ReplayGain values of the stream that was actually downloaded
+    pub fn with_playback(mut self, playback: &TrackPlaybackInfoResponse) -> Self {
+        self.replay_gain = Some(ReplayGain {
+            track_gain: playback.track_replay_gain,
+            track_peak: playback.track_peak_amplitude,
+            album_gain: Some(playback.album_replay_gain),
+            album_peak: Some(playback.album_peak_amplitude),
+        });
+        self
+    }
+
+    /// Field/value pairs as Vorbis comments, multi-valued fields repeat the field name
+    pub fn vorbis_comments(&self) -> Vec<(&'static str, String)> {
+        let mut comments = vec![("TITLE", self.title.clone())];
+        comments.extend(self.artists.iter().map(|artist| ("ARTIST", artist.clone())));
+
+        let optional = [
+            ("ALBUM", self.album.clone()),
+            ("ALBUMARTIST", self.album_artist.clone()),
+            ("TRACKNUMBER", self.track_number.map(|n| n.to_string())),
+            ("DISCNUMBER", self.volume_number.map(|n| n.to_string())),
+            ("DATE", self.date.clone()),
+            ("ISRC", self.isrc.clone()),
+            ("COPYRIGHT", self.copyright.clone()),
+            ("BPM", self.bpm.map(format_bpm)),
+            ("INITIALKEY", self.key.clone()),
+            ("LYRICS", self.lyrics.clone()),
+        ];
+        comments.extend(
+            optional
+                .into_iter()
+                .filter_map(|(field, value)| value.map(|value| (field, value))),
+        );
+
+        if let Some(gain) = &self.replay_gain {
+            comments.extend(gain.fields());
+        }
+
+        comments
+    }
+}
+
+impl ReplayGain {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("REPLAYGAIN_TRACK_GAIN", format_gain(self.track_gain)),
+            ("REPLAYGAIN_TRACK_PEAK", format_peak(self.track_peak)),
+        ];
+        if let Some(gain) = self.album_gain {
+            fields.push(("REPLAYGAIN_ALBUM_GAIN", format_gain(gain)));
+        }
+        if let Some(peak) = self.album_peak {
+            fields.push(("REPLAYGAIN_ALBUM_PEAK", format_peak(peak)));
+        }
+        fields
+    }
+}
+
+fn format_gain(gain: f64) -> String {
+    format!("{gain:.2} dB")
+}
+
+fn format_peak(peak: f64) -> String {
+    format!("{peak:.6}")
+}
+
+fn format_bpm(bpm: f32) -> String {
+    format!("{}", bpm.round() as u32)
+}
+
+impl TidalClient {
+    /// Collects the tags of a track, with lyrics and cover art depending on `options`
+    pub async fn fetch_track_tags(
+        &self,
+        track_id: impl Into<TrackId>,
+        options: &TagOptions,
+    ) -> Result<TrackTags, TidalError> {
+        let track_id = track_id.into();
+        let track = self.get_track(track_id.clone()).await?;
+        let mut tags = TrackTags::from_track(&track);
+
+        if let Some(album) = &track.album {
+            tags = match self.get_album(album.id.to_string()).await {
+                Ok(album) => tags.with_album(&album),
+                Err(TidalError::NotFound) => tags,
+                Err(e) => return Err(e),
+            };
+        }
+
+        if options.lyrics {
+            tags.lyrics = match self.get_track_lyrics(track_id).await {
+                Ok(lyrics) if !lyrics.lyrics.is_empty() => Some(lyrics.lyrics),
+                Ok(_) | Err(TidalError::NotFound) => None,
+                Err(e) => return Err(e),
+            };
+        }
+
+        let cover_uuid = track
+            .album
+            .as_ref()
+            .and_then(|album| album.cover.as_deref());
+        if let (true, Some(uuid)) = (options.cover, cover_uuid) {
+            tags.cover = self.fetch_cover(uuid, options.cover_size).await?;
+        }
+
+        Ok(tags)
+    }
+
+    /// Fetches the tags of a track and writes them to an already downloaded file
+    pub async fn tag_file(
+        &self,
+        track_id: impl Into<TrackId>,
+        path: impl AsRef<Path>,
+        container: &Container,
+        options: &TagOptions,
+    ) -> Result<TrackTags, TidalError> {
+        let tags = self.fetch_track_tags(track_id, options).await?;
+        write_tags(path, container, &tags).await?;
+        Ok(tags)
+    }
+
+    async fn fetch_cover(&self, uuid: &str, size: u32) -> Result<Option<CoverArt>, TidalError> {
+        let url = resources::image_url(self.rq.resources_base_url(), uuid, size);
+        let response = self.rq.http_client().get(&url).send().await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                warn!(%url, "cover art not found, tagging without it");
+                Ok(None)
+            }
+            status if !status.is_success() => Err(TidalError::InvalidResponse(format!(
+                "cover art request failed with HTTP {status}"
+            ))),
+            _ => {
+                let data = response.bytes().await?.to_vec();
+                let mime_type = if data.starts_with(b"\x89PNG") {
+                    "image/png"
+                } else {
+                    "image/jpeg"
+                };
+
+                Ok(Some(CoverArt {
+                    data,
+                    mime_type: mime_type.to_string(),
+                }))
+            }
+        }
+    }
+}
+
+/// Writes `tags` to the file at `path`, replacing existing tags
+///
+/// The tagged file is written next to `path` and renamed over it once complete.
+pub async fn write_tags(
+    path: impl AsRef<Path>,
+    container: &Container,
+    tags: &TrackTags,
+) -> Result<(), TidalError> {
+    let path = path.as_ref().to_path_buf();
+    let container = container.clone();
+    let tags = tags.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let tmp_path = tagging_path(&path);
+        let result = (|| {
+            let reader = io::BufReader::new(std::fs::File::open(&path)?);
+            let mut writer = io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+            match container {
+                Container::Flac => write_flac_tags(reader, &mut writer, &tags)?,
+                Container::Mp4 => write_mp4_tags(reader, &mut writer, &tags)?,
+                Container::MpegTs => {
+                    return Err(TidalError::InvalidArgument(
+                        "can't tag MPEG-TS files".to_string(),
+                    ));
+                }
+                Container::Other(mime_type) => {
+                    return Err(TidalError::InvalidArgument(format!(
+                        "can't tag {mime_type} files"
+                    )));
+                }
+            }
+            writer.flush()?;
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => {
+                std::fs::rename(&tmp_path, &path)?;
+                debug!(path = %path.display(), "tags written");
+                Ok(())
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp_path);
+                Err(e)
+            }
+        }
+    })
+    .await
+    .map_err(|e| TidalError::Other(format!("tagging task failed: {}", e)))?
+}
+
+fn tagging_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tagging");
+    PathBuf::from(name)
+}
+
+/// Copies a FLAC stream, replacing its Vorbis comment, picture and padding blocks
+pub fn write_flac_tags<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    tags: &TrackTags,
+) -> Result<(), TidalError> {
+    let mut magic = [0_u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        return Err(TidalError::InvalidArgument("not a FLAC file".to_string()));
+    }
+
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    loop {
+        let mut header = [0_u8; 4];
+        reader.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        let mut body = vec![0_u8; len];
+        reader.read_exact(&mut body)?;
+        // VORBIS_COMMENT, PICTURE and PADDING are replaced
+        if !matches!(block_type, 1 | 4 | 6) {
+            blocks.push((block_type, body));
+        }
+        if last {
+            break;
+        }
+    }
+
+    blocks.push((4, vorbis_comment_block(tags)));
+    if let Some(cover) = &tags.cover {
+        blocks.push((6, picture_block(cover)?));
+    }
+    blocks.push((1, vec![0; FLAC_PADDING]));
+
+    writer.write_all(b"fLaC")?;
+    let count = blocks.len();
+    for (index, (block_type, body)) in blocks.iter().enumerate() {
+        let len = u32::try_from(body.len())
+            .ok()
+            .filter(|len| *len < 1 << 24)
+            .ok_or_else(|| TidalError::InvalidArgument("FLAC metadata block too large".into()))?;
+        let last = if index + 1 == count { 0x80 } else { 0 };
+        writer.write_all(&[block_type | last])?;
+        writer.write_all(&len.to_be_bytes()[1..])?;
+        writer.write_all(body)?;
+    }
+
+    io::copy(&mut reader, &mut writer)?;
+    Ok(())
+}
+
+fn vorbis_comment_block(tags: &TrackTags) -> Vec<u8> {
+    let comments = tags.vorbis_comments();
+
+    let mut body = Vec::new();
+    body.extend((VENDOR.len() as u32).to_le_bytes());
+    body.extend(VENDOR.as_bytes());
+    body.extend((comments.len() as u32).to_le_bytes());
+    for (field, value) in comments {
+        let comment = format!("{field}={value}");
+        body.extend((comment.len() as u32).to_le_bytes());
+        body.extend(comment.as_bytes());
+    }
+    body
+}
+
+fn picture_block(cover: &CoverArt) -> Result<Vec<u8>, TidalError> {
+    let data_len = u32::try_from(cover.data.len())
+        .map_err(|_| TidalError::InvalidArgument("cover art too large".to_string()))?;
+
+    let mut body = Vec::new();
+    // front cover
+    body.extend(3_u32.to_be_bytes());
+    body.extend((cover.mime_type.len() as u32).to_be_bytes());
+    body.extend(cover.mime_type.as_bytes());
+    // empty description, then width, height, depth and colors left as unknown
+    body.extend([0_u8; 4 * 5]);
+    body.extend(data_len.to_be_bytes());
+    body.extend(&cover.data);
+    Ok(body)
+}
+
+/// Copies an MP4 stream, replacing `moov/udta/meta`
+///
+/// When `moov` precedes the media data, chunk offsets (`stco`/`co64`) are shifted by the size
+/// difference of the new `moov`.
+pub fn write_mp4_tags<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    tags: &TrackTags,
+) -> Result<(), TidalError> {
+    let mut seen_media = false;
+    let mut written_moov = false;
+
+    loop {
+        let mut header = [0_u8; 8];
+        match read_full(&mut reader, &mut header)? {
+            0 => break,
+            8 => {}
+            _ => return Err(mp4_error("truncated box header")),
+        }
+
+        let size = u64::from(u32::from_be_bytes(header[..4].try_into().unwrap()));
+        let kind: [u8; 4] = header[4..].try_into().unwrap();
+        let mut large = [0_u8; 8];
+        let payload_len = match size {
+            0 => None,
+            1 => {
+                reader.read_exact(&mut large)?;
+                Some(
+                    u64::from_be_bytes(large)
+                        .checked_sub(16)
+                        .ok_or_else(|| mp4_error("invalid box size"))?,
+                )
+            }
+            size => Some(
+                size.checked_sub(8)
+                    .ok_or_else(|| mp4_error("invalid box size"))?,
+            ),
+        };
+
+        if &kind == b"moov" {
+            let len = payload_len.ok_or_else(|| mp4_error("moov without size"))?;
+            let mut moov = Vec::new();
+            (&mut reader).take(len).read_to_end(&mut moov)?;
+            if moov.len() as u64 != len {
+                return Err(mp4_error("truncated moov"));
+            }
+
+            let old_len = len + if size == 1 { 16 } else { 8 };
+            let mut new_moov = rewrite_moov(&moov, tags)?;
+            let mut new_box = mp4_box(b"moov", &new_moov);
+            if !seen_media {
+                let delta = new_box.len() as i64 - old_len as i64;
+                shift_chunk_offsets(&mut new_moov, delta)?;
+                new_box = mp4_box(b"moov", &new_moov);
+            }
+
+            writer.write_all(&new_box)?;
+            written_moov = true;
+            continue;
+        }
+
+        if matches!(&kind, b"mdat" | b"moof") {
+            seen_media = true;
+        }
+
+        writer.write_all(&header)?;
+        if size == 1 {
+            writer.write_all(&large)?;
+        }
+        match payload_len {
+            Some(len) => {
+                let copied = io::copy(&mut (&mut reader).take(len), &mut writer)?;
+                if copied != len {
+                    return Err(mp4_error("truncated box"));
+                }
+            }
+            None => {
+                io::copy(&mut reader, &mut writer)?;
+                break;
+            }
+        }
+    }
+
+    if !written_moov {
+        return Err(mp4_error("no moov box found"));
+    }
+    Ok(())
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn mp4_error(message: &str) -> TidalError {
+    TidalError::InvalidArgument(format!("invalid MP4 file: {message}"))
+}
+
+fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 8);
+    out.extend(((payload.len() + 8) as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(payload);
+    out
+}
+
+/// Kind of a child box and the range of the whole box within its parent's payload
+type ChildBox = ([u8; 4], Range<usize>);
+
+fn mp4_children(data: &[u8]) -> Result<Vec<ChildBox>, TidalError> {
+    let mut children = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let header = data
+            .get(offset..offset + 8)
+            .ok_or_else(|| mp4_error("truncated child box"))?;
+        let size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            0 => data.len() - offset,
+            1 => {
+                let large = data
+                    .get(offset + 8..offset + 16)
+                    .ok_or_else(|| mp4_error("truncated child box"))?;
+                usize::try_from(u64::from_be_bytes(large.try_into().unwrap()))
+                    .map_err(|_| mp4_error("box too large"))?
+            }
+            size => size as usize,
+        };
+        if size < 8 || offset + size > data.len() {
+            return Err(mp4_error("invalid child box size"));
+        }
+
+        children.push((header[4..8].try_into().unwrap(), offset..offset + size));
+        offset += size;
+    }
+    Ok(children)
+}
+
+fn rewrite_moov(moov: &[u8], tags: &TrackTags) -> Result<Vec<u8>, TidalError> {
+    let mut out = Vec::with_capacity(moov.len());
+    let mut udta_children = Vec::new();
+
+    for (kind, range) in mp4_children(moov)? {
+        if &kind == b"udta" {
+            // keep everything but the old metadata
+            let udta = &moov[range.start + 8..range.end];
+            for (child_kind, child_range) in mp4_children(udta)? {
+                if &child_kind != b"meta" {
+                    udta_children.extend(&udta[child_range]);
+                }
+            }
+        } else {
+            out.extend(&moov[range]);
+        }
+    }
+
+    udta_children.extend(meta_box(tags)?);
+    out.extend(mp4_box(b"udta", &udta_children));
+    Ok(out)
+}
+
+fn meta_box(tags: &TrackTags) -> Result<Vec<u8>, TidalError> {
+    let mut hdlr = vec![0_u8; 8];
+    hdlr.extend(b"mdir");
+    hdlr.extend(b"appl");
+    hdlr.extend([0_u8; 9]);
+
+    let mut meta = vec![0_u8; 4];
+    meta.extend(mp4_box(b"hdlr", &hdlr));
+    meta.extend(mp4_box(b"ilst", &ilst_items(tags)?));
+    Ok(mp4_box(b"meta", &meta))
+}
+
+const DATA_UTF8: u32 = 1;
+const DATA_JPEG: u32 = 13;
+const DATA_PNG: u32 = 14;
+const DATA_INT: u32 = 21;
+const DATA_IMPLICIT: u32 = 0;
+
+fn data_atom(data_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut data = data_type.to_be_bytes().to_vec();
+    data.extend([0_u8; 4]);
+    data.extend(payload);
+    mp4_box(b"data", &data)
+}
+
+fn text_item(kind: &[u8; 4], value: &str) -> Vec<u8> {
+    mp4_box(kind, &data_atom(DATA_UTF8, value.as_bytes()))
+}
+
+fn freeform_item(name: &str, value: &str) -> Vec<u8> {
+    let mut mean = vec![0_u8; 4];
+    mean.extend(ITUNES_MEAN.as_bytes());
+    let mut name_atom = vec![0_u8; 4];
+    name_atom.extend(name.as_bytes());
+
+    let mut item = mp4_box(b"mean", &mean);
+    item.extend(mp4_box(b"name", &name_atom));
+    item.extend(data_atom(DATA_UTF8, value.as_bytes()));
+    mp4_box(b"----", &item)
+}
+
+fn ilst_items(tags: &TrackTags) -> Result<Vec<u8>, TidalError> {
+    let mut ilst = text_item(b"\xa9nam", &tags.title);
+    if !tags.artists.is_empty() {
+        ilst.extend(text_item(b"\xa9ART", &tags.artists.join(", ")));
+    }
+    if let Some(album) = &tags.album {
+        ilst.extend(text_item(b"\xa9alb", album));
+    }
+    if let Some(album_artist) = &tags.album_artist {
+        ilst.extend(text_item(b"aART", album_artist));
+    }
+    if let Some(number) = tags.track_number {
+        ilst.extend(number_pair_item(b"trkn", number, true)?);
+    }
+    if let Some(number) = tags.volume_number {
+        ilst.extend(number_pair_item(b"disk", number, false)?);
+    }
+    if let Some(date) = &tags.date {
+        ilst.extend(text_item(b"\xa9day", date));
+    }
+    if let Some(copyright) = &tags.copyright {
+        ilst.extend(text_item(b"cprt", copyright));
+    }
+    if let Some(bpm) = tags.bpm {
+        let bpm = bpm.round().clamp(0.0, f32::from(u16::MAX)) as u16;
+        ilst.extend(mp4_box(b"tmpo", &data_atom(DATA_INT, &bpm.to_be_bytes())));
+    }
+    if let Some(lyrics) = &tags.lyrics {
+        ilst.extend(text_item(b"\xa9lyr", lyrics));
+    }
+    if let Some(cover) = &tags.cover {
+        let data_type = if cover.mime_type == "image/png" {
+            DATA_PNG
+        } else {
+            DATA_JPEG
+        };
+        ilst.extend(mp4_box(b"covr", &data_atom(data_type, &cover.data)));
+    }
+    if let Some(isrc) = &tags.isrc {
+        ilst.extend(freeform_item("ISRC", isrc));
+    }
+    if let Some(key) = &tags.key {
+        ilst.extend(freeform_item("initialkey", key));
+    }
+    if let Some(gain) = &tags.replay_gain {
+        for (field, value) in gain.fields() {
+            ilst.extend(freeform_item(&field.to_ascii_lowercase(), &value));
+        }
+    }
+    Ok(ilst)
+}
+
+/// `trkn` and `disk` hold the number and an unknown (zero) total
+fn number_pair_item(kind: &[u8; 4], number: u32, trailing: bool) -> Result<Vec<u8>, TidalError> {
+    let number = u16::try_from(number).map_err(|_| mp4_error("track number too large"))?;
+    let mut payload = vec![0_u8; 2];
+    payload.extend(number.to_be_bytes());
+    payload.extend([0_u8; 2]);
+    if trailing {
+        payload.extend([0_u8; 2]);
+    }
+    Ok(mp4_box(kind, &data_atom(DATA_IMPLICIT, &payload)))
+}
+
+/// Shifts every `stco`/`co64` entry inside `moov/trak/mdia/minf/stbl` by `delta`
+fn shift_chunk_offsets(moov: &mut [u8], delta: i64) -> Result<(), TidalError> {
+    if delta == 0 {
+        return Ok(());
+    }
+
+    let path: [&[u8; 4]; 4] = [b"trak", b"mdia", b"minf", b"stbl"];
+    shift_in(moov, &path, delta)
+}
+
+fn shift_in(data: &mut [u8], path: &[&[u8; 4]], delta: i64) -> Result<(), TidalError> {
+    for (kind, range) in mp4_children(data)? {
+        let payload = &mut data[range.start + 8..range.end];
+        match path.split_first() {
+            Some((next, rest)) if &kind == *next => shift_in(payload, rest, delta)?,
+            None if &kind == b"stco" || &kind == b"co64" => {
+                let wide = &kind == b"co64";
+                let entry_len = if wide { 8 } else { 4 };
+                let count = payload
+                    .get(4..8)
+                    .map(|count| u32::from_be_bytes(count.try_into().unwrap()) as usize)
+                    .ok_or_else(|| mp4_error("truncated chunk offset box"))?;
+                let entries = payload
+                    .get_mut(8..8 + count * entry_len)
+                    .ok_or_else(|| mp4_error("truncated chunk offset box"))?;
+
+                for entry in entries.chunks_exact_mut(entry_len) {
+                    if wide {
+                        let offset = u64::from_be_bytes((&*entry).try_into().unwrap());
+                        let shifted = offset
+                            .checked_add_signed(delta)
+                            .ok_or_else(|| mp4_error("chunk offset out of range"))?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    } else {
+                        let offset = u64::from(u32::from_be_bytes((&*entry).try_into().unwrap()));
+                        let shifted = offset
+                            .checked_add_signed(delta)
+                            .and_then(|offset| u32::try_from(offset).ok())
+                            .ok_or_else(|| mp4_error("chunk offset out of range"))?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CoverArt, ReplayGain, TagOptions, TrackTags, mp4_box, mp4_children, write_flac_tags,
+        write_mp4_tags,
+    };
+    use crate::{
+        download::Container,
+        test_support::{TestResponse, TestServer, album_json, api_client, temp_path, track_json},
+    };
+
+    fn tags() -> TrackTags {
+        TrackTags {
+            title: "Song".to_string(),
+            artists: vec!["One".to_string(), "Two".to_string()],
+            album: Some("Record".to_string()),
+            track_number: Some(3),
+            isrc: Some("USABC1234567".to_string()),
+            lyrics: Some("la la la".to_string()),
+            replay_gain: Some(ReplayGain {
+                track_gain: -7.25,
+                track_peak: 0.988,
+                album_gain: Some(-6.5),
+                album_peak: Some(1.0),
+            }),
+            cover: Some(CoverArt {
+                data: vec![0xff, 0xd8, 0xff],
+                mime_type: "image/jpeg".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn flac_tags_replace_existing_comments() {
+        let mut input = b"fLaC".to_vec();
+        // STREAMINFO, an old Vorbis comment flagged as last, then frames
+        input.extend([0x00, 0, 0, 34]);
+        input.extend([0x11; 34]);
+        input.extend([0x84, 0, 0, 3, b'o', b'l', b'd']);
+        input.extend(b"FRAMES");
+
+        let mut output = Vec::new();
+        write_flac_tags(input.as_slice(), &mut output, &tags()).expect("tagging should work");
+
+        assert_eq!(&output[..8], &[b'f', b'L', b'a', b'C', 0x00, 0, 0, 34]);
+        assert_eq!(output[8 + 34], 0x04);
+        assert!(output.ends_with(b"FRAMES"));
+        assert!(!find(&output, b"old"));
+        assert!(find(&output, b"ARTIST=One"));
+        assert!(find(&output, b"ARTIST=Two"));
+        assert!(find(&output, b"REPLAYGAIN_TRACK_GAIN=-7.25 dB"));
+        assert!(find(&output, b"REPLAYGAIN_ALBUM_PEAK=1.000000"));
+        assert!(find(&output, b"LYRICS=la la la"));
+        assert!(find(&output, b"image/jpeg"));
+
+        // the padding block is last
+        let padding_start = output.len() - b"FRAMES".len() - super::FLAC_PADDING - 4;
+        assert_eq!(output[padding_start], 0x81);
+    }
+
+    #[test]
+    fn mp4_tags_shift_chunk_offsets_when_moov_comes_first() {
+        let mut stco = vec![0_u8; 4];
+        stco.extend(1_u32.to_be_bytes());
+        stco.extend(100_u32.to_be_bytes());
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stco", &stco));
+        let trak = mp4_box(b"trak", &mp4_box(b"mdia", &mp4_box(b"minf", &stbl)));
+        let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &[0; 8]), trak].concat());
+        let input = [
+            mp4_box(b"ftyp", b"M4A "),
+            moov.clone(),
+            mp4_box(b"mdat", b"AUDIO"),
+        ]
+        .concat();
+
+        let mut output = Vec::new();
+        write_mp4_tags(input.as_slice(), &mut output, &tags()).expect("tagging should work");
+
+        let boxes = mp4_children(&output).unwrap();
+        let kinds: Vec<_> = boxes.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"ftyp", b"moov", b"mdat"]);
+        assert!(output.ends_with(b"AUDIO"));
+
+        let new_moov = &output[boxes[1].1.clone()];
+        let delta = (new_moov.len() - moov.len()) as u32;
+        assert!(find(new_moov, &(100 + delta).to_be_bytes()));
+        assert!(find(new_moov, b"\xa9nam"));
+        assert!(find(new_moov, b"One, Two"));
+        assert!(find(new_moov, b"replaygain_track_gain"));
+        assert!(find(new_moov, b"covr"));
+    }
+
+    #[tokio::test]
+    async fn tag_file_fetches_track_and_album_artist() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/tracks/7/" => TestResponse::ok(track_json(7, "Song", "Guest").to_string()),
+            "/albums/100/" => TestResponse::ok(album_json(100, "Album", "Band").to_string()),
+            _ => TestResponse::new(404),
+        });
+        let client = api_client(&server);
+        let path = temp_path("tag-file.flac");
+        let mut flac = b"fLaC".to_vec();
+        flac.extend([0x80, 0, 0, 34]);
+        flac.extend([0_u8; 34]);
+        flac.extend(b"frames");
+        std::fs::write(&path, &flac).unwrap();
+
+        let options = TagOptions {
+            cover: false,
+            ..Default::default()
+        };
+        let tags = client
+            .tag_file(7.to_string(), &path, &Container::Flac, &options)
+            .await
+            .expect("tagging should succeed");
+
+        assert_eq!(tags.artists, ["Guest"]);
+        assert_eq!(tags.album_artist.as_deref(), Some("Band"));
+        assert_eq!(tags.date.as_deref(), Some("2020-01-02"));
+        // the track has no lyrics
+        assert_eq!(tags.lyrics, None);
+        let data = std::fs::read(&path).unwrap();
+        assert!(find(&data, b"ARTIST=Guest"));
+        assert!(find(&data, b"ALBUMARTIST=Band"));
+        assert!(data.ends_with(b"frames"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+//! Downloading videos from their HLS playlists
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::{
+    TidalClient,
+    client::models::video::{
+        config::VideoPlaybackInfoConfig,
+        hls::{Variant, VariantSelection},
+        playback::VideoPlaybackInfoResponse,
+    },
+    download::{Container, ProgressCallback, part_path, segments::SegmentFetchOptions},
+    error::TidalError,
+    ids::VideoId,
+};
+
+/// Options for [`TidalClient::download_video`]
+#[derive(Clone, Default)]
+pub struct VideoDownloadOptions {
+    /// Playback config used to request the stream, unset fields fall back to the session config
+    pub playback: VideoPlaybackInfoConfig,
+    /// Which variant of the master playlist to download
+    pub variant: VariantSelection,
+    /// Replace the destination file if it already exists
+    pub overwrite: bool,
+    /// Concurrency and bandwidth cap for the segments
+    pub segments: SegmentFetchOptions,
+    pub progress: Option<ProgressCallback>,
+}
+
+impl fmt::Debug for VideoDownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VideoDownloadOptions")
+            .field("variant", &self.variant)
+            .field("overwrite", &self.overwrite)
+            .field("segments", &self.segments)
+            .field("progress", &self.progress.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A finished video download
+#[derive(Debug, Clone)]
+pub struct DownloadedVideo {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// [`Container::MpegTs`] for TS segments, [`Container::Mp4`] for fragmented MP4 segments
+    pub container: Container,
+    /// Variant that was downloaded, `None` if the manifest pointed to a media playlist directly
+    pub variant: Option<Variant>,
+    pub video_quality: String,
+}
+
+impl TidalClient {
+    /// Downloads a video to `dest`
+    ///
+    /// The HLS manifest is requested through `get_video_postpaywall_playback_info`, the variant
+    /// picked by [`VideoDownloadOptions::variant`] is downloaded segment by segment into a
+    /// `.part` file next to `dest`, which is renamed to `dest` once every segment arrived. TS
+    /// segments are concatenated as is, fragmented MP4 segments follow their initialization
+    /// section, both make a playable file. MP4 files usually get the `.mp4` extension, TS files
+    /// `.ts`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tidlers::{TidalClient, auth::TidalAuth};
+    /// # use tidlers::client::models::{playback::VideoQuality, video::hls::VariantSelection};
+    /// # use tidlers::download::video::VideoDownloadOptions;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = TidalClient::new(&TidalAuth::with_oauth());
+    /// let options = VideoDownloadOptions {
+    ///     variant: VariantSelection {
+    ///         quality: Some(VideoQuality::Medium),
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let video = client.download_video("123456789", "video.ts", options).await?;
+    /// println!("wrote {} bytes ({:?})", video.bytes, video.container);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_video(
+        &self,
+        video_id: impl Into<VideoId>,
+        dest: impl AsRef<Path>,
+        options: VideoDownloadOptions,
+    ) -> Result<DownloadedVideo, TidalError> {
+        let video_id = video_id.into();
+        debug!(%video_id, "requesting playback info for video download");
+        let playback = self
+            .get_video_postpaywall_playback_info(video_id, Some(options.playback.clone()))
+            .await?;
+
+        self.download_video_playback(&playback, dest, &options)
+            .await
+    }
+
+    /// Downloads the video stream described by already fetched playback info to `dest`
+    pub async fn download_video_playback(
+        &self,
+        playback: &VideoPlaybackInfoResponse,
+        dest: impl AsRef<Path>,
+        options: &VideoDownloadOptions,
+    ) -> Result<DownloadedVideo, TidalError> {
+        let dest = dest.as_ref();
+        if !options.overwrite && fs::try_exists(dest).await? {
+            return Err(TidalError::InvalidArgument(format!(
+                "{} already exists",
+                dest.display()
+            )));
+        }
+
+        let stream = self
+            .get_video_media_playlist(playback, &options.variant)
+            .await?;
+        if stream.playlist.segments.is_empty() {
+            return Err(TidalError::InvalidManifest(
+                "media playlist has no segments".to_string(),
+            ));
+        }
+        if !stream.playlist.ended {
+            warn!("media playlist has no EXT-X-ENDLIST, downloading the segments listed so far");
+        }
+
+        let parts: Vec<String> = stream.playlist.urls().collect();
+        let part_path = part_path(dest);
+        let result = self
+            .write_segments(
+                &parts,
+                &part_path,
+                &options.segments,
+                options.progress.as_ref(),
+            )
+            .await;
+        let bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = fs::remove_file(&part_path).await;
+                return Err(e);
+            }
+        };
+        fs::rename(&part_path, dest).await?;
+
+        info!(
+            path = %dest.display(),
+            bytes,
+            segments = stream.playlist.segments.len(),
+            "video downloaded"
+        );
+
+        Ok(DownloadedVideo {
+            path: dest.to_path_buf(),
+            bytes,
+            container: if stream.playlist.is_fragmented_mp4() {
+                Container::Mp4
+            } else {
+                Container::MpegTs
+            },
+            variant: stream.variant,
+            video_quality: playback.video_quality.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde_json::json;
+
+    use super::VideoDownloadOptions;
+    use crate::{
+        client::models::{playback::VideoQuality, video::hls::VariantSelection},
+        download::Container,
+        test_support::{TestResponse, TestServer, api_client, temp_path},
+    };
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080
+1080p/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720
+720p/index.m3u8
+";
+
+    fn video_server(media: &'static str) -> TestServer {
+        TestServer::spawn(move |request| match request.path.as_str() {
+            "/videos/1/playbackinfopostpaywall" => {
+                let host = request.header("host").unwrap();
+                let manifest = json!({
+                    "mimeType": "application/vnd.apple.mpegurl",
+                    "urls": [format!("http://{host}/hls/master.m3u8")]
+                });
+                TestResponse::ok(
+                    json!({
+                        "videoId": 1, "assetPresentation": "FULL", "streamType": "ON_DEMAND",
+                        "videoQuality": "HIGH", "manifestMimeType": "application/vnd.tidal.emu",
+                        "manifestHash": "", "manifest": BASE64.encode(manifest.to_string())
+                    })
+                    .to_string(),
+                )
+            }
+            "/hls/master.m3u8" => TestResponse::ok(MASTER),
+            "/hls/720p/index.m3u8" => TestResponse::ok(media),
+            "/hls/720p/init.mp4" => TestResponse::ok("init"),
+            "/hls/720p/a.ts" | "/hls/720p/a.m4s" => TestResponse::ok("aaa"),
+            "/hls/720p/b.ts" | "/hls/720p/b.m4s" => TestResponse::ok("bb"),
+            _ => TestResponse::new(404),
+        })
+    }
+
+    fn medium() -> VideoDownloadOptions {
+        VideoDownloadOptions {
+            variant: VariantSelection {
+                quality: Some(VideoQuality::Medium),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_ts_segments_of_the_selected_variant() {
+        let server = video_server(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\na.ts\n#EXTINF:2,\nb.ts\n#EXT-X-ENDLIST\n",
+        );
+        let client = api_client(&server);
+        let dest = temp_path("video.ts");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let progress = seen.clone();
+
+        let options = VideoDownloadOptions {
+            progress: Some(Arc::new(move |p| {
+                progress
+                    .lock()
+                    .unwrap()
+                    .push((p.parts_downloaded, p.total_parts))
+            })),
+            ..medium()
+        };
+        let video = client.download_video("1", &dest, options).await.unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"aaabb");
+        assert_eq!(video.bytes, 5);
+        assert_eq!(video.container, Container::MpegTs);
+        assert_eq!(
+            video
+                .variant
+                .and_then(|variant| variant.resolution)
+                .unwrap()
+                .height,
+            720
+        );
+        assert_eq!(*seen.lock().unwrap(), [(1, 2), (2, 2)]);
+        assert!(
+            !server
+                .request_lines()
+                .contains(&"GET /hls/1080p/index.m3u8".to_string())
+        );
+
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fragmented_mp4_segments_follow_their_init_section() {
+        let server = video_server(
+            "#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:4,\na.m4s\n#EXTINF:2,\nb.m4s\n#EXT-X-ENDLIST\n",
+        );
+        let client = api_client(&server);
+        let dest = temp_path("video.mp4");
+
+        let video = client.download_video("1", &dest, medium()).await.unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"initaaabb");
+        assert_eq!(video.container, Container::Mp4);
+
+        std::fs::remove_file(dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_segment_fails_and_removes_partial_file() {
+        let server = video_server("#EXTM3U\n#EXTINF:4,\na.ts\n#EXTINF:4,\nc.ts\n#EXT-X-ENDLIST\n");
+        let client = api_client(&server);
+        let dest = temp_path("broken-video.ts");
+
+        let result = client.download_video("1", &dest, medium()).await;
+
+        assert!(result.is_err());
+        assert!(!dest.exists());
+        assert!(!crate::download::part_path(&dest).exists());
+    }
+}
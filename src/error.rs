@@ -27,9 +27,27 @@ pub enum TidalError {
     #[error("authentication failed: {0}")]
     Auth(String),
 
+    #[error("authorization denied: {error}{}", description.as_deref().map(|d| format!(" ({d})")).unwrap_or_default())]
+    AuthorizationDenied {
+        error: String,
+        description: Option<String>,
+    },
+
+    #[error("PKCE redirect state doesn't match the pending login")]
+    PkceStateMismatch,
+
+    #[error("failed to decrypt session: {0}")]
+    SessionDecryption(String),
+
     #[error("invalid response from API: {0}")]
     InvalidResponse(String),
 
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("unsupported stream encryption: {0}")]
+    UnsupportedEncryption(String),
+
     #[error("invalid argument: {0}")]
     InvalidArgument(String),
 
@@ -60,6 +78,16 @@ pub enum TidalError {
     #[serde(serialize_with = "serialize_generic")]
     UrlParseError(#[from] url::ParseError),
 
+    #[error("io error: {0}")]
+    #[serde(serialize_with = "serialize_generic")]
+    Io(#[from] std::io::Error),
+
+    #[error("download incomplete: {0}")]
+    IncompleteDownload(String),
+
+    #[error("remux failed: {0}")]
+    Remux(String),
+
     #[error("{0}")]
     Other(String),
 }
@@ -14,6 +14,31 @@
 //!   - Direct access token (`TidalAuth::with_access_token(...)`)
 //! - DASH manifest parsing for HiRes playback
 //! - Session persistence (`get_json()` / `from_json()`)
+//! - Automatic access token refresh on expiry or `401` responses (shared between client clones)
+//! - Opt-in retries with exponential backoff and `Retry-After` handling (`set_retry_policy(...)`)
+//! - Opt-in client-side rate limiting per API base URL, shared between client clones (`set_rate_limiter(...)`)
+//! - Pagination streams that walk every page of offset- and cursor-based endpoints (`get_album_items_all(...)`, `get_playlist_items_all(...)`, ...)
+//! - Typed JSON:API documents for the OpenAPI v2 endpoints (`openapi_get_tracks_by_id_typed(...)`, `Document<T>`, `Resource<A>`)
+//! - Typed `include` lists (`OpenApiQuery`) and resolution of included relationships into an object graph (`Document::resolve()`)
+//! - DASH MPD parsing with `SegmentTimeline` support that lists the exact segment URLs of a HiRes stream (`DashManifest::segments()`)
+//! - Track downloads straight to disk with progress reporting and completeness checks (`download_track(...)`)
+//! - Pure Rust remuxing of HiRes FLAC-in-MP4 streams into native `.flac` files (`remux_flac`, `remux_fmp4_flac(...)`)
+//! - Tagging of downloaded FLAC and MP4 files with TIDAL metadata, lyrics, ReplayGain and cover art (`tag_file(...)`, `DownloadOptions::tags`)
+//! - Album and playlist batch downloads with bounded concurrency, path templates and `folder.jpg` covers (`download_album(...)`, `download_playlist(...)`)
+//! - Resumable download manager with a persistent job queue, HTTP Range/segment checkpoints and pause/resume/cancel (`DownloadManager`)
+//! - Seekable `AsyncRead`/`AsyncSeek` track streams with lazy range fetching, DASH segment seeking and a blocking `Read + Seek` adapter for decoders (`open_track_stream(...)`, `TrackStream`)
+//! - Concurrent DASH segment fetching that still delivers segments in order, with per-segment retries and a bandwidth cap (`fetch_segments(...)`, `SegmentFetchOptions`)
+//! - Local HTTP streaming proxy serving `/track/{id}` with `Range` support to players like mpv or VLC, behind the `proxy` feature (`start_stream_proxy(...)`, `StreamProxy`)
+//! - HLS master/media playlist parsing with variant selection by quality, resolution and bandwidth, and video downloads of TS or fragmented MP4 segments into one file (`get_video_media_playlist(...)`, `download_video(...)`)
+//! - Transparent AES-CTR decryption of legacy `OLD_AES` streams in downloads, the download manager and track streams, with a typed `UnsupportedEncryption` error for other schemes (`decrypt::StreamKey`)
+//! - Immersive audio: typed `AudioMode` (Dolby Atmos, Sony 360 Reality Audio) in playback configs and session defaults, `AudioCodec` parsing of E-AC-3/AC-4/MPEG-H manifests and `Track::immersive_modes()`
+//! - Quality negotiation from a preference list capped by the track, subscription and login, with a typed report of requested vs. delivered quality, sample rate, bit depth and codec (`negotiate_track_playback(...)`, `QualityReport`)
+//! - Typed `QualityTag` for media metadata tags, track/playback qualities and audio modes with an `Unknown(String)` fallback, and stream properties (sample rate, bit depth, channels) parsed from DASH representations (`stream_properties()`)
+//! - Automatic PKCE login through a one-shot loopback redirect listener with `state` check, timeout and cancellation, behind the `pkce-loopback` feature (`start_loopback_pkce_login(...)`, `finish_loopback_pkce_login(...)`)
+//! - PKCE logins with a fresh code verifier and CSRF `state` per `initiate_pkce_login()`, persisted in the session and checked by `finish_pkce_login(...)`, with redirect errors surfaced as `TidalError::AuthorizationDenied`
+//! - Device-code login as a `Stream` of `OAuthStatus` honouring RFC 8628 `slow_down`, with typed pending/denied/expired states, cancellation by dropping the stream and custom scopes (`start_device_login(...)`, `device_login_stream(...)`)
+//! - Pluggable session persistence through a `SessionStore` trait, saved automatically after every login and token refresh, with an atomic owner-only `FileSessionStore` and a restore-on-startup path (`with_session_store(...)`, `set_session_store(...)`)
+//! - Passphrase encrypted session files with a versioned header, Argon2id key derivation and XChaCha20-Poly1305 encryption (`get_encrypted(...)`, `from_encrypted(...)`, `EncryptedFileSessionStore`)
 //! - `tracing` for auth/session/request flows
 //!
 //! ## Example
@@ -43,13 +68,25 @@
 
 pub mod auth;
 pub mod client;
+pub mod decrypt;
+pub mod download;
 pub mod error;
 pub mod ids;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+pub mod rate_limit;
 pub mod requests;
 pub mod resources;
 pub mod session;
+pub mod session_crypto;
+pub mod session_store;
+pub mod stream;
 pub mod urls;
 pub mod utils;
+
+#[cfg(test)]
+pub(crate) mod test_support;
+
 pub use client::models::responses;
 
 // Re-export main types for convenience
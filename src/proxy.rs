@@ -0,0 +1,581 @@
+//! Local HTTP server that streams TIDAL tracks to external players
+//!
+//! [`StreamProxy`] answers `GET` and `HEAD` requests for `/track/{id}` by resolving the track's
+//! playback info on demand and serving the stream as one continuous body. DASH streams are
+//! stitched together from their init and media segments, so a HiRes track plays like a single
+//! fragmented MP4 file. Bodies are read through a [`TrackStream`], which makes `Range` requests
+//! cheap and lets mpv, VLC or a browser seek without downloading the whole track first.
+//!
+//! Available with the `proxy` feature.
+
+use std::{
+    collections::HashMap,
+    io::{self, SeekFrom},
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    TidalClient,
+    download::DownloadPlan,
+    error::TidalError,
+    ids::TrackId,
+    requests::RequestClientError,
+    stream::{StreamOptions, TrackStream},
+};
+
+/// Longest request head the proxy reads before giving up on a connection
+const MAX_HEAD_LEN: usize = 16 * 1024;
+
+/// Options for [`TidalClient::start_stream_proxy`]
+#[derive(Clone)]
+pub struct ProxyOptions {
+    /// Address to listen on, port `0` picks a free port
+    pub addr: SocketAddr,
+    /// Playback config and read-ahead of the served streams
+    pub stream: StreamOptions,
+    /// How long resolved playback info is reused for further requests of the same track
+    ///
+    /// Players open a new request for every seek, reusing the playback info saves an API call
+    /// and the `HEAD` requests for the DASH segment sizes each time.
+    pub cache_ttl: Duration,
+}
+
+impl Default for ProxyOptions {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            stream: StreamOptions::default(),
+            cache_ttl: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// A running stream proxy, stops accepting connections when dropped
+///
+/// # Example
+///
+/// ```no_run
+/// # use tidlers::{TidalClient, auth::TidalAuth};
+/// # use tidlers::proxy::ProxyOptions;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = TidalClient::new(&TidalAuth::with_oauth());
+/// let proxy = client.start_stream_proxy(ProxyOptions::default()).await?;
+///
+/// // e.g. `mpv http://127.0.0.1:PORT/track/123456789`
+/// let url = proxy.track_url("123456789");
+/// std::process::Command::new("mpv").arg(&url).status()?;
+///
+/// proxy.shutdown();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct StreamProxy {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl StreamProxy {
+    /// Address the proxy is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// URL serving the track with `track_id`
+    pub fn track_url(&self, track_id: impl Into<TrackId>) -> String {
+        format!("http://{}/track/{}", self.addr, track_id.into())
+    }
+
+    /// Stops accepting connections, responses already being sent are finished
+    pub fn shutdown(self) {}
+}
+
+impl Drop for StreamProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl TidalClient {
+    /// Starts a local HTTP server streaming tracks at `/track/{id}`, see [`crate::proxy`]
+    ///
+    /// The proxy works with a clone of the client, tokens it refreshes are shared with this
+    /// client. It runs on the current tokio runtime until the returned [`StreamProxy`] is
+    /// dropped.
+    pub async fn start_stream_proxy(
+        &self,
+        options: ProxyOptions,
+    ) -> Result<StreamProxy, TidalError> {
+        let listener = TcpListener::bind(options.addr).await?;
+        let addr = listener.local_addr()?;
+        debug!(%addr, "stream proxy listening");
+
+        let state = Arc::new(ProxyState {
+            client: self.clone(),
+            options,
+            tracks: Mutex::new(HashMap::new()),
+        });
+
+        let task = tokio::spawn(async move {
+            loop {
+                let (socket, peer) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        warn!(%error, "stream proxy failed to accept a connection");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = state.serve(socket).await {
+                        debug!(%peer, %error, "stream proxy connection ended early");
+                    }
+                });
+            }
+        });
+
+        Ok(StreamProxy { addr, task })
+    }
+}
+
+/// A track whose playback info was resolved recently
+struct CachedTrack {
+    resolved: Instant,
+    stream: TrackStream,
+    content_type: String,
+}
+
+struct ProxyState {
+    client: TidalClient,
+    options: ProxyOptions,
+    tracks: Mutex<HashMap<TrackId, CachedTrack>>,
+}
+
+/// Request line and the headers the proxy looks at
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    range: Option<String>,
+}
+
+/// Part of the stream a `Range` header asks for
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,
+    /// Inclusive start and end offsets
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+impl ProxyState {
+    async fn serve(&self, socket: TcpStream) -> io::Result<()> {
+        let mut socket = BufReader::new(socket);
+        let request = read_request(&mut socket).await?;
+        let socket = socket.get_mut();
+
+        let Some(request) = request else {
+            return write_error(socket, 400, "malformed request", false).await;
+        };
+        let head_only = request.method == "HEAD";
+        if !matches!(request.method.as_str(), "GET" | "HEAD") {
+            return write_error(socket, 405, "only GET and HEAD are supported", false).await;
+        }
+
+        let path = request.path.split('?').next().unwrap_or_default();
+        let Some(track_id) = path
+            .strip_prefix("/track/")
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+        else {
+            return write_error(socket, 404, "expected /track/{id}", head_only).await;
+        };
+        let track_id = TrackId::from(track_id);
+
+        let (mut stream, content_type) = match self.open(&track_id).await {
+            Ok(opened) => opened,
+            Err(
+                TidalError::NotFound
+                | TidalError::RequestClient(RequestClientError::StatusCode {
+                    status: StatusCode::NOT_FOUND,
+                    ..
+                }),
+            ) => {
+                return write_error(socket, 404, "track not found", head_only).await;
+            }
+            Err(error) => {
+                warn!(%track_id, %error, "stream proxy failed to resolve track");
+                return write_error(socket, 502, &error.to_string(), head_only).await;
+            }
+        };
+
+        // resolves the length like `len()`, which would hold a non-`Send` `&TrackStream` across
+        // the await
+        let len = match stream.seek(SeekFrom::End(0)).await {
+            Ok(len) => len,
+            Err(error) => {
+                // the cached stream URLs may have expired, resolve them again next time
+                self.forget(&track_id);
+                warn!(%track_id, %error, "stream proxy failed to get the stream length");
+                return write_error(socket, 502, &error.to_string(), head_only).await;
+            }
+        };
+
+        let mut headers = vec![
+            ("Content-Type", content_type),
+            ("Accept-Ranges", "bytes".to_string()),
+        ];
+        let (status, start, end) = match request.range.as_deref().map(|r| parse_range(r, len)) {
+            None | Some(ByteRange::Full) => (200, 0, len),
+            Some(ByteRange::Partial(start, end)) => {
+                headers.push(("Content-Range", format!("bytes {start}-{end}/{len}")));
+                (206, start, end + 1)
+            }
+            Some(ByteRange::Unsatisfiable) => {
+                let headers = [
+                    ("Content-Range", format!("bytes */{len}")),
+                    ("Content-Length", "0".to_string()),
+                ];
+                return write_head(socket, 416, &headers).await;
+            }
+        };
+        headers.push(("Content-Length", (end - start).to_string()));
+
+        debug!(%track_id, status, start, end, "stream proxy serving track");
+        write_head(socket, status, &headers).await?;
+        if head_only || start == end {
+            return Ok(());
+        }
+
+        stream.seek(SeekFrom::Start(start)).await?;
+        tokio::io::copy(&mut (&mut stream).take(end - start), socket).await?;
+        socket.shutdown().await
+    }
+
+    /// Stream over the track's playback info and its content type, resolved when not cached
+    async fn open(&self, track_id: &TrackId) -> Result<(TrackStream, String), TidalError> {
+        if let Some(cached) = self
+            .tracks
+            .lock()
+            .unwrap()
+            .get(track_id)
+            .filter(|cached| cached.resolved.elapsed() < self.options.cache_ttl)
+        {
+            return Ok((cached.stream.reopen(), cached.content_type.clone()));
+        }
+
+        let playback = self
+            .client
+            .get_track_postpaywall_playback_info(
+                track_id.clone(),
+                Some(self.options.stream.playback.clone()),
+            )
+            .await?;
+        let plan = DownloadPlan::from_playback(&playback)?;
+        let stream = TrackStream::from_playback(&self.client, &playback, &self.options.stream)?;
+        let content_type = if plan.mime_type.is_empty() {
+            "application/octet-stream".to_string()
+        } else {
+            plan.mime_type
+        };
+
+        let mut tracks = self.tracks.lock().unwrap();
+        tracks.retain(|_, cached| cached.resolved.elapsed() < self.options.cache_ttl);
+        tracks.insert(
+            track_id.clone(),
+            CachedTrack {
+                resolved: Instant::now(),
+                stream: stream.reopen(),
+                content_type: content_type.clone(),
+            },
+        );
+
+        Ok((stream, content_type))
+    }
+
+    fn forget(&self, track_id: &TrackId) {
+        self.tracks.lock().unwrap().remove(track_id);
+    }
+}
+
+/// Reads the request head, `None` if it isn't HTTP
+async fn read_request(socket: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut head_len = 0;
+    let mut line = String::new();
+
+    let mut read_line = async |line: &mut String| -> io::Result<bool> {
+        line.clear();
+        let read = socket.read_line(line).await?;
+        head_len += read;
+        if head_len > MAX_HEAD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too long",
+            ));
+        }
+        Ok(read > 0 && !line.trim_end().is_empty())
+    };
+
+    if !read_line(&mut line).await? {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(None);
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Ok(None);
+    }
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        range: None,
+    };
+
+    while read_line(&mut line).await? {
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("range")
+        {
+            request.range = Some(value.trim().to_string());
+        }
+    }
+
+    Ok(Some(request))
+}
+
+/// Parses a `Range` header against a stream of `len` bytes
+///
+/// Malformed headers and multiple ranges are ignored and get the whole stream, as HTTP allows.
+fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some((start, end)) = value
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.trim().split_once('-'))
+    else {
+        return ByteRange::Full;
+    };
+
+    let last = match len.checked_sub(1) {
+        Some(last) => last,
+        None => return ByteRange::Unsatisfiable,
+    };
+
+    match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        // suffix range, the last `n` bytes
+        (None, Some(n)) if start.is_empty() => match n {
+            0 => ByteRange::Unsatisfiable,
+            n => ByteRange::Partial(len.saturating_sub(n), last),
+        },
+        (Some(start), _) if start > last => ByteRange::Unsatisfiable,
+        (Some(start), None) if end.is_empty() => ByteRange::Partial(start, last),
+        (Some(start), Some(end)) if start <= end => ByteRange::Partial(start, end.min(last)),
+        _ => ByteRange::Full,
+    }
+}
+
+async fn write_head(
+    socket: &mut TcpStream,
+    status: u16,
+    headers: &[(&str, String)],
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {status} {}\r\n", reason(status));
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    socket.write_all(head.as_bytes()).await
+}
+
+async fn write_error(
+    socket: &mut TcpStream,
+    status: u16,
+    message: &str,
+    head_only: bool,
+) -> io::Result<()> {
+    let headers = [
+        ("Content-Type", "text/plain; charset=utf-8".to_string()),
+        ("Content-Length", message.len().to_string()),
+    ];
+    write_head(socket, status, &headers).await?;
+    if !head_only {
+        socket.write_all(message.as_bytes()).await?;
+    }
+    socket.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        502 => "Bad Gateway",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use reqwest::{StatusCode, header};
+
+    use super::{ByteRange, ProxyOptions, parse_range};
+    use crate::test_support::{
+        TestResponse, TestServer, api_client, dash_playback_json, playback_json,
+    };
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        assert_eq!(parse_range("bytes=2-5", 10), ByteRange::Partial(2, 5));
+        assert_eq!(parse_range("bytes=4-", 10), ByteRange::Partial(4, 9));
+        assert_eq!(parse_range("bytes=-3", 10), ByteRange::Partial(7, 9));
+        assert_eq!(parse_range("bytes=8-100", 10), ByteRange::Partial(8, 9));
+        assert_eq!(parse_range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-2", 10), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 10), ByteRange::Full);
+    }
+
+    #[tokio::test]
+    async fn serves_progressive_tracks_with_ranges() {
+        let playbacks = AtomicUsize::new(0);
+        let server = TestServer::spawn(move |request| match request.path.as_str() {
+            "/tracks/1/playbackinfopostpaywall" => {
+                playbacks.fetch_add(1, Ordering::SeqCst);
+                let host = request.header("host").unwrap();
+                let url = format!("http://{host}/track.flac");
+                TestResponse::ok(playback_json(1, &url).to_string())
+            }
+            "/track.flac" => TestResponse::file(request, b"0123456789"),
+            _ => TestResponse::new(404),
+        });
+        let client = api_client(&server);
+        let proxy = client
+            .start_stream_proxy(ProxyOptions::default())
+            .await
+            .unwrap();
+        let http = reqwest::Client::new();
+
+        let response = http.get(proxy.track_url("1")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/flac");
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(response.bytes().await.unwrap(), "0123456789");
+
+        let response = http
+            .get(proxy.track_url("1"))
+            .header(header::RANGE, "bytes=2-5")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+        assert_eq!(response.bytes().await.unwrap(), "2345");
+
+        let response = http
+            .get(proxy.track_url("1"))
+            .header(header::RANGE, "bytes=20-")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+
+        // playback info is resolved once and reused for the following requests
+        let playback_requests = server
+            .request_lines()
+            .iter()
+            .filter(|line| line.ends_with("/playbackinfopostpaywall"))
+            .count();
+        assert_eq!(playback_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn stitches_dash_segments_into_one_body() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/tracks/2/playbackinfopostpaywall" => {
+                let host = request.header("host").unwrap();
+                let base = format!("http://{host}/dash/");
+                TestResponse::ok(dash_playback_json(2, &base, 2).to_string())
+            }
+            "/dash/init.mp4" => TestResponse::file(request, b"init"),
+            "/dash/1.m4s" => TestResponse::file(request, b"first"),
+            "/dash/2.m4s" => TestResponse::file(request, b"second"),
+            _ => TestResponse::new(404),
+        });
+        let client = api_client(&server);
+        let proxy = client
+            .start_stream_proxy(ProxyOptions::default())
+            .await
+            .unwrap();
+        let http = reqwest::Client::new();
+
+        let response = http.get(proxy.track_url("2")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/mp4");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "15");
+        assert_eq!(response.bytes().await.unwrap(), "initfirstsecond");
+
+        // a range crossing the boundary between two segments
+        let response = http
+            .get(proxy.track_url("2"))
+            .header(header::RANGE, "bytes=6-10")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 6-10/15");
+        assert_eq!(response.bytes().await.unwrap(), "rstse");
+    }
+
+    #[tokio::test]
+    async fn head_requests_get_no_body_and_unknown_paths_404() {
+        let server = TestServer::spawn(|request| match request.path.as_str() {
+            "/tracks/3/playbackinfopostpaywall" => {
+                let host = request.header("host").unwrap();
+                let url = format!("http://{host}/track.flac");
+                TestResponse::ok(playback_json(3, &url).to_string())
+            }
+            "/track.flac" => TestResponse::file(request, b"fLaC-data"),
+            _ => TestResponse::new(404),
+        });
+        let client = api_client(&server);
+        let proxy = client
+            .start_stream_proxy(ProxyOptions::default())
+            .await
+            .unwrap();
+        let http = reqwest::Client::new();
+
+        let response = http.head(proxy.track_url("3")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "9");
+        assert!(
+            !server
+                .request_lines()
+                .contains(&"GET /track.flac".to_string())
+        );
+
+        let unknown = format!("http://{}/album/3", proxy.addr());
+        let response = http.get(unknown).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = http.get(proxy.track_url("4")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+use crate::urls::{API_V1_LOCATION, API_V2_LOCATION, OPEN_API_V2_LOCATION, WEB_API_V2_LOCATION};
+
+/// Pacing rules for requests sent to a single base URL
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    /// Sustained number of requests allowed per second
+    pub requests_per_second: f64,
+    /// Number of requests that may be sent back to back before pacing kicks in
+    pub burst: u32,
+    /// Maximum number of requests in flight at the same time, `None` means unlimited
+    ///
+    /// A request counts as in flight until its response headers arrive, reading the body is not
+    /// covered.
+    pub max_concurrent: Option<usize>,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 5.0,
+            burst: 10,
+            max_concurrent: Some(4),
+        }
+    }
+}
+
+/// Client-side rate limiter keyed by base URL
+///
+/// The limiter lives inside the `RequestClient` and all of its state is reference counted, so
+/// every clone of a `TidalClient` paces its requests through the same buckets. Requests to base
+/// URLs without a configured limit are not throttled.
+///
+/// # Example
+///
+/// ```
+/// # use tidlers::{TidalClient, auth::TidalAuth};
+/// # use tidlers::rate_limit::{RateLimit, RateLimiter};
+/// # use tidlers::urls::OPEN_API_V2_LOCATION;
+/// let limiter = RateLimiter::for_api_locations(RateLimit::default()).with_limit(
+///     OPEN_API_V2_LOCATION,
+///     RateLimit {
+///         requests_per_second: 2.0,
+///         burst: 2,
+///         max_concurrent: Some(1),
+///     },
+/// );
+///
+/// let mut client = TidalClient::new(&TidalAuth::with_oauth());
+/// client.set_rate_limiter(Some(limiter));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<String, Arc<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter without any configured limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a limiter applying the same limit to each of the TIDAL API base URLs
+    ///
+    /// Every base URL gets its own bucket, they don't share a budget.
+    pub fn for_api_locations(limit: RateLimit) -> Self {
+        [
+            API_V1_LOCATION,
+            API_V2_LOCATION,
+            OPEN_API_V2_LOCATION,
+            WEB_API_V2_LOCATION,
+        ]
+        .into_iter()
+        .fold(Self::new(), |limiter, base_url| {
+            limiter.with_limit(base_url, limit.clone())
+        })
+    }
+
+    /// Sets the limit for a base URL, replacing any previous limit for it
+    pub fn with_limit(mut self, base_url: impl Into<String>, limit: RateLimit) -> Self {
+        self.buckets
+            .insert(base_url.into(), Arc::new(Bucket::new(limit)));
+        self
+    }
+
+    /// Returns the limit configured for a base URL
+    pub fn limit_for(&self, base_url: &str) -> Option<&RateLimit> {
+        self.buckets.get(base_url).map(|bucket| &bucket.limit)
+    }
+
+    /// Waits until a request to `base_url` may be sent
+    ///
+    /// The returned permit holds a concurrency slot (if limited) until it is dropped.
+    pub(crate) async fn acquire(&self, base_url: &str) -> Option<RateLimitPermit> {
+        let bucket = self.buckets.get(base_url)?;
+
+        let permit = match &bucket.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("rate limiter semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        bucket.take_token(base_url).await;
+
+        Some(RateLimitPermit { _permit: permit })
+    }
+}
+
+/// Held while a rate limited request is in flight
+#[derive(Debug)]
+pub(crate) struct RateLimitPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    state: Mutex<BucketState>,
+    concurrency: Option<Arc<Semaphore>>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        let burst = f64::from(limit.burst.max(1));
+        let concurrency = limit
+            .max_concurrent
+            .map(|max| Arc::new(Semaphore::new(max.max(1))));
+
+        Self {
+            limit,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+            concurrency,
+        }
+    }
+
+    async fn take_token(&self, base_url: &str) {
+        let rate = self.limit.requests_per_second;
+        if rate <= 0.0 || !rate.is_finite() {
+            return;
+        }
+
+        let capacity = f64::from(self.limit.burst.max(1));
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * rate).min(capacity);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - state.tokens) / rate)
+            };
+
+            debug!(
+                base_url,
+                wait_ms = wait.as_millis() as u64,
+                "rate limit reached, delaying request"
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use reqwest::Method;
+
+    use super::{RateLimit, RateLimiter};
+    use crate::{
+        requests::{RequestClient, TidalRequest},
+        test_support::{TestResponse, TestServer},
+    };
+
+    fn limit(requests_per_second: f64, burst: u32, max_concurrent: Option<usize>) -> RateLimit {
+        RateLimit {
+            requests_per_second,
+            burst,
+            max_concurrent,
+        }
+    }
+
+    #[tokio::test]
+    async fn unconfigured_base_url_is_not_limited() {
+        let limiter = RateLimiter::new().with_limit("http://limited", limit(1.0, 1, None));
+
+        assert!(limiter.acquire("http://other").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_burst_are_paced() {
+        let limiter = RateLimiter::new().with_limit("http://limited", limit(50.0, 1, None));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire("http://limited").await;
+        }
+
+        // first request uses the burst, the other two wait ~20ms each
+        assert!(start.elapsed() >= Duration::from_millis(35));
+    }
+
+    #[tokio::test]
+    async fn requests_within_burst_are_not_delayed() {
+        let limiter = RateLimiter::new().with_limit("http://limited", limit(1.0, 3, None));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire("http://limited").await;
+        }
+
+        // pacing at 1 request per second would take at least 2s
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn clones_share_the_same_bucket() {
+        let limiter = RateLimiter::new().with_limit("http://limited", limit(50.0, 1, None));
+        let clone = limiter.clone();
+
+        limiter.acquire("http://limited").await;
+        let start = Instant::now();
+        clone.acquire("http://limited").await;
+
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+
+    #[tokio::test]
+    async fn requests_never_exceed_max_concurrent() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (in_flight, max_seen) = (active.clone(), peak.clone());
+        let server = TestServer::spawn(move |_| {
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_seen.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(30));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            TestResponse::new(204)
+        });
+
+        let mut client = RequestClient::new(server.url().to_string());
+        client.set_rate_limiter(Some(
+            RateLimiter::new().with_limit(server.url(), limit(1000.0, 10, Some(2))),
+        ));
+
+        let requests = (0..6).map(|_| {
+            let client = client.clone();
+            async move {
+                client
+                    .request(TidalRequest::new(Method::GET, "/limited".to_string()))
+                    .await
+            }
+        });
+        let results = futures_util::future::join_all(requests).await;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(server.requests().len(), 6);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrency_is_limited_until_permit_is_dropped() {
+        let limiter = RateLimiter::new().with_limit("http://limited", limit(1000.0, 10, Some(1)));
+
+        let permit = limiter.acquire("http://limited").await;
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(20), limiter.acquire("http://limited"))
+                .await;
+        assert!(blocked.is_err());
+
+        drop(permit);
+        let unblocked =
+            tokio::time::timeout(Duration::from_millis(20), limiter.acquire("http://limited"))
+                .await;
+        assert!(unblocked.is_ok());
+    }
+}
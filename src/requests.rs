@@ -1,11 +1,18 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
+use rand::RngExt;
 use reqwest::{
     Method,
     header::{HeaderMap, HeaderValue},
 };
 use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
+use url::Position;
+
+use crate::{
+    rate_limit::RateLimiter,
+    urls::{OAUTH2_V1_LOCATION, PKCE_AUTH_URL, RESOURCES_LOCATION},
+};
 
 /// HTTP client wrapper for making API requests
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -15,6 +22,86 @@ pub struct RequestClient {
 
     #[serde(skip)]
     client: reqwest::Client,
+
+    #[serde(skip)]
+    retry_policy: Option<RetryPolicy>,
+
+    #[serde(skip)]
+    rate_limiter: Option<RateLimiter>,
+
+    #[serde(skip)]
+    oauth_base_url: Option<String>,
+
+    #[serde(skip)]
+    pkce_auth_url: Option<String>,
+
+    #[serde(skip)]
+    resources_base_url: Option<String>,
+}
+
+/// Controls how failed requests are retried
+///
+/// Requests are retried on `429 Too Many Requests`, `5xx` gateway/server errors and connection
+/// failures, waiting an exponentially growing delay between attempts.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tidlers::requests::RetryPolicy;
+/// let policy = RetryPolicy {
+///     max_attempts: 5,
+///     base_delay: Duration::from_millis(250),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every following retry
+    pub base_delay: Duration,
+    /// Upper bound for a single delay, also the longest `Retry-After` the client is willing to wait
+    pub max_delay: Duration,
+    /// Fraction (`0.0..=1.0`) of each delay that is randomized to spread out retries
+    pub jitter: f64,
+    /// Use the server provided `Retry-After` header instead of the computed delay
+    pub respect_retry_after: bool,
+    /// Also retry non-idempotent methods (`POST`), off by default
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            respect_retry_after: true,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the backoff delay before the given retry (1 = first retry)
+    pub(crate) fn backoff_delay(&self, retry: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+
+        let scale = 1.0 - jitter * rand::rng().random_range(0.0..1.0);
+        delay.mul_f64(scale)
+    }
+
+    fn allows_method(&self, method: &Method) -> bool {
+        self.retry_non_idempotent || method.is_idempotent()
+    }
 }
 
 /// Represents an HTTP request to the Tidal API
@@ -86,6 +173,39 @@ pub enum RequestClientError {
         url: String,
         body_snippet: String,
     },
+
+    #[error("rate limited by the API (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+}
+
+impl RequestClientError {
+    /// Whether the request may succeed if it's sent again
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } => true,
+            Self::StatusCode { status, .. } => matches!(
+                *status,
+                reqwest::StatusCode::INTERNAL_SERVER_ERROR
+                    | reqwest::StatusCode::BAD_GATEWAY
+                    | reqwest::StatusCode::SERVICE_UNAVAILABLE
+                    | reqwest::StatusCode::GATEWAY_TIMEOUT
+            ),
+            Self::RequestError(e) => e.is_connect() || e.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
+/// Parses a `Retry-After` header value, either delay in seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
 }
 
 impl RequestClient {
@@ -116,9 +236,86 @@ impl RequestClient {
             base_url,
             client,
             user_agent: "Mozilla/5.0 (Linux; Android 12; wv) AppleWebKit/537.36 (KHTML, like Gecko) Version/4.0 Chrome/91.0.4472.114 Safari/537.36".to_string(),
+            retry_policy: None,
+            rate_limiter: None,
+            oauth_base_url: None,
+            pkce_auth_url: None,
+            resources_base_url: None,
         }
     }
 
+    /// Sets the retry policy, `None` disables retries
+    pub fn set_retry_policy(&mut self, retry_policy: Option<RetryPolicy>) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Returns the current retry policy
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    /// Sets the client-side rate limiter, `None` disables rate limiting
+    ///
+    /// The limiter is shared by every clone of this client.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    /// Returns the current rate limiter
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Base URL of the OAuth2 token endpoints
+    pub(crate) fn oauth_base_url(&self) -> &str {
+        self.oauth_base_url.as_deref().unwrap_or(OAUTH2_V1_LOCATION)
+    }
+
+    /// Points the OAuth2 token endpoints at another server
+    #[cfg(test)]
+    pub(crate) fn set_oauth_base_url(&mut self, oauth_base_url: impl Into<String>) {
+        self.oauth_base_url = Some(oauth_base_url.into());
+    }
+
+    /// Login page PKCE authorization starts at
+    pub(crate) fn pkce_auth_url(&self) -> &str {
+        self.pkce_auth_url.as_deref().unwrap_or(PKCE_AUTH_URL)
+    }
+
+    /// Points PKCE authorization at another server
+    #[cfg(all(test, feature = "pkce-loopback"))]
+    pub(crate) fn set_pkce_auth_url(&mut self, pkce_auth_url: impl Into<String>) {
+        self.pkce_auth_url = Some(pkce_auth_url.into());
+    }
+
+    /// Base URL of the image server cover art is fetched from
+    pub(crate) fn resources_base_url(&self) -> &str {
+        self.resources_base_url
+            .as_deref()
+            .unwrap_or(RESOURCES_LOCATION)
+    }
+
+    /// Points cover art requests at another server
+    #[cfg(test)]
+    pub(crate) fn set_resources_base_url(&mut self, resources_base_url: impl Into<String>) {
+        self.resources_base_url = Some(resources_base_url.into());
+    }
+
+    /// Underlying HTTP client, used for requests outside of the TIDAL API such as CDN downloads
+    pub(crate) fn http_client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Sends a `HEAD` request to an absolute URL outside of the TIDAL API, such as a CDN segment
+    ///
+    /// The request is rate limited under the URL's origin and retried like API requests.
+    pub(crate) async fn head(&self, url: &str) -> Result<reqwest::Response, RequestClientError> {
+        let url = reqwest::Url::parse(url)?;
+        let mut request = TidalRequest::new(Method::HEAD, url[Position::BeforePath..].to_string());
+        request.base_url = Some(url.origin().ascii_serialization());
+        self.request(request).await
+    }
+
     /// Internal method to execute HTTP requests with all configured options
     async fn requests_basic(
         &self,
@@ -151,6 +348,13 @@ impl RequestClient {
 
         let base_url = request.base_url.unwrap_or(self.base_url.clone());
 
+        // held until the response headers arrive, the body is read by the caller after the permit
+        // is released
+        let _rate_limit_permit = match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.acquire(&base_url).await,
+            None => None,
+        };
+
         if request.send_params_as_form {
             for (key, value) in req_params.drain() {
                 req_form.insert(key, value);
@@ -178,6 +382,7 @@ impl RequestClient {
             Method::DELETE => Ok(self.client.delete(url_w_params)),
             Method::PUT => Ok(self.client.put(url_w_params)),
             Method::POST => Ok(self.client.post(url_w_params)),
+            Method::HEAD => Ok(self.client.head(url_w_params)),
             _ => Err(RequestClientError::InvalidMethod),
         };
 
@@ -230,6 +435,21 @@ impl RequestClient {
                 return Err(RequestClientError::Unauthorized);
             }
 
+            if req_status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = req
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after);
+                warn!(
+                    method = %method,
+                    url = %req.url(),
+                    retry_after_ms = retry_after.map(|d| d.as_millis() as u64),
+                    "received rate limited HTTP response"
+                );
+                return Err(RequestClientError::RateLimited { retry_after });
+            }
+
             let req_url = req.url().to_string();
             let body = req
                 .text()
@@ -254,13 +474,58 @@ impl RequestClient {
     }
 
     /// Executes an HTTP request and returns the response
+    ///
+    /// Failed requests are retried according to the configured [`RetryPolicy`].
     pub async fn request(
         &self,
         request: TidalRequest,
     ) -> Result<reqwest::Response, RequestClientError> {
-        let req = self.requests_basic(request).await?;
+        let Some(policy) = self
+            .retry_policy
+            .as_ref()
+            .filter(|policy| policy.allows_method(&request.method))
+        else {
+            return self.requests_basic(request).await;
+        };
 
-        Ok(req)
+        let mut attempt = 1;
+        loop {
+            let err = match self.requests_basic(request.clone()).await {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+
+            if attempt >= policy.max_attempts || !err.is_retryable() {
+                return Err(err);
+            }
+
+            let delay = match err {
+                RequestClientError::RateLimited {
+                    retry_after: Some(retry_after),
+                } if policy.respect_retry_after => {
+                    if retry_after > policy.max_delay {
+                        warn!(
+                            retry_after_ms = retry_after.as_millis() as u64,
+                            "Retry-After exceeds maximum retry delay, giving up"
+                        );
+                        return Err(err);
+                    }
+                    retry_after
+                }
+                _ => policy.backoff_delay(attempt),
+            };
+
+            debug!(
+                method = %request.method,
+                path = %request.path,
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                error = %err,
+                "retrying HTTP request"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
 }
 
@@ -270,11 +535,13 @@ mod tests {
         io::{Read, Write},
         net::TcpListener,
         thread,
+        time::Duration,
     };
 
     use reqwest::Method;
 
-    use super::{RequestClient, RequestClientError, TidalRequest};
+    use super::{RequestClient, RequestClientError, RetryPolicy, TidalRequest, parse_retry_after};
+    use crate::test_support::{TestResponse, TestServer};
 
     fn spawn_one_shot_http_server(raw_response: &'static str) -> (String, thread::JoinHandle<()>) {
         let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test listener");
@@ -293,6 +560,15 @@ mod tests {
         (format!("http://{}", addr), handle)
     }
 
+    fn fast_retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            jitter: 0.0,
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn error_body_snippet_handles_empty_body() {
         assert_eq!(
@@ -367,4 +643,82 @@ mod tests {
         let response = result.expect("request should succeed");
         assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
     }
+
+    #[test]
+    fn parse_retry_after_accepts_seconds_and_dates() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn backoff_delay_grows_exponentially_and_is_capped() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.backoff_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_delay(3), Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn request_retries_server_errors_until_success() {
+        let server = TestServer::sequence(vec![TestResponse::new(503), TestResponse::new(204)]);
+        let mut client = RequestClient::new(server.url().to_string());
+        client.set_retry_policy(Some(fast_retry_policy(3)));
+        let request = TidalRequest::new(Method::GET, "/flaky".to_string());
+
+        let result = client.request(request).await;
+        assert_eq!(server.requests().len(), 2);
+
+        let response = result.expect("request should succeed after retry");
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn request_returns_rate_limited_when_retries_are_exhausted() {
+        let server = TestServer::sequence(vec![
+            TestResponse::new(429).header("Retry-After", 0),
+            TestResponse::new(429).header("Retry-After", 0),
+        ]);
+        let mut client = RequestClient::new(server.url().to_string());
+        client.set_retry_policy(Some(fast_retry_policy(2)));
+        let request = TidalRequest::new(Method::GET, "/busy".to_string());
+
+        let result = client.request(request).await;
+        assert_eq!(server.requests().len(), 2);
+
+        assert!(matches!(
+            result,
+            Err(RequestClientError::RateLimited {
+                retry_after: Some(Duration::ZERO)
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_does_not_retry_non_idempotent_methods_by_default() {
+        let server = TestServer::sequence(vec![TestResponse::new(503).body("busy")]);
+        let mut client = RequestClient::new(server.url().to_string());
+        client.set_retry_policy(Some(fast_retry_policy(3)));
+        let request = TidalRequest::new(Method::POST, "/create".to_string());
+
+        let result = client.request(request).await;
+        assert_eq!(server.requests().len(), 1);
+
+        assert!(matches!(
+            result,
+            Err(RequestClientError::StatusCode {
+                status: reqwest::StatusCode::SERVICE_UNAVAILABLE,
+                ..
+            })
+        ));
+    }
 }
@@ -1,3 +1,5 @@
+use crate::urls::RESOURCES_LOCATION;
+
 pub const DEFAULT_SIZE_PX: u32 = 320;
 
 /// Returns CDN URL for cover art with default size of (DEFAULT_SIZE_PX)x(DEFAULT_SIZE_PX)
@@ -7,8 +9,13 @@ pub fn uuid_to_url(uuid: &str) -> String {
 
 /// Returns CDN URL for cover art with a specific size in pixels
 pub fn uuid_to_url_with_size(uuid: &str, size_px: u32) -> String {
+    image_url(RESOURCES_LOCATION, uuid, size_px)
+}
+
+/// Cover art URL on the resources server at `base_url`
+pub(crate) fn image_url(base_url: &str, uuid: &str, size_px: u32) -> String {
     format!(
-        "https://resources.tidal.com/images/{}/{size_px}x{size_px}.jpg",
+        "{base_url}/images/{}/{size_px}x{size_px}.jpg",
         uuid.replace('-', "/")
     )
 }
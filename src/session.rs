@@ -1,12 +1,13 @@
 use crate::{
-    auth::TidalAuth,
-    client::models::playback::{AudioQuality, PlaybackMode, VideoQuality},
+    auth::{TidalAuth, shared::SharedAuth},
+    client::models::playback::{AudioMode, AudioQuality, PlaybackMode, VideoQuality},
 };
 
 /// Contains session configuration for a Tidal client
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TidalSession {
-    pub auth: TidalAuth,
+    /// Authentication state, shared between clones of the client
+    pub auth: SharedAuth,
 
     #[serde(default = "default_locale")]
     pub locale: String,
@@ -17,6 +18,9 @@ pub struct TidalSession {
     #[serde(default = "default_audio_quality")]
     pub audio_quality: AudioQuality,
 
+    #[serde(default = "default_audio_mode")]
+    pub audio_mode: AudioMode,
+
     #[serde(default = "default_video_quality")]
     pub video_quality: VideoQuality,
 
@@ -36,6 +40,10 @@ fn default_audio_quality() -> AudioQuality {
     AudioQuality::High
 }
 
+fn default_audio_mode() -> AudioMode {
+    AudioMode::Stereo
+}
+
 fn default_video_quality() -> VideoQuality {
     VideoQuality::High
 }
@@ -48,11 +56,12 @@ impl TidalSession {
     /// Creates a new session with the provided authentication credentials
     pub(crate) fn new(credentials: &TidalAuth) -> TidalSession {
         TidalSession {
-            auth: credentials.clone(),
+            auth: SharedAuth::new(credentials.clone()),
             locale: default_locale(),
             time_offset: default_time_offset(),
 
             audio_quality: AudioQuality::High,
+            audio_mode: AudioMode::Stereo,
             video_quality: VideoQuality::High,
             playback_mode: PlaybackMode::Stream,
         }
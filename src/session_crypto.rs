@@ -0,0 +1,304 @@
+//! Passphrase encrypted session format
+//!
+//! [`TidalClient::get_json`](crate::TidalClient::get_json) contains the access and refresh
+//! tokens in plain text. [`encrypt_session`] seals it with a key derived from a passphrase, so a
+//! session file can sit on a shared machine without leaking long-lived refresh tokens.
+//!
+//! The format is a versioned header followed by the sealed session:
+//!
+//! | bytes  | content                                          |
+//! |--------|--------------------------------------------------|
+//! | 8      | magic `TIDLSESS`                                 |
+//! | 1      | format version, currently `1`                    |
+//! | 4      | Argon2id memory cost in KiB, big-endian          |
+//! | 4      | Argon2id iterations, big-endian                  |
+//! | 4      | Argon2id parallelism, big-endian                 |
+//! | 16     | salt                                             |
+//! | 24     | nonce                                            |
+//! | n + 16 | XChaCha20-Poly1305 encrypted session JSON        |
+//!
+//! Version 1 derives a 32 byte key with Argon2id from the passphrase and salt and seals the JSON
+//! with XChaCha20-Poly1305, authenticating the header as associated data. A wrong passphrase
+//! and a tampered file both fail with [`TidalError::SessionDecryption`].
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    XChaCha20Poly1305, XNonce,
+    aead::{Aead, KeyInit, Payload},
+};
+use rand::Rng;
+
+use crate::error::TidalError;
+
+const MAGIC: &[u8; 8] = b"TIDLSESS";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LEN + NONCE_LEN;
+const TAG_LEN: usize = 16;
+
+/// Upper bound for the memory cost read from a header, so a crafted file can't exhaust memory
+const MAX_MEMORY_KIB: u32 = 256 * 1024;
+/// Upper bound for iterations and parallelism read from a header
+const MAX_ITERATIONS: u32 = 16;
+
+/// Argon2id cost parameters of a session key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// The cheapest parameters Argon2id accepts, for tests
+    #[cfg(test)]
+    pub(crate) const FAST: Self = Self {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+}
+
+impl Default for KdfParams {
+    /// The `argon2` crate defaults, the OWASP recommendation for Argon2id
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// A key derived from a passphrase, with the salt and parameters it was derived with
+///
+/// Deriving is deliberately slow, so a key is derived once and every session sealed with it
+/// only gets a fresh nonce.
+#[derive(Clone)]
+pub(crate) struct SessionKey {
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+impl SessionKey {
+    /// Derives a key with a fresh salt
+    pub(crate) fn derive(passphrase: &str, params: KdfParams) -> Result<Self, TidalError> {
+        let mut salt = [0; SALT_LEN];
+        rand::rng().fill_bytes(&mut salt);
+        Self::derive_with_salt(passphrase, params, salt)
+    }
+
+    fn derive_with_salt(
+        passphrase: &str,
+        params: KdfParams,
+        salt: [u8; SALT_LEN],
+    ) -> Result<Self, TidalError> {
+        let invalid = |e: argon2::Error| {
+            TidalError::SessionDecryption(format!("invalid key derivation parameters: {e}"))
+        };
+        let argon2 = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(
+                params.memory_kib,
+                params.iterations,
+                params.parallelism,
+                Some(32),
+            )
+            .map_err(invalid)?,
+        );
+
+        let mut key = [0; 32];
+        argon2
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(invalid)?;
+        Ok(Self { params, salt, key })
+    }
+
+    /// Whether this key was derived for the session in `header`
+    pub(crate) fn matches(&self, header: &Header<'_>) -> bool {
+        self.params == header.params && self.salt == header.salt
+    }
+
+    /// Seals a session JSON under a fresh nonce
+    pub(crate) fn encrypt(&self, json: &str) -> Vec<u8> {
+        let mut nonce = [0; NONCE_LEN];
+        rand::rng().fill_bytes(&mut nonce);
+
+        let mut data = Vec::with_capacity(HEADER_LEN + json.len() + TAG_LEN);
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.extend_from_slice(&self.params.memory_kib.to_be_bytes());
+        data.extend_from_slice(&self.params.iterations.to_be_bytes());
+        data.extend_from_slice(&self.params.parallelism.to_be_bytes());
+        data.extend_from_slice(&self.salt);
+        data.extend_from_slice(&nonce);
+
+        let sealed = self
+            .cipher()
+            .encrypt(
+                &XNonce::from(nonce),
+                Payload {
+                    msg: json.as_bytes(),
+                    aad: &data,
+                },
+            )
+            .expect("session JSON is within the cipher's length limit");
+        data.extend_from_slice(&sealed);
+        data
+    }
+
+    /// Opens a session sealed with this key, see [`SessionKey::matches`]
+    pub(crate) fn decrypt(&self, header: &Header<'_>) -> Result<String, TidalError> {
+        let json = self
+            .cipher()
+            .decrypt(
+                &XNonce::from(header.nonce),
+                Payload {
+                    msg: header.sealed,
+                    aad: header.aad,
+                },
+            )
+            .map_err(|_| {
+                TidalError::SessionDecryption("wrong passphrase or corrupted session".to_string())
+            })?;
+        Ok(String::from_utf8(json)?)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.key.into())
+    }
+}
+
+/// Header fields of an encrypted session and the bytes they cover
+#[derive(Debug)]
+pub(crate) struct Header<'a> {
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    aad: &'a [u8],
+    sealed: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    pub(crate) fn parse(data: &'a [u8]) -> Result<Self, TidalError> {
+        let invalid = |reason: &str| TidalError::SessionDecryption(reason.to_string());
+
+        if data.len() < HEADER_LEN + TAG_LEN || !data.starts_with(MAGIC) {
+            return Err(invalid("not an encrypted session"));
+        }
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(TidalError::SessionDecryption(format!(
+                "unsupported format version {version}"
+            )));
+        }
+
+        let (aad, sealed) = data.split_at(HEADER_LEN);
+        let mut fields = aad[MAGIC.len() + 1..].chunks(4);
+        let mut next_u32 = || u32::from_be_bytes(fields.next().unwrap().try_into().unwrap());
+        let params = KdfParams {
+            memory_kib: next_u32(),
+            iterations: next_u32(),
+            parallelism: next_u32(),
+        };
+        if params.memory_kib > MAX_MEMORY_KIB
+            || params.iterations > MAX_ITERATIONS
+            || params.parallelism > MAX_ITERATIONS
+        {
+            return Err(invalid("invalid key derivation parameters"));
+        }
+
+        let salt_start = MAGIC.len() + 1 + 3 * 4;
+        let nonce_start = salt_start + SALT_LEN;
+        Ok(Self {
+            params,
+            salt: aad[salt_start..nonce_start].try_into().unwrap(),
+            nonce: aad[nonce_start..].try_into().unwrap(),
+            aad,
+            sealed,
+        })
+    }
+
+    /// Derives the key this session was sealed with
+    pub(crate) fn derive_key(&self, passphrase: &str) -> Result<SessionKey, TidalError> {
+        SessionKey::derive_with_salt(passphrase, self.params, self.salt)
+    }
+}
+
+/// Encrypts a session JSON with a key derived from `passphrase`
+pub fn encrypt_session(json: &str, passphrase: &str) -> Vec<u8> {
+    SessionKey::derive(passphrase, KdfParams::default())
+        .expect("default key derivation parameters are valid")
+        .encrypt(json)
+}
+
+/// Decrypts a session encrypted by [`encrypt_session`] back to its JSON
+pub fn decrypt_session(data: &[u8], passphrase: &str) -> Result<String, TidalError> {
+    let header = Header::parse(data)?;
+    header.derive_key(passphrase)?.decrypt(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KdfParams, SessionKey, decrypt_session};
+    use crate::error::TidalError;
+
+    const JSON: &str = r#"{"session":{"auth":{"refresh_token":"secret"}}}"#;
+
+    fn encrypt(json: &str, passphrase: &str) -> Vec<u8> {
+        SessionKey::derive(passphrase, KdfParams::FAST)
+            .unwrap()
+            .encrypt(json)
+    }
+
+    #[test]
+    fn sessions_roundtrip_without_leaking_tokens() {
+        let data = encrypt(JSON, "hunter2");
+
+        assert!(data.starts_with(b"TIDLSESS\x01"));
+        assert!(!data.windows(6).any(|w| w == b"secret"));
+        assert_eq!(decrypt_session(&data, "hunter2").unwrap(), JSON);
+        // fresh salt and nonce every time
+        assert_ne!(encrypt(JSON, "hunter2"), data);
+
+        // a reused key only changes the nonce
+        let key = SessionKey::derive("hunter2", KdfParams::FAST).unwrap();
+        let (first, second) = (key.encrypt(JSON), key.encrypt(JSON));
+        assert_eq!(first[..37], second[..37]);
+        assert_ne!(first[37..61], second[37..61]);
+        assert_eq!(decrypt_session(&second, "hunter2").unwrap(), JSON);
+    }
+
+    #[test]
+    fn wrong_passphrase_tampering_and_unknown_versions_are_rejected() {
+        let data = encrypt(JSON, "hunter2");
+        let fails = |data: &[u8], passphrase| {
+            matches!(
+                decrypt_session(data, passphrase),
+                Err(TidalError::SessionDecryption(_))
+            )
+        };
+
+        assert!(fails(&data, "hunter3"));
+
+        // the ciphertext and the header are both authenticated
+        for index in [data.len() - 20, 40] {
+            let mut tampered = data.clone();
+            tampered[index] ^= 1;
+            assert!(fails(&tampered, "hunter2"));
+        }
+
+        let mut future = data.clone();
+        future[8] = 2;
+        assert!(fails(&future, "hunter2"));
+
+        let mut costly = data.clone();
+        costly[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(fails(&costly, "hunter2"));
+
+        assert!(fails(JSON.as_bytes(), "hunter2"));
+    }
+}
@@ -0,0 +1,277 @@
+//! Pluggable persistence of the client session
+//!
+//! A [`SessionStore`] attached to a client with [`TidalClient::set_session_store`] or
+//! [`TidalClient::with_session_store`] is saved to whenever a login or token refresh changes the
+//! auth state, so a refreshed token isn't lost when the application forgets to save it.
+//!
+//! [`TidalClient::set_session_store`]: crate::TidalClient::set_session_store
+//! [`TidalClient::with_session_store`]: crate::TidalClient::with_session_store
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::TidalError,
+    session_crypto::{Header, KdfParams, SessionKey},
+};
+
+/// Storage for the JSON of [`TidalClient::get_json`](crate::TidalClient::get_json)
+pub trait SessionStore: fmt::Debug + Send + Sync {
+    /// Saves the serialized session, replacing the previous one
+    ///
+    /// Called on a blocking thread, see [`TidalClient::save_session`](crate::TidalClient::save_session).
+    fn save(&self, session: &str) -> Result<(), TidalError>;
+
+    /// Loads the last saved session, `None` when nothing was saved yet
+    fn load(&self) -> Result<Option<String>, TidalError>;
+}
+
+/// Stores the session in a single file
+///
+/// Saving writes a temporary file next to it and renames it over the old one, so a crash never
+/// leaves a half written session behind. On Unix the file is only readable by its owner.
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes `data` to the file atomically
+    pub(crate) fn write(&self, data: &[u8]) -> Result<(), TidalError> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let result = create_private(&tmp_path).and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        });
+        if let Err(e) = result.and_then(|()| fs::rename(&tmp_path, &self.path)) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Contents of the file, `None` if it doesn't exist
+    pub(crate) fn read(&self) -> Result<Option<Vec<u8>>, TidalError> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn save(&self, session: &str) -> Result<(), TidalError> {
+        self.write(session.as_bytes())
+    }
+
+    fn load(&self) -> Result<Option<String>, TidalError> {
+        self.read()?
+            .map(|data| String::from_utf8(data).map_err(TidalError::from))
+            .transpose()
+    }
+}
+
+/// Stores the session in a file encrypted with a passphrase, see
+/// [`session_crypto`](crate::session_crypto)
+///
+/// Written like [`FileSessionStore`], loading fails with [`TidalError::SessionDecryption`] for a
+/// wrong passphrase. The key is derived once and reused for every save, so a token refresh only
+/// pays for the encryption.
+#[derive(Clone)]
+pub struct EncryptedFileSessionStore {
+    file: FileSessionStore,
+    passphrase: String,
+    kdf: KdfParams,
+    /// Key of the last saved or loaded session, shared with clones
+    key: Arc<Mutex<Option<SessionKey>>>,
+}
+
+impl fmt::Debug for EncryptedFileSessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileSessionStore")
+            .field("path", &self.file.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedFileSessionStore {
+    pub fn new(path: impl Into<PathBuf>, passphrase: impl Into<String>) -> Self {
+        Self {
+            file: FileSessionStore::new(path),
+            passphrase: passphrase.into(),
+            kdf: KdfParams::default(),
+            key: Arc::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+impl SessionStore for EncryptedFileSessionStore {
+    fn save(&self, session: &str) -> Result<(), TidalError> {
+        let data = {
+            let mut key = self.key.lock().unwrap();
+            let key = match &mut *key {
+                Some(key) => key,
+                None => key.insert(SessionKey::derive(&self.passphrase, self.kdf)?),
+            };
+            key.encrypt(session)
+        };
+        self.file.write(&data)
+    }
+
+    fn load(&self) -> Result<Option<String>, TidalError> {
+        let Some(data) = self.file.read()? else {
+            return Ok(None);
+        };
+        let header = Header::parse(&data)?;
+
+        let mut key = self.key.lock().unwrap();
+        if let Some(key) = key.as_ref().filter(|key| key.matches(&header)) {
+            return key.decrypt(&header).map(Some);
+        }
+        let derived = header.derive_key(&self.passphrase)?;
+        let session = derived.decrypt(&header)?;
+        // later saves keep the file's salt
+        *key = Some(derived);
+        Ok(Some(session))
+    }
+}
+
+/// Creates (or truncates) `path` with owner-only permissions
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+    use std::{path::Path, sync::Arc};
+
+    use serde_json::json;
+
+    use super::{EncryptedFileSessionStore, FileSessionStore, SessionStore};
+    use crate::{
+        TidalClient,
+        auth::TidalAuth,
+        error::TidalError,
+        session_crypto::KdfParams,
+        test_support::{TestResponse, TestServer, api_client, temp_path, user_json},
+    };
+
+    #[test]
+    fn file_store_roundtrips_and_replaces_atomically() {
+        let path = temp_path("session.json");
+        let store = FileSessionStore::new(&path);
+
+        assert_eq!(store.load().unwrap(), None);
+        store.save("first").unwrap();
+        store.save("second").unwrap();
+
+        assert_eq!(store.load().unwrap().as_deref(), Some("second"));
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        assert!(!Path::new(&tmp).exists());
+        #[cfg(unix)]
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+
+    #[tokio::test]
+    async fn refreshed_token_is_saved_and_restored_with_the_user() {
+        let server = TestServer::sequence(vec![TestResponse::ok(
+            json!({
+                "access_token": "refreshed", "expires_in": 3600, "user_id": 42,
+                "token_type": "Bearer", "scope": "r_usr", "clientName": "test",
+                "user": user_json()
+            })
+            .to_string(),
+        )]);
+        let path = temp_path("session.json");
+        let mut client = api_client(&server);
+        client.session.auth.write().refresh_token = Some("refresh".to_string());
+        client.set_session_store(Some(Arc::new(FileSessionStore::new(&path))));
+
+        client.refresh_access_token(true).await.unwrap();
+
+        let restored =
+            TidalClient::with_session_store(&TidalAuth::new(), FileSessionStore::new(&path))
+                .unwrap();
+        assert_eq!(
+            restored.session.auth.read().access_token.as_deref(),
+            Some("refreshed")
+        );
+        assert_eq!(restored.user_info.get().map(|user| user.user_id), Some(42));
+    }
+
+    #[test]
+    fn missing_session_starts_a_new_client() {
+        let path = temp_path("session.json");
+        let client =
+            TidalClient::with_session_store(&TidalAuth::with_oauth(), FileSessionStore::new(&path))
+                .unwrap();
+
+        assert!(client.session.auth.read().oauth_login);
+        assert!(!client.user_info.is_some());
+    }
+
+    #[tokio::test]
+    async fn encrypted_store_needs_the_passphrase() {
+        let path = temp_path("session.bin");
+        let mut store = EncryptedFileSessionStore::new(&path, "hunter2");
+        store.kdf = KdfParams::FAST;
+        let mut client =
+            TidalClient::new(&TidalAuth::with_access_token("secret-token".to_string()));
+        client.set_session_store(Some(Arc::new(store.clone())));
+
+        client.save_session().await.unwrap();
+        let first = std::fs::read(&path).unwrap();
+        client.save_session().await.unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert!(!data.windows(12).any(|w| w == b"secret-token"));
+        // the key is reused, only the nonce after the salt changes
+        assert_eq!(first[..37], data[..37]);
+        assert_ne!(first, data);
+        let restored = TidalClient::with_session_store(&TidalAuth::new(), store).unwrap();
+        assert_eq!(
+            restored.session.auth.read().access_token.as_deref(),
+            Some("secret-token")
+        );
+        let wrong = EncryptedFileSessionStore::new(&path, "hunter3");
+        assert!(matches!(
+            wrong.load(),
+            Err(TidalError::SessionDecryption(_))
+        ));
+    }
+}
@@ -0,0 +1,878 @@
+//! Seekable byte streams over TIDAL track streams
+//!
+//! [`TrackStream`] presents a progressive file or the init and media segments of a DASH stream
+//! as one contiguous byte stream that is fetched lazily as it's read. Progressive streams are
+//! read with HTTP `Range` requests of `read_ahead` bytes. DASH segments are fetched whole and
+//! retried like in [`TidalClient::fetch_segments`], sizes of skipped segments are learned with
+//! `HEAD` requests, so seeking never downloads the media in between. Unlike `fetch_segments`,
+//! segments are requested one at a time as the reader reaches them: a seek makes anything
+//! fetched ahead useless, and a player reads far slower than a segment downloads. [`BlockingTrackStream`] adapts it to `std::io::Read + Seek` for decoders running
+//! on their own thread.
+
+use std::{
+    future::Future,
+    io::{self, SeekFrom},
+    ops::Range,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use futures_util::{StreamExt, TryStreamExt, stream};
+use reqwest::{StatusCode, header};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf};
+use tracing::debug;
+
+use crate::{
+    TidalClient,
+    client::models::track::{
+        config::TrackPlaybackInfoConfig,
+        playback::{ParsedTrackManifest, TrackPlaybackInfoResponse},
+    },
+    decrypt::StreamKey,
+    download::{
+        DownloadPlan,
+        segments::{SegmentFetchOptions, Throttle, fetch_segment},
+    },
+    error::TidalError,
+    ids::TrackId,
+    requests::RequestClient,
+};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send>>;
+
+/// Options for [`TidalClient::open_track_stream`]
+#[derive(Clone)]
+pub struct StreamOptions {
+    /// Bytes fetched ahead of the read position with every request, DASH streams fetch whole
+    /// segments until at least this many bytes are buffered
+    pub read_ahead: usize,
+    /// Playback config used to request the stream, unset fields fall back to the session config
+    pub playback: TrackPlaybackInfoConfig,
+    /// DASH segment requests: segments are retried with `retry_policy` and share the
+    /// `max_bytes_per_second` cap, `concurrency` caps the `HEAD` requests sent at once to learn
+    /// the sizes of skipped segments
+    pub segments: SegmentFetchOptions,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            read_ahead: 1024 * 1024,
+            playback: TrackPlaybackInfoConfig::default(),
+            segments: SegmentFetchOptions::default(),
+        }
+    }
+}
+
+/// A part of a segmented stream
+#[derive(Debug)]
+struct Part {
+    url: String,
+    /// Start of the segment in the track, `None` for the init segment
+    start: Option<Duration>,
+    duration: Duration,
+}
+
+#[derive(Debug)]
+enum SourceKind {
+    Progressive {
+        url: String,
+        len: Mutex<Option<u64>>,
+        /// Key of `OLD_AES` streams, every fetched range is decrypted with it
+        key: Option<StreamKey>,
+    },
+    Segmented {
+        parts: Vec<Part>,
+        sizes: Mutex<Vec<Option<u64>>>,
+    },
+}
+
+#[derive(Debug)]
+struct Source {
+    rq: RequestClient,
+    kind: SourceKind,
+    read_ahead: usize,
+    segments: SegmentFetchOptions,
+    throttle: Option<Throttle>,
+}
+
+/// Bytes of the stream starting at `start`
+#[derive(Debug, Default)]
+struct Chunk {
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl Chunk {
+    fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+}
+
+/// Lazily fetched, seekable stream of a track's bytes
+///
+/// # Example
+///
+/// ```no_run
+/// # use tidlers::{TidalClient, auth::TidalAuth};
+/// # use tidlers::stream::StreamOptions;
+/// # use tokio::io::{AsyncReadExt, AsyncSeekExt};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = TidalClient::new(&TidalAuth::with_oauth());
+/// let mut stream = client
+///     .open_track_stream("123456789", StreamOptions::default())
+///     .await?;
+///
+/// let mut header = [0_u8; 4];
+/// stream.read_exact(&mut header).await?;
+/// stream.seek(std::io::SeekFrom::Start(1_000_000)).await?;
+///
+/// // hand it to a decoder on its own thread
+/// let reader = stream.into_blocking(tokio::runtime::Handle::current());
+/// std::thread::spawn(move || {
+///     let _ = reader; // e.g. symphonia's ReadOnlySource / MediaSourceStream
+/// });
+/// # Ok(())
+/// # }
+/// ```
+pub struct TrackStream {
+    source: Arc<Source>,
+    position: u64,
+    buffer: Chunk,
+    /// Fetch in flight and the position it was started for
+    pending: Option<(u64, BoxFuture<Chunk>)>,
+    seek: Option<SeekFrom>,
+    pending_len: Option<BoxFuture<u64>>,
+}
+
+impl std::fmt::Debug for TrackStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackStream")
+            .field("source", &self.source)
+            .field("position", &self.position)
+            .field("buffered", &(self.buffer.start..self.buffer.end()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl TidalClient {
+    /// Requests playback info for a track and opens a [`TrackStream`] over it
+    pub async fn open_track_stream(
+        &self,
+        track_id: impl Into<TrackId>,
+        options: StreamOptions,
+    ) -> Result<TrackStream, TidalError> {
+        let playback = self
+            .get_track_postpaywall_playback_info(track_id, Some(options.playback.clone()))
+            .await?;
+        TrackStream::from_playback(self, &playback, &options)
+    }
+}
+
+impl TrackStream {
+    /// Opens a stream over already fetched playback info, nothing is requested until read
+    pub fn from_playback(
+        client: &TidalClient,
+        playback: &TrackPlaybackInfoResponse,
+        options: &StreamOptions,
+    ) -> Result<Self, TidalError> {
+        // rejects encrypted and empty manifests
+        let plan = DownloadPlan::from_playback(playback)?;
+
+        let kind = match &playback.manifest_parsed {
+            Some(ParsedTrackManifest::Dash(dash)) => {
+                let list = dash.segments()?;
+                let mut parts = Vec::with_capacity(list.segments.len() + 1);
+                if let Some(url) = list.initialization {
+                    parts.push(Part {
+                        url,
+                        start: None,
+                        duration: Duration::ZERO,
+                    });
+                }
+
+                let mut start = Duration::ZERO;
+                for segment in list.segments {
+                    parts.push(Part {
+                        url: segment.url,
+                        start: Some(start),
+                        duration: segment.duration,
+                    });
+                    start += segment.duration;
+                }
+                SourceKind::Segmented {
+                    sizes: Mutex::new(vec![None; parts.len()]),
+                    parts,
+                }
+            }
+            _ => SourceKind::Progressive {
+                url: plan.parts[0].clone(),
+                len: Mutex::new(None),
+                key: plan.key,
+            },
+        };
+
+        Ok(Self::new(client.rq.clone(), kind, options))
+    }
+
+    fn new(rq: RequestClient, kind: SourceKind, options: &StreamOptions) -> Self {
+        Self {
+            source: Arc::new(Source {
+                rq,
+                kind,
+                read_ahead: options.read_ahead.max(1),
+                segments: options.segments.clone(),
+                throttle: options.segments.max_bytes_per_second.map(Throttle::new),
+            }),
+            position: 0,
+            buffer: Chunk::default(),
+            pending: None,
+            seek: None,
+            pending_len: None,
+        }
+    }
+
+    /// Fresh stream at position 0 over the same source, reusing the segment sizes it learned
+    #[cfg(feature = "proxy")]
+    pub(crate) fn reopen(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            position: 0,
+            buffer: Chunk::default(),
+            pending: None,
+            seek: None,
+            pending_len: None,
+        }
+    }
+
+    /// Current read position
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Length of the stream if it's already known, see [`len`](Self::len) to resolve it
+    pub fn byte_len(&self) -> Option<u64> {
+        self.source.known_len()
+    }
+
+    /// Resolves the length of the stream, with `HEAD` requests for DASH segments
+    pub async fn len(&self) -> io::Result<u64> {
+        self.source.clone().total_len().await
+    }
+
+    /// Length of the track for DASH streams, `None` for progressive streams
+    pub fn duration(&self) -> Option<Duration> {
+        match &self.source.kind {
+            SourceKind::Segmented { parts, .. } => Some(parts.iter().map(|p| p.duration).sum()),
+            SourceKind::Progressive { .. } => None,
+        }
+    }
+
+    /// Moves to the start of the DASH segment playing at `time` and returns its byte offset
+    ///
+    /// Decoders reading fragmented MP4 resync on the segment's `moof`. Progressive streams have
+    /// no time index and fail with [`io::ErrorKind::Unsupported`].
+    pub async fn seek_to_time(&mut self, time: Duration) -> io::Result<u64> {
+        let SourceKind::Segmented { parts, .. } = &self.source.kind else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "progressive streams can only seek by byte offset",
+            ));
+        };
+
+        let index = parts
+            .iter()
+            .position(|part| part.start.is_some_and(|start| time < start + part.duration))
+            .or_else(|| parts.iter().rposition(|part| part.start.is_some()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "stream has no segments"))?;
+
+        let offset = self.source.clone().parts_len(0..index).await?;
+        debug!(?time, segment = index, offset, "seeking to segment");
+
+        self.seek(SeekFrom::Start(offset)).await
+    }
+
+    /// Adapts the stream to blocking `Read + Seek`, driving it on `handle`
+    pub fn into_blocking(self, handle: tokio::runtime::Handle) -> BlockingTrackStream {
+        BlockingTrackStream {
+            stream: self,
+            handle,
+        }
+    }
+
+    /// Copies buffered bytes at the read position, `false` if there are none
+    fn read_buffered(&mut self, buf: &mut ReadBuf<'_>) -> bool {
+        if self.position < self.buffer.start || self.position >= self.buffer.end() {
+            return false;
+        }
+
+        let start = (self.position - self.buffer.start) as usize;
+        let available = &self.buffer.data[start..];
+        let len = available.len().min(buf.remaining());
+        buf.put_slice(&available[..len]);
+        self.position += len as u64;
+        true
+    }
+}
+
+impl AsyncRead for TrackStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if buf.remaining() == 0 || this.read_buffered(buf) {
+            return Poll::Ready(Ok(()));
+        }
+
+        let position = this.position;
+        if this.pending.as_ref().is_none_or(|(at, _)| *at != position) {
+            let source = this.source.clone();
+            this.pending = Some((position, Box::pin(source.fetch(position))));
+        }
+
+        let (_, fetch) = this.pending.as_mut().unwrap();
+        let chunk = ready!(fetch.as_mut().poll(cx));
+        this.pending = None;
+
+        this.buffer = chunk?;
+        // nothing at the position means the end of the stream
+        this.read_buffered(buf);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for TrackStream {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        self.seek = Some(position);
+        self.pending_len = None;
+        Ok(())
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = &mut *self;
+        let target = match this.seek {
+            None => return Poll::Ready(Ok(this.position)),
+            Some(SeekFrom::Start(offset)) => Some(offset),
+            Some(SeekFrom::Current(delta)) => this.position.checked_add_signed(delta),
+            Some(SeekFrom::End(delta)) => {
+                let len = match this.source.known_len() {
+                    Some(len) => len,
+                    None => {
+                        let source = this.source.clone();
+                        let fetch = this
+                            .pending_len
+                            .get_or_insert_with(|| Box::pin(source.total_len()));
+                        let len = ready!(fetch.as_mut().poll(cx));
+                        this.pending_len = None;
+                        len?
+                    }
+                };
+                len.checked_add_signed(delta)
+            }
+        };
+        this.seek = None;
+
+        let target = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the stream",
+            )
+        })?;
+        this.position = target;
+        Poll::Ready(Ok(target))
+    }
+}
+
+impl Source {
+    fn known_len(&self) -> Option<u64> {
+        match &self.kind {
+            SourceKind::Progressive { len, .. } => *len.lock().unwrap(),
+            SourceKind::Segmented { sizes, .. } => sizes.lock().unwrap().iter().copied().sum(),
+        }
+    }
+
+    async fn total_len(self: Arc<Self>) -> io::Result<u64> {
+        if let Some(len) = self.known_len() {
+            return Ok(len);
+        }
+
+        match &self.kind {
+            SourceKind::Progressive { url, len, .. } => {
+                let response = self.rq.head(url).await.map_err(io::Error::other)?;
+                let total = header_length(&response).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "stream length unknown")
+                })?;
+                *len.lock().unwrap() = Some(total);
+                Ok(total)
+            }
+            SourceKind::Segmented { parts, .. } => {
+                let count = parts.len();
+                self.parts_len(0..count).await
+            }
+        }
+    }
+
+    /// Combined size of the DASH parts in `range`, resolving unknown sizes with at most
+    /// `concurrency` requests in flight
+    async fn parts_len(self: Arc<Self>, range: Range<usize>) -> io::Result<u64> {
+        stream::iter(range)
+            .map(|index| self.clone().part_size(index))
+            .buffered(self.segments.concurrency.max(1))
+            .try_fold(0, |total, size| async move { Ok(total + size) })
+            .await
+    }
+
+    /// Size of a DASH part, asking the server with `HEAD` if it isn't known yet
+    async fn part_size(self: Arc<Self>, index: usize) -> io::Result<u64> {
+        let SourceKind::Segmented { parts, sizes } = &self.kind else {
+            unreachable!("only segmented streams have parts");
+        };
+        if let Some(size) = sizes.lock().unwrap()[index] {
+            return Ok(size);
+        }
+
+        let response = self
+            .rq
+            .head(&parts[index].url)
+            .await
+            .map_err(io::Error::other)?;
+        let size = match header_length(&response) {
+            Some(size) => size,
+            // no length in the headers, fall back to fetching the segment
+            None => self.get_part(index).await?.len() as u64,
+        };
+
+        sizes.lock().unwrap()[index] = Some(size);
+        Ok(size)
+    }
+
+    async fn get_part(&self, index: usize) -> io::Result<Vec<u8>> {
+        let SourceKind::Segmented { parts, sizes } = &self.kind else {
+            unreachable!("only segmented streams have parts");
+        };
+
+        let data = fetch_segment(
+            &self.rq,
+            &parts[index].url,
+            &self.segments.retry_policy,
+            self.throttle.as_ref(),
+        )
+        .await
+        .map_err(io::Error::other)?;
+
+        sizes.lock().unwrap()[index] = Some(data.len() as u64);
+        Ok(data)
+    }
+
+    /// Fetches the bytes at `position` and up to `read_ahead` after it, empty at the end
+    async fn fetch(self: Arc<Self>, position: u64) -> io::Result<Chunk> {
+        match &self.kind {
+            SourceKind::Progressive { url, len, key } => {
+                let mut chunk = self.fetch_range(url, len, position).await?;
+                if let Some(key) = key {
+                    key.apply(chunk.start, &mut chunk.data);
+                }
+                Ok(chunk)
+            }
+            SourceKind::Segmented { parts, sizes } => {
+                let mut offset = 0;
+                let mut chunk: Option<Chunk> = None;
+
+                for index in 0..parts.len() {
+                    let known = sizes.lock().unwrap()[index];
+                    match &mut chunk {
+                        None => {
+                            // sequential reads fetch the next part right away
+                            let size = match known {
+                                Some(size) => size,
+                                None if offset == position => {
+                                    let data = self.get_part(index).await?;
+                                    let size = data.len() as u64;
+                                    if size > 0 {
+                                        chunk = Some(Chunk {
+                                            start: offset,
+                                            data,
+                                        });
+                                    }
+                                    offset += size;
+                                    continue;
+                                }
+                                None => self.clone().part_size(index).await?,
+                            };
+
+                            if position >= offset + size {
+                                offset += size;
+                                continue;
+                            }
+
+                            let data = self.get_part(index).await?;
+                            offset += data.len() as u64;
+                            chunk = Some(Chunk {
+                                start: offset - data.len() as u64,
+                                data,
+                            });
+                        }
+                        Some(chunk) => {
+                            if chunk.end() - position >= self.read_ahead as u64 {
+                                break;
+                            }
+                            let data = self.get_part(index).await?;
+                            chunk.data.extend(data);
+                        }
+                    }
+                }
+
+                Ok(chunk.unwrap_or(Chunk {
+                    start: position,
+                    data: Vec::new(),
+                }))
+            }
+        }
+    }
+
+    async fn fetch_range(
+        &self,
+        url: &str,
+        len: &Mutex<Option<u64>>,
+        position: u64,
+    ) -> io::Result<Chunk> {
+        if len.lock().unwrap().is_some_and(|len| position >= len) {
+            return Ok(Chunk {
+                start: position,
+                data: Vec::new(),
+            });
+        }
+
+        let end = position + self.read_ahead as u64 - 1;
+        let response = self
+            .rq
+            .http_client()
+            .get(url)
+            .header(header::RANGE, format!("bytes={position}-{end}"))
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                if let Some(total) = response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.rsplit_once('/'))
+                    .and_then(|(_, total)| total.parse().ok())
+                {
+                    *len.lock().unwrap() = Some(total);
+                }
+
+                let data = response.bytes().await.map_err(io::Error::other)?.to_vec();
+                Ok(Chunk {
+                    start: position,
+                    data,
+                })
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => Ok(Chunk {
+                start: position,
+                data: Vec::new(),
+            }),
+            StatusCode::OK => {
+                // the server ignored the range and sent everything
+                let data = response.bytes().await.map_err(io::Error::other)?.to_vec();
+                *len.lock().unwrap() = Some(data.len() as u64);
+                Ok(Chunk { start: 0, data })
+            }
+            status => Err(io::Error::other(format!(
+                "stream request failed with HTTP {status}"
+            ))),
+        }
+    }
+}
+
+/// `Content-Length` as sent by the server
+///
+/// [`reqwest::Response::content_length`] is the size of the body, which is always 0 for `HEAD`.
+fn header_length(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Blocking `Read + Seek` over a [`TrackStream`]
+///
+/// Every call blocks on the runtime the stream was handed, so it must be used outside of async
+/// code, e.g. on a decoder thread. The runtime's I/O has to be driven while the call blocks: a
+/// multi-threaded runtime does that on its own, a current-thread runtime only while another
+/// thread is inside its `block_on`.
+#[derive(Debug)]
+pub struct BlockingTrackStream {
+    stream: TrackStream,
+    handle: tokio::runtime::Handle,
+}
+
+impl BlockingTrackStream {
+    /// Length of the stream, resolving it if needed
+    pub fn len(&self) -> io::Result<u64> {
+        self.handle.block_on(self.stream.len())
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    pub fn into_inner(self) -> TrackStream {
+        self.stream
+    }
+}
+
+impl io::Read for BlockingTrackStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.handle.block_on(self.stream.read(buf))
+    }
+}
+
+impl io::Seek for BlockingTrackStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.handle.block_on(self.stream.seek(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, SeekFrom},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    use super::{Part, SourceKind, StreamOptions, TrackStream};
+    use crate::{
+        decrypt::StreamKey,
+        download::segments::SegmentFetchOptions,
+        requests::{RequestClient, RetryPolicy},
+        test_support::{TestResponse, TestServer, encrypt_old_aes, old_aes_key_id},
+    };
+
+    fn read_ahead(read_ahead: usize) -> StreamOptions {
+        StreamOptions {
+            read_ahead,
+            ..Default::default()
+        }
+    }
+
+    fn segmented(server: &TestServer, names: &[&str]) -> TrackStream {
+        let parts: Vec<Part> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| Part {
+                url: server.at(name),
+                start: (index > 0).then(|| Duration::from_secs(4 * (index as u64 - 1))),
+                duration: if index > 0 {
+                    Duration::from_secs(4)
+                } else {
+                    Duration::ZERO
+                },
+            })
+            .collect();
+
+        TrackStream::new(
+            RequestClient::new(String::new()),
+            SourceKind::Segmented {
+                sizes: Mutex::new(vec![None; parts.len()]),
+                parts,
+            },
+            &read_ahead(4),
+        )
+    }
+
+    #[tokio::test]
+    async fn progressive_stream_reads_ranges_and_seeks() {
+        let server = TestServer::files(vec![("track.flac", b"0123456789".to_vec())]);
+        let mut stream = TrackStream::new(
+            RequestClient::new(String::new()),
+            SourceKind::Progressive {
+                url: server.at("track.flac"),
+                len: Mutex::new(None),
+                key: None,
+            },
+            &read_ahead(4),
+        );
+
+        let mut buf = [0_u8; 3];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"012");
+        assert_eq!(stream.byte_len(), Some(10));
+
+        stream.seek(SeekFrom::End(-2)).await.unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"89");
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn encrypted_stream_decrypts_every_range() {
+        let plain: Vec<u8> = (0..40).collect();
+        let server = TestServer::files(vec![("track.flac", encrypt_old_aes(&plain))]);
+        let mut stream = TrackStream::new(
+            RequestClient::new(String::new()),
+            SourceKind::Progressive {
+                url: server.at("track.flac"),
+                len: Mutex::new(None),
+                key: Some(StreamKey::from_key_id(&old_aes_key_id()).unwrap()),
+            },
+            &read_ahead(6),
+        );
+
+        // a seek into the middle of a cipher block
+        stream.seek(SeekFrom::Start(21)).await.unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, &plain[21..]);
+    }
+
+    #[tokio::test]
+    async fn segmented_stream_concatenates_parts() {
+        let server = TestServer::files(vec![
+            ("init", b"II".to_vec()),
+            ("1", b"AAA".to_vec()),
+            ("2", b"BBB".to_vec()),
+        ]);
+        let mut stream = segmented(&server, &["init", "1", "2"]);
+
+        let mut all = Vec::new();
+        stream.read_to_end(&mut all).await.unwrap();
+        assert_eq!(all, b"IIAAABBB");
+        assert_eq!(stream.len().await.unwrap(), 8);
+        assert_eq!(stream.duration(), Some(Duration::from_secs(8)));
+    }
+
+    #[tokio::test]
+    async fn seek_to_time_skips_segments_with_head_requests() {
+        let server = TestServer::files(vec![
+            ("init", b"II".to_vec()),
+            ("1", b"AAA".to_vec()),
+            ("2", b"BBB".to_vec()),
+        ]);
+        let mut stream = segmented(&server, &["init", "1", "2"]);
+
+        let offset = stream.seek_to_time(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(offset, 5);
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"BBB");
+
+        let lines = server.request_lines();
+        assert!(lines.iter().any(|line| line == "HEAD /1"));
+        assert!(!lines.iter().any(|line| line == "GET /1"));
+    }
+
+    #[tokio::test]
+    async fn failed_segments_are_retried_while_reading() {
+        let failed_once = AtomicBool::new(false);
+        let server = TestServer::spawn(move |request| {
+            if request.path == "/1" && !failed_once.swap(true, Ordering::SeqCst) {
+                return TestResponse::new(503);
+            }
+            TestResponse::file(request, b"AAA")
+        });
+        let mut stream = segmented(&server, &["init", "1"]);
+
+        let mut all = Vec::new();
+        stream.read_to_end(&mut all).await.unwrap();
+        assert_eq!(all, b"AAAAAA");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn segment_sizes_are_resolved_a_few_at_a_time_with_retries() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let failed_once = AtomicBool::new(false);
+        let server = TestServer::spawn({
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            move |request| {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if request.path == "/3" && !failed_once.swap(true, Ordering::SeqCst) {
+                    return TestResponse::new(503);
+                }
+                TestResponse::file(request, b"ABCD")
+            }
+        });
+        let mut rq = RequestClient::new(String::new());
+        rq.set_retry_policy(Some(RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            jitter: 0.0,
+            ..Default::default()
+        }));
+        let names = ["init", "1", "2", "3", "4", "5", "6", "7"];
+        let parts: Vec<Part> = names
+            .iter()
+            .map(|name| Part {
+                url: server.at(name),
+                start: None,
+                duration: Duration::ZERO,
+            })
+            .collect();
+        let stream = TrackStream::new(
+            rq,
+            SourceKind::Segmented {
+                sizes: Mutex::new(vec![None; parts.len()]),
+                parts,
+            },
+            &StreamOptions {
+                segments: SegmentFetchOptions {
+                    concurrency: 2,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(stream.len().await.unwrap(), 32);
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(server.requests().len(), 9);
+    }
+
+    #[test]
+    fn blocking_adapter_reads_and_seeks() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let server = TestServer::files(vec![("track.flac", b"0123456789".to_vec())]);
+        let stream = TrackStream::new(
+            RequestClient::new(String::new()),
+            SourceKind::Progressive {
+                url: server.at("track.flac"),
+                len: Mutex::new(None),
+                key: None,
+            },
+            &read_ahead(4),
+        );
+
+        let mut reader = stream.into_blocking(runtime.handle().clone());
+        std::io::Seek::seek(&mut reader, SeekFrom::Start(6)).unwrap();
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "6789");
+        assert_eq!(reader.len().unwrap(), 10);
+    }
+}
@@ -0,0 +1,543 @@
+//! Fixtures shared by the unit tests: a local HTTP server, API responses, temp paths and media
+//! files
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use aes::{Aes128, Aes256};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use cbc::cipher::{BlockModeEncrypt, KeyIvInit, StreamCipher, block_padding::NoPadding};
+use serde_json::{Value, json};
+
+use crate::{TidalClient, auth::TidalAuth, decrypt::MASTER_KEY, requests::RequestClient};
+
+/// Unique path in the temp dir, `name` keeps the extension for tests that care about it
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("tidlers-{}-{n}-{name}", std::process::id()))
+}
+
+/// A request received by [`TestServer`]
+#[derive(Debug, Clone)]
+pub(crate) struct TestRequest {
+    pub method: String,
+    pub path: String,
+    /// Query parameters, not percent-decoded
+    pub query: Vec<(String, String)>,
+    /// Header names are lowercased
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Field of a `application/x-www-form-urlencoded` body, decoded
+    pub fn form(&self, name: &str) -> Option<String> {
+        url::form_urlencoded::parse(&self.body)
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// Start and optional end of a `Range: bytes=a-b` header
+    pub fn range(&self) -> Option<(usize, Option<usize>)> {
+        let range = self.header("range")?.strip_prefix("bytes=")?;
+        let (start, end) = range.split_once('-')?;
+        Some((start.parse().ok()?, end.parse().ok()))
+    }
+}
+
+/// Response written by [`TestServer`], `Content-Length` and `Connection: close` are added
+#[derive(Debug, Clone)]
+pub(crate) struct TestResponse {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// Length advertised instead of the body length
+    length: Option<usize>,
+}
+
+impl TestResponse {
+    pub fn new(status: u16) -> Self {
+        let reason = match status {
+            200 => "OK",
+            204 => "No Content",
+            206 => "Partial Content",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            416 => "Range Not Satisfiable",
+            429 => "Too Many Requests",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "Status",
+        };
+        Self {
+            status: format!("{status} {reason}"),
+            headers: Vec::new(),
+            body: Vec::new(),
+            length: None,
+        }
+    }
+
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200).body(body)
+    }
+
+    pub fn header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Advertises `length` bytes but only sends the body, like a dropped connection
+    pub fn truncated(mut self, length: usize) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Serves `body` honouring the request's `Range` header, headers only for `HEAD`
+    pub fn file(request: &TestRequest, body: &[u8]) -> Self {
+        let response = match request.range() {
+            Some((start, _)) if start >= body.len() => Self::new(416),
+            Some((start, end)) => {
+                let end = end.unwrap_or(usize::MAX).min(body.len() - 1);
+                Self::new(206)
+                    .header(
+                        "Content-Range",
+                        format!("bytes {start}-{end}/{}", body.len()),
+                    )
+                    .body(&body[start..=end])
+            }
+            None => Self::ok(body),
+        };
+
+        if request.method == "HEAD" {
+            let length = response.body.len();
+            response.body(Vec::new()).truncated(length)
+        } else {
+            response
+        }
+    }
+
+    fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.length.unwrap_or(self.body.len())
+        ));
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+type Handler = dyn Fn(&TestRequest) -> TestResponse + Send + Sync;
+
+/// Local HTTP/1.1 server answering every connection on its own thread
+///
+/// The server lives until the test process exits.
+pub(crate) struct TestServer {
+    url: String,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+}
+
+impl TestServer {
+    pub fn spawn(handler: impl Fn(&TestRequest) -> TestResponse + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test listener");
+        let addr = listener.local_addr().expect("failed to get listener addr");
+        let requests: Arc<Mutex<Vec<TestRequest>>> = Arc::default();
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let handler = handler.clone();
+                let log = log.clone();
+                thread::spawn(move || {
+                    let Some(request) = read_request(&mut stream) else {
+                        return;
+                    };
+                    log.lock().unwrap().push(request.clone());
+                    let _ = handler(&request).write_to(&mut stream);
+                });
+            }
+        });
+
+        Self {
+            url: format!("http://{addr}"),
+            requests,
+        }
+    }
+
+    /// Answers requests with `responses` in order, later requests get a 500
+    pub fn sequence(responses: Vec<TestResponse>) -> Self {
+        let next = AtomicUsize::new(0);
+        Self::spawn(move |_| {
+            responses
+                .get(next.fetch_add(1, Ordering::SeqCst))
+                .cloned()
+                .unwrap_or_else(|| TestResponse::new(500).body("unexpected request"))
+        })
+    }
+
+    /// Serves `/<name>` for every file with `Range` and `HEAD` support, 404 otherwise
+    pub fn files(files: Vec<(&'static str, Vec<u8>)>) -> Self {
+        Self::spawn(move |request| {
+            match files
+                .iter()
+                .find(|(name, _)| request.path.trim_start_matches('/') == *name)
+            {
+                Some((_, body)) => TestResponse::file(request, body),
+                None => TestResponse::new(404),
+            }
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// `url` with `path` appended
+    pub fn at(&self, path: &str) -> String {
+        format!("{}/{}", self.url, path.trim_start_matches('/'))
+    }
+
+    /// Requests received so far, in arrival order
+    pub fn requests(&self) -> Vec<TestRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// `METHOD /path` of every request received so far
+    pub fn request_lines(&self) -> Vec<String> {
+        self.requests()
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.path))
+            .collect()
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<TestRequest> {
+    let mut data = Vec::new();
+    let mut buf = [0_u8; 4096];
+    let head_end = loop {
+        let read = stream.read(&mut buf).ok()?;
+        if read == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buf[..read]);
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut start = lines.next()?.split_whitespace();
+    let method = start.next()?.to_string();
+    // handlers match on the path alone, the query is split off
+    let target = start.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    // read the whole body so the client doesn't see a reset connection
+    let mut body = data.split_off(head_end);
+    while body.len() < length {
+        let read = stream.read(&mut buf).ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+
+    Some(TestRequest {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body,
+    })
+}
+
+/// Client sending its API, token and cover art requests to `server`, logged in as a `CZ` user
+pub(crate) fn api_client(server: &TestServer) -> TidalClient {
+    let mut client = TidalClient::new(&TidalAuth::with_access_token("token".to_string()));
+    client.rq = RequestClient::new(server.url().to_string());
+    client.rq.set_oauth_base_url(server.url());
+    client.rq.set_resources_base_url(server.url());
+    client
+        .user_info
+        .set(Some(serde_json::from_value(user_json()).unwrap()));
+    client
+}
+
+pub(crate) fn user_json() -> Value {
+    json!({
+        "userId": 42, "email": "a@b.c", "countryCode": "CZ", "fullName": null,
+        "firstName": null, "lastName": null, "nickname": null, "username": "user",
+        "address": null, "city": null, "postalcode": null, "usState": null,
+        "phoneNumber": null, "birthday": 0, "channelId": 0, "parentId": 0,
+        "acceptedEULA": true, "created": 0, "updated": 0, "facebookUid": null,
+        "appleUid": null, "googleUid": null, "accountLinkCreated": false,
+        "emailVerified": true, "newUser": false
+    })
+}
+
+pub(crate) fn artist_json(id: u64, name: &str) -> Value {
+    json!({
+        "id": id, "name": name, "handle": null, "picture": null, "userId": null, "type": "MAIN",
+        "artistRoles": null, "artistTypes": null, "relationType": null, "spotlighted": null,
+        "url": null
+    })
+}
+
+/// Track `id` by `artist` on album 100 (`Album`), track and disc number 1
+pub(crate) fn track_json(id: u64, title: &str, artist: &str) -> Value {
+    // split in two, a single `json!` hits the macro recursion limit
+    let mut track = json!({
+        "id": id, "title": title, "duration": 180, "replayGain": -7.5, "peak": 0.9,
+        "allowStreaming": true, "streamReady": true, "payToStream": false,
+        "adSupportedStreamReady": true, "djReady": true, "stemReady": false,
+        "streamStartDate": null, "premiumStreamingOnly": false, "trackNumber": 1,
+        "volumeNumber": 1, "version": null, "popularity": 0, "copyright": null, "bpm": null
+    });
+    let rest = json!({
+        "key": null, "keyScale": null, "url": "", "isrc": null, "editable": false,
+        "explicit": false, "audioQuality": "LOSSLESS", "audioModes": ["STEREO"],
+        "mediaMetadata": null, "upload": false, "accessType": null, "spotlighted": null,
+        "dateAdded": null, "index": null, "artist": artist_json(1, artist),
+        "artists": [artist_json(1, artist)],
+        "album": {
+            "id": 100, "title": "Album", "cover": null, "vibrantColor": null, "videoCover": null,
+            "releaseDate": "2020-01-02"
+        },
+        "mixes": null, "itemUuid": null
+    });
+    track
+        .as_object_mut()
+        .unwrap()
+        .extend(rest.as_object().unwrap().clone());
+    track
+}
+
+/// Playlist `uuid` titled `title`
+pub(crate) fn playlist_json(uuid: &str, title: &str) -> Value {
+    json!({
+        "uuid": uuid, "title": title, "numberOfTracks": 0, "numberOfVideos": 0,
+        "creator": {"id": 42}, "description": "", "duration": 0, "lastUpdated": "",
+        "created": "", "type": "USER", "publicPlaylist": false, "url": "", "image": "",
+        "popularity": 0, "squareImage": "", "customImageUrl": null, "promotedArtists": [],
+        "lastItemAddedAt": null
+    })
+}
+
+/// Offset page of album or playlist items answering `request`'s `limit` and `offset`
+///
+/// `items` are `(type, item)` pairs, the response carries an `ETag` like playlist pages do.
+pub(crate) fn items_page(request: &TestRequest, items: &[(&str, Value)]) -> TestResponse {
+    let number = |name, default| {
+        request
+            .param(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    let (limit, offset): (usize, usize) = (number("limit", 20), number("offset", 0));
+    let page: Vec<Value> = items
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(kind, item)| json!({"item": item, "type": kind, "cut": null}))
+        .collect();
+
+    let body = json!({
+        "limit": limit, "offset": offset, "totalNumberOfItems": items.len(), "items": page
+    });
+    TestResponse::ok(body.to_string()).header("ETag", "\"1\"")
+}
+
+/// Playback info of a progressive FLAC stream served from `url`
+pub(crate) fn playback_json(track_id: u64, url: &str) -> Value {
+    let manifest = json!({
+        "mimeType": "audio/flac", "codecs": "flac", "encryptionType": "NONE", "urls": [url]
+    });
+    json!({
+        "trackId": track_id, "assetPresentation": "FULL", "audioMode": "STEREO",
+        "audioQuality": "LOSSLESS", "manifestMimeType": "application/vnd.tidal.bts",
+        "manifestHash": "", "manifest": BASE64.encode(manifest.to_string()),
+        "albumReplayGain": 0.0, "albumPeakAmplitude": 0.0, "trackReplayGain": 0.0,
+        "trackPeakAmplitude": 0.0
+    })
+}
+
+/// Playback info of a HiRes DASH stream with `init.mp4` and `segments` media segments at `base`
+pub(crate) fn dash_playback_json(track_id: u64, base: &str, segments: u64) -> Value {
+    let mpd = format!(
+        r#"<MPD mediaPresentationDuration="PT{}S"><Period><AdaptationSet mimeType="audio/mp4">
+            <Representation id="r" codecs="flac" bandwidth="3000000"><BaseURL>{base}</BaseURL>
+            <SegmentTemplate timescale="1000" duration="2000" startNumber="1"
+                initialization="init.mp4" media="$Number$.m4s"/>
+            </Representation></AdaptationSet></Period></MPD>"#,
+        segments * 2
+    );
+    let mut playback = playback_json(track_id, "");
+    playback["audioQuality"] = json!("HI_RES_LOSSLESS");
+    playback["manifestMimeType"] = json!("application/dash+xml");
+    playback["manifest"] = json!(BASE64.encode(mpd));
+    playback
+}
+
+/// Album `id` by `artist` released on 2020-01-02
+pub(crate) fn album_json(id: u64, title: &str, artist: &str) -> Value {
+    json!({
+        "id": id, "title": title, "duration": 360, "streamReady": true, "payToStream": false,
+        "adSupportedStreamReady": true, "djReady": true, "stemReady": false,
+        "streamStartDate": "2020-01-02T00:00:00.000+0000", "allowStreaming": true,
+        "premiumStreamingOnly": false, "numberOfTracks": 2, "numberOfVideos": 0,
+        "numberOfVolumes": 1, "releaseDate": "2020-01-02", "copyright": "", "type": "ALBUM",
+        "version": null, "url": "", "cover": "aaaa-bbbb", "vibrantColor": null,
+        "videoCover": null, "explicit": false, "upc": "", "popularity": 0,
+        "audioQuality": "LOSSLESS", "audioModes": ["STEREO"], "upload": false,
+        "artist": artist_json(2, artist), "artists": [artist_json(2, artist)]
+    })
+}
+
+/// MP4 box of `kind` around `payload`
+pub(crate) fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+fn streaminfo(sample_rate: u32, total_samples: u64) -> Vec<u8> {
+    let mut info = vec![0_u8; 34];
+    info[0..2].copy_from_slice(&4096_u16.to_be_bytes());
+    info[2..4].copy_from_slice(&4096_u16.to_be_bytes());
+    info[10] = (sample_rate >> 12) as u8;
+    info[11] = (sample_rate >> 4) as u8;
+    // 2 channels, 24 bits per sample
+    info[12] = ((sample_rate & 0x0f) as u8) << 4 | (1 << 1) | (23 >> 4);
+    info[13] = ((23 & 0x0f) << 4) | ((total_samples >> 32) as u8 & 0x0f);
+    info[14..18].copy_from_slice(&(total_samples as u32).to_be_bytes());
+    info
+}
+
+/// Init segment of a fragmented MP4 carrying 96 kHz FLAC
+pub(crate) fn fmp4_flac_init(total_samples: u64) -> Vec<u8> {
+    // STREAMINFO without the last flag, followed by a flagged padding block
+    let mut dfla = vec![0, 0, 0, 0, 0x00, 0, 0, 34];
+    dfla.extend(streaminfo(96_000, total_samples));
+    dfla.extend([0x81, 0, 0, 2, 0, 0]);
+
+    let mut flac_entry = vec![0_u8; 28];
+    flac_entry.extend(mp4_box(b"dfLa", &dfla));
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend(mp4_box(b"fLaC", &flac_entry));
+
+    let mut mdhd = vec![0_u8; 20];
+    mdhd[12..16].copy_from_slice(&96_000_u32.to_be_bytes());
+
+    let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+    let minf = mp4_box(b"minf", &stbl);
+    let mdia = mp4_box(b"mdia", &[mp4_box(b"mdhd", &mdhd), minf].concat());
+    let moov = mp4_box(b"moov", &mp4_box(b"trak", &mdia));
+
+    [mp4_box(b"ftyp", b"iso6"), moov].concat()
+}
+
+/// `moof` + `mdat` with one sample per frame, all of `duration`
+pub(crate) fn fmp4_flac_fragment(frames: &[&[u8]], duration: u32) -> Vec<u8> {
+    // tfhd with default-base-is-moof, trun with data offset, durations and sizes
+    let tfhd = [0, 0x02, 0, 0, 0, 0, 0, 1];
+    let build = |data_offset: u32| {
+        let mut trun = vec![0, 0, 0x03, 0x01];
+        trun.extend((frames.len() as u32).to_be_bytes());
+        trun.extend(data_offset.to_be_bytes());
+        for frame in frames {
+            trun.extend(duration.to_be_bytes());
+            trun.extend((frame.len() as u32).to_be_bytes());
+        }
+        let traf = mp4_box(
+            b"traf",
+            &[mp4_box(b"tfhd", &tfhd), mp4_box(b"trun", &trun)].concat(),
+        );
+        mp4_box(b"moof", &[mp4_box(b"mfhd", &[0; 8]), traf].concat())
+    };
+
+    let moof_len = build(0).len() as u32;
+    let moof = build(moof_len + 8);
+    [moof, mp4_box(b"mdat", &frames.concat())].concat()
+}
+
+/// Key of the stream [`old_aes_key_id`] hands out
+pub(crate) const KEY: [u8; 16] = *b"0123456789abcdef";
+/// Nonce of the stream [`old_aes_key_id`] hands out
+pub(crate) const NONCE: [u8; 8] = *b"noncenon";
+
+/// `OLD_AES` key ID carrying `KEY` and `NONCE`, as TIDAL would hand it out
+pub(crate) fn old_aes_key_id() -> String {
+    let iv = [7_u8; 16];
+    let mut token = [0_u8; 32];
+    token[..16].copy_from_slice(&KEY);
+    token[16..24].copy_from_slice(&NONCE);
+
+    let master_key = BASE64.decode(MASTER_KEY).unwrap();
+    let encrypted = cbc::Encryptor::<Aes256>::new_from_slices(&master_key, &iv)
+        .unwrap()
+        .encrypt_padded::<NoPadding>(&mut token, 32)
+        .unwrap()
+        .to_vec();
+    BASE64.encode([iv.as_slice(), &encrypted].concat())
+}
+
+/// `plain` encrypted the way `OLD_AES` streams are
+pub(crate) fn encrypt_old_aes(plain: &[u8]) -> Vec<u8> {
+    let mut iv = [0; 16];
+    iv[..8].copy_from_slice(&NONCE);
+    let mut data = plain.to_vec();
+    ctr::Ctr64BE::<Aes128>::new(&KEY.into(), &iv.into()).apply_keystream(&mut data);
+    data
+}
@@ -5,3 +5,4 @@ pub const WEB_API_V2_LOCATION: &str = "https://tidal.com/v2";
 pub const OAUTH2_V1_LOCATION: &str = "https://auth.tidal.com/v1/oauth2";
 pub const PKCE_URI_REDIRECT: &str = "https://tidal.com/android/login/auth";
 pub const PKCE_AUTH_URL: &str = "https://login.tidal.com/authorize";
+pub const RESOURCES_LOCATION: &str = "https://resources.tidal.com";